@@ -0,0 +1,79 @@
+use argparse::{ArgumentParser, List, StoreTrue};
+use stdf_reader::{audit_summary, lint_stdf};
+
+// Define a struct to hold the arguments
+struct Arguments {
+    stdf_filenames: Vec<String>,
+    lint: bool,
+}
+
+fn main() {
+    // Call the function to parse the arguments
+    let args = parse_arguments();
+
+    // every file is audited, the exit code tells if any of them failed
+    let mut all_passed = true;
+    for stdf_filename in &args.stdf_filenames {
+        let audit = match audit_summary(stdf_filename) {
+            Ok(audit) => audit,
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                std::process::exit(1);
+            }
+        };
+
+        for mismatch in &audit.mismatches {
+            println!("  {}", mismatch);
+        }
+        println!("{} '{}': {} parts, {} summary records, {} mismatches",
+            if audit.passed() { "PASS" } else { "FAIL" }, stdf_filename, audit.parts_read, audit.summary_recs, audit.mismatches.len());
+
+        all_passed &= audit.passed();
+
+        // conformance to the STDF spec is optional
+        if args.lint {
+            let issues = match lint_stdf(stdf_filename) {
+                Ok(issues) => issues,
+                Err(err) => {
+                    eprintln!("Error: {}", err);
+                    std::process::exit(1);
+                }
+            };
+
+            for issue in &issues {
+                println!("  {}", issue);
+            }
+            println!("{} '{}': {} spec violations", if issues.is_empty() { "PASS" } else { "FAIL" }, stdf_filename, issues.len());
+
+            all_passed &= issues.is_empty();
+        }
+    }
+
+    if !all_passed {
+        std::process::exit(1);
+    }
+}
+
+// Function to parse the arguments
+fn parse_arguments() -> Arguments {
+    let mut args = Arguments { stdf_filenames: Vec::new(), lint: false };
+
+    // force lifetime for Argument parser to be short
+    {
+        // Create ArgumentParser variable
+        let mut ap = ArgumentParser::new();
+
+        // Application description
+        ap.set_description("Checks the HBR/SBR/PCR/TSR summary records of STDF files against their part and test data, exits with 1 on any mismatch");
+
+        // Add all arguments and associated variables
+        ap.refer(&mut args.lint).add_option(&["--lint"], StoreTrue, "Also check the record order and fields against the STDF V4 spec");
+        ap.refer(&mut args.stdf_filenames).add_argument("Stdf Inputs", List, "Stdf input files to be audited").required();
+
+        // parse arguments and store
+        ap.parse_args_or_exit();
+    }
+
+    // Return the arguments in a struct
+    args
+}
@@ -0,0 +1,60 @@
+use argparse::{ArgumentParser, List, StoreOption};
+use stdf_reader::compare_stdf;
+
+// Define a struct to hold the arguments
+struct Arguments {
+    stdf_filenames: Vec<String>,
+    csv_filename: Option<String>,
+}
+
+fn main() {
+    // Call the function to parse the arguments
+    let args = parse_arguments();
+
+    // the first file is the reference the others are compared with
+    if args.stdf_filenames.len() < 2 {
+        eprintln!("Error: at least two STDF files are needed to compare them");
+        std::process::exit(1);
+    }
+
+    let comparison = match compare_stdf(&args.stdf_filenames) {
+        Ok(comparison) => comparison,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    print!("{}", comparison.to_text());
+
+    if let Some(csv_filename) = &args.csv_filename {
+        if let Err(err) = comparison.write_csv(csv_filename) {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Function to parse the arguments
+fn parse_arguments() -> Arguments {
+    let mut args = Arguments { stdf_filenames: Vec::new(), csv_filename: None };
+
+    // force lifetime for Argument parser to be short
+    {
+        // Create ArgumentParser variable
+        let mut ap = ArgumentParser::new();
+
+        // Application description
+        ap.set_description("Compares the parametric tests of STDF files with the first one, matched by test number and name: mean/sigma shift, KS statistic, yield delta, limit changes and tests missing in some of the files");
+
+        // Add all arguments and associated variables
+        ap.refer(&mut args.csv_filename).add_option(&["-c", "--csv"], StoreOption, "Also write the comparison to this csv file");
+        ap.refer(&mut args.stdf_filenames).add_argument("Stdf Inputs", List, "Stdf input files to be compared, the first one is the reference").required();
+
+        // parse arguments and store
+        ap.parse_args_or_exit();
+    }
+
+    // Return the arguments in a struct
+    args
+}
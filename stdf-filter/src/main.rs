@@ -0,0 +1,137 @@
+use std::str::FromStr;
+
+use argparse::{ArgumentParser, Store, StoreOption};
+use regex::Regex;
+use stdf_reader::{filter_stdf, StdfFilter};
+
+// Define a struct to hold the arguments
+struct Arguments {
+    stdf_filename: String,
+    output_filename: String,
+    part_id: String,
+    hard_bins: String,
+    soft_bins: String,
+    sites: String,
+    x_min: Option<i16>,
+    x_max: Option<i16>,
+    y_min: Option<i16>,
+    y_max: Option<i16>,
+    test_nums: String,
+    test_txt: String,
+}
+
+// comma separated list of numbers, e.g. "1,3,7"
+fn parse_list<T: FromStr>(name: &str, list: &str) -> Result<Vec<T>, String> {
+    list.split(',')
+        .filter(|item| !item.trim().is_empty())
+        .map(|item| item.trim().parse::<T>().map_err(|_| format!("'{}' in {} is not a valid number", item, name)))
+        .collect()
+}
+
+// comma separated list of test numbers or inclusive ranges, e.g. "100-199,300"
+fn parse_ranges(list: &str) -> Result<Vec<(u32, u32)>, String> {
+    list.split(',')
+        .filter(|item| !item.trim().is_empty())
+        .map(|item| {
+            let (first, last) = item.split_once('-').unwrap_or((item, item));
+            match (first.trim().parse::<u32>(), last.trim().parse::<u32>()) {
+                (Ok(first), Ok(last)) if first <= last => Ok((first, last)),
+                _ => Err(format!("'{}' in --test-num is not a valid test number or range", item)),
+            }
+        })
+        .collect()
+}
+
+fn parse_regex(name: &str, regex: &str) -> Result<Option<Regex>, String> {
+    if regex.is_empty() { return Ok(None); }
+    Regex::new(regex).map(Some).map_err(|e| format!("{} is not a valid regex: {}", name, e))
+}
+
+fn parse_range(min: Option<i16>, max: Option<i16>) -> Option<(i16, i16)> {
+    match (min, max) {
+        (None, None) => None,
+        (min, max) => Some((min.unwrap_or(i16::MIN), max.unwrap_or(i16::MAX))),
+    }
+}
+
+fn build_filter(args: &Arguments) -> Result<StdfFilter, String> {
+    Ok(StdfFilter {
+        part_id: parse_regex("--part-id", &args.part_id)?,
+        hard_bins: parse_list("--hard-bin", &args.hard_bins)?,
+        soft_bins: parse_list("--soft-bin", &args.soft_bins)?,
+        sites: parse_list("--site", &args.sites)?,
+        x_range: parse_range(args.x_min, args.x_max),
+        y_range: parse_range(args.y_min, args.y_max),
+        test_nums: parse_ranges(&args.test_nums)?,
+        test_txt: parse_regex("--test-txt", &args.test_txt)?,
+    })
+}
+
+fn main() {
+    // Call the function to parse the arguments
+    let args = parse_arguments();
+
+    let filter = match build_filter(&args) {
+        Ok(filter) => filter,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    // do actual filtering
+    match filter_stdf(&args.stdf_filename, &args.output_filename, &filter) {
+        Ok(counts) => println!("Kept {} of {} parts from '{}' in '{}'", counts.parts_kept, counts.parts_read, args.stdf_filename, args.output_filename),
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Function to parse the arguments
+fn parse_arguments() -> Arguments {
+    let mut args = Arguments {
+        stdf_filename: String::new(),
+        output_filename: String::new(),
+        part_id: String::new(),
+        hard_bins: String::new(),
+        soft_bins: String::new(),
+        sites: String::new(),
+        x_min: None,
+        x_max: None,
+        y_min: None,
+        y_max: None,
+        test_nums: String::new(),
+        test_txt: String::new(),
+    };
+
+    // force lifetime for Argument parser to be short
+    {
+        // Create ArgumentParser variable
+        let mut ap = ArgumentParser::new();
+
+        // Application description
+        ap.set_description("Writes the selected parts and tests of an STDF to a new, smaller STDF");
+
+        // Add all arguments and associated variables
+        ap.refer(&mut args.stdf_filename).add_argument("Stdf Input", Store, "Stdf input file to be filtered").required();
+        ap.refer(&mut args.output_filename).add_argument("Stdf Output", Store, "Filtered stdf output file, ending it in .gz compresses it").required();
+        ap.refer(&mut args.part_id).add_option(&["--part-id"], Store, "Keep parts whose part id matches the regex");
+        ap.refer(&mut args.hard_bins).add_option(&["--hard-bin"], Store, "Keep parts in these hard bins, comma separated");
+        ap.refer(&mut args.soft_bins).add_option(&["--soft-bin"], Store, "Keep parts in these soft bins, comma separated");
+        ap.refer(&mut args.sites).add_option(&["--site"], Store, "Keep parts tested on these sites, comma separated");
+        ap.refer(&mut args.x_min).add_option(&["--x-min"], StoreOption, "Keep parts with a wafer x coordinate of at least this");
+        ap.refer(&mut args.x_max).add_option(&["--x-max"], StoreOption, "Keep parts with a wafer x coordinate of at most this");
+        ap.refer(&mut args.y_min).add_option(&["--y-min"], StoreOption, "Keep parts with a wafer y coordinate of at least this");
+        ap.refer(&mut args.y_max).add_option(&["--y-max"], StoreOption, "Keep parts with a wafer y coordinate of at most this");
+        ap.refer(&mut args.test_nums).add_option(&["--test-num"], Store, "Keep these test numbers or ranges, e.g. 100-199,300");
+        ap.refer(&mut args.test_txt).add_option(&["--test-txt"], Store, "Keep tests whose test text matches the regex");
+
+        // parse arguments and store
+        ap.parse_args_or_exit();
+    }
+
+    // Return the arguments in a struct
+    args
+}
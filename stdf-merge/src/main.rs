@@ -0,0 +1,75 @@
+use argparse::{ArgumentParser, List, Store, StoreTrue};
+use stdf_reader::{merge_stdf, MergeOptions, RetestKey};
+
+// Define a struct to hold the arguments
+struct Arguments {
+    stdf_filenames: Vec<String>,
+    output_filename: String,
+    renumber: bool,
+    keep_last_retest: String,
+    last_mir: bool,
+}
+
+fn parse_retest_key(key: &str) -> Result<Option<RetestKey>, String> {
+    match key {
+        "" => Ok(None),
+        "part-id" => Ok(Some(RetestKey::PartId)),
+        "xy" => Ok(Some(RetestKey::Coordinates)),
+        _ => Err(format!("'{}' is not a valid --keep-last-retest, use part-id or xy", key)),
+    }
+}
+
+fn main() {
+    // Call the function to parse the arguments
+    let args = parse_arguments();
+
+    let options = match parse_retest_key(&args.keep_last_retest) {
+        Ok(keep_last_retest) => MergeOptions { renumber_part_ids: args.renumber, keep_last_retest, use_last_mir: args.last_mir },
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    // do actual merging
+    match merge_stdf(&args.stdf_filenames, &args.output_filename, &options) {
+        Ok(counts) => println!("Merged {} of {} parts from {} stdf files in '{}'", counts.parts_kept, counts.parts_read, args.stdf_filenames.len(), args.output_filename),
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Function to parse the arguments
+fn parse_arguments() -> Arguments {
+    let mut args = Arguments {
+        stdf_filenames: Vec::new(),
+        output_filename: String::new(),
+        renumber: false,
+        keep_last_retest: String::new(),
+        last_mir: false,
+    };
+
+    // force lifetime for Argument parser to be short
+    {
+        // Create ArgumentParser variable
+        let mut ap = ArgumentParser::new();
+
+        // Application description
+        ap.set_description("Merges the STDF files of a lot into a single STDF, in the order given");
+
+        // Add all arguments and associated variables
+        ap.refer(&mut args.output_filename).add_option(&["-o", "--output"], Store, "Merged stdf output file, ending it in .gz compresses it").required();
+        ap.refer(&mut args.renumber).add_option(&["--renumber"], StoreTrue, "Renumber the part ids of the merged parts starting at 1");
+        ap.refer(&mut args.keep_last_retest).add_option(&["--keep-last-retest"], Store, "Only keep the last test of retested parts, matched by part-id or xy");
+        ap.refer(&mut args.last_mir).add_option(&["--last-mir"], StoreTrue, "Use the MIR of the last stdf instead of the first one");
+        ap.refer(&mut args.stdf_filenames).add_argument("Stdf Inputs", List, "Stdf input files to be merged").required();
+
+        // parse arguments and store
+        ap.parse_args_or_exit();
+    }
+
+    // Return the arguments in a struct
+    args
+}
@@ -0,0 +1,112 @@
+use std::fmt;
+
+/// Error returned by every fallible function in stdf_reader
+#[derive(Debug)]
+pub enum StdfUtilError {
+    /// Opening, creating or writing a file failed
+    Io { path: String, source: std::io::Error },
+
+    /// rust_stdf was unable to decode the STDF, code and msg are the ones reported by rust_stdf
+    Decode { path: String, code: u8, msg: String },
+
+    /// A record couldn't be serialized by StdfWriter, e.g. a Cn longer than 255 bytes
+    Encode { path: String, record: &'static str, msg: String },
+
+    /// The DTR configuration file couldn't be loaded
+    DtrConfig { path: String, msg: String },
+
+    /// A regex in the DTR configuration file is invalid
+    DtrRegex { section: String, source: regex::Error },
+
+    /// A record references another record that isn't in the STDF,
+    /// e.g. an MPR rtn_indx that points at a PMR that was never written
+    MissingReference { record: &'static str, test_num: u32, reference: &'static str, index: u32 },
+
+    /// Error reported by SQLite while exporting
+    Sqlite(rusqlite::Error),
+
+    /// Error reported by polars while building or writing a DataFrame
+    Polars(polars::error::PolarsError),
+
+    /// Error reported by png while writing a wafer map
+    Png(png::EncodingError),
+
+    /// A RecordQuery couldn't be parsed
+    Query { query: String, msg: String },
+
+    /// The limits file of relimit_stdf couldn't be loaded
+    LimitsFile { path: String, msg: String },
+
+    /// A part that passed fails a new limit of a test without bins in the limits file,
+    /// and relimit_stdf wasn't given a fail bin either
+    MissingFailBin { part_idx: u32, test_num: u32 },
+
+    /// A PMR or SDR of an STDF given to merge_stdf redefines the pin or site group
+    /// an earlier STDF defined differently
+    MergeConflict { path: String, record: &'static str, index: u32 },
+}
+
+impl StdfUtilError {
+    /// Helper for map_err, wraps an io error with the path it happened on
+    pub fn io(path: &str) -> impl FnOnce(std::io::Error) -> Self + '_ {
+        move |source| Self::Io { path: path.to_owned(), source }
+    }
+}
+
+impl fmt::Display for StdfUtilError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io { path, source } => write!(f, "I/O error on '{}': {}", path, source),
+            Self::Decode { path, code, msg } => write!(f, "Unable to decode stdf '{}' (code {}): {}", path, code, msg),
+            Self::Encode { path, record, msg } => write!(f, "Unable to write {} to stdf '{}': {}", record, path, msg),
+            Self::DtrConfig { path, msg } => write!(f, "Unable to load dtr configuration '{}': {}", path, msg),
+            Self::DtrRegex { section, source } => write!(f, "Error while parsing regex in section {} in dtr configuration file: {}", section, source),
+            Self::MissingReference { record, test_num, reference, index } => {
+                write!(f, "{} for test {} references {} index {} which was not found", record, test_num, reference, index)
+            },
+            Self::Sqlite(source) => write!(f, "SQLite error: {}", source),
+            Self::Polars(source) => write!(f, "Polars error: {}", source),
+            Self::Png(source) => write!(f, "PNG error: {}", source),
+            Self::Query { query, msg } => write!(f, "Invalid query '{}': {}", query, msg),
+            Self::LimitsFile { path, msg } => write!(f, "Unable to load limits file '{}': {}", path, msg),
+            Self::MissingFailBin { part_idx, test_num } => {
+                write!(f, "Part {} now fails test {} which has no hard_bin/soft_bin in the limits file, a fail bin is needed for it", part_idx, test_num)
+            },
+            Self::MergeConflict { path, record, index } => {
+                write!(f, "{} {} of stdf '{}' differs from the one an earlier stdf defined, they can't be merged", record, index, path)
+            },
+        }
+    }
+}
+
+impl std::error::Error for StdfUtilError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            Self::DtrRegex { source, .. } => Some(source),
+            Self::Sqlite(source) => Some(source),
+            Self::Polars(source) => Some(source),
+            Self::Png(source) => Some(source),
+            Self::Decode { .. } | Self::Encode { .. } | Self::DtrConfig { .. } | Self::MissingReference { .. } | Self::Query { .. } | Self::LimitsFile { .. }
+                | Self::MissingFailBin { .. } | Self::MergeConflict { .. } => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for StdfUtilError {
+    fn from(error: rusqlite::Error) -> Self {
+        Self::Sqlite(error)
+    }
+}
+
+impl From<polars::error::PolarsError> for StdfUtilError {
+    fn from(error: polars::error::PolarsError) -> Self {
+        Self::Polars(error)
+    }
+}
+
+impl From<png::EncodingError> for StdfUtilError {
+    fn from(error: png::EncodingError) -> Self {
+        Self::Png(error)
+    }
+}
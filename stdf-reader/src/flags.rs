@@ -0,0 +1,302 @@
+//////////////////////////////////////////////////////////////////////
+/// Description: TEST_FLG of a PTR, MPR or FTR. Pass/fail is only known when
+///              bit 6 is clear, bit 7 then tells if the test failed
+//////////////////////////////////////////////////////////////////////
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TestFlags(pub u8);
+
+impl From<[u8; 1]> for TestFlags {
+    fn from(test_flg: [u8; 1]) -> Self {
+        Self(test_flg[0])
+    }
+}
+
+impl TestFlags {
+    pub fn alarm(&self) -> bool {
+        self.0 & 0x01 != 0
+    }
+
+    /// The result of a PTR/MPR isn't valid, reserved for FTR's
+    pub fn result_invalid(&self) -> bool {
+        self.0 & 0x02 != 0
+    }
+
+    pub fn unreliable(&self) -> bool {
+        self.0 & 0x04 != 0
+    }
+
+    pub fn timeout(&self) -> bool {
+        self.0 & 0x08 != 0
+    }
+
+    pub fn not_executed(&self) -> bool {
+        self.0 & 0x10 != 0
+    }
+
+    pub fn aborted(&self) -> bool {
+        self.0 & 0x20 != 0
+    }
+
+    /// The test completed without a pass/fail indication
+    pub fn no_pass_fail(&self) -> bool {
+        self.0 & 0x40 != 0
+    }
+
+    /// true when the test failed, None without a pass/fail indication
+    pub fn failed(&self) -> Option<bool> {
+        (!self.no_pass_fail()).then_some(self.0 & 0x80 != 0)
+    }
+
+    pub fn is_fail(&self) -> bool {
+        self.failed() == Some(true)
+    }
+
+    pub fn is_pass(&self) -> bool {
+        self.failed() == Some(false)
+    }
+
+    /// The result was measured and is valid, what goes into statistics and graphs
+    pub fn is_valid(&self) -> bool {
+        !self.result_invalid() && !self.not_executed()
+    }
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: PARM_FLG of a PTR or MPR, bits 6 and 7 tell whether a result
+///              equal to a limit passes
+//////////////////////////////////////////////////////////////////////
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ParmFlags(pub u8);
+
+impl From<[u8; 1]> for ParmFlags {
+    fn from(parm_flg: [u8; 1]) -> Self {
+        Self(parm_flg[0])
+    }
+}
+
+impl ParmFlags {
+    pub fn scale_error(&self) -> bool {
+        self.0 & 0x01 != 0
+    }
+
+    pub fn drift_error(&self) -> bool {
+        self.0 & 0x02 != 0
+    }
+
+    pub fn oscillation(&self) -> bool {
+        self.0 & 0x04 != 0
+    }
+
+    pub fn above_hi_limit(&self) -> bool {
+        self.0 & 0x08 != 0
+    }
+
+    pub fn below_lo_limit(&self) -> bool {
+        self.0 & 0x10 != 0
+    }
+
+    pub fn passed_alternate_limits(&self) -> bool {
+        self.0 & 0x20 != 0
+    }
+
+    /// A result equal to the lo limit passes
+    pub fn lo_limit_inclusive(&self) -> bool {
+        self.0 & 0x40 != 0
+    }
+
+    /// A result equal to the hi limit passes
+    pub fn hi_limit_inclusive(&self) -> bool {
+        self.0 & 0x80 != 0
+    }
+
+    /// A result passes the limits, a missing limit passes everything
+    pub fn within_limits(&self, result: f32, lo_limit: Option<f64>, hi_limit: Option<f64>) -> bool {
+        let result = result as f64;
+        let lo_ok = lo_limit.is_none_or(|lo_limit| result > lo_limit || (self.lo_limit_inclusive() && result == lo_limit));
+        let hi_ok = hi_limit.is_none_or(|hi_limit| result < hi_limit || (self.hi_limit_inclusive() && result == hi_limit));
+        lo_ok && hi_ok
+    }
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: OPT_FLAG of a PTR or MPR, a record without one has every
+///              optional field valid
+//////////////////////////////////////////////////////////////////////
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PtrOptFlags(pub u8);
+
+impl From<Option<[u8; 1]>> for PtrOptFlags {
+    fn from(opt_flag: Option<[u8; 1]>) -> Self {
+        Self(opt_flag.map(|opt_flag| opt_flag[0]).unwrap_or(0))
+    }
+}
+
+impl PtrOptFlags {
+    pub const LO_LIMIT_INVALID: u8 = 0x10;
+    pub const HI_LIMIT_INVALID: u8 = 0x20;
+
+    pub fn res_scal_valid(&self) -> bool {
+        self.0 & 0x01 == 0
+    }
+
+    pub fn lo_spec_valid(&self) -> bool {
+        self.0 & 0x04 == 0
+    }
+
+    pub fn hi_spec_valid(&self) -> bool {
+        self.0 & 0x08 == 0
+    }
+
+    /// The test has a lo limit, it may still be invalid
+    pub fn has_lo_limit(&self) -> bool {
+        self.0 & 0x40 == 0
+    }
+
+    /// The test has a hi limit, it may still be invalid
+    pub fn has_hi_limit(&self) -> bool {
+        self.0 & 0x80 == 0
+    }
+
+    /// lo_limit and llm_scal can be used
+    pub fn lo_limit_valid(&self) -> bool {
+        self.has_lo_limit() && self.0 & Self::LO_LIMIT_INVALID == 0
+    }
+
+    /// hi_limit and hlm_scal can be used
+    pub fn hi_limit_valid(&self) -> bool {
+        self.has_hi_limit() && self.0 & Self::HI_LIMIT_INVALID == 0
+    }
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: OPT_FLAG of an FTR, each bit set makes a field invalid
+//////////////////////////////////////////////////////////////////////
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FtrOptFlags(pub u8);
+
+impl From<[u8; 1]> for FtrOptFlags {
+    fn from(opt_flag: [u8; 1]) -> Self {
+        Self(opt_flag[0])
+    }
+}
+
+impl FtrOptFlags {
+    pub fn cycl_cnt_valid(&self) -> bool {
+        self.0 & 0x01 == 0
+    }
+
+    pub fn rel_vadr_valid(&self) -> bool {
+        self.0 & 0x02 == 0
+    }
+
+    pub fn rept_cnt_valid(&self) -> bool {
+        self.0 & 0x04 == 0
+    }
+
+    pub fn num_fail_valid(&self) -> bool {
+        self.0 & 0x08 == 0
+    }
+
+    /// xfail_ad and yfail_ad can be used
+    pub fn fail_address_valid(&self) -> bool {
+        self.0 & 0x10 == 0
+    }
+
+    pub fn vect_off_valid(&self) -> bool {
+        self.0 & 0x20 == 0
+    }
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: OPT_FLAG of a TSR, each bit set makes a field invalid
+//////////////////////////////////////////////////////////////////////
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TsrOptFlags(pub u8);
+
+impl From<[u8; 1]> for TsrOptFlags {
+    fn from(opt_flag: [u8; 1]) -> Self {
+        Self(opt_flag[0])
+    }
+}
+
+impl TsrOptFlags {
+    pub const TEST_TIM_INVALID: u8 = 0x04;
+    /// test_min, test_max, tst_sums and tst_sqrs, the fields computed from the test results
+    pub const RESULT_STATS_INVALID: u8 = 0x33;
+
+    pub fn test_min_valid(&self) -> bool {
+        self.0 & 0x01 == 0
+    }
+
+    pub fn test_max_valid(&self) -> bool {
+        self.0 & 0x02 == 0
+    }
+
+    pub fn test_tim_valid(&self) -> bool {
+        self.0 & Self::TEST_TIM_INVALID == 0
+    }
+
+    pub fn tst_sums_valid(&self) -> bool {
+        self.0 & 0x10 == 0
+    }
+
+    pub fn tst_sqrs_valid(&self) -> bool {
+        self.0 & 0x20 == 0
+    }
+
+    /// Marks test_min, test_max, tst_sums and tst_sqrs valid or invalid at once
+    pub fn set_result_stats_valid(&mut self, valid: bool) {
+        if valid { self.0 &= !Self::RESULT_STATS_INVALID; } else { self.0 |= Self::RESULT_STATS_INVALID; }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: PART_FLG of a PRR. Pass/fail is only known when bit 4 is
+///              clear, bit 3 then tells if the part failed
+//////////////////////////////////////////////////////////////////////
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PartFlags(pub u8);
+
+impl From<[u8; 1]> for PartFlags {
+    fn from(part_flg: [u8; 1]) -> Self {
+        Self(part_flg[0])
+    }
+}
+
+impl PartFlags {
+    /// The part supersedes an earlier one with the same part_id
+    pub fn retest_same_id(&self) -> bool {
+        self.0 & 0x01 != 0
+    }
+
+    /// The part supersedes an earlier one at the same x/y coordinates
+    pub fn retest_same_xy(&self) -> bool {
+        self.0 & 0x02 != 0
+    }
+
+    pub fn is_retest(&self) -> bool {
+        self.retest_same_id() || self.retest_same_xy()
+    }
+
+    /// Testing of the part ended abnormally
+    pub fn abnormal_end(&self) -> bool {
+        self.0 & 0x04 != 0
+    }
+
+    pub fn no_pass_fail(&self) -> bool {
+        self.0 & 0x10 != 0
+    }
+
+    /// true when the part failed, None without a pass/fail indication
+    pub fn failed(&self) -> Option<bool> {
+        (!self.no_pass_fail()).then_some(self.0 & 0x08 != 0)
+    }
+
+    pub fn is_fail(&self) -> bool {
+        self.failed() == Some(true)
+    }
+
+    pub fn is_pass(&self) -> bool {
+        self.failed() == Some(false)
+    }
+}
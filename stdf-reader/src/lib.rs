@@ -1,38 +1,53 @@
-use std::{collections::{BTreeMap, HashMap}, fs::File, io::{BufReader, Write}};
-use sprintf::sprintf;
+use std::{collections::BTreeMap, fs::File, io::{BufReader, BufWriter, Read, Write}};
 
+mod error;
+mod flags;
+mod part_iterator;
 mod rec_to_string;
+mod record_query;
+mod relimit;
+mod stdf2parquet;
+mod stdf2sqlite;
+mod stdf2wafermap;
+mod stdf_compare;
+mod stdf_filter;
+mod stdf_lint;
+mod stdf_merge;
+mod stdf_writer;
+mod summary_audit;
+mod summary_counts;
+mod test_plan;
+mod test_stats;
+mod value_format;
+mod wafer_map;
 pub mod stdf_parser;
 
+pub use error::StdfUtilError;
+pub use flags::{FtrOptFlags, ParmFlags, PartFlags, PtrOptFlags, TestFlags, TsrOptFlags};
+pub use part_iterator::{Part, PartIterator, PartTracker};
+pub use rec_to_string::rec_to_string;
+pub use record_query::{QueryFields, QueryValue, RecordFields, RecordQuery};
+pub use relimit::{relimit_stdf, LimitOverride, LimitOverrides, RelimitOptions, RelimitPart, RelimitReport, SiteYield};
 pub use stdf_parser::*;
-
-use polars;
-use regex::Regex;
-use rust_stdf::{stdf_file::StdfReader, *};
-use const_crc32::crc32;
+pub use stdf2parquet::{convert_stdf2parquet, stdf_to_dataframe, DataFrameLayout};
+pub use stdf2sqlite::convert_stdf2sqlite;
+pub use stdf2wafermap::{convert_stdf2wafermap, WaferMapFormat, WaferMapOptions};
+pub use stdf_compare::{compare_stdf, CompareKey, FileTestStats, StdfComparison, TestComparison, TestShift};
+pub use stdf_filter::{filter_stdf, FilterCounts, StdfFilter};
+pub use stdf_lint::{lint_stdf, LintIssue};
+pub use stdf_merge::{merge_stdf, MergeCounts, MergeOptions, RetestKey};
+pub use stdf_writer::StdfWriter;
+pub use summary_audit::{audit_summary, SummaryAudit, SummaryMismatch};
+pub use test_plan::{FieldChange, PlanChange, PlanChangeKind, TestPlan, TestPlanEntry};
+pub use test_stats::{TestId, TestStatistics, TestStats, TestSummary, SUMMARY_PERCENTILES};
+pub use value_format::{format_value, scale_prefix, scale_value, scaled_units, TestFormat};
+pub use wafer_map::{BinInfo, Die, DieGrid, WaferConfig, WaferMap, WaferMaps, WaferSide};
+
+pub use polars;
 
 #[macro_use]
 extern crate ini;
 
-#[derive(Debug, Clone)]
-pub struct DtrConfiguration {
-    name: String,
-    regex: Regex,
-    id_fmt: String,
-    link_to_records: Vec<String>,
-    text_fmt: String,
-    clear_on_prr: bool,
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub struct DtrInfo {
-    pub uuid: String,
-    pub id: String,
-    pub inject_into: Vec<String>,
-    pub text: String,
-    pub clear_on_prr: bool,
-}
-
 #[derive(Debug, PartialEq, Clone)]
 pub struct StdfInfo {
     pub site_number: u8,
@@ -42,75 +57,37 @@ pub struct StdfInfo {
     pub test_count: Vec<u16>,
 }
 
-pub struct FirstPassInfo {
-    min_site_num: u8,
-    part_ids: Vec<String>,
-    dtr_info: Vec<DtrInfo>,
-}
+pub(crate) fn open_stdf(stdf_path: &str) -> Result<StdfReader<BufReader<File>>, StdfUtilError> {
+    // determine the compression by file extension, same as rust_stdf does
+    let compress_type = match stdf_path.rsplit('.').next() {
+        #[cfg(feature = "gzip")]
+        Some("gz") => CompressType::GzipCompressed,
+        #[cfg(feature = "bzip")]
+        Some("bz2") => CompressType::BzipCompressed,
+        #[cfg(feature = "zipfile")]
+        Some("zip") => CompressType::ZipCompressed,
+        _ => CompressType::Uncompressed,
+    };
 
-pub fn load_dtr_config(config_fname: &Option<String>) -> Vec<DtrConfiguration> {
-    let mut ret_val = Vec::<DtrConfiguration>::new();
-
-    if let Some(config_fname) = config_fname {
-        // grab dtr configuration
-        let dtr_cfg_dict =  ini!(config_fname.as_str());
-
-        for (key, val) in dtr_cfg_dict {
-            ret_val.push(DtrConfiguration {
-                name: key.to_owned(),
-                regex: Regex::new(val["regex"].to_owned().unwrap_or("".to_string()).as_str()).expect(format!("Error while parsing regex in section {} in dtr configuration file.", key).as_str()),
-                id_fmt: val["id_fmt"].to_owned().unwrap_or("".into()),
-                link_to_records: val["link_to_records"].to_owned().unwrap_or("".to_string()).split(",").into_iter().map(|s| s.to_ascii_uppercase().into()).collect(),
-                text_fmt: val["text_fmt"].to_owned().unwrap_or("".to_string()),
-                clear_on_prr: val["clear_on_prr"].to_owned().unwrap_or("False".into()).eq_ignore_ascii_case("true")
-            })
-        }
-    }
+    // open the file here so failing to open it is reported as an io error and not a decode error
+    let stdf_file = File::open(stdf_path).map_err(StdfUtilError::io(stdf_path))?;
 
-    return ret_val;
+    StdfReader::from(BufReader::with_capacity(2 << 20, stdf_file), &compress_type)
+        .map_err(|e| StdfUtilError::Decode { path: stdf_path.to_owned(), code: e.code, msg: e.msg })
 }
 
-pub fn parse_dtr(rec: &DTR, dtr_cfg_dict: &Vec<DtrConfiguration>) -> Option<DtrInfo> {
-    // Look through DTR configuration passed in and find matches to regex
-    for val in dtr_cfg_dict {
-
-        // check if matching regex
-        if val.regex.is_match(&rec.text_dat) {
-            
-            // we have a match, handle it
-
-            // format the id, or return default
-            let id = if val.id_fmt.is_empty() {
-                rec.text_dat.to_owned()
-            } else {
-                val.regex.replace(&rec.text_dat, val.id_fmt.to_owned()).into_owned()
-            };
-
-            // format the text or return default
-            let text = if val.text_fmt.is_empty() {
-                rec.text_dat.to_owned()
-            } else {
-                val.regex.replace(&rec.text_dat, val.text_fmt.to_owned()).into_owned()
-            };
-
-            // compute crc32 of dtr
-            let uuid = crc32(rec.text_dat.as_bytes());
-
-            // construct the return value
-            return Some(DtrInfo {
-                uuid: format!("{:x}", uuid),
-                id,
-                inject_into: val.link_to_records.to_owned(),
-                text,
-                clear_on_prr: val.clear_on_prr,
-            });
-        }
-    }
+pub(crate) fn mpr_pin_label(rec: &MPR, pin_idx: usize, pmr_dict: &BTreeMap<u16, PMR>) -> Result<Option<String>, StdfUtilError> {
+    // no index for this result, nothing to label it with
+    let Some(pmr_indx) = rec.rtn_indx.as_ref().and_then(|rtn_indx| rtn_indx.get(pin_idx)) else { return Ok(None) };
 
-    return None;
+    match pmr_dict.get(pmr_indx) {
+        Some(pmr) if pmr.log_nam.is_empty() => Ok(Some(pmr.chan_nam.to_owned())),
+        Some(pmr) => Ok(Some(pmr.log_nam.to_owned())),
+        None => Err(StdfUtilError::MissingReference { record: "MPR", test_num: rec.test_num, reference: "PMR", index: *pmr_indx as u32 }),
+    }
 }
 
-fn vec_mean<T: Ord+Clone>(numbers: &Vec<T>) -> f64 
+fn vec_mean<T: Ord+Clone>(numbers: &[T]) -> f64 
 where
     f64: std::convert::From<T> {
 
@@ -119,16 +96,16 @@ where
 
     // sum all numbers
     for num in numbers {
-        sum = sum + f64::from(num.clone());
+        sum += f64::from(num.clone());
     }
 
     // return average
     sum / numbers.len() as f64
 }
 
-fn vec_median<T: Ord+Clone>(numbers: &Vec<T>) -> T {
+fn vec_median<T: Ord+Clone>(numbers: &[T]) -> T {
     // sort numbers
-    let mut numbers = numbers.clone();
+    let mut numbers = numbers.to_vec();
     numbers.sort();
 
     // find middle point in array
@@ -138,390 +115,264 @@ fn vec_median<T: Ord+Clone>(numbers: &Vec<T>) -> T {
     numbers[mid].clone()
 }
 
-fn vec_min<T: Ord+Clone>(numbers: &Vec<T>) -> T {
+fn vec_min<T: Ord+Clone>(numbers: &[T]) -> T {
     // sort numbers
-    let mut numbers = numbers.clone();
+    let mut numbers = numbers.to_vec();
     numbers.sort();
 
     // return middle point
     numbers.first().unwrap().clone()
 }
 
-fn vec_max<T: Ord+Clone>(numbers: &Vec<T>) -> T {
+fn vec_max<T: Ord+Clone>(numbers: &[T]) -> T {
     // sort numbers
-    let mut numbers = numbers.clone();
+    let mut numbers = numbers.to_vec();
     numbers.sort();
 
     // return middle point
     numbers.last().unwrap().clone()
 }
 
-fn handle_mpr_defaults(rec: &MPR, test_defaults_mpr: &mut HashMap<u32, MPR>) -> MPR {
-    // clone the MPR record for manupulation
-    let mut rec = rec.clone();
-
-    if rec.opt_flag != None && rec.opt_flag.unwrap()[0] & 0x40 != 0 { rec.lo_limit = None; rec.llm_scal = None; }
-    if rec.opt_flag != None && rec.opt_flag.unwrap()[0] & 0x80 != 0 { rec.hi_limit = None; rec.hlm_scal = None; }
-    
-    if let Some(defaults) = test_defaults_mpr.get(&rec.test_num) {
-        // We have access to the defaults, update what needs updating
-        if rec.opt_flag == None { rec.opt_flag = defaults.opt_flag.to_owned(); }
-        if rec.res_scal == None { rec.res_scal = defaults.res_scal.to_owned(); }
-        if rec.llm_scal == None { rec.llm_scal = defaults.llm_scal.to_owned(); }
-        if rec.hlm_scal == None { rec.hlm_scal = defaults.hlm_scal.to_owned(); }
-        if rec.lo_limit == None { rec.lo_limit = defaults.lo_limit.to_owned(); }
-        if rec.hi_limit == None { rec.hi_limit = defaults.hi_limit.to_owned(); }
-        if rec.start_in == None { rec.start_in = defaults.start_in.to_owned(); }
-        if rec.incr_in  == None { rec.incr_in  = defaults.incr_in.to_owned();  }
-        if rec.rtn_indx == None { rec.rtn_indx = defaults.rtn_indx.to_owned(); }
-        if rec.units    == None { rec.units    = defaults.units.to_owned();    }
-        if rec.units_in == None { rec.units_in = defaults.units_in.to_owned(); }
-        if rec.c_resfmt == None { rec.c_resfmt = defaults.c_resfmt.to_owned(); }
-        if rec.c_llmfmt == None { rec.c_llmfmt = defaults.c_llmfmt.to_owned(); }
-        if rec.c_hlmfmt == None { rec.c_hlmfmt = defaults.c_hlmfmt.to_owned(); }
-        if rec.lo_spec  == None { rec.lo_spec  = defaults.lo_spec.to_owned();  }
-        if rec.hi_spec  == None { rec.hi_spec  = defaults.hi_spec.to_owned();  }
-    } else {
-        // don't have the updates, store the record for later use
-        test_defaults_mpr.insert(rec.test_num, rec.clone());
-    }
+fn dtr_text<'a>(dtr_info: &'a [DtrInfo], id: &str) -> &'a str {
+    // dtr info that isn't attached to the record leaves the column empty
+    dtr_info.iter().find(|info| info.id == id).map(|info| info.text.as_str()).unwrap_or("")
+}
 
-    rec
+//////////////////////////////////////////////////////////////////////
+/// Description: Writes the test rows to a temporary body file, DTR columns are
+///              discovered as they show up and the header is written last
+//////////////////////////////////////////////////////////////////////
+struct CsvTestsWriter {
+    body_path: String,
+    // None once finish took it
+    body_file: Option<BufWriter<File>>,
+    dtr_ids: Vec<String>,
+    // length in bytes of every row written, texts may hold line breaks so the body is copied back by length
+    row_lens: Vec<usize>,
+    // (first row, dtr column count) every time a new dtr column shows up
+    dtr_col_starts: Vec<(u64, usize)>,
 }
 
-fn handle_ptr_defaults(rec: &PTR, test_defaults_ptr: &mut HashMap<u32, PTR>) -> PTR {
-    // clone the PTR record for manupulation
-    let mut rec = rec.clone();
-
-    if rec.opt_flag != None && rec.opt_flag.unwrap()[0] & 0x40 != 0 { rec.lo_limit = None; rec.llm_scal = None; }
-    if rec.opt_flag != None && rec.opt_flag.unwrap()[0] & 0x80 != 0 { rec.hi_limit = None; rec.hlm_scal = None; }
-
-    if let Some(defaults) = test_defaults_ptr.get(&rec.test_num) {
-        // We have access to the defaults, update what needs updating
-        if rec.opt_flag == None { rec.opt_flag = defaults.opt_flag.to_owned(); }
-        if rec.res_scal == None { rec.res_scal = defaults.res_scal.to_owned(); }
-        if rec.llm_scal == None { rec.llm_scal = defaults.llm_scal.to_owned(); }
-        if rec.hlm_scal == None { rec.hlm_scal = defaults.hlm_scal.to_owned(); }
-        if rec.lo_limit == None { rec.lo_limit = defaults.lo_limit.to_owned(); }
-        if rec.hi_limit == None { rec.hi_limit = defaults.hi_limit.to_owned(); }
-        if rec.units    == None { rec.units    = defaults.units.to_owned();    }
-        if rec.c_resfmt == None { rec.c_resfmt = defaults.c_resfmt.to_owned(); }
-        if rec.c_llmfmt == None { rec.c_llmfmt = defaults.c_llmfmt.to_owned(); }
-        if rec.c_hlmfmt == None { rec.c_hlmfmt = defaults.c_hlmfmt.to_owned(); }
-        if rec.lo_spec  == None { rec.lo_spec  = defaults.lo_spec.to_owned();  }
-        if rec.hi_spec  == None { rec.hi_spec  = defaults.hi_spec.to_owned();  }
-    } else {
-        // don't have the updates, store the record for later use
-        test_defaults_ptr.insert(rec.test_num, rec.clone());
+impl CsvTestsWriter {
+    fn new(csv_path: &str) -> Result<Self, StdfUtilError> {
+        let body_path = format!("{}.tmp", csv_path);
+        let body_file = File::create(&body_path).map_err(StdfUtilError::io(&body_path))?;
+
+        Ok(Self { body_path, body_file: Some(BufWriter::new(body_file)), dtr_ids: Vec::new(), row_lens: Vec::new(), dtr_col_starts: vec![(0, 0)] })
     }
 
-    rec
-}
+    fn write_row(&mut self, fields: &str, dtr_info: &[DtrInfo]) -> Result<(), StdfUtilError> {
+        // new dtr ids become new columns at the end
+        for info in dtr_info {
+            if !self.dtr_ids.contains(&info.id) {
+                self.dtr_ids.push(info.id.to_owned());
+                self.dtr_col_starts.push((self.row_lens.len() as u64, self.dtr_ids.len()));
+            }
+        }
 
-fn handle_ftr_defaults(rec: &FTR, test_defaults_ftr: &mut HashMap<u32, FTR>) -> FTR {
-    // clone the PTR record for manupulation
-    let mut rec = rec.clone();
-
-    if let Some(defaults) = test_defaults_ftr.get(&rec.test_num) {
-        // We have access to the defaults, update what needs updating
-        if rec.opt_flag[0] & 0x01 > 0 { rec.cycl_cnt = defaults.cycl_cnt.to_owned(); }
-        if rec.opt_flag[0] & 0x02 > 0 { rec.rel_vadr = defaults.rel_vadr.to_owned(); }
-        if rec.opt_flag[0] & 0x04 > 0 { rec.rept_cnt = defaults.rept_cnt.to_owned(); }
-        if rec.opt_flag[0] & 0x08 > 0 { rec.num_fail = defaults.num_fail.to_owned(); }
-        if rec.opt_flag[0] & 0x10 > 0 { rec.xfail_ad = defaults.xfail_ad.to_owned(); }
-        if rec.opt_flag[0] & 0x10 > 0 { rec.yfail_ad = defaults.yfail_ad.to_owned(); }
-        if rec.opt_flag[0] & 0x20 > 0 { rec.vect_off = defaults.vect_off.to_owned(); }
-        if rec.rtn_icnt == 0          { rec.rtn_indx = defaults.rtn_indx.to_owned(); }
-        if rec.rtn_icnt == 0          { rec.rtn_stat = defaults.rtn_stat.to_owned(); }
-        if rec.pgm_icnt == 0          { rec.pgm_indx = defaults.pgm_indx.to_owned(); }
-        if rec.pgm_icnt == 0          { rec.pgm_stat = defaults.pgm_stat.to_owned(); }
-        if rec.fail_pin.len() == 0    { rec.fail_pin = defaults.fail_pin.to_owned(); }
-        if rec.vect_nam.len() == 0    { rec.vect_nam = defaults.vect_nam.to_owned(); }
-        if rec.time_set.len() == 0    { rec.time_set = defaults.time_set.to_owned(); }
-        if rec.op_code.len() == 0     { rec.op_code  = defaults.op_code.to_owned();  }
-        if rec.test_txt.len() == 0    { rec.test_txt = defaults.test_txt.to_owned(); }
-        if rec.alarm_id.len() == 0    { rec.alarm_id = defaults.alarm_id.to_owned(); }
-        if rec.prog_txt.len() == 0    { rec.prog_txt = defaults.prog_txt.to_owned(); }
-        if rec.rslt_txt.len() == 0    { rec.rslt_txt = defaults.rslt_txt.to_owned(); }
-        if rec.patg_num == 255        { rec.patg_num = defaults.patg_num.to_owned(); }
-        if rec.spin_map.len() == 0    { rec.spin_map = defaults.spin_map.to_owned(); }
-    } else {
-        // don't have the updates, store the record for later use
-        test_defaults_ftr.insert(rec.test_num, rec.clone());
-    }
+        let mut row = fields.to_owned();
+        for id in &self.dtr_ids {
+            row += &format!(",\"{}\"", dtr_text(dtr_info, id));
+        }
+        row += "\n";
 
-    rec
-}
+        let Some(body_file) = self.body_file.as_mut() else { return Ok(()) };
+        body_file.write_all(row.as_bytes()).map_err(StdfUtilError::io(&self.body_path))?;
+        self.row_lens.push(row.len());
 
-//////////////////////////////////////////////////////////////////////
-/// Description: Makes first pass thru the STDF finding all Test ID's and DTR ID's
-//////////////////////////////////////////////////////////////////////
-pub fn first_pass_stdf(stdf_path: &String, dtr_config: &Vec<DtrConfiguration>) -> Result<FirstPassInfo, String> {
-    let mut min_site_num = 255u8;
-    let mut part_ids = Vec::<String>::new();
-    let mut dtr_info = Vec::<DtrInfo>::new();
-    
-    let mut reader = match StdfReader::new(&stdf_path) {
-        // return if successful
-        Ok(reader) => reader,
-
-        // print full error and return the error message if not
-        Err(err) => {
-            println!("Error while loading stdf: {:?}\n", err);
-            return Err(err.msg);
+        Ok(())
+    }
+
+    fn finish(mut self, csv_path: &str) -> Result<(), StdfUtilError> {
+        let body_path = self.body_path.to_owned();
+        if let Some(body_file) = self.body_file.take() {
+            body_file.into_inner().map_err(|e| StdfUtilError::Io { path: body_path.to_owned(), source: e.into_error() })?;
         }
-    };
 
-    
-    for stdf_rec in reader.get_record_iter() {
-        if let Ok(stdf_rec) = stdf_rec {
-            match stdf_rec {
-                // map all dtr id's for later
-                StdfRecord::DTR(rec) => if let Some(l_dtr_info) = parse_dtr(&rec, &dtr_config) {
-                    let mut contains_dtr = false;
-                    for info in &dtr_info {
-                        if info.id == l_dtr_info.id {
-                            contains_dtr = true;
-                            break;
-                        }
-                    }
-                    if contains_dtr == false {
-                        dtr_info.push(l_dtr_info.clone());
-                    }
-                },
-
-                // map PRR test_ids based on when they're encountered
-                StdfRecord::PRR(rec) => {
-                    min_site_num = if min_site_num < rec.site_num { min_site_num } else { rec.site_num };
-                    part_ids.push(rec.part_id);
-                },
-
-                // ignore most records
-                _ => {},
+        let mut csv_file = BufWriter::new(File::create(csv_path).map_err(StdfUtilError::io(csv_path))?);
+
+        // header has every dtr column found
+        let mut header = "\"Part ID\",\"TNum\",\"SiteNum\",\"TestText\",\"Context\",\"Low Limit\",\"Result\",\"Hi Limit\"".to_string();
+        for id in &self.dtr_ids {
+            header += &format!(",\"{}\"", id);
+        }
+        header += "\n";
+        csv_file.write_all(header.as_bytes()).map_err(StdfUtilError::io(csv_path))?;
+
+        // copy the body, padding rows written before the later dtr columns showed up
+        let mut body_file = BufReader::new(File::open(&body_path).map_err(StdfUtilError::io(&body_path))?);
+        let mut dtr_col_starts = self.dtr_col_starts.iter().peekable();
+        let mut dtr_col_count = 0;
+        let mut row = Vec::new();
+        for (row_idx, row_len) in self.row_lens.iter().enumerate() {
+            row.resize(*row_len, 0);
+            body_file.read_exact(&mut row).map_err(StdfUtilError::io(&body_path))?;
+            while let Some((_, col_count)) = dtr_col_starts.next_if(|(start, _)| *start <= row_idx as u64) {
+                dtr_col_count = *col_count;
             }
+
+            // the padding goes in front of the line break that ends the row
+            row.pop();
+            row.extend(",\"\"".repeat(self.dtr_ids.len() - dtr_col_count).as_bytes());
+            row.push(b'\n');
+            csv_file.write_all(&row).map_err(StdfUtilError::io(csv_path))?;
         }
-    }
+        csv_file.flush().map_err(StdfUtilError::io(csv_path))?;
 
-    Ok(FirstPassInfo { min_site_num, part_ids, dtr_info })
+        std::fs::remove_file(&body_path).map_err(StdfUtilError::io(&body_path))
+    }
 }
 
-fn data_to_string(data: &Option<f32>, scale: &Option<i8>, format: &Option<String>, default: &String) -> String {
-    // grab scale or default to 0
-    let scale = scale.unwrap_or(0);
+impl Drop for CsvTestsWriter {
+    // a conversion that failed part way doesn't leave its body file behind
+    fn drop(&mut self) {
+        self.body_file.take();
+        let _ = std::fs::remove_file(&self.body_path);
+    }
+}
 
-    // determine the format to use
-    let format = format.clone().unwrap_or("%f".to_string());
-    let format = if format == "" { "%f".into() } else { format };
+// values of the tests csv without a C format of their own, as the csv always had them
+const CSV_DEFAULT_FORMAT: &str = "%f";
+
+fn write_part_tests(tests_writer: &mut CsvTestsWriter, part: &Part, pmr_dict: &BTreeMap<u16, PMR>) -> Result<(), StdfUtilError> {
+    let part_id = part.part_id.replace('"', "\"\"");
+
+    for ParsedRecord { record, dtr_info, .. } in &part.results {
+        match record {
+            StdfRecord::FTR(rec) => {
+                let test_flags = TestFlags::from(rec.test_flg);
+                let limit = if test_flags.no_pass_fail() { "" } else { "1" };
+                let result = match test_flags.failed() { None => "", Some(false) => "1", Some(true) => "0" };
+                let test_txt = rec.test_txt.replace('"', "\"\"");
+                let context = rec.vect_nam.replace('"', "\"\"");
+                let context = if context.is_empty() { "".into() } else { "vect_name: ".to_string() + context.as_str() };
+
+                tests_writer.write_row(&format!("\"{}\",\"{}\",\"{}\",\"=\"\"{}\"\"\",\"=\"\"{}\"\"\",\"{}\",\"{}\",\"{}\"", part_id, rec.test_num, rec.site_num, test_txt, context, limit, result, limit), dtr_info)?;
+            },
+
+            StdfRecord::PTR(rec) => {
+                let test_format = TestFormat::from(rec).with_default_format(CSV_DEFAULT_FORMAT);
+                let lo_limit = test_format.lo_limit(rec.lo_limit);
+                let hi_limit = test_format.hi_limit(rec.hi_limit);
+                let result = test_format.result(rec.result);
+                let test_txt = rec.test_txt.replace('"', "\"\"");
+                let context = test_format.units().replace('"', "\"\"");
+                let context = if context.is_empty() { "".into() } else { "units: ".to_string() + context.as_str() };
+
+                tests_writer.write_row(&format!("\"{}\",\"{}\",\"{}\",\"=\"\"{}\"\"\",\"=\"\"{}\"\"\",\"{}\",\"{}\",\"{}\"", part_id, rec.test_num, rec.site_num, test_txt, context, lo_limit, result, hi_limit), dtr_info)?;
+            },
+
+            StdfRecord::MPR(rec) => {
+                let test_format = TestFormat::from(rec).with_default_format(CSV_DEFAULT_FORMAT);
+                let lo_limit = test_format.lo_limit(rec.lo_limit);
+                let hi_limit = test_format.hi_limit(rec.hi_limit);
+                let test_txt = rec.test_txt.replace('"', "\"\"");
+                for (i, rtn_rslt) in rec.rtn_rslt.iter().enumerate() {
+                    let result = test_format.result(*rtn_rslt);
+                    let context = match mpr_pin_label(rec, i, pmr_dict)? {
+                        Some(pin_label) => "pin: ".to_string() + pin_label.as_str(),
+                        None => "".into(),
+                    };
+
+                    tests_writer.write_row(&format!("\"{}\",\"{}.{}\",\"{}\",\"=\"\"{}\"\"\",\"=\"\"{}\"\"\",\"{}\",\"{}\",\"{}\"", part_id, rec.test_num, i, rec.site_num, test_txt, context, lo_limit, result, hi_limit), dtr_info)?;
+                }
+            },
 
-    // format data using all information available.
-    if let Some(data) = data {
-        if data.is_finite() {
-            sprintf!(format.as_str(), data * 10f32.powi(scale as i32)).unwrap_or(format!("{}", data * 10f32.powi(scale as i32))) //("Error Formatting Data".to_string())
-        } else {
-            default.clone()
+            // STR's don't have a csv representation
+            _rec => {},
         }
-    } else {
-        default.clone()
     }
+
+    Ok(())
 }
 
-pub fn convert_stdf2csv(stdf_path: &String, csv_path: &String, dtr_cfg_file: &Option<String>) -> Result<(), String> {
-    // dictionary lookup table for default values
-    let mut test_defaults_ptr = HashMap::<u32, PTR>::new();
-    let mut test_defaults_mpr = HashMap::<u32, MPR>::new();
-    let mut test_defaults_ftr = HashMap::<u32, FTR>::new();
-    let dtr_config = load_dtr_config(dtr_cfg_file);
-    let mut dtr_map = BTreeMap::<String, DtrInfo>::new();
-    let mut dtr_id_in_col_idx_order = Vec::<String>::new();
+//////////////////////////////////////////////////////////////////////
+/// Description: Converts the STDF to csv files in a single pass, test results are
+///              held per head/site until the PRR of the part provides the part id
+//////////////////////////////////////////////////////////////////////
+pub fn convert_stdf2csv(stdf_path: &String, csv_path: &str, dtr_cfg_file: &Option<String>) -> Result<(), StdfUtilError> {
     let csv_part_summary_path = csv_path.replace(".csv", ".part.summary.csv");
     let csv_stdf_summary_path = csv_path.replace(".csv", ".stdf.summary.csv");
+    let csv_test_summary_path = csv_path.replace(".csv", ".test.summary.csv");
     let csv_path = csv_path.replace(".csv", ".tests.csv");
     let mut stdf_summary_statistics = BTreeMap::<u16, BTreeMap<u16, BTreeMap<u8, StdfInfo>>>::new();
+    let mut test_statistics = TestStatistics::new();
     let mut pmr_dict = BTreeMap::<u16, PMR>::new();
+    let mut part_tracker = PartTracker::new();
 
     // open csv files or error out
-    let mut csv_file = std::fs::File::create(&csv_path).expect(format!("Error while trying to create the csv file {}.", &csv_path).as_str());
-    let mut csv_part_summary_file = std::fs::File::create(&csv_part_summary_path).expect(format!("Error while trying to create the csv file {}.", &csv_part_summary_path).as_str());
-    let mut csv_stdf_summary_file = std::fs::File::create(&csv_stdf_summary_path).expect(format!("Error while trying to create the csv file {}.", &csv_stdf_summary_path).as_str());
-
-    // perform first pass through STDF to collect identifiers
-    let mut first_pass_info = match first_pass_stdf(stdf_path, &dtr_config) {
-        Ok(tuple) => tuple,
-        Err(msg) => return Err(msg)
-    };
+    let mut tests_writer = CsvTestsWriter::new(&csv_path)?;
+    let mut csv_part_summary_file = File::create(&csv_part_summary_path).map_err(StdfUtilError::io(&csv_part_summary_path))?;
+    let mut csv_stdf_summary_file = File::create(&csv_stdf_summary_path).map_err(StdfUtilError::io(&csv_stdf_summary_path))?;
 
     // open stdf file and start reading
-    let mut reader = match StdfReader::new(&stdf_path) {
-        // return if successful
-        Ok(reader) => reader,
-
-        // print full error and return the error message if not
-        Err(err) => {
-            println!("Error while loading stdf: {:?}\n", err);
-            return Err(err.msg);
-        }
-    };
+    let parser = StdfParser::new(stdf_path, dtr_cfg_file)?;
 
     /////////////////////////////////////////////////////////
-    // Write out test header
+    // write out part summary header
     /////////////////////////////////////////////////////////
-    csv_file.write(b"\"Part ID\",\"TNum\",\"SiteNum\",\"TestText\",\"Context\",\"Low Limit\",\"Result\",\"Hi Limit\"").expect("Error while trying to write to the csv file.");
-    for info in first_pass_info.dtr_info {
-        let id = info.id.clone();
-        let inject_into = info.inject_into.clone();
-        if inject_into.contains(&"PTR".into()) || inject_into.contains(&"MPR".into()) || inject_into.contains(&"FTR".into()) {
-            // write the id out to the file
-            csv_file.write(format!("\",\"{}", id).as_bytes()).expect("Error while trying to write to the csv file.");
-
-            // add the id onto the map, this is something we care about tracking...
-            dtr_map.insert(id.clone(), info.clone());
-            dtr_id_in_col_idx_order.push(id.clone());
+    csv_part_summary_file.write_all(b"\"Part ID\",\"SiteNum\",\"Test Time\",\"Hard Bin\",\"Soft Bin\",\"Test Count\",\"X\",\"Y\"\n").map_err(StdfUtilError::io(&csv_part_summary_path))?;
+
+    for parsed_rec in parser {
+        let parsed_rec = parsed_rec?;
+
+        if let StdfRecord::PMR(rec) = &parsed_rec.record {
+            pmr_dict.insert(rec.pmr_indx, rec.to_owned());
         }
-    }
-    csv_file.write(b"\n").expect("Error while trying to write to the csv file.");
 
-    /////////////////////////////////////////////////////////
-    // write out part summary header
-    /////////////////////////////////////////////////////////
-    csv_part_summary_file.write(b"\"Part ID\",\"SiteNum\",\"Test Time\",\"Hard Bin\",\"Soft Bin\",\"Test Count\",\"X\",\"Y\"\n").expect("Error while trying to write to the part summary csv file.");
-
-    for stdf_rec in reader.get_record_iter() {
-        if let Ok(stdf_rec) = stdf_rec {
-            match stdf_rec {
-                // Informational Record
-                StdfRecord::DTR(rec) => if let Some(dtr_info) = parse_dtr(&rec, &dtr_config) {
-                    dtr_map.insert(dtr_info.id.to_owned(), dtr_info.to_owned());
-                },
-
-                // Test Records
-                StdfRecord::FTR(rec) => {
-                    let part_id = first_pass_info.part_ids[usize::from(rec.site_num-first_pass_info.min_site_num)].clone();
-                    let limit = if (rec.test_flg[0] & 0x40) != 0 { "" } else { "1" };
-                    let result = if (rec.test_flg[0] & 0x40) != 0 { "" } else { if rec.test_flg[0] == 0 { "1" } else { "0" } };
-                    let test_txt = rec.test_txt.replace("\"", "\"\"");
-                    let context = rec.vect_nam.replace("\"", "\"\"");
-                    let context = if context == "" { "".into() } else { "vect_name: ".to_string() + context.as_str() };
-                    
-                    let rec = handle_ftr_defaults(&rec, &mut test_defaults_ftr);
-
-                    csv_file.write(format!("\"{}\",\"{}\",\"{}\",\"=\"\"{}\"\"\",\"=\"\"{}\"\"\",\"{}\",\"{}\",\"{}\"", part_id, rec.test_num, rec.site_num, test_txt, context, limit, result, limit).as_bytes()).expect("Error while trying to write to the csv file.");
-                    for id in &dtr_id_in_col_idx_order {
-                        csv_file.write(format!(",\"{}\"", dtr_map.get(id).unwrap().text).as_bytes()).expect("Error while trying to write to the csv file.");
-                    }
-                    csv_file.write(b"\n").expect("Error while trying to write to the csv file.");
-                },
-
-                StdfRecord::PTR(rec) => {
-                    let part_id = first_pass_info.part_ids[usize::from(rec.site_num-first_pass_info.min_site_num)].clone();
-
-                    let rec = handle_ptr_defaults(&rec, &mut test_defaults_ptr);
-
-                    let lo_limit = data_to_string(&rec.lo_limit, &rec.llm_scal, &rec.c_llmfmt, &String::new());
-                    let hi_limit = data_to_string(&rec.hi_limit, &rec.hlm_scal, &rec.c_hlmfmt, &String::new());
-                    let result = data_to_string(&Some(rec.result), &rec.res_scal, &rec.c_resfmt, &String::new());
-                    let test_txt = rec.test_txt.replace("\"", "\"\"").to_string();
-                    let context = rec.units.unwrap_or("".into()).replace("\"", "\"\"");
-                    let context = if context == "" { "".into() } else { "units: ".to_string() + context.as_str() };
-                    
-                    csv_file.write(format!("\"{}\",\"{}\",\"{}\",\"=\"\"{}\"\"\",\"=\"\"{}\"\"\",\"{}\",\"{}\",\"{}\"", part_id, rec.test_num, rec.site_num, test_txt, context, lo_limit, result, hi_limit).as_bytes()).expect("Error while trying to write to the csv file.");
-                    for id in &dtr_id_in_col_idx_order {
-                        csv_file.write(format!(",\"{}\"", dtr_map.get(id).unwrap().text).as_bytes()).expect("Error while trying to write to the csv file.");
-                    }
-                    csv_file.write(b"\n").expect("Error while trying to write to the csv file.");
-                },
-
-                StdfRecord::MPR(rec) => {
-                    let part_id = first_pass_info.part_ids[usize::from(rec.site_num-first_pass_info.min_site_num)].clone();
-
-                    let rec = handle_mpr_defaults(&rec, &mut test_defaults_mpr);
-//
-                    let lo_limit = data_to_string(&rec.lo_limit, &rec.llm_scal, &rec.c_llmfmt, &String::new());
-                    let hi_limit = data_to_string(&rec.hi_limit, &rec.hlm_scal, &rec.c_hlmfmt, &String::new());
-                    let test_txt = rec.test_txt.replace("\"", "\"\"").to_string();
-                    let rtn_indx = rec.rtn_indx.unwrap_or(Vec::<u16>::new());
-                    for i in 0..(rec.rtn_rslt.len()-1) {
-                        let result = data_to_string(&Some(rec.rtn_rslt[i]), &rec.res_scal, &rec.c_resfmt, &String::new());
-                        let pin_label = if pmr_dict[rtn_indx.get(i).unwrap()].log_nam == "" {
-                            pmr_dict[rtn_indx.get(i).unwrap()].chan_nam.clone()
-                        } else {
-                            pmr_dict[rtn_indx.get(i).unwrap()].log_nam.clone()
-                        };
-                        // let pin_label = pmr_dict[rtn_indx.get(i).unwrap()].log_nam.clone() + ":" + pmr_dict[rtn_indx.get(i).unwrap()].chan_nam.as_str();
-                        // let pin_label = pmr_dict[rtn_indx.get(i).unwrap()].log_nam.clone();
-                        let context = if i < rtn_indx.len() && pmr_dict.contains_key(rtn_indx.get(i).unwrap()) { "pin: ".to_string() + pin_label.as_str() } else { "".into() };
-
-                        csv_file.write(format!("\"{}\",\"{}.{}\",\"{}\",\"=\"\"{}\"\"\",\"=\"\"{}\"\"\",\"{}\",\"{}\",\"{}\"", part_id, rec.test_num, i, rec.site_num, test_txt, context, lo_limit, result, hi_limit).as_bytes()).expect("Error while trying to write to the csv file.");
-                        for id in &dtr_id_in_col_idx_order {
-                            csv_file.write(format!(",\"{}\"", dtr_map.get(id).unwrap().text).as_bytes()).expect("Error while trying to write to the csv file.");
-                        }
-                        csv_file.write(b"\n").expect("Error while trying to write to the csv file.");
-                    }
-                    
-                },
-
-                // Part Results Record for summary files and popping part id
-                StdfRecord::PRR(rec) => {
-                    /////////////////////////////////////////////////////////
-                    // Update parts summary
-                    /////////////////////////////////////////////////////////
-                    csv_part_summary_file.write(format!("\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\"\n", rec.part_id, rec.site_num, rec.test_t, rec.hard_bin, rec.soft_bin, rec.num_test, rec.x_coord, rec.y_coord).as_bytes())
-                                                .expect("Error while trying to write to the part summary csv file.");
-                    
-                    // add to summary stats for stdf
-                    let sum = stdf_summary_statistics.entry(rec.hard_bin).or_insert(BTreeMap::new());
-                    let sum = sum.entry(rec.soft_bin).or_insert(BTreeMap::new());
-                    if let Some(sum) = sum.get_mut(&rec.site_num) {
-                        sum.test_count.push(rec.num_test);
-                        sum.test_time.push(rec.test_t);
-                    } else {
-                        sum.insert(rec.site_num.to_owned(), StdfInfo {hard_bin: rec.hard_bin, site_number: rec.site_num, soft_bin: rec.soft_bin, test_count: vec![rec.num_test], test_time: vec![rec.test_t]});
-                    }
-
-                    /////////////////////////////////////////////////////////
-                    // We've now encountered this Part ID, drop it from the list
-                    /////////////////////////////////////////////////////////
-                    first_pass_info.part_ids.remove(0);
-
-                    /////////////////////////////////////////////////////////
-                    // clear dtr info if set
-                    /////////////////////////////////////////////////////////
-                    for (_, info) in &mut dtr_map {
-                        if info.clear_on_prr {
-                            info.text = "".into();
-                        }
-                    }
-                },
-
-                StdfRecord::PMR(rec) => {
-                    pmr_dict.insert(rec.pmr_indx, rec.clone());
-                },
-
-                _rec => {
-                    // do nothing, just here temporarily
-                }
-            }
+        // Part Results Record completes a part for the tests and summary files
+        let Some(part) = part_tracker.process(&parsed_rec) else { continue };
+
+        write_part_tests(&mut tests_writer, &part, &pmr_dict)?;
+        test_statistics.add_part(&part);
+
+        // a part cut short by the next PIR on its site only has tests
+        if !part.finished { continue; }
+
+        /////////////////////////////////////////////////////////
+        // Update parts summary
+        /////////////////////////////////////////////////////////
+        csv_part_summary_file.write_all(format!("\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\"\n", part.part_id, part.site_num, part.test_t, part.hard_bin, part.soft_bin, part.num_test, part.x_coord, part.y_coord).as_bytes())
+                                    .map_err(StdfUtilError::io(&csv_part_summary_path))?;
+
+        // add to summary stats for stdf
+        let sum = stdf_summary_statistics.entry(part.hard_bin).or_default();
+        let sum = sum.entry(part.soft_bin).or_default();
+        if let Some(sum) = sum.get_mut(&part.site_num) {
+            sum.test_count.push(part.num_test);
+            sum.test_time.push(part.test_t);
+        } else {
+            sum.insert(part.site_num, StdfInfo {hard_bin: part.hard_bin, site_number: part.site_num, soft_bin: part.soft_bin, test_count: vec![part.num_test], test_time: vec![part.test_t]});
         }
     }
 
+    // parts without a PRR still have their tests written, just without a part id
+    for part in part_tracker.finish() {
+        write_part_tests(&mut tests_writer, &part, &pmr_dict)?;
+        test_statistics.add_part(&part);
+    }
+    tests_writer.finish(&csv_path)?;
+    test_statistics.write_csv(&csv_test_summary_path)?;
+
     /////////////////////////////////////////////////////////
     // Write out the STDF Summary
     /////////////////////////////////////////////////////////
     // write the header
     csv_stdf_summary_file
-        .write(b"\"Hard Bin\",\" Soft Bin\",\" SiteNum\",\" Part Count\",\" Min Test Time\",\" Average Test Time\",\" Max Test Time\",\"Min Test Count\",\"Median Test Count\",\" Max Test Count\"\n")
-        .expect("Error while trying to write to the stdf summary csv file.");
+        .write_all(b"\"Hard Bin\",\" Soft Bin\",\" SiteNum\",\" Part Count\",\" Min Test Time\",\" Average Test Time\",\" Max Test Time\",\"Min Test Count\",\"Median Test Count\",\" Max Test Count\"\n")
+        .map_err(StdfUtilError::io(&csv_stdf_summary_path))?;
 
     // go through stats and write them out
-    for (_, val) in stdf_summary_statistics {
-        for (_, val) in val {
-            for (_, val) in val {
+    for val in stdf_summary_statistics.values() {
+        for val in val.values() {
+            for val in val.values() {
                 // grab appropriate stats to write
                 csv_stdf_summary_file
-                    .write(format!("\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\"\n", 
+                    .write_all(format!("\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\"\n", 
                                         val.hard_bin, val.soft_bin, val.site_number, val.test_time.len(), 
                                         vec_min(&val.test_time), vec_mean(&val.test_time), vec_max(&val.test_time),
                                         vec_min(&val.test_count), vec_median(&val.test_count), vec_max(&val.test_count)).as_bytes())
-                    .expect("Error while trying to write to the stdf summary csv file.");
+                    .map_err(StdfUtilError::io(&csv_stdf_summary_path))?;
             }
         }
     }
@@ -529,92 +380,22 @@ pub fn convert_stdf2csv(stdf_path: &String, csv_path: &String, dtr_cfg_file: &Op
     Ok(())
 }
 
-
-// this needs to be shifted into a structure to be easier to manage.
-// pub fn init_stdf(stdf_path: &String, config_fname: &Option<String>) -> Result<(StdfReader<BufReader<File>>, Vec<DtrConfiguration>, FirstPassInfo), String> {
-//     let dtr_config = load_dtr_config(config_fname);
-//     let first_pass_info = first_pass_stdf(stdf_path, &dtr_config).unwrap_or(FirstPassInfo { min_site_num: 0, part_ids: Vec::new(), dtr_info: Vec::new() });
-//     // open stdf file and start reading
-//     let stdf_reader = match StdfReader::new(&stdf_path) {
-//         // return if successful
-//         Ok(stdf_reader) => stdf_reader,
-
-//         // print full error and return the error message if not
-//         Err(err) => {
-//             println!("Error while loading stdf: {:?}\n", err);
-//             return Err(err.msg);
-//         }
-//     };
-
-//     Ok((stdf_reader, dtr_config, first_pass_info))
-// }
-
-// pub fn next_record(stdf_reader: StdfReader<BufReader<File>>, dtr_config: Vec<DtrConfiguration>, first_pass_info: FirstPassInfo) {
-//     // grab next record
-
-// }
-
-// pub fn read_stdf(stdf_path: &String, config_fname: &Option<String>) -> Result<Vec<StdfRecord>, String> {
-//     let dtr_config = load_dtr_config(config_fname);
-//     let records = Vec::new();
-
-//     let data = first_pass_stdf(stdf_path, &dtr_config).unwrap_or(FirstPassInfo { min_site_num: 0, part_ids: Vec::new(), dtr_info: Vec::new() });
-
-//     Ok(records)
-// }
-
-pub fn convert_stdf2text(stdf_path: &String, txt_path: &String, pretty_print: bool, use_test_defaults: bool) -> Result<(), String> {
+pub fn convert_stdf2text(stdf_path: &String, txt_path: &String, pretty_print: bool, use_test_defaults: bool) -> Result<(), StdfUtilError> {
     // open csv files or error out
-    let mut txt_file = std::fs::File::create(&txt_path).expect(format!("Error while trying to create the text file {}.", &txt_path).as_str());
-    let mut i = 0;
-
-    let mut test_defaults_ptr = HashMap::<u32, PTR>::new();
-    let mut test_defaults_mpr = HashMap::<u32, MPR>::new();
-    let mut test_defaults_ftr = HashMap::<u32, FTR>::new();
+    let mut txt_file = File::create(txt_path).map_err(StdfUtilError::io(txt_path))?;
 
     // open stdf file and start reading
-    let mut reader = match StdfReader::new(&stdf_path) {
-        // return if successful
-        Ok(reader) => reader,
-
-        // print full error and return the error message if not
-        Err(err) => {
-            println!("Error while loading stdf: {:?}\n", err);
-            return Err(err.msg);
-        }
-    };
+    let mut parser = StdfParser::new(stdf_path, &None)?;
+    parser.set_use_test_defaults(use_test_defaults);
 
-    for stdf_rec in reader.get_record_iter() {
-        if let Ok(stdf_rec) = stdf_rec {
-            i = i + 1;
-
-            let stdf_rec = if use_test_defaults {
-                match &stdf_rec {
-                    StdfRecord::PTR(rec) => {
-                        StdfRecord::PTR(handle_ptr_defaults(rec, &mut test_defaults_ptr))
-                    },
-                    StdfRecord::MPR(rec) => {
-                        StdfRecord::MPR(handle_mpr_defaults(rec, &mut test_defaults_mpr))
-                    },
-                    StdfRecord::FTR(rec) => {
-                        StdfRecord::FTR(handle_ftr_defaults(rec, &mut test_defaults_ftr))
-                    },
-                    _ => stdf_rec,
-                }
-            } else {
-                stdf_rec
-            };
-            
-            let txt = rec_to_string::rec_to_string(&stdf_rec, pretty_print);
-            let txt = if txt.is_empty() { format!("UNFORMATTED {:?}", &stdf_rec) } else { txt };
-            
-            writeln!(&mut txt_file, "{}", txt).expect("Error while trying to write to text file");
-        }
-    }
+    for parsed_rec in parser {
+        let stdf_rec = parsed_rec?.record;
 
-    Ok(())
-}
+        let txt = rec_to_string(&stdf_rec, pretty_print);
+        let txt = if txt.is_empty() { format!("UNFORMATTED {:?}", &stdf_rec) } else { txt };
+        
+        writeln!(&mut txt_file, "{}", txt).map_err(StdfUtilError::io(txt_path))?;
+    }
 
-pub fn convert_stdf2sqlite(stdf_path: &String, sqlite_path: &String, dtr_cfg_file: &Option<String>) -> Result<(), String> {
     Ok(())
 }
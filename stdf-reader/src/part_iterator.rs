@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+
+use rust_stdf::*;
+
+use crate::{ParsedRecord, StdfParser, StdfUtilError};
+
+/// A part assembled from its PIR, test results and PRR
+#[derive(Debug, Clone)]
+pub struct Part {
+    /// Position of the part in the STDF, counted in PIR order starting at 1
+    pub part_idx: u32,
+    pub head_num: u8,
+    pub site_num: u8,
+
+    // PRR information, left at the STDF defaults until the PRR is seen
+    pub part_id: String,
+    pub part_txt: String,
+    pub part_flg: [u8; 1],
+    pub x_coord: i16,
+    pub y_coord: i16,
+    pub hard_bin: u16,
+    pub soft_bin: u16,
+    pub num_test: u16,
+    pub test_t: u32,
+    pub part_fix: Vec<u8>,
+
+    /// PTR/MPR/FTR/STR records of this part in STDF order, each with its attached DTR info
+    pub results: Vec<ParsedRecord>,
+
+    /// false when the STDF ended before the PRR of this part showed up
+    pub finished: bool,
+}
+
+impl Part {
+    fn new(part_idx: u32, head_num: u8, site_num: u8) -> Self {
+        Self {
+            part_idx,
+            head_num,
+            site_num,
+            part_id: String::new(),
+            part_txt: String::new(),
+            part_flg: [0],
+            x_coord: -32768,
+            y_coord: -32768,
+            hard_bin: 0,
+            soft_bin: 65535,
+            num_test: 0,
+            test_t: 0,
+            part_fix: Vec::new(),
+            results: Vec::new(),
+            finished: false,
+        }
+    }
+
+    fn finish(&mut self, rec: &PRR) {
+        self.part_id = rec.part_id.to_owned();
+        self.part_txt = rec.part_txt.to_owned();
+        self.part_flg = rec.part_flg;
+        self.x_coord = rec.x_coord;
+        self.y_coord = rec.y_coord;
+        self.hard_bin = rec.hard_bin;
+        self.soft_bin = rec.soft_bin;
+        self.num_test = rec.num_test;
+        self.test_t = rec.test_t;
+        self.part_fix = rec.part_fix.to_owned();
+        self.finished = true;
+    }
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: Tracks the parts currently in test by (head, site), feed it
+///              every record in order and it hands back each part at its PRR
+//////////////////////////////////////////////////////////////////////
+#[derive(Debug, Default)]
+pub struct PartTracker {
+    part_count: u32,
+    open_parts: HashMap<(u8, u8), Part>,
+    // parts don't keep their results, for callers that only need the part_idx
+    tracking_only: bool,
+}
+
+impl PartTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //////////////////////////////////////////////////////////////////////
+    /// Description: Tracker whose parts are handed back without results, the
+    ///              records of a part aren't buffered until its PRR
+    //////////////////////////////////////////////////////////////////////
+    pub fn tracking_only() -> Self {
+        Self { tracking_only: true, ..Self::default() }
+    }
+
+    fn open_part_mut(&mut self, head_num: u8, site_num: u8) -> &mut Part {
+        // results without a PIR still need a part to land in
+        let part_count = &mut self.part_count;
+        self.open_parts.entry((head_num, site_num)).or_insert_with(|| {
+            *part_count += 1;
+            Part::new(*part_count, head_num, site_num)
+        })
+    }
+
+    fn add_result(&mut self, head_num: u8, site_num: u8, parsed_rec: &ParsedRecord) {
+        let tracking_only = self.tracking_only;
+        let part = self.open_part_mut(head_num, site_num);
+        if !tracking_only { part.results.push(parsed_rec.to_owned()); }
+    }
+
+    //////////////////////////////////////////////////////////////////////
+    /// Description: Processes the next record, returns the part it completes if it is a PRR,
+    ///              or the unfinished part a PIR on the same head/site puts an end to
+    //////////////////////////////////////////////////////////////////////
+    pub fn process(&mut self, parsed_rec: &ParsedRecord) -> Option<Part> {
+        match &parsed_rec.record {
+            StdfRecord::PIR(rec) => {
+                // a PIR on a site that never got its PRR replaces the unfinished part, it's handed back as is
+                self.part_count += 1;
+                self.open_parts.insert((rec.head_num, rec.site_num), Part::new(self.part_count, rec.head_num, rec.site_num))
+            },
+            StdfRecord::PTR(rec) => { self.add_result(rec.head_num, rec.site_num, parsed_rec); None },
+            StdfRecord::MPR(rec) => { self.add_result(rec.head_num, rec.site_num, parsed_rec); None },
+            StdfRecord::FTR(rec) => { self.add_result(rec.head_num, rec.site_num, parsed_rec); None },
+            StdfRecord::STR(rec) => { self.add_result(rec.head_num, rec.site_num, parsed_rec); None },
+            StdfRecord::PRR(rec) => {
+                self.open_part_mut(rec.head_num, rec.site_num);
+                let mut part = self.open_parts.remove(&(rec.head_num, rec.site_num))?;
+                part.finish(rec);
+                Some(part)
+            },
+            _ => None,
+        }
+    }
+
+    /// Part currently in test on the head/site, if any
+    pub fn open_part(&self, head_num: u8, site_num: u8) -> Option<&Part> {
+        self.open_parts.get(&(head_num, site_num))
+    }
+
+    /// Index of the part currently in test on the head/site, if any
+    pub fn part_idx(&self, head_num: u8, site_num: u8) -> Option<u32> {
+        self.open_part(head_num, site_num).map(|part| part.part_idx)
+    }
+
+    //////////////////////////////////////////////////////////////////////
+    /// Description: Returns the parts that never got a PRR in PIR order, used at end of file
+    //////////////////////////////////////////////////////////////////////
+    pub fn finish(&mut self) -> Vec<Part> {
+        let mut parts: Vec<Part> = self.open_parts.drain().map(|(_, part)| part).collect();
+        parts.sort_by_key(|part| part.part_idx);
+        parts
+    }
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: Iterates the complete parts of an STDF in PRR order, a part
+///              cut short by the next PIR on its site comes out at that PIR and
+///              parts still open at the end of the file come last
+//////////////////////////////////////////////////////////////////////
+pub struct PartIterator {
+    parser: StdfParser,
+    tracker: PartTracker,
+    unfinished: Option<std::vec::IntoIter<Part>>,
+}
+
+impl PartIterator {
+    pub fn new(path: &String, config_fname: &Option<String>) -> Result<Self, StdfUtilError> {
+        Ok(Self::from(StdfParser::new(path, config_fname)?))
+    }
+}
+
+impl From<StdfParser> for PartIterator {
+    fn from(parser: StdfParser) -> Self {
+        Self { parser, tracker: PartTracker::new(), unfinished: None }
+    }
+}
+
+impl Iterator for PartIterator {
+    type Item = Result<Part, StdfUtilError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(unfinished) = &mut self.unfinished {
+            return unfinished.next().map(Ok);
+        }
+
+        for parsed_rec in self.parser.by_ref() {
+            match parsed_rec {
+                Ok(parsed_rec) => if let Some(part) = self.tracker.process(&parsed_rec) {
+                    return Some(Ok(part));
+                },
+                Err(e) => return Some(Err(e)),
+            }
+        }
+
+        // end of file, hand out whatever never got a PRR
+        let mut unfinished = self.tracker.finish().into_iter();
+        let part = unfinished.next();
+        self.unfinished = Some(unfinished);
+        part.map(Ok)
+    }
+}
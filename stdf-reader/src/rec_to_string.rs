@@ -1,4 +1,3 @@
-use std::fmt;
 use rust_stdf::*;
 
 pub fn rec_to_string(rec: &StdfRecord, pretty_print: bool) -> String {
@@ -360,10 +359,10 @@ pub fn rec_to_string(rec: &StdfRecord, pretty_print: bool) -> String {
                         sep, indent, "hlm_scal", if let Some(v) = &rec.hlm_scal { format!("{}", v) } else { "None".into() },
                         sep, indent, "lo_limit", if let Some(v) = &rec.lo_limit { format!("{}", v) } else { "None".into() },
                         sep, indent, "hi_limit", if let Some(v) = &rec.hi_limit { format!("{}", v) } else { "None".into() },
-                        sep, indent, "units   ", if let Some(v) = &rec.units    { format!("{}", v) } else { "None".into() },
-                        sep, indent, "c_resfmt", if let Some(v) = &rec.c_resfmt { format!("{}", v) } else { "None".into() },
-                        sep, indent, "c_llmfmt", if let Some(v) = &rec.c_llmfmt { format!("{}", v) } else { "None".into() },
-                        sep, indent, "c_hlmfmt", if let Some(v) = &rec.c_hlmfmt { format!("{}", v) } else { "None".into() },
+                        sep, indent, "units   ", if let Some(v) = &rec.units    { v.to_string() } else { "None".into() },
+                        sep, indent, "c_resfmt", if let Some(v) = &rec.c_resfmt { v.to_string() } else { "None".into() },
+                        sep, indent, "c_llmfmt", if let Some(v) = &rec.c_llmfmt { v.to_string() } else { "None".into() },
+                        sep, indent, "c_hlmfmt", if let Some(v) = &rec.c_hlmfmt { v.to_string() } else { "None".into() },
                         sep, indent, "lo_spec ", if let Some(v) = &rec.lo_spec  { format!("{}", v) } else { "None".into() },
                         sep, indent, "hi_spec ", if let Some(v) = &rec.hi_spec  { format!("{}", v) } else { "None".into() },
                         sep)
@@ -417,11 +416,11 @@ pub fn rec_to_string(rec: &StdfRecord, pretty_print: bool) -> String {
                         sep, indent, "start_in", if let Some(v) = &rec.start_in { format!("{}", v) } else { "None".into() },
                         sep, indent, "incr_in ", if let Some(v) = &rec.incr_in  { format!("{}", v) } else { "None".into() },
                         sep, indent, "rtn_indx", if let Some(v) = &rec.rtn_indx { format!("{:?}", v) } else { "None".into() },
-                        sep, indent, "units   ", if let Some(v) = &rec.units    { format!("{}", v) } else { "None".into() },
-                        sep, indent, "units_in", if let Some(v) = &rec.units_in { format!("{}", v) } else { "None".into() },
-                        sep, indent, "c_resfmt", if let Some(v) = &rec.c_resfmt { format!("{}", v) } else { "None".into() },
-                        sep, indent, "c_llmfmt", if let Some(v) = &rec.c_llmfmt { format!("{}", v) } else { "None".into() },
-                        sep, indent, "c_hlmfmt", if let Some(v) = &rec.c_hlmfmt { format!("{}", v) } else { "None".into() },
+                        sep, indent, "units   ", if let Some(v) = &rec.units    { v.to_string() } else { "None".into() },
+                        sep, indent, "units_in", if let Some(v) = &rec.units_in { v.to_string() } else { "None".into() },
+                        sep, indent, "c_resfmt", if let Some(v) = &rec.c_resfmt { v.to_string() } else { "None".into() },
+                        sep, indent, "c_llmfmt", if let Some(v) = &rec.c_llmfmt { v.to_string() } else { "None".into() },
+                        sep, indent, "c_hlmfmt", if let Some(v) = &rec.c_hlmfmt { v.to_string() } else { "None".into() },
                         sep, indent, "lo_spec ", if let Some(v) = &rec.lo_spec  { format!("{}", v) } else { "None".into() },
                         sep, indent, "hi_spec ", if let Some(v) = &rec.hi_spec  { format!("{}", v) } else { "None".into() },
                         sep)
@@ -498,7 +497,7 @@ pub fn rec_to_string(rec: &StdfRecord, pretty_print: bool) -> String {
         StdfRecord::GDR(rec) => {
             let mut ret_string = format!("GDR {{{}{}{}: {}", sep, indent, "fld_cnt", rec.fld_cnt);
             for (i, val) in rec.gen_data.iter().enumerate() {
-                ret_string = ret_string + format!("{}{}{}[{}]: {:?}", sep, indent, "gen_data", i, val).as_str();
+                ret_string += format!("{}{}{}[{}]: {:?}", sep, indent, "gen_data", i, val).as_str();
             }
             ret_string + format!("{}}}", sep).as_str()
         },
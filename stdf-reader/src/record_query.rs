@@ -0,0 +1,503 @@
+use regex::Regex;
+use rust_stdf::*;
+
+use crate::{FtrOptFlags, ParsedRecord, PartFlags, PartTracker, StdfUtilError, TestFlags, TestFormat};
+
+// fields every record may have, dtr info is looked up as dtr.<id>
+const FIELD_NAMES: [&str; 13] = ["type", "head", "site", "part", "test_num", "test_txt", "result", "lo_limit", "hi_limit", "units", "failed", "passed", "text"];
+
+/// Value of a field or of a constant of a query
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryValue {
+    Num(f64),
+    Str(String),
+    Bool(bool),
+}
+
+/// Fields a query is evaluated against, None when the record doesn't have the field
+pub trait QueryFields {
+    fn field(&self, name: &str) -> Option<QueryValue>;
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: Fields of a record for queries, fields the record type
+///              doesn't have are left as None/empty
+//////////////////////////////////////////////////////////////////////
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RecordFields {
+    pub rec_type: &'static str,
+    pub head_num: Option<u8>,
+    pub site_num: Option<u8>,
+    /// Index of the part in test on the head/site, same numbering as Part::part_idx
+    pub part_idx: Option<u32>,
+    pub test_num: Option<u32>,
+    pub test_txt: String,
+    /// PTR result, FTR num_fail, PTR/MPR values are scaled by res_scal like stdf2ui shows them
+    pub result: Option<f64>,
+    pub lo_limit: Option<f64>,
+    pub hi_limit: Option<f64>,
+    /// Units with the prefix of res_scal
+    pub units: String,
+    /// None when the record flags its pass/fail as invalid or has none
+    pub failed: Option<bool>,
+    /// DTR text_dat
+    pub text: String,
+    /// (id, text) of the dtr info attached to the record
+    pub dtr: Vec<(String, String)>,
+}
+
+impl RecordFields {
+    //////////////////////////////////////////////////////////////////////
+    /// Description: Fields of a parsed record, the part comes from the tracker
+    ///              which has to have processed the records before this one
+    //////////////////////////////////////////////////////////////////////
+    pub fn new(parsed_rec: &ParsedRecord, part_tracker: &PartTracker) -> Self {
+        let mut fields = Self {
+            rec_type: stdf_record_type::get_rec_name_from_code(parsed_rec.record.get_type()),
+            dtr: parsed_rec.dtr_info.iter().map(|info| (info.id.to_owned(), info.text.to_owned())).collect(),
+            ..Default::default()
+        };
+
+        let (head_num, site_num) = match &parsed_rec.record {
+            StdfRecord::PTR(rec) => {
+                fields.test_num = Some(rec.test_num);
+                fields.test_txt = rec.test_txt.to_owned();
+                let test_format = TestFormat::from(rec);
+                fields.result = Some(test_format.scale(rec.result as f64));
+                fields.lo_limit = rec.lo_limit.map(|limit| test_format.scale(limit as f64));
+                fields.hi_limit = rec.hi_limit.map(|limit| test_format.scale(limit as f64));
+                fields.units = test_format.units();
+                fields.failed = TestFlags::from(rec.test_flg).failed();
+                (rec.head_num, rec.site_num)
+            },
+            StdfRecord::MPR(rec) => {
+                fields.test_num = Some(rec.test_num);
+                fields.test_txt = rec.test_txt.to_owned();
+                let test_format = TestFormat::from(rec);
+                fields.lo_limit = rec.lo_limit.map(|limit| test_format.scale(limit as f64));
+                fields.hi_limit = rec.hi_limit.map(|limit| test_format.scale(limit as f64));
+                fields.units = test_format.units();
+                fields.failed = TestFlags::from(rec.test_flg).failed();
+                (rec.head_num, rec.site_num)
+            },
+            StdfRecord::FTR(rec) => {
+                fields.test_num = Some(rec.test_num);
+                fields.test_txt = rec.test_txt.to_owned();
+                fields.result = FtrOptFlags::from(rec.opt_flag).num_fail_valid().then_some(rec.num_fail as f64);
+                fields.failed = TestFlags::from(rec.test_flg).failed();
+                (rec.head_num, rec.site_num)
+            },
+            StdfRecord::PIR(rec) => (rec.head_num, rec.site_num),
+            StdfRecord::PRR(rec) => {
+                fields.failed = PartFlags::from(rec.part_flg).failed();
+                (rec.head_num, rec.site_num)
+            },
+            StdfRecord::DTR(rec) => {
+                fields.text = rec.text_dat.to_owned();
+                return fields;
+            },
+            _ => return fields,
+        };
+
+        fields.head_num = Some(head_num);
+        fields.site_num = Some(site_num);
+        fields.part_idx = part_tracker.part_idx(head_num, site_num);
+        fields
+    }
+}
+
+impl QueryFields for RecordFields {
+    fn field(&self, name: &str) -> Option<QueryValue> {
+        let num = |value: Option<f64>| value.map(QueryValue::Num);
+        let text = |value: &str| Some(QueryValue::Str(value.to_owned()));
+
+        match name {
+            "type" => text(self.rec_type),
+            "head" => num(self.head_num.map(f64::from)),
+            "site" => num(self.site_num.map(f64::from)),
+            "part" => num(self.part_idx.map(f64::from)),
+            "test_num" => num(self.test_num.map(f64::from)),
+            "test_txt" => text(&self.test_txt),
+            "result" => num(self.result),
+            "lo_limit" => num(self.lo_limit),
+            "hi_limit" => num(self.hi_limit),
+            "units" => text(&self.units),
+            "failed" => self.failed.map(QueryValue::Bool),
+            "passed" => self.failed.map(|failed| QueryValue::Bool(!failed)),
+            "text" => text(&self.text),
+            _ => {
+                let id = name.strip_prefix("dtr.")?;
+                self.dtr.iter().find(|(dtr_id, _)| dtr_id == id).map(|(_, text)| QueryValue::Str(text.to_owned()))
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Num(f64),
+    Str(String),
+    Op(&'static str),
+}
+
+// operators, the longer ones first so they win over their prefix
+const OPERATORS: [&str; 15] = ["..", "==", "!=", "<=", ">=", "=", "<", ">", "~", "+", "-", "*", "/", "(", ")"];
+
+// tokens of the query with the character position each one starts at
+fn tokenize(query: &str) -> Result<Vec<(usize, Token)>, String> {
+    let chars: Vec<char> = query.chars().collect();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while pos < chars.len() {
+        let c = chars[pos];
+        let start = pos;
+        if c.is_whitespace() {
+            pos += 1;
+        } else if c.is_ascii_digit() {
+            // a '.' only belongs to the number when a digit follows it, 1..2 is a range
+            while pos < chars.len() && chars[pos].is_ascii_digit() { pos += 1; }
+            if pos + 1 < chars.len() && chars[pos] == '.' && chars[pos + 1].is_ascii_digit() {
+                pos += 1;
+                while pos < chars.len() && chars[pos].is_ascii_digit() { pos += 1; }
+            }
+            if pos < chars.len() && (chars[pos] == 'e' || chars[pos] == 'E') {
+                let digits = if pos + 1 < chars.len() && (chars[pos + 1] == '+' || chars[pos + 1] == '-') { pos + 2 } else { pos + 1 };
+                if digits < chars.len() && chars[digits].is_ascii_digit() {
+                    pos = digits;
+                    while pos < chars.len() && chars[pos].is_ascii_digit() { pos += 1; }
+                }
+            }
+            let text: String = chars[start..pos].iter().collect();
+            let num = text.parse().map_err(|_| format!("invalid number '{}' at {}", text, start))?;
+            tokens.push((start, Token::Num(num)));
+        } else if c.is_alphabetic() || c == '_' {
+            while pos < chars.len() && (chars[pos].is_alphanumeric() || chars[pos] == '_' || chars[pos] == '.') && !(chars[pos] == '.' && chars.get(pos + 1) == Some(&'.')) {
+                pos += 1;
+            }
+            tokens.push((start, Token::Ident(chars[start..pos].iter().collect())));
+        } else if c == '\'' || c == '"' {
+            let end = chars[pos + 1..].iter().position(|ch| *ch == c).ok_or(format!("unterminated string at {}", start))?;
+            tokens.push((start, Token::Str(chars[pos + 1..pos + 1 + end].iter().collect())));
+            pos += end + 2;
+        } else {
+            let rest: String = chars[pos..chars.len().min(pos + 2)].iter().collect();
+            let op = OPERATORS.iter().find(|op| rest.starts_with(**op)).ok_or(format!("unexpected '{}' at {}", c, start))?;
+            tokens.push((start, Token::Op(op)));
+            pos += op.len();
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Value(QueryValue),
+    Field(String),
+    Neg(Box<Operand>),
+    Arith(Box<Operand>, &'static str, Box<Operand>),
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Cmp(Operand, &'static str, Operand),
+    In(Operand, Operand, Operand),
+    Match(Operand, Regex),
+    Test(Operand),
+}
+
+impl Operand {
+    fn has_field(&self) -> bool {
+        match self {
+            Self::Value(_) => false,
+            Self::Field(_) => true,
+            Self::Neg(operand) => operand.has_field(),
+            Self::Arith(lhs, _, rhs) => lhs.has_field() || rhs.has_field(),
+        }
+    }
+
+    fn eval(&self, fields: &dyn QueryFields) -> Option<QueryValue> {
+        match self {
+            Self::Value(value) => Some(value.to_owned()),
+            Self::Field(name) => fields.field(name),
+            Self::Neg(operand) => Some(QueryValue::Num(-as_num(&operand.eval(fields)?)?)),
+            Self::Arith(lhs, op, rhs) => {
+                let (lhs, rhs) = (as_num(&lhs.eval(fields)?)?, as_num(&rhs.eval(fields)?)?);
+                Some(QueryValue::Num(match *op {
+                    "+" => lhs + rhs,
+                    "-" => lhs - rhs,
+                    "*" => lhs * rhs,
+                    _ => lhs / rhs,
+                }))
+            },
+        }
+    }
+}
+
+fn as_num(value: &QueryValue) -> Option<f64> {
+    match value {
+        QueryValue::Num(num) => Some(*num),
+        QueryValue::Str(text) => text.trim().parse().ok(),
+        QueryValue::Bool(_) => None,
+    }
+}
+
+fn as_bool(value: &QueryValue) -> Option<bool> {
+    match value {
+        QueryValue::Bool(value) => Some(*value),
+        QueryValue::Str(text) if text.eq_ignore_ascii_case("true") => Some(true),
+        QueryValue::Str(text) if text.eq_ignore_ascii_case("false") => Some(false),
+        _ => None,
+    }
+}
+
+// order of two values, numbers when both are numbers, otherwise text ignoring case
+fn compare(lhs: &QueryValue, rhs: &QueryValue) -> Option<std::cmp::Ordering> {
+    match (lhs, rhs) {
+        (QueryValue::Bool(_), _) | (_, QueryValue::Bool(_)) => as_bool(lhs)?.partial_cmp(&as_bool(rhs)?),
+        (QueryValue::Str(lhs), QueryValue::Str(rhs)) => Some(lhs.to_lowercase().cmp(&rhs.to_lowercase())),
+        _ => as_num(lhs)?.partial_cmp(&as_num(rhs)?),
+    }
+}
+
+impl Expr {
+    fn eval(&self, fields: &dyn QueryFields) -> bool {
+        match self {
+            Self::Or(lhs, rhs) => lhs.eval(fields) || rhs.eval(fields),
+            Self::And(lhs, rhs) => lhs.eval(fields) && rhs.eval(fields),
+            Self::Not(expr) => !expr.eval(fields),
+            Self::Cmp(lhs, op, rhs) => {
+                let Some(ordering) = lhs.eval(fields).zip(rhs.eval(fields)).and_then(|(lhs, rhs)| compare(&lhs, &rhs)) else { return false };
+                match *op {
+                    "=" | "==" => ordering.is_eq(),
+                    "!=" => ordering.is_ne(),
+                    "<" => ordering.is_lt(),
+                    "<=" => ordering.is_le(),
+                    ">" => ordering.is_gt(),
+                    _ => ordering.is_ge(),
+                }
+            },
+            Self::In(operand, first, last) => {
+                let value = |operand: &Operand| operand.eval(fields).as_ref().and_then(as_num);
+                match (value(operand), value(first), value(last)) {
+                    (Some(value), Some(first), Some(last)) => first <= value && value <= last,
+                    _ => false,
+                }
+            },
+            Self::Match(operand, regex) => match operand.eval(fields) {
+                Some(QueryValue::Str(text)) => regex.is_match(&text),
+                Some(QueryValue::Num(num)) => regex.is_match(&num.to_string()),
+                _ => false,
+            },
+            Self::Test(operand) => match operand.eval(fields) {
+                Some(QueryValue::Bool(value)) => value,
+                Some(QueryValue::Num(num)) => num != 0.0,
+                Some(QueryValue::Str(text)) => !text.is_empty(),
+                None => false,
+            },
+        }
+    }
+}
+
+// recursive descent parser, or < and < not < comparison < + - < * / < unary minus
+struct Parser<'a> {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    extra_fields: &'a [&'a str],
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    fn peek_op(&self, ops: &[&'static str]) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Op(op)) => ops.iter().find(|candidate| *candidate == op).copied(),
+            _ => None,
+        }
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(ident)) if ident.eq_ignore_ascii_case(keyword))
+    }
+
+    fn error(&self, expected: &str) -> String {
+        match self.tokens.get(self.pos) {
+            Some((pos, Token::Ident(ident))) => format!("expected {} at {}, found '{}'", expected, pos, ident),
+            Some((pos, Token::Num(num))) => format!("expected {} at {}, found {}", expected, pos, num),
+            Some((pos, Token::Str(text))) => format!("expected {} at {}, found '{}'", expected, pos, text),
+            Some((pos, Token::Op(op))) => format!("expected {} at {}, found '{}'", expected, pos, op),
+            None => format!("expected {} at the end", expected),
+        }
+    }
+
+    fn is_field(&self, name: &str) -> bool {
+        FIELD_NAMES.contains(&name) || self.extra_fields.contains(&name) || name.strip_prefix("dtr.").is_some_and(|id| !id.is_empty())
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_and()?;
+        while self.peek_keyword("or") {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_not()?;
+        while self.peek_keyword("and") {
+            self.pos += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_not()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, String> {
+        if self.peek_keyword("not") {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.parse_not()?)));
+        }
+
+        // a parenthesis opens either a group of conditions or an arithmetic operand,
+        //  try the condition first and fall back to the group
+        let start = self.pos;
+        match self.parse_condition() {
+            Err(_) if self.tokens.get(start).is_some_and(|(_, token)| *token == Token::Op("(")) => {
+                self.pos = start + 1;
+                let expr = self.parse_or()?;
+                if self.peek_op(&[")"]).is_none() { return Err(self.error("')'")); }
+                self.pos += 1;
+                Ok(expr)
+            },
+            result => result,
+        }
+    }
+
+    fn parse_condition(&mut self) -> Result<Expr, String> {
+        let start = self.tokens.get(self.pos).map(|(pos, _)| *pos).unwrap_or_default();
+        let lhs = self.parse_sum()?;
+
+        if let Some(op) = self.peek_op(&["=", "==", "!=", "<", "<=", ">", ">="]) {
+            self.pos += 1;
+            let rhs = self.parse_sum()?;
+            if !lhs.has_field() && !rhs.has_field() {
+                return Err(match lhs {
+                    Operand::Value(QueryValue::Str(word)) => format!("'{}' at {} is not a field", word, start),
+                    _ => format!("the condition at {} doesn't use any field", start),
+                });
+            }
+            return Ok(Expr::Cmp(lhs, op, rhs));
+        }
+
+        if self.peek_op(&["~"]).is_some() {
+            self.pos += 1;
+            let pattern = match self.tokens.get(self.pos) {
+                Some((_, Token::Str(text) | Token::Ident(text))) => text.to_owned(),
+                _ => return Err(self.error("a regex")),
+            };
+            self.pos += 1;
+            let regex = Regex::new(&pattern).map_err(|err| format!("invalid regex '{}': {}", pattern, err))?;
+            return Ok(Expr::Match(lhs, regex));
+        }
+
+        if self.peek_keyword("in") {
+            self.pos += 1;
+            let first = self.parse_sum()?;
+            if self.peek_op(&[".."]).is_none() { return Err(self.error("'..'")); }
+            self.pos += 1;
+            let last = self.parse_sum()?;
+            return Ok(Expr::In(lhs, first, last));
+        }
+
+        // a field on its own is true when it is set, e.g. failed
+        match lhs {
+            Operand::Field(_) => Ok(Expr::Test(lhs)),
+            Operand::Value(QueryValue::Str(word)) if self.peek().is_none_or(|token| !matches!(token, Token::Op(_))) => Err(format!("'{}' at {} is not a field", word, start)),
+            _ => Err(self.error("a comparison")),
+        }
+    }
+
+    fn parse_sum(&mut self) -> Result<Operand, String> {
+        let mut operand = self.parse_product()?;
+        while let Some(op) = self.peek_op(&["+", "-"]) {
+            self.pos += 1;
+            operand = Operand::Arith(Box::new(operand), op, Box::new(self.parse_product()?));
+        }
+        Ok(operand)
+    }
+
+    fn parse_product(&mut self) -> Result<Operand, String> {
+        let mut operand = self.parse_unary()?;
+        while let Some(op) = self.peek_op(&["*", "/"]) {
+            self.pos += 1;
+            operand = Operand::Arith(Box::new(operand), op, Box::new(self.parse_unary()?));
+        }
+        Ok(operand)
+    }
+
+    fn parse_unary(&mut self) -> Result<Operand, String> {
+        let operand = match self.peek().cloned() {
+            Some(Token::Op("-")) => {
+                self.pos += 1;
+                return Ok(Operand::Neg(Box::new(self.parse_unary()?)));
+            },
+            Some(Token::Op("(")) => {
+                self.pos += 1;
+                let operand = self.parse_sum()?;
+                if self.peek_op(&[")"]).is_none() { return Err(self.error("')'")); }
+                operand
+            },
+            Some(Token::Num(num)) => Operand::Value(QueryValue::Num(num)),
+            Some(Token::Str(text)) => Operand::Value(QueryValue::Str(text)),
+            // keywords can't be fields or words
+            Some(Token::Ident(ident)) if ["and", "or", "not", "in"].iter().any(|keyword| ident.eq_ignore_ascii_case(keyword)) => return Err(self.error("a field or value")),
+            // a word that isn't a field is text, e.g. PTR in type=PTR
+            Some(Token::Ident(ident)) => if self.is_field(&ident) { Operand::Field(ident) } else { Operand::Value(QueryValue::Str(ident)) },
+            _ => return Err(self.error("a field or value")),
+        };
+        self.pos += 1;
+        Ok(operand)
+    }
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: Condition on the fields of a record, e.g.
+///              type=PTR and test_num in 1000..2000 and site=3 and failed
+///              Conditions are joined by and/or/not with parentheses, compared
+///              with = != < <= > >=, in first..last (inclusive) and ~ regex,
+///              numbers may be computed with + - * /, e.g. result > hi_limit*0.9.
+///              A word that isn't a field is text, a missing field never matches.
+//////////////////////////////////////////////////////////////////////
+#[derive(Debug, Clone)]
+pub struct RecordQuery {
+    expr: Expr,
+}
+
+impl RecordQuery {
+    //////////////////////////////////////////////////////////////////////
+    /// Description: Parses a query, extra_fields are names of fields the
+    ///              QueryFields of the caller has on top of the RecordFields ones
+    //////////////////////////////////////////////////////////////////////
+    pub fn parse(query: &str, extra_fields: &[&str]) -> Result<Self, StdfUtilError> {
+        let error = |msg: String| StdfUtilError::Query { query: query.to_owned(), msg };
+        let tokens = tokenize(query).map_err(error)?;
+        if tokens.is_empty() { return Err(error("the query is empty".to_string())); }
+
+        let mut parser = Parser { tokens, pos: 0, extra_fields };
+        let expr = parser.parse_or().map_err(error)?;
+        if parser.pos < parser.tokens.len() { return Err(error(parser.error("'and' or 'or'"))); }
+
+        Ok(Self { expr })
+    }
+
+    pub fn matches(&self, fields: &dyn QueryFields) -> bool {
+        self.expr.eval(fields)
+    }
+}
@@ -0,0 +1,384 @@
+use std::{collections::{BTreeMap, HashMap}, fs::File, io::{BufRead, BufReader, BufWriter, Write}};
+
+use rust_stdf::*;
+
+use crate::{test_stats::valid_limit, value_format::text_table, ParmFlags, Part, PartFlags, PartIterator, PtrOptFlags, StdfUtilError, TestFlags};
+
+//////////////////////////////////////////////////////////////////////
+/// Description: New limits of a test, a limit left at None keeps the one of
+///              the STDF and Some(None) removes it
+//////////////////////////////////////////////////////////////////////
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LimitOverride {
+    pub lo_limit: Option<Option<f32>>,
+    pub hi_limit: Option<Option<f32>>,
+    /// Bins of the parts this test is now the first failing test of
+    pub hard_bin: Option<u16>,
+    pub soft_bin: Option<u16>,
+}
+
+impl LimitOverride {
+    // limits of a PTR/MPR with the overrides applied
+    fn limits(&self, lo_limit: Option<f64>, hi_limit: Option<f64>) -> (Option<f64>, Option<f64>) {
+        let limit = |new_limit: Option<Option<f32>>, limit: Option<f64>| match new_limit {
+            Some(new_limit) => new_limit.map(|new_limit| new_limit as f64),
+            None => limit,
+        };
+        (limit(self.lo_limit, lo_limit), limit(self.hi_limit, hi_limit))
+    }
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: New limits by test number or test name, a test number wins
+///              over a name and names are matched ignoring case
+//////////////////////////////////////////////////////////////////////
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LimitOverrides {
+    by_num: HashMap<u32, LimitOverride>,
+    by_txt: HashMap<String, LimitOverride>,
+}
+
+// value of a limit in a limits file, empty keeps the limit of the STDF and none removes it
+fn parse_limit(value: &str) -> Result<Option<Option<f32>>, String> {
+    match value.trim() {
+        "" => Ok(None),
+        value if value.eq_ignore_ascii_case("none") => Ok(Some(None)),
+        value => value.parse().map(|limit| Some(Some(limit))).map_err(|_| format!("'{}' is not a limit", value)),
+    }
+}
+
+fn parse_bin(value: &str) -> Result<Option<u16>, String> {
+    match value.trim() {
+        "" => Ok(None),
+        value => value.parse().map(Some).map_err(|_| format!("'{}' is not a bin number", value)),
+    }
+}
+
+// fields of a csv line, double quotes in a quoted field are escaped by doubling them
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => { chars.next(); fields.last_mut().unwrap().push('"'); },
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+impl LimitOverrides {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert_num(&mut self, test_num: u32, limits: LimitOverride) {
+        self.by_num.insert(test_num, limits);
+    }
+
+    pub fn insert_txt(&mut self, test_txt: &str, limits: LimitOverride) {
+        self.by_txt.insert(test_txt.to_lowercase(), limits);
+    }
+
+    pub fn get(&self, test_num: u32, test_txt: &str) -> Option<&LimitOverride> {
+        self.by_num.get(&test_num).or_else(|| self.by_txt.get(&test_txt.to_lowercase()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_num.is_empty() && self.by_txt.is_empty()
+    }
+
+    //////////////////////////////////////////////////////////////////////
+    /// Description: Reads a limits file, an .ini has a section per test named
+    ///              by its number or name, anything else is read as a csv with
+    ///              a test number and/or test name column. Both take lo_limit,
+    ///              hi_limit, hard_bin and soft_bin, so a test plan csv of
+    ///              stdf-testplan can be edited and used as is
+    //////////////////////////////////////////////////////////////////////
+    pub fn read(limits_path: &String) -> Result<Self, StdfUtilError> {
+        if limits_path.to_lowercase().ends_with(".ini") {
+            Self::read_ini(limits_path).map_err(|msg| StdfUtilError::LimitsFile { path: limits_path.to_owned(), msg })
+        } else {
+            Self::read_csv(limits_path)
+        }
+    }
+
+    fn read_ini(limits_path: &str) -> Result<Self, String> {
+        let mut overrides = Self::new();
+        for (section, values) in ini!(safe limits_path)? {
+            let value = |key: &str| values.get(key).cloned().flatten().unwrap_or_default();
+            let section_error = |msg: String| format!("[{}] {}", section, msg);
+            let limits = LimitOverride {
+                lo_limit: parse_limit(&value("lo_limit")).map_err(section_error)?,
+                hi_limit: parse_limit(&value("hi_limit")).map_err(section_error)?,
+                hard_bin: parse_bin(&value("hard_bin")).map_err(section_error)?,
+                soft_bin: parse_bin(&value("soft_bin")).map_err(section_error)?,
+            };
+
+            match section.parse() {
+                Ok(test_num) => overrides.insert_num(test_num, limits),
+                Err(_) => overrides.insert_txt(&section, limits),
+            }
+        }
+        Ok(overrides)
+    }
+
+    // the io errors are reported as such, the content errors as LimitsFile
+    fn read_csv(limits_path: &str) -> Result<Self, StdfUtilError> {
+        let limits_error = |msg: String| StdfUtilError::LimitsFile { path: limits_path.to_owned(), msg };
+        let mut lines = BufReader::new(File::open(limits_path).map_err(StdfUtilError::io(limits_path))?).lines();
+        let Some(header) = lines.next().transpose().map_err(StdfUtilError::io(limits_path))? else { return Err(limits_error("the file is empty".to_string())) };
+
+        // columns are found by name, "Test Number", "test_num" and "TESTNUM" are the same column
+        let header: Vec<String> = csv_fields(&header).iter().map(|name| name.to_lowercase().replace([' ', '_'], "")).collect();
+        let column = |names: &[&str]| header.iter().position(|name| names.contains(&name.as_str()));
+        let num_col = column(&["testnum", "testnumber", "tnum"]);
+        let txt_col = column(&["testtxt", "testname", "testtext"]);
+        if num_col.is_none() && txt_col.is_none() { return Err(limits_error("no test number or test name column".to_string())); }
+        let (lo_col, hi_col, hard_col, soft_col) = (column(&["lolimit"]), column(&["hilimit"]), column(&["hardbin"]), column(&["softbin"]));
+
+        let mut overrides = Self::new();
+        for (line_idx, line) in lines.enumerate() {
+            let line = line.map_err(StdfUtilError::io(limits_path))?;
+            if line.trim().is_empty() { continue; }
+
+            let fields = csv_fields(&line);
+            let field = |col: Option<usize>| col.and_then(|col| fields.get(col)).map(|field| field.trim()).unwrap_or_default();
+            let line_error = |msg: String| limits_error(format!("line {}: {}", line_idx + 2, msg));
+            let limits = match (parse_limit(field(lo_col)), parse_limit(field(hi_col)), parse_bin(field(hard_col)), parse_bin(field(soft_col))) {
+                (Ok(lo_limit), Ok(hi_limit), Ok(hard_bin), Ok(soft_bin)) => LimitOverride { lo_limit, hi_limit, hard_bin, soft_bin },
+                (Err(msg), ..) | (_, Err(msg), ..) | (.., Err(msg), _) | (.., Err(msg)) => return Err(line_error(msg)),
+            };
+
+            match (field(num_col), field(txt_col)) {
+                ("", "") => return Err(line_error("no test number or test name".to_string())),
+                ("", test_txt) => overrides.insert_txt(test_txt, limits),
+                (test_num, _) => match test_num.parse() {
+                    Ok(test_num) => overrides.insert_num(test_num, limits),
+                    Err(_) => return Err(line_error(format!("'{}' is not a test number", test_num))),
+                },
+            }
+        }
+        Ok(overrides)
+    }
+}
+
+/// Bins given to the parts whose pass/fail changed, None keeps the bin of the STDF
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct RelimitOptions {
+    /// Bins of the parts now failing a test without bins in the limits file, a passing
+    /// part failing such a test is an error without them
+    pub fail_hard_bin: Option<u16>,
+    pub fail_soft_bin: Option<u16>,
+    /// Bins of the failing parts that now pass
+    pub pass_hard_bin: Option<u16>,
+    pub pass_soft_bin: Option<u16>,
+}
+
+/// A part before and after the new limits
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RelimitPart {
+    pub part_idx: u32,
+    pub head_num: u8,
+    pub site_num: u8,
+    pub part_id: String,
+    pub x_coord: i16,
+    pub y_coord: i16,
+    pub failed_before: bool,
+    pub failed_after: bool,
+    /// Test number of the first failing test
+    pub first_fail_before: Option<u32>,
+    pub first_fail_after: Option<u32>,
+    pub hard_bin_before: u16,
+    pub soft_bin_before: u16,
+    pub hard_bin_after: u16,
+    pub soft_bin_after: u16,
+}
+
+impl RelimitPart {
+    pub fn rebinned(&self) -> bool {
+        self.hard_bin_before != self.hard_bin_after || self.soft_bin_before != self.soft_bin_after
+    }
+}
+
+/// Part counts of a site, or of all of them
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct SiteYield {
+    pub parts: u32,
+    pub passed_before: u32,
+    pub passed_after: u32,
+}
+
+impl SiteYield {
+    fn add(&mut self, part: &RelimitPart) {
+        self.parts += 1;
+        if !part.failed_before { self.passed_before += 1; }
+        if !part.failed_after { self.passed_after += 1; }
+    }
+
+    pub fn yield_before(&self) -> Option<f64> {
+        (self.parts > 0).then(|| self.passed_before as f64 * 100.0 / self.parts as f64)
+    }
+
+    pub fn yield_after(&self) -> Option<f64> {
+        (self.parts > 0).then(|| self.passed_after as f64 * 100.0 / self.parts as f64)
+    }
+}
+
+// test number of a PTR/MPR/FTR, whether it failed and whether it fails with the new limits of the test
+fn test_failed<'a>(rec: &StdfRecord, overrides: &'a LimitOverrides) -> Option<(u32, bool, bool, Option<&'a LimitOverride>)> {
+    let (test_num, test_txt, test_flags, parm_flags, results, lo_limit, hi_limit, opt_flags) = match rec {
+        StdfRecord::PTR(rec) => (rec.test_num, &rec.test_txt, TestFlags::from(rec.test_flg), ParmFlags::from(rec.parm_flg), vec![rec.result], rec.lo_limit, rec.hi_limit, PtrOptFlags::from(rec.opt_flag)),
+        StdfRecord::MPR(rec) => (rec.test_num, &rec.test_txt, TestFlags::from(rec.test_flg), ParmFlags::from(rec.parm_flg), rec.rtn_rslt.to_owned(), rec.lo_limit, rec.hi_limit, PtrOptFlags::from(rec.opt_flag)),
+        StdfRecord::FTR(rec) => return Some((rec.test_num, TestFlags::from(rec.test_flg).is_fail(), TestFlags::from(rec.test_flg).is_fail(), None)),
+        _ => return None,
+    };
+    let failed = test_flags.is_fail();
+
+    // invalid results and tests that didn't run keep the verdict of the tester
+    let Some(limits) = overrides.get(test_num, test_txt) else { return Some((test_num, failed, failed, None)) };
+    if !test_flags.is_valid() || results.is_empty() { return Some((test_num, failed, failed, Some(limits))); }
+
+    let (lo_limit, hi_limit) = limits.limits(valid_limit(lo_limit, opt_flags.lo_limit_valid()), valid_limit(hi_limit, opt_flags.hi_limit_valid()));
+    let failed_after = results.iter().any(|result| !parm_flags.within_limits(*result, lo_limit, hi_limit));
+    Some((test_num, failed, failed_after, Some(limits)))
+}
+
+// judges a part again with the new limits, the bins only change with its first failing test
+fn relimit_part(part: &Part, overrides: &LimitOverrides, options: &RelimitOptions) -> Result<RelimitPart, StdfUtilError> {
+    let mut first_fail_before = None;
+    let mut first_fail_after: Option<(u32, Option<&LimitOverride>)> = None;
+    for parsed_rec in &part.results {
+        let Some((test_num, failed_before, failed_after, limits)) = test_failed(&parsed_rec.record, overrides) else { continue };
+
+        if failed_before && first_fail_before.is_none() { first_fail_before = Some(test_num); }
+        if failed_after && first_fail_after.is_none() { first_fail_after = Some((test_num, limits)); }
+    }
+
+    // a part failed without any failing test keeps failing, whatever made it fail isn't in the results
+    let failed_before = PartFlags::from(part.part_flg).is_fail();
+    let failed_after = first_fail_after.is_some() || (failed_before && first_fail_before.is_none());
+
+    let (mut hard_bin, mut soft_bin) = (part.hard_bin, part.soft_bin);
+    match &first_fail_after {
+        Some((test_num, limits)) if first_fail_before != Some(*test_num) => {
+            let fail_hard_bin = limits.and_then(|limits| limits.hard_bin).or(options.fail_hard_bin);
+            let fail_soft_bin = limits.and_then(|limits| limits.soft_bin).or(options.fail_soft_bin);
+
+            // a failing part keeps its fail bins, a passing one can't keep its pass bins
+            if !failed_before && (fail_hard_bin.is_none() || fail_soft_bin.is_none()) {
+                return Err(StdfUtilError::MissingFailBin { part_idx: part.part_idx, test_num: *test_num });
+            }
+            hard_bin = fail_hard_bin.unwrap_or(hard_bin);
+            soft_bin = fail_soft_bin.unwrap_or(soft_bin);
+        },
+        None if failed_before && !failed_after => {
+            hard_bin = options.pass_hard_bin.unwrap_or(hard_bin);
+            soft_bin = options.pass_soft_bin.unwrap_or(soft_bin);
+        },
+        _ => {},
+    }
+
+    Ok(RelimitPart {
+        part_idx: part.part_idx,
+        head_num: part.head_num,
+        site_num: part.site_num,
+        part_id: part.part_id.to_owned(),
+        x_coord: part.x_coord,
+        y_coord: part.y_coord,
+        failed_before,
+        failed_after,
+        first_fail_before,
+        first_fail_after: first_fail_after.map(|(test_num, _)| test_num),
+        hard_bin_before: part.hard_bin,
+        soft_bin_before: part.soft_bin,
+        hard_bin_after: hard_bin,
+        soft_bin_after: soft_bin,
+    })
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: Parts of an STDF judged again with new limits, in PRR order
+//////////////////////////////////////////////////////////////////////
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RelimitReport {
+    pub parts: Vec<RelimitPart>,
+    /// Part counts by (head, site)
+    pub sites: BTreeMap<(u8, u8), SiteYield>,
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: Judges every PTR/MPR of an STDF again with the new limits, a
+///              result equal to a limit passes as the parm_flg of the test says,
+///              and works out the pass/fail and bins of each part from it.
+///              Parts that never got a PRR are left out. A passing part that now
+///              fails a test needs fail bins from the limits file or the options
+//////////////////////////////////////////////////////////////////////
+pub fn relimit_stdf(stdf_path: &String, overrides: &LimitOverrides, options: &RelimitOptions) -> Result<RelimitReport, StdfUtilError> {
+    let mut report = RelimitReport::default();
+    for part in PartIterator::new(stdf_path, &None)? {
+        let part = part?;
+        if !part.finished { continue; }
+
+        let part = relimit_part(&part, overrides, options)?;
+        report.sites.entry((part.head_num, part.site_num)).or_default().add(&part);
+        report.parts.push(part);
+    }
+    Ok(report)
+}
+
+impl RelimitReport {
+    /// Part counts of all sites together
+    pub fn total(&self) -> SiteYield {
+        let mut total = SiteYield::default();
+        self.parts.iter().for_each(|part| total.add(part));
+        total
+    }
+
+    //////////////////////////////////////////////////////////////////////
+    /// Description: Writes a row per part with its pass/fail and bins before
+    ///              and after the new limits
+    //////////////////////////////////////////////////////////////////////
+    pub fn write_csv(&self, csv_path: &str) -> Result<(), StdfUtilError> {
+        let mut csv_file = BufWriter::new(File::create(csv_path).map_err(StdfUtilError::io(csv_path))?);
+        writeln!(csv_file, "\"Part ID\",\"Head\",\"SiteNum\",\"X\",\"Y\",\"Pass Before\",\"Pass After\",\"First Fail Before\",\"First Fail After\",\"Hard Bin Before\",\"Soft Bin Before\",\"Hard Bin After\",\"Soft Bin After\"")
+            .map_err(StdfUtilError::io(csv_path))?;
+
+        let test_num = |test_num: Option<u32>| test_num.map(|test_num| test_num.to_string()).unwrap_or_default();
+        for part in &self.parts {
+            writeln!(csv_file, "\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\"",
+                part.part_id.replace('"', "\"\""), part.head_num, part.site_num, part.x_coord, part.y_coord, !part.failed_before, !part.failed_after,
+                test_num(part.first_fail_before), test_num(part.first_fail_after), part.hard_bin_before, part.soft_bin_before, part.hard_bin_after, part.soft_bin_after)
+                .map_err(StdfUtilError::io(csv_path))?;
+        }
+
+        csv_file.flush().map_err(StdfUtilError::io(csv_path))
+    }
+
+    //////////////////////////////////////////////////////////////////////
+    /// Description: Table of the yield before and after the new limits per site
+    ///              and for all of them, followed by how many parts changed
+    //////////////////////////////////////////////////////////////////////
+    pub fn to_text(&self) -> String {
+        let pct = |value: Option<f64>| value.map(|value| format!("{:.2}%", value)).unwrap_or_default();
+        let row = |head: String, site: String, counts: &SiteYield| {
+            let delta = counts.yield_after().zip(counts.yield_before()).map(|(after, before)| format!("{:+.2}%", after - before)).unwrap_or_default();
+            vec![head, site, counts.parts.to_string(), counts.passed_before.to_string(), pct(counts.yield_before()), counts.passed_after.to_string(), pct(counts.yield_after()), delta]
+        };
+
+        let mut rows: Vec<Vec<String>> = vec![["Head", "Site", "Parts", "Passed Before", "Yield Before", "Passed After", "Yield After", "Delta"].map(String::from).to_vec()];
+        rows.extend(self.sites.iter().map(|((head_num, site_num), counts)| row(head_num.to_string(), site_num.to_string(), counts)));
+        rows.push(row("All".to_string(), "".to_string(), &self.total()));
+
+        let mut text = text_table(&rows);
+
+        let now_passing = self.parts.iter().filter(|part| part.failed_before && !part.failed_after).count();
+        let now_failing = self.parts.iter().filter(|part| !part.failed_before && part.failed_after).count();
+        let rebinned = self.parts.iter().filter(|part| part.rebinned()).count();
+        text += &format!("\n{} parts now pass, {} now fail, {} rebinned\n", now_passing, now_failing, rebinned);
+        text
+    }
+}
@@ -0,0 +1,286 @@
+use std::collections::{BTreeMap, HashMap};
+
+use polars::prelude::*;
+use rust_stdf::*;
+
+use crate::{mpr_pin_label, scale_value, DtrInfo, ParsedRecord, Part, PartTracker, StdfParser, StdfUtilError, TestFlags, TestFormat};
+
+/// Shape of the DataFrame built by stdf_to_dataframe
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DataFrameLayout {
+    /// One row per test result, same columns as the convert_stdf2csv tests file
+    Long,
+    /// One row per part, one column per test
+    Wide,
+}
+
+// single test result of a part
+struct ResultRow {
+    tnum: String,
+    test_txt: String,
+    context: String,
+    lo_limit: Option<f64>,
+    result: Option<f64>,
+    hi_limit: Option<f64>,
+    dtr_text: Vec<(String, String)>,
+}
+
+// column storage for the long layout
+#[derive(Default)]
+struct LongColumns {
+    part_id: Vec<String>,
+    tnum: Vec<String>,
+    site_num: Vec<u32>,
+    test_txt: Vec<String>,
+    context: Vec<String>,
+    lo_limit: Vec<Option<f64>>,
+    result: Vec<Option<f64>>,
+    hi_limit: Vec<Option<f64>>,
+    dtr: BTreeMap<String, Vec<Option<String>>>,
+    dtr_order: Vec<String>,
+}
+
+// column storage for the wide layout
+#[derive(Default)]
+struct WideColumns {
+    part_id: Vec<String>,
+    site_num: Vec<u32>,
+    // parts without a PRR have no bins
+    hard_bin: Vec<Option<u32>>,
+    soft_bin: Vec<Option<u32>>,
+    tests: HashMap<String, Vec<Option<f64>>>,
+    test_order: Vec<String>,
+}
+
+fn scaled(data: Option<f32>, scale: Option<i8>) -> Option<f64> {
+    // same scaling convert_stdf2csv applies, non-finite values are treated as missing
+    match data {
+        Some(data) if data.is_finite() => Some(scale_value(data as f64, scale)),
+        _ => None,
+    }
+}
+
+fn dtr_text(dtr_info: &[DtrInfo]) -> Vec<(String, String)> {
+    dtr_info.iter()
+        .map(|info| (info.id.to_owned(), info.text.to_owned()))
+        .collect()
+}
+
+impl LongColumns {
+    fn push(&mut self, part_id: &str, site_num: u8, row: ResultRow) {
+        let row_count = self.part_id.len();
+
+        self.part_id.push(part_id.to_owned());
+        self.tnum.push(row.tnum);
+        self.site_num.push(site_num as u32);
+        self.test_txt.push(row.test_txt);
+        self.context.push(row.context);
+        self.lo_limit.push(row.lo_limit);
+        self.result.push(row.result);
+        self.hi_limit.push(row.hi_limit);
+
+        // DTR columns are discovered as they show up, back fill them for rows that came before
+        for (id, text) in row.dtr_text {
+            if !self.dtr.contains_key(&id) {
+                self.dtr.insert(id.to_owned(), vec![None; row_count]);
+                self.dtr_order.push(id.to_owned());
+            }
+            if let Some(col) = self.dtr.get_mut(&id) {
+                col.push(Some(text));
+            }
+        }
+        for col in self.dtr.values_mut() {
+            col.resize(row_count + 1, None);
+        }
+    }
+
+    fn into_dataframe(self) -> PolarsResult<DataFrame> {
+        let mut columns = vec![
+            Series::new("Part ID".into(), self.part_id),
+            Series::new("TNum".into(), self.tnum),
+            Series::new("SiteNum".into(), self.site_num),
+            Series::new("TestText".into(), self.test_txt),
+            Series::new("Context".into(), self.context),
+            Series::new("Low Limit".into(), self.lo_limit),
+            Series::new("Result".into(), self.result),
+            Series::new("Hi Limit".into(), self.hi_limit),
+        ];
+        let mut dtr = self.dtr;
+        for id in self.dtr_order {
+            if let Some(col) = dtr.remove(&id) {
+                columns.push(Series::new(id.as_str().into(), col));
+            }
+        }
+
+        DataFrame::new(columns)
+    }
+}
+
+impl WideColumns {
+    fn push(&mut self, part: &Part, rows: Vec<ResultRow>) {
+        let row_count = self.part_id.len();
+
+        self.part_id.push(part.part_id.to_owned());
+        self.site_num.push(part.site_num as u32);
+        self.hard_bin.push(part.finished.then_some(part.hard_bin as u32));
+        self.soft_bin.push(part.finished.then_some(part.soft_bin as u32));
+
+        // every test is a column, new tests are back filled for parts that came before
+        for row in rows {
+            let name = format!("{}:{}", row.tnum, row.test_txt);
+            if !self.tests.contains_key(&name) {
+                self.tests.insert(name.to_owned(), vec![None; row_count]);
+                self.test_order.push(name.to_owned());
+            }
+            if let Some(col) = self.tests.get_mut(&name) {
+                // a test executed twice on the same part keeps the last result
+                col.resize(row_count, None);
+                col.push(row.result);
+            }
+        }
+        for col in self.tests.values_mut() {
+            col.resize(row_count + 1, None);
+        }
+    }
+
+    fn into_dataframe(self) -> PolarsResult<DataFrame> {
+        let mut columns = vec![
+            Series::new("Part ID".into(), self.part_id),
+            Series::new("SiteNum".into(), self.site_num),
+            Series::new("Hard Bin".into(), self.hard_bin),
+            Series::new("Soft Bin".into(), self.soft_bin),
+        ];
+        let mut tests = self.tests;
+        for name in self.test_order {
+            if let Some(col) = tests.remove(&name) {
+                columns.push(Series::new(name.as_str().into(), col));
+            }
+        }
+
+        DataFrame::new(columns)
+    }
+}
+
+fn part_rows(part: &Part, pmr_dict: &BTreeMap<u16, PMR>) -> Result<Vec<ResultRow>, StdfUtilError> {
+    let mut rows = Vec::new();
+
+    for ParsedRecord { record, dtr_info, .. } in &part.results {
+        match record {
+            StdfRecord::FTR(rec) => {
+                let failed = TestFlags::from(rec.test_flg).failed();
+                let invalid = failed.is_none();
+
+                rows.push(ResultRow {
+                    tnum: rec.test_num.to_string(),
+                    test_txt: rec.test_txt.to_owned(),
+                    context: if rec.vect_nam.is_empty() { String::new() } else { format!("vect_name: {}", rec.vect_nam) },
+                    lo_limit: if invalid { None } else { Some(1.0) },
+                    result: failed.map(|failed| if failed { 0.0 } else { 1.0 }),
+                    hi_limit: if invalid { None } else { Some(1.0) },
+                    dtr_text: dtr_text(dtr_info),
+                });
+            },
+
+            StdfRecord::PTR(rec) => {
+                let units = TestFormat::from(rec).units();
+
+                rows.push(ResultRow {
+                    tnum: rec.test_num.to_string(),
+                    test_txt: rec.test_txt.to_owned(),
+                    context: if units.is_empty() { String::new() } else { format!("units: {}", units) },
+                    lo_limit: scaled(rec.lo_limit, rec.res_scal),
+                    result: scaled(Some(rec.result), rec.res_scal),
+                    hi_limit: scaled(rec.hi_limit, rec.res_scal),
+                    dtr_text: dtr_text(dtr_info),
+                });
+            },
+
+            StdfRecord::MPR(rec) => {
+                for (i, result) in rec.rtn_rslt.iter().enumerate() {
+                    let context = match mpr_pin_label(rec, i, pmr_dict)? {
+                        Some(pin_label) => format!("pin: {}", pin_label),
+                        None => String::new(),
+                    };
+
+                    rows.push(ResultRow {
+                        tnum: format!("{}.{}", rec.test_num, i),
+                        test_txt: rec.test_txt.to_owned(),
+                        context,
+                        lo_limit: scaled(rec.lo_limit, rec.res_scal),
+                        result: scaled(Some(*result), rec.res_scal),
+                        hi_limit: scaled(rec.hi_limit, rec.res_scal),
+                        dtr_text: dtr_text(dtr_info),
+                    });
+                }
+            },
+
+            // STR's don't contribute to the DataFrame
+            _rec => {},
+        }
+    }
+
+    Ok(rows)
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: Builds an in memory DataFrame of the per part test results, parts
+///              cut short by a PIR or without a PRR keep their results like the csv
+//////////////////////////////////////////////////////////////////////
+pub fn stdf_to_dataframe(stdf_path: &String, dtr_cfg_file: &Option<String>, layout: DataFrameLayout) -> Result<DataFrame, StdfUtilError> {
+    let mut pmr_dict = BTreeMap::<u16, PMR>::new();
+    let mut part_tracker = PartTracker::new();
+    let mut long_columns = LongColumns::default();
+    let mut wide_columns = WideColumns::default();
+
+    let mut add_part = |part: Part, pmr_dict: &BTreeMap<u16, PMR>| -> Result<(), StdfUtilError> {
+        let rows = part_rows(&part, pmr_dict)?;
+
+        match layout {
+            DataFrameLayout::Long => for row in rows {
+                long_columns.push(&part.part_id, part.site_num, row);
+            },
+            DataFrameLayout::Wide => wide_columns.push(&part, rows),
+        }
+
+        Ok(())
+    };
+
+    // open stdf file and start reading
+    let parser = StdfParser::new(stdf_path, dtr_cfg_file)?;
+
+    for parsed_rec in parser {
+        let parsed_rec = parsed_rec?;
+
+        if let StdfRecord::PMR(rec) = &parsed_rec.record {
+            pmr_dict.insert(rec.pmr_indx, rec.to_owned());
+        }
+
+        if let Some(part) = part_tracker.process(&parsed_rec) {
+            add_part(part, &pmr_dict)?;
+        }
+    }
+
+    // parts without a PRR still have their results, just without a part id
+    for part in part_tracker.finish() {
+        add_part(part, &pmr_dict)?;
+    }
+
+    let df = match layout {
+        DataFrameLayout::Long => long_columns.into_dataframe()?,
+        DataFrameLayout::Wide => wide_columns.into_dataframe()?,
+    };
+
+    Ok(df)
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: Writes the DataFrame from stdf_to_dataframe to a parquet file
+//////////////////////////////////////////////////////////////////////
+pub fn convert_stdf2parquet(stdf_path: &String, parquet_path: &String, dtr_cfg_file: &Option<String>, layout: DataFrameLayout) -> Result<(), StdfUtilError> {
+    let mut df = stdf_to_dataframe(stdf_path, dtr_cfg_file, layout)?;
+
+    let parquet_file = std::fs::File::create(parquet_path).map_err(StdfUtilError::io(parquet_path))?;
+    ParquetWriter::new(parquet_file).finish(&mut df)?;
+
+    Ok(())
+}
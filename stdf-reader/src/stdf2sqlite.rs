@@ -0,0 +1,232 @@
+use rusqlite::{params, Connection};
+use rust_stdf::*;
+
+use crate::{ParsedRecord, PartTracker, StdfParser, StdfUtilError};
+
+// Normalized schema written by convert_stdf2sqlite.
+//  - file_info holds the FAR/MIR/MRR information, one row per converted file
+//  - parts holds one row per PIR/PRR pair, results reference it thru part_idx,
+//    parts that never got their PRR (cut short by the next PIR on their site or
+//    still open at the end of the file) have finished 0 and no PRR information
+//  - tests holds the first occurrence (defaults) of every test number of every record type,
+//    results reference it thru (rec_type, test_num) as a PTR, MPR and FTR may share a test number
+//  - rec_idx is the record position in the STDF, used to order results and DTR's
+const SQLITE_SCHEMA: &str = "
+CREATE TABLE file_info (
+    file_path TEXT, cpu_type INTEGER, stdf_ver INTEGER,
+    setup_t INTEGER, start_t INTEGER, finish_t INTEGER, stat_num INTEGER,
+    mode_cod TEXT, rtst_cod TEXT, prot_cod TEXT, burn_tim INTEGER, cmod_cod TEXT,
+    lot_id TEXT, part_typ TEXT, node_nam TEXT, tstr_typ TEXT, job_nam TEXT, job_rev TEXT,
+    sblot_id TEXT, oper_nam TEXT, exec_typ TEXT, exec_ver TEXT, test_cod TEXT, tst_temp TEXT,
+    user_txt TEXT, aux_file TEXT, pkg_typ TEXT, famly_id TEXT, date_cod TEXT, facil_id TEXT,
+    floor_id TEXT, proc_id TEXT, oper_frq TEXT, spec_nam TEXT, spec_ver TEXT, flow_id TEXT,
+    setup_id TEXT, dsgn_rev TEXT, eng_id TEXT, rom_cod TEXT, serl_num TEXT, supr_nam TEXT,
+    disp_cod TEXT, usr_desc TEXT, exc_desc TEXT
+);
+CREATE TABLE parts (
+    part_idx INTEGER PRIMARY KEY, head_num INTEGER, site_num INTEGER,
+    part_id TEXT, part_txt TEXT, part_flg INTEGER, x_coord INTEGER, y_coord INTEGER,
+    hard_bin INTEGER, soft_bin INTEGER, num_test INTEGER, test_t INTEGER, finished INTEGER
+);
+CREATE TABLE tests (
+    test_num INTEGER, rec_type TEXT, test_txt TEXT, units TEXT,
+    res_scal INTEGER, llm_scal INTEGER, hlm_scal INTEGER, lo_limit REAL, hi_limit REAL,
+    lo_spec REAL, hi_spec REAL, c_resfmt TEXT, c_llmfmt TEXT, c_hlmfmt TEXT,
+    PRIMARY KEY (rec_type, test_num)
+);
+CREATE TABLE ptr_results (
+    rec_idx INTEGER, part_idx INTEGER REFERENCES parts(part_idx), rec_type TEXT, test_num INTEGER,
+    head_num INTEGER, site_num INTEGER, test_flg INTEGER, parm_flg INTEGER,
+    result REAL, lo_limit REAL, hi_limit REAL,
+    FOREIGN KEY (rec_type, test_num) REFERENCES tests(rec_type, test_num)
+);
+CREATE TABLE mpr_results (
+    rec_idx INTEGER, part_idx INTEGER REFERENCES parts(part_idx), rec_type TEXT, test_num INTEGER,
+    head_num INTEGER, site_num INTEGER, test_flg INTEGER, parm_flg INTEGER,
+    pin_idx INTEGER, pmr_indx INTEGER, rtn_stat INTEGER, result REAL,
+    FOREIGN KEY (rec_type, test_num) REFERENCES tests(rec_type, test_num)
+);
+CREATE TABLE ftr_results (
+    rec_idx INTEGER, part_idx INTEGER REFERENCES parts(part_idx), rec_type TEXT, test_num INTEGER,
+    head_num INTEGER, site_num INTEGER, test_flg INTEGER, cycl_cnt INTEGER, rel_vadr INTEGER,
+    rept_cnt INTEGER, num_fail INTEGER, vect_nam TEXT, time_set TEXT, op_code TEXT,
+    FOREIGN KEY (rec_type, test_num) REFERENCES tests(rec_type, test_num)
+);
+CREATE TABLE pins (
+    pmr_indx INTEGER, head_num INTEGER, site_num INTEGER, chan_typ INTEGER,
+    chan_nam TEXT, phy_nam TEXT, log_nam TEXT,
+    PRIMARY KEY (pmr_indx, head_num, site_num)
+);
+CREATE TABLE hard_bins (
+    head_num INTEGER, site_num INTEGER, bin_num INTEGER, bin_cnt INTEGER, bin_pf TEXT, bin_nam TEXT
+);
+CREATE TABLE soft_bins (
+    head_num INTEGER, site_num INTEGER, bin_num INTEGER, bin_cnt INTEGER, bin_pf TEXT, bin_nam TEXT
+);
+CREATE TABLE dtrs (
+    rec_idx INTEGER, uuid TEXT, dtr_id TEXT, text TEXT, text_dat TEXT
+);
+";
+
+// indexes are created after the data is inserted, it's faster than maintaining them on every insert
+const SQLITE_INDEXES: &str = "
+CREATE INDEX ptr_results_test_num ON ptr_results(rec_type, test_num);
+CREATE INDEX ptr_results_part_idx ON ptr_results(part_idx);
+CREATE INDEX mpr_results_test_num ON mpr_results(rec_type, test_num);
+CREATE INDEX mpr_results_part_idx ON mpr_results(part_idx);
+CREATE INDEX ftr_results_test_num ON ftr_results(rec_type, test_num);
+CREATE INDEX ftr_results_part_idx ON ftr_results(part_idx);
+";
+
+//////////////////////////////////////////////////////////////////////
+/// Description: Converts an STDF into a normalized SQLite database, test defaults
+///              are applied to PTR/MPR/FTR records before they are inserted
+//////////////////////////////////////////////////////////////////////
+pub fn convert_stdf2sqlite(stdf_path: &String, sqlite_path: &String, dtr_cfg_file: &Option<String>) -> Result<(), StdfUtilError> {
+    // numbers the parts the way PartIterator does, part_idx is the parts primary key
+    let mut part_tracker = PartTracker::tracking_only();
+    let mut last_part_idx = 0;
+
+    // open stdf file and start reading
+    let parser = StdfParser::new(stdf_path, dtr_cfg_file)?;
+
+    // the database is always created from scratch
+    if std::path::Path::new(sqlite_path).exists() {
+        std::fs::remove_file(sqlite_path).map_err(StdfUtilError::io(sqlite_path))?;
+    }
+    let mut conn = Connection::open(sqlite_path)?;
+    conn.execute_batch(SQLITE_SCHEMA)?;
+
+    // everything goes in a single transaction, otherwise sqlite syncs on every insert
+    let tx = conn.transaction()?;
+    {
+        tx.execute("INSERT INTO file_info (file_path) VALUES (?1)", params![stdf_path])?;
+
+        let mut insert_part = tx.prepare("INSERT INTO parts (part_idx, head_num, site_num, finished) VALUES (?1, ?2, ?3, 0)")?;
+        let mut update_part = tx.prepare("UPDATE parts SET part_id = ?2, part_txt = ?3, part_flg = ?4, x_coord = ?5, y_coord = ?6, \
+                                              hard_bin = ?7, soft_bin = ?8, num_test = ?9, test_t = ?10, finished = 1 WHERE part_idx = ?1")?;
+        let mut insert_test = tx.prepare("INSERT OR IGNORE INTO tests (test_num, rec_type, test_txt, units, res_scal, llm_scal, hlm_scal, \
+                                              lo_limit, hi_limit, lo_spec, hi_spec, c_resfmt, c_llmfmt, c_hlmfmt) \
+                                              VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)")?;
+        let mut insert_ptr = tx.prepare("INSERT INTO ptr_results VALUES (?1, ?2, 'PTR', ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)")?;
+        let mut insert_mpr = tx.prepare("INSERT INTO mpr_results VALUES (?1, ?2, 'MPR', ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)")?;
+        let mut insert_ftr = tx.prepare("INSERT INTO ftr_results VALUES (?1, ?2, 'FTR', ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)")?;
+        let mut insert_pin = tx.prepare("INSERT OR REPLACE INTO pins VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)")?;
+        let mut insert_hbin = tx.prepare("INSERT INTO hard_bins VALUES (?1, ?2, ?3, ?4, ?5, ?6)")?;
+        let mut insert_sbin = tx.prepare("INSERT INTO soft_bins VALUES (?1, ?2, ?3, ?4, ?5, ?6)")?;
+        let mut insert_dtr = tx.prepare("INSERT INTO dtrs VALUES (?1, ?2, ?3, ?4, ?5)")?;
+
+        for (rec_idx, parsed_rec) in parser.enumerate() {
+            let parsed_rec = parsed_rec?;
+            let rec_idx = rec_idx as i64;
+
+            // the part a PIR cuts short keeps its unfinished row, results and PRR's
+            // without a PIR get a part from the tracker like they do everywhere else
+            let part = match part_tracker.process(&parsed_rec) {
+                Some(part) if part.finished => Some((part.part_idx, part.head_num, part.site_num)),
+                _ => match &parsed_rec.record {
+                    StdfRecord::PIR(rec) => part_tracker.part_idx(rec.head_num, rec.site_num).map(|part_idx| (part_idx, rec.head_num, rec.site_num)),
+                    StdfRecord::PTR(rec) => part_tracker.part_idx(rec.head_num, rec.site_num).map(|part_idx| (part_idx, rec.head_num, rec.site_num)),
+                    StdfRecord::MPR(rec) => part_tracker.part_idx(rec.head_num, rec.site_num).map(|part_idx| (part_idx, rec.head_num, rec.site_num)),
+                    StdfRecord::FTR(rec) => part_tracker.part_idx(rec.head_num, rec.site_num).map(|part_idx| (part_idx, rec.head_num, rec.site_num)),
+                    _ => None,
+                },
+            };
+
+            // the row is added the first time the part shows up
+            if let Some((part_idx, head_num, site_num)) = part.filter(|(part_idx, ..)| *part_idx > last_part_idx) {
+                insert_part.execute(params![part_idx, head_num, site_num])?;
+                last_part_idx = part_idx;
+            }
+            let part_idx = part.map(|(part_idx, ..)| part_idx);
+
+            let ParsedRecord { record, dtr_info, .. } = parsed_rec;
+
+            match record {
+                // File information
+                StdfRecord::FAR(rec) => {
+                    tx.execute("UPDATE file_info SET cpu_type = ?1, stdf_ver = ?2", params![rec.cpu_type, rec.stdf_ver])?;
+                },
+
+                StdfRecord::MIR(rec) => {
+                    tx.execute("UPDATE file_info SET setup_t = ?1, start_t = ?2, stat_num = ?3, mode_cod = ?4, rtst_cod = ?5, prot_cod = ?6, \
+                                    burn_tim = ?7, cmod_cod = ?8, lot_id = ?9, part_typ = ?10, node_nam = ?11, tstr_typ = ?12, job_nam = ?13, \
+                                    job_rev = ?14, sblot_id = ?15, oper_nam = ?16, exec_typ = ?17, exec_ver = ?18, test_cod = ?19, tst_temp = ?20, \
+                                    user_txt = ?21, aux_file = ?22, pkg_typ = ?23, famly_id = ?24, date_cod = ?25, facil_id = ?26, floor_id = ?27, \
+                                    proc_id = ?28, oper_frq = ?29, spec_nam = ?30, spec_ver = ?31, flow_id = ?32, setup_id = ?33, dsgn_rev = ?34, \
+                                    eng_id = ?35, rom_cod = ?36, serl_num = ?37, supr_nam = ?38",
+                               params![rec.setup_t, rec.start_t, rec.stat_num, rec.mode_cod.to_string(), rec.rtst_cod.to_string(),
+                                       rec.prot_cod.to_string(), rec.burn_tim, rec.cmod_cod.to_string(), rec.lot_id, rec.part_typ,
+                                       rec.node_nam, rec.tstr_typ, rec.job_nam, rec.job_rev, rec.sblot_id, rec.oper_nam, rec.exec_typ,
+                                       rec.exec_ver, rec.test_cod, rec.tst_temp, rec.user_txt, rec.aux_file, rec.pkg_typ, rec.famly_id,
+                                       rec.date_cod, rec.facil_id, rec.floor_id, rec.proc_id, rec.oper_frq, rec.spec_nam, rec.spec_ver,
+                                       rec.flow_id, rec.setup_id, rec.dsgn_rev, rec.eng_id, rec.rom_cod, rec.serl_num, rec.supr_nam])?;
+                },
+
+                StdfRecord::MRR(rec) => {
+                    tx.execute("UPDATE file_info SET finish_t = ?1, disp_cod = ?2, usr_desc = ?3, exc_desc = ?4",
+                               params![rec.finish_t, rec.disp_cod.to_string(), rec.usr_desc, rec.exc_desc])?;
+                },
+
+                // Pin and bin information
+                StdfRecord::PMR(rec) => {
+                    insert_pin.execute(params![rec.pmr_indx, rec.head_num, rec.site_num, rec.chan_typ, rec.chan_nam, rec.phy_nam, rec.log_nam])?;
+                },
+
+                StdfRecord::HBR(rec) => {
+                    insert_hbin.execute(params![rec.head_num, rec.site_num, rec.hbin_num, rec.hbin_cnt, rec.hbin_pf.to_string(), rec.hbin_nam])?;
+                },
+
+                StdfRecord::SBR(rec) => {
+                    insert_sbin.execute(params![rec.head_num, rec.site_num, rec.sbin_num, rec.sbin_cnt, rec.sbin_pf.to_string(), rec.sbin_nam])?;
+                },
+
+                // Informational Record
+                StdfRecord::DTR(rec) => for dtr_info in dtr_info {
+                    insert_dtr.execute(params![rec_idx, dtr_info.uuid, dtr_info.id, dtr_info.text, rec.text_dat])?;
+                },
+
+                // Part Results, the PIR row was added above
+                StdfRecord::PRR(rec) => {
+                    update_part.execute(params![part_idx, rec.part_id, rec.part_txt, rec.part_flg[0], rec.x_coord, rec.y_coord,
+                                                rec.hard_bin, rec.soft_bin, rec.num_test, rec.test_t])?;
+                },
+
+                // Test Records
+                StdfRecord::PTR(rec) => {
+                    insert_test.execute(params![rec.test_num, "PTR", rec.test_txt, rec.units, rec.res_scal, rec.llm_scal, rec.hlm_scal,
+                                                rec.lo_limit, rec.hi_limit, rec.lo_spec, rec.hi_spec, rec.c_resfmt, rec.c_llmfmt, rec.c_hlmfmt])?;
+                    insert_ptr.execute(params![rec_idx, part_idx, rec.test_num, rec.head_num, rec.site_num, rec.test_flg[0], rec.parm_flg[0],
+                                               rec.result, rec.lo_limit, rec.hi_limit])?;
+                },
+
+                StdfRecord::MPR(rec) => {
+                    let rtn_indx = rec.rtn_indx.clone().unwrap_or_default();
+
+                    insert_test.execute(params![rec.test_num, "MPR", rec.test_txt, rec.units, rec.res_scal, rec.llm_scal, rec.hlm_scal,
+                                                rec.lo_limit, rec.hi_limit, rec.lo_spec, rec.hi_spec, rec.c_resfmt, rec.c_llmfmt, rec.c_hlmfmt])?;
+                    for (pin_idx, result) in rec.rtn_rslt.iter().enumerate() {
+                        insert_mpr.execute(params![rec_idx, part_idx, rec.test_num, rec.head_num, rec.site_num, rec.test_flg[0], rec.parm_flg[0],
+                                                   pin_idx as i64, rtn_indx.get(pin_idx), rec.rtn_stat.get(pin_idx), result])?;
+                    }
+                },
+
+                StdfRecord::FTR(rec) => {
+                    insert_test.execute(params![rec.test_num, "FTR", rec.test_txt, None::<String>, None::<i8>, None::<i8>, None::<i8>,
+                                                None::<f32>, None::<f32>, None::<f32>, None::<f32>, None::<String>, None::<String>, None::<String>])?;
+                    insert_ftr.execute(params![rec_idx, part_idx, rec.test_num, rec.head_num, rec.site_num, rec.test_flg[0], rec.cycl_cnt,
+                                               rec.rel_vadr, rec.rept_cnt, rec.num_fail, rec.vect_nam, rec.time_set, rec.op_code])?;
+                },
+
+                _rec => {
+                    // remaining records aren't part of the schema
+                }
+            }
+        }
+    }
+    tx.commit()?;
+
+    conn.execute_batch(SQLITE_INDEXES)?;
+
+    Ok(())
+}
@@ -0,0 +1,435 @@
+use std::{collections::{BTreeMap, HashMap}, fs::File, io::{BufWriter, Write}};
+
+use rust_stdf::*;
+
+use crate::{BinInfo, Die, DieGrid, PartTracker, StdfParser, StdfUtilError, TestFlags, TestFormat, WaferConfig, WaferMap, WaferMaps, WaferSide};
+
+type Rgb = [u8; 3];
+
+// colors of the bins, passing bins are green, failing ones are picked by bin number
+const PASS_BIN_COLORS: [Rgb; 2] = [[0x2E, 0xA0, 0x43], [0x7C, 0xD6, 0x7F]];
+const FAIL_BIN_COLORS: [Rgb; 10] = [[0xD6, 0x27, 0x28], [0xFF, 0xD7, 0x00], [0xA0, 0x3C, 0xC8], [0x17, 0xBE, 0xCF], [0x1F, 0x55, 0xD4],
+    [0xFF, 0x7F, 0x0E], [0x8C, 0x56, 0x4B], [0xE3, 0x77, 0xC2], [0x7F, 0x7F, 0x7F], [0xBC, 0xBD, 0x22]];
+const BACKGROUND: Rgb = [0xFF, 0xFF, 0xFF];
+const NO_RESULT: Rgb = [0xC8, 0xC8, 0xC8];
+const FLAT: Rgb = [0x40, 0x40, 0x40];
+
+// width of the legend next to the SVG map
+const SVG_LEGEND_WIDTH: u32 = 260;
+
+/// File formats stdf2wafermap can write
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaferMapFormat {
+    Png,
+    Svg,
+    /// Bin numbers row by row, with a header and the bin legend
+    Ascii,
+    /// SEMI E142 style XML bin code map
+    E142,
+}
+
+/// Options of convert_stdf2wafermap
+#[derive(Debug, Clone, PartialEq)]
+pub struct WaferMapOptions {
+    pub formats: Vec<WaferMapFormat>,
+    /// Map the soft bins instead of the hard bins
+    pub soft_bins: bool,
+    /// PTR tests to write a heat map of their results for, PNG/SVG only
+    pub heat_map_tests: Vec<u32>,
+    /// Size of a die in pixels in the PNG/SVG
+    pub die_size: u32,
+}
+
+impl Default for WaferMapOptions {
+    fn default() -> Self {
+        Self {
+            formats: vec![WaferMapFormat::Png, WaferMapFormat::Svg, WaferMapFormat::Ascii, WaferMapFormat::E142],
+            soft_bins: false,
+            heat_map_tests: Vec::new(),
+            die_size: 8,
+        }
+    }
+}
+
+// colors of the grid rows top to bottom, None where no die was tested
+type ColorGrid = Vec<Vec<Option<Rgb>>>;
+
+// an entry of the legend of a map
+struct LegendEntry {
+    color: Rgb,
+    text: String,
+}
+
+fn side_name(side: Option<WaferSide>) -> &'static str {
+    match side {
+        Some(WaferSide::Up) => "up",
+        Some(WaferSide::Down) => "down",
+        Some(WaferSide::Left) => "left",
+        Some(WaferSide::Right) => "right",
+        None => "unknown",
+    }
+}
+
+// wafer ids go into file names, keep them to safe characters
+fn file_safe(text: &str) -> String {
+    text.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '.' { c } else { '_' }).collect()
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn bin_color(bin: u16, pass: Option<bool>) -> Rgb {
+    match pass {
+        Some(true) => PASS_BIN_COLORS[bin as usize % PASS_BIN_COLORS.len()],
+        _ => FAIL_BIN_COLORS[bin as usize % FAIL_BIN_COLORS.len()],
+    }
+}
+
+// blue for the lowest result over green to red for the highest
+fn heat_color(value: f64, min: f64, max: f64) -> Rgb {
+    let t = if max > min { ((value - min) / (max - min)).clamp(0.0, 1.0) } else { 0.5 };
+    let mix = |from: Rgb, to: Rgb, t: f64| -> Rgb {
+        [0, 1, 2].map(|i| (from[i] as f64 + (to[i] as f64 - from[i] as f64) * t).round() as u8)
+    };
+    if t < 0.5 { mix([0x1F, 0x55, 0xD4], [0x2E, 0xA0, 0x43], t * 2.0) } else { mix([0x2E, 0xA0, 0x43], [0xD6, 0x27, 0x28], (t - 0.5) * 2.0) }
+}
+
+fn color_grid(grid: &DieGrid, color: impl Fn(usize) -> Option<Rgb>) -> ColorGrid {
+    (0..grid.rows).map(|row| (0..grid.cols).map(|col| grid.die(col, row).and_then(&color)).collect()).collect()
+}
+
+// the map of the bins with how many dies ended up in each bin
+struct BinMap<'a> {
+    soft_bins: bool,
+    bins: &'a BTreeMap<u16, BinInfo>,
+}
+
+impl BinMap<'_> {
+    fn bin(&self, die: &Die) -> u16 {
+        if self.soft_bins { die.soft_bin } else { die.hard_bin }
+    }
+
+    // the HBR/SBR tells if a bin passes, otherwise the PRR of the die
+    fn pass(&self, die: &Die) -> Option<bool> {
+        self.bins.get(&self.bin(die)).and_then(|info| info.pass).or(die.passed)
+    }
+
+    fn color(&self, die: &Die) -> Rgb {
+        bin_color(self.bin(die), self.pass(die))
+    }
+
+    // (bin, pass, count) of the last test of every die
+    fn counts(&self, wafer: &WaferMap) -> BTreeMap<u16, (Option<bool>, u32)> {
+        let mut counts = BTreeMap::new();
+        for die in wafer.last_dies() {
+            counts.entry(self.bin(die)).or_insert((self.pass(die), 0)).1 += 1;
+        }
+        counts
+    }
+
+    fn name(&self, bin: u16) -> &str {
+        self.bins.get(&bin).map_or("", |info| info.name.as_str())
+    }
+
+    fn legend(&self, wafer: &WaferMap) -> Vec<LegendEntry> {
+        self.counts(wafer).into_iter()
+            .map(|(bin, (pass, count))| LegendEntry { color: bin_color(bin, pass), text: format!("bin {} {} ({})", bin, self.name(bin), count) })
+            .collect()
+    }
+}
+
+fn write_png(png_path: &String, colors: &ColorGrid, die_size: u32, flat: Option<WaferSide>) -> Result<(), StdfUtilError> {
+    // a die wide margin all around leaves room for the flat
+    let cols = colors.first().map_or(0, |row| row.len()) as u32;
+    let rows = colors.len() as u32;
+    let (width, height) = ((cols + 2) * die_size, (rows + 2) * die_size);
+    let mut pixels: Vec<Rgb> = vec![BACKGROUND; (width * height) as usize];
+    let mut fill = |x0: u32, y0: u32, w: u32, h: u32, color: Rgb| {
+        for y in y0..y0 + h {
+            pixels[(y * width + x0) as usize..(y * width + x0 + w) as usize].fill(color);
+        }
+    };
+
+    // dies get a one pixel gap once they're large enough
+    let gap = if die_size >= 4 { 1 } else { 0 };
+    for (row, row_colors) in colors.iter().enumerate() {
+        for (col, color) in row_colors.iter().enumerate() {
+            if let Some(color) = color {
+                fill((col as u32 + 1) * die_size, (row as u32 + 1) * die_size, die_size - gap, die_size - gap, *color);
+            }
+        }
+    }
+
+    match flat {
+        Some(WaferSide::Up) => fill(width / 3, die_size / 4, width / 3, die_size / 2, FLAT),
+        Some(WaferSide::Down) => fill(width / 3, height - die_size * 3 / 4, width / 3, die_size / 2, FLAT),
+        Some(WaferSide::Left) => fill(die_size / 4, height / 3, die_size / 2, height / 3, FLAT),
+        Some(WaferSide::Right) => fill(width - die_size * 3 / 4, height / 3, die_size / 2, height / 3, FLAT),
+        None => {},
+    }
+
+    let file = File::create(png_path).map_err(StdfUtilError::io(png_path))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(pixels.as_flattened())?;
+    writer.finish()?;
+    Ok(())
+}
+
+fn write_svg(svg_path: &String, colors: &ColorGrid, die_size: u32, flat: Option<WaferSide>, title: &str, legend: &[LegendEntry]) -> Result<(), StdfUtilError> {
+    let cols = colors.first().map_or(0, |row| row.len()) as u32;
+    let rows = colors.len() as u32;
+    let (map_width, map_height) = ((cols + 2) * die_size, (rows + 2) * die_size);
+    let width = map_width + SVG_LEGEND_WIDTH;
+    let height = (map_height + 24).max(40 + legend.len() as u32 * 16);
+    let rgb = |color: &Rgb| format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2]);
+
+    let file = File::create(svg_path).map_err(StdfUtilError::io(svg_path))?;
+    let mut svg = BufWriter::new(file);
+    let mut write_svg = || -> std::io::Result<()> {
+        writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" font-family="monospace" font-size="12">"#, width, height)?;
+        writeln!(svg, r#"<rect width="{}" height="{}" fill="{}"/>"#, width, height, rgb(&BACKGROUND))?;
+        writeln!(svg, r#"<text x="4" y="16">{}</text>"#, xml_escape(title))?;
+
+        // the map goes below the title
+        writeln!(svg, r#"<g transform="translate(0,24)">"#)?;
+        for (row, row_colors) in colors.iter().enumerate() {
+            for (col, color) in row_colors.iter().enumerate() {
+                if let Some(color) = color {
+                    writeln!(svg, r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}" stroke="white" stroke-width="0.5"/>"#,
+                        (col as u32 + 1) * die_size, (row as u32 + 1) * die_size, die_size, die_size, rgb(color))?;
+                }
+            }
+        }
+        let flat_rect = match flat {
+            Some(WaferSide::Up) => Some((map_width / 3, die_size / 4, map_width / 3, die_size / 2)),
+            Some(WaferSide::Down) => Some((map_width / 3, map_height - die_size * 3 / 4, map_width / 3, die_size / 2)),
+            Some(WaferSide::Left) => Some((die_size / 4, map_height / 3, die_size / 2, map_height / 3)),
+            Some(WaferSide::Right) => Some((map_width - die_size * 3 / 4, map_height / 3, die_size / 2, map_height / 3)),
+            None => None,
+        };
+        if let Some((x, y, w, h)) = flat_rect {
+            writeln!(svg, r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#, x, y, w.max(1), h.max(1), rgb(&FLAT))?;
+        }
+        writeln!(svg, "</g>")?;
+
+        for (idx, entry) in legend.iter().enumerate() {
+            let y = 40 + idx as u32 * 16;
+            writeln!(svg, r#"<rect x="{}" y="{}" width="12" height="12" fill="{}"/>"#, map_width + 8, y - 10, rgb(&entry.color))?;
+            writeln!(svg, r#"<text x="{}" y="{}">{}</text>"#, map_width + 26, y, xml_escape(&entry.text))?;
+        }
+        writeln!(svg, "</svg>")?;
+        svg.flush()
+    };
+    write_svg().map_err(StdfUtilError::io(svg_path))
+}
+
+// header lines of the ascii map
+fn ascii_header(wafer: &WaferMap, grid: &DieGrid, config: &WaferConfig, lot_id: &str, bin_type: &str) -> Vec<String> {
+    let (x_first, y_first) = grid.coords(0, 0);
+    let (x_last, y_last) = grid.coords(grid.cols.saturating_sub(1), grid.rows.saturating_sub(1));
+    vec![
+        format!("LOT: {}", lot_id),
+        format!("WAFER: {}", wafer.wafer_id),
+        format!("HEAD: {}", wafer.head_num),
+        format!("BIN TYPE: {}", bin_type),
+        format!("FLAT: {}", side_name(config.flat)),
+        format!("COLUMNS: {} (x {} to {})", grid.cols, x_first, x_last),
+        format!("ROWS: {} (y {} to {})", grid.rows, y_first, y_last),
+    ]
+}
+
+fn write_ascii(txt_path: &String, wafer: &WaferMap, grid: &DieGrid, config: &WaferConfig, lot_id: &str, bin_map: &BinMap) -> Result<(), StdfUtilError> {
+    let bin_type = if bin_map.soft_bins { "soft" } else { "hard" };
+    let counts = bin_map.counts(wafer);
+    let bin_width = counts.keys().map(|bin| bin.to_string().len()).max().unwrap_or(1);
+
+    let file = File::create(txt_path).map_err(StdfUtilError::io(txt_path))?;
+    let mut txt = BufWriter::new(file);
+    let mut write_txt = || -> std::io::Result<()> {
+        for line in ascii_header(wafer, grid, config, lot_id, bin_type) {
+            writeln!(txt, "{}", line)?;
+        }
+        for (bin, (pass, count)) in &counts {
+            let pass = match pass { Some(true) => "P", Some(false) => "F", None => " " };
+            writeln!(txt, "BIN {:>width$} {} {:>6} {}", bin, pass, count, bin_map.name(*bin), width = bin_width)?;
+        }
+        writeln!(txt)?;
+
+        // dies without a test are dots
+        for row in 0..grid.rows {
+            let cells: Vec<String> = (0..grid.cols)
+                .map(|col| match grid.die(col, row) {
+                    Some(idx) => format!("{:>width$}", bin_map.bin(&wafer.dies[idx]), width = bin_width),
+                    None => ".".repeat(bin_width),
+                })
+                .collect();
+            writeln!(txt, "{}", cells.join(" "))?;
+        }
+        txt.flush()
+    };
+    write_txt().map_err(StdfUtilError::io(txt_path))
+}
+
+fn write_e142(xml_path: &String, wafer: &WaferMap, grid: &DieGrid, config: &WaferConfig, lot_id: &str, bin_map: &BinMap) -> Result<(), StdfUtilError> {
+    // rows are written top to bottom, the origin is the corner of the first x/y
+    let (origin, axis) = match (config.x_right, config.y_up) {
+        (true, true) => ("LowerLeft", "UpRight"),
+        (true, false) => ("UpperLeft", "DownRight"),
+        (false, true) => ("LowerRight", "UpLeft"),
+        (false, false) => ("UpperRight", "DownLeft"),
+    };
+    let orientation = match config.flat {
+        Some(WaferSide::Left) => 90,
+        Some(WaferSide::Up) => 180,
+        Some(WaferSide::Right) => 270,
+        _ => 0,
+    };
+    let (x_lower_left, y_lower_left) = grid.coords(0, grid.rows.saturating_sub(1));
+    let wafer_id = xml_escape(&wafer.wafer_id);
+
+    let file = File::create(xml_path).map_err(StdfUtilError::io(xml_path))?;
+    let mut xml = BufWriter::new(file);
+    let mut write_xml = || -> std::io::Result<()> {
+        writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(xml, r#"<MapData xmlns="urn:semi-org:xsd.E142-1.V1005.SEMI">"#)?;
+        writeln!(xml, r#"  <Layouts>"#)?;
+        writeln!(xml, r#"    <Layout LayoutId="WaferLayout" DefaultUnits="mm" TopLevel="true">"#)?;
+        writeln!(xml, r#"      <Dimension X="{}" Y="{}"/>"#, grid.cols, grid.rows)?;
+        writeln!(xml, r#"      <LowerLeft X="{}" Y="{}"/>"#, x_lower_left, y_lower_left)?;
+        if config.die_wid > 0.0 && config.die_ht > 0.0 {
+            writeln!(xml, r#"      <DeviceSize X="{}" Y="{}"/>"#, config.die_wid, config.die_ht)?;
+        }
+        writeln!(xml, r#"    </Layout>"#)?;
+        writeln!(xml, r#"  </Layouts>"#)?;
+        writeln!(xml, r#"  <Substrates>"#)?;
+        writeln!(xml, r#"    <Substrate SubstrateType="Wafer" SubstrateId="{}">"#, wafer_id)?;
+        writeln!(xml, r#"      <LotId>{}</LotId>"#, xml_escape(lot_id))?;
+        writeln!(xml, r#"    </Substrate>"#)?;
+        writeln!(xml, r#"  </Substrates>"#)?;
+        writeln!(xml, r#"  <SubstrateMaps>"#)?;
+        writeln!(xml, r#"    <SubstrateMap SubstrateType="Wafer" SubstrateId="{}" Orientation="{}" OriginLocation="{}" AxisDirection="{}" LayoutSpecifier="WaferLayout">"#,
+            wafer_id, orientation, origin, axis)?;
+        writeln!(xml, r#"      <Overlay MapName="{}BinCodeMap" MapVersion="1">"#, if bin_map.soft_bins { "Soft" } else { "Hard" })?;
+
+        // Integer2 bins are four hex digits, FFFF marks a position without a die
+        writeln!(xml, r#"        <BinCodeMap BinType="Integer2" NullBin="FFFF">"#)?;
+        writeln!(xml, r#"          <BinDefinitions>"#)?;
+        for (bin, (pass, count)) in bin_map.counts(wafer) {
+            let quality = match pass { Some(true) => "Pass", Some(false) => "Fail", None => "Unknown" };
+            writeln!(xml, r#"            <BinDefinition BinCode="{:04X}" BinCount="{}" BinQuality="{}" BinDescription="{}"/>"#, bin, count, quality, xml_escape(bin_map.name(bin)))?;
+        }
+        writeln!(xml, r#"          </BinDefinitions>"#)?;
+        for row in 0..grid.rows {
+            let bins: String = (0..grid.cols)
+                .map(|col| grid.die(col, row).map_or("FFFF".to_string(), |idx| format!("{:04X}", bin_map.bin(&wafer.dies[idx]))))
+                .collect();
+            writeln!(xml, r#"          <BinCode>{}</BinCode>"#, bins)?;
+        }
+        writeln!(xml, r#"        </BinCodeMap>"#)?;
+        writeln!(xml, r#"      </Overlay>"#)?;
+        writeln!(xml, r#"    </SubstrateMap>"#)?;
+        writeln!(xml, r#"  </SubstrateMaps>"#)?;
+        writeln!(xml, r#"</MapData>"#)?;
+        xml.flush()
+    };
+    write_xml().map_err(StdfUtilError::io(xml_path))
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: Writes the bin map of every wafer of an STDF, and the heat maps of
+///              the requested PTR tests, to files named out_prefix, wafer id and
+///              map type, returns the paths of the files written
+//////////////////////////////////////////////////////////////////////
+pub fn convert_stdf2wafermap(stdf_path: &String, out_prefix: &String, options: &WaferMapOptions) -> Result<Vec<String>, StdfUtilError> {
+    // the heat maps need the res_scal and units of the first PTR of their test
+    let parser = StdfParser::new(stdf_path, &None)?;
+
+    let mut tracker = PartTracker::new();
+    let mut wafer_maps = WaferMaps::new();
+    let mut lot_id = String::new();
+    // scaled results of the heat map tests by (part_idx, test_num), the last one of a test wins
+    let mut results: HashMap<(u32, u32), f64> = HashMap::new();
+    let mut test_formats: HashMap<u32, TestFormat> = HashMap::new();
+    for parsed_rec in parser {
+        let parsed_rec = parsed_rec?;
+        if let StdfRecord::MIR(rec) = &parsed_rec.record { lot_id = rec.lot_id.to_owned(); }
+        wafer_maps.add_record(&parsed_rec.record);
+
+        if let Some(part) = tracker.process(&parsed_rec) {
+            wafer_maps.add_part(&part);
+            for parsed_rec in &part.results {
+                if let StdfRecord::PTR(rec) = &parsed_rec.record {
+                    if options.heat_map_tests.contains(&rec.test_num) && TestFlags::from(rec.test_flg).is_valid() && rec.result.is_finite() {
+                        let test_format = test_formats.entry(rec.test_num).or_insert_with(|| TestFormat::from(rec));
+                        results.insert((part.part_idx, rec.test_num), test_format.scale(rec.result as f64));
+                    }
+                }
+            }
+        }
+    }
+    wafer_maps.wafers.retain(|wafer| !wafer.dies.is_empty());
+
+    let bin_map = BinMap { soft_bins: options.soft_bins, bins: if options.soft_bins { &wafer_maps.soft_bins } else { &wafer_maps.hard_bins } };
+    let bin_type = if options.soft_bins { "sbin" } else { "hbin" };
+    let config = &wafer_maps.config;
+    let mut written = Vec::new();
+    let mut used_names: HashMap<String, u32> = HashMap::new();
+
+    for (wafer_idx, wafer) in wafer_maps.wafers.iter().enumerate() {
+        // a wafer id can show up more than once, e.g. when a wafer is continued
+        let name = if wafer.wafer_id.is_empty() { format!("wafer{}", wafer_idx + 1) } else { file_safe(&wafer.wafer_id) };
+        let seen = used_names.entry(name.to_owned()).or_insert(0);
+        *seen += 1;
+        let name = if *seen > 1 { format!("{}_{}", name, seen) } else { name };
+        let path = |suffix: &str| format!("{}.{}.{}", out_prefix, name, suffix);
+        let grid = wafer.grid(config);
+
+        let colors = color_grid(&grid, |idx| Some(bin_map.color(&wafer.dies[idx])));
+        let title = format!("lot {} wafer {} {} bins", lot_id, wafer.wafer_id, if options.soft_bins { "soft" } else { "hard" });
+        for format in &options.formats {
+            let map_path = match format {
+                WaferMapFormat::Png => { let png_path = path(&format!("{}.png", bin_type)); write_png(&png_path, &colors, options.die_size, config.flat)?; png_path },
+                WaferMapFormat::Svg => { let svg_path = path(&format!("{}.svg", bin_type)); write_svg(&svg_path, &colors, options.die_size, config.flat, &title, &bin_map.legend(wafer))?; svg_path },
+                WaferMapFormat::Ascii => { let txt_path = path(&format!("{}.txt", bin_type)); write_ascii(&txt_path, wafer, &grid, config, &lot_id, &bin_map)?; txt_path },
+                WaferMapFormat::E142 => { let xml_path = path(&format!("{}.xml", bin_type)); write_e142(&xml_path, wafer, &grid, config, &lot_id, &bin_map)?; xml_path },
+            };
+            written.push(map_path);
+        }
+
+        for test_num in &options.heat_map_tests {
+            let die_result = |idx: usize| results.get(&(wafer.dies[idx].part_idx, *test_num)).copied();
+            let (min, max) = (0..wafer.dies.len()).filter_map(die_result)
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), result| (min.min(result), max.max(result)));
+            let colors = color_grid(&grid, |idx| Some(die_result(idx).map_or(NO_RESULT, |result| heat_color(result, min, max))));
+            let units = test_formats.get(test_num).map(|test_format| test_format.units()).unwrap_or_default();
+            let legend_text = |label: &str, value: f64| format!("{} {} {}", label, value as f32, units).trim_end().to_string();
+            let legend = if min <= max {
+                vec![
+                    LegendEntry { color: heat_color(max, min, max), text: legend_text("max", max) },
+                    LegendEntry { color: heat_color((min + max) / 2.0, min, max), text: legend_text("mid", (min + max) / 2.0) },
+                    LegendEntry { color: heat_color(min, min, max), text: legend_text("min", min) },
+                    LegendEntry { color: NO_RESULT, text: "no result".to_string() },
+                ]
+            } else {
+                vec![LegendEntry { color: NO_RESULT, text: "no result".to_string() }]
+            };
+
+            let title = format!("lot {} wafer {} test {}", lot_id, wafer.wafer_id, test_num);
+            for format in &options.formats {
+                let map_path = match format {
+                    WaferMapFormat::Png => { let png_path = path(&format!("test{}.png", test_num)); write_png(&png_path, &colors, options.die_size, config.flat)?; png_path },
+                    WaferMapFormat::Svg => { let svg_path = path(&format!("test{}.svg", test_num)); write_svg(&svg_path, &colors, options.die_size, config.flat, &title, &legend)?; svg_path },
+                    // bin codes only
+                    WaferMapFormat::Ascii | WaferMapFormat::E142 => continue,
+                };
+                written.push(map_path);
+            }
+        }
+    }
+
+    Ok(written)
+}
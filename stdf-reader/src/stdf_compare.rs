@@ -0,0 +1,221 @@
+use std::{collections::BTreeMap, fs::File, io::{BufWriter, Write}};
+
+use crate::{value_format::text_table, StdfParser, StdfUtilError, TestId, TestStatistics, TestStats};
+
+/// A test as it is matched between files, by test number and name, an MPR pin is a test of its own
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CompareKey {
+    pub test_id: TestId,
+    pub test_txt: String,
+}
+
+/// Statistics of a test in one file, all heads and sites together
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FileTestStats {
+    pub units: String,
+    pub lo_limit: Option<f64>,
+    pub hi_limit: Option<f64>,
+    pub stats: TestStats,
+}
+
+/// How a test of a file moved from the reference file, the first one
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TestShift {
+    /// Mean of the file minus mean of the reference
+    pub mean_shift: Option<f64>,
+    /// mean_shift in stdevs of the reference
+    pub mean_shift_sigma: Option<f64>,
+    /// Stdev of the file over stdev of the reference
+    pub stdev_ratio: Option<f64>,
+    /// Two sample Kolmogorov-Smirnov statistic of the results
+    pub ks: Option<f64>,
+    /// Yield of the file minus yield of the reference, in percent
+    pub yield_delta: Option<f64>,
+    pub limits_changed: bool,
+}
+
+/// A test with its statistics in each file, None for the files that don't have it
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestComparison {
+    pub key: CompareKey,
+    pub files: Vec<Option<FileTestStats>>,
+}
+
+impl TestComparison {
+    //////////////////////////////////////////////////////////////////////
+    /// Description: Shift of the test in a file from the reference file, None when
+    ///              either file doesn't have the test
+    //////////////////////////////////////////////////////////////////////
+    pub fn shift(&self, file_idx: usize) -> Option<TestShift> {
+        let reference = self.files.first()?.as_ref()?;
+        let file = self.files.get(file_idx)?.as_ref()?;
+
+        let mean_shift = file.stats.mean().zip(reference.stats.mean()).map(|(mean, ref_mean)| mean - ref_mean);
+        let ref_stdev = reference.stats.stdev().filter(|stdev| *stdev > 0.0);
+        Some(TestShift {
+            mean_shift,
+            mean_shift_sigma: mean_shift.zip(ref_stdev).map(|(shift, stdev)| shift / stdev),
+            stdev_ratio: file.stats.stdev().zip(ref_stdev).map(|(stdev, ref_stdev)| stdev / ref_stdev),
+            ks: file.stats.ks_statistic(&reference.stats),
+            yield_delta: file.stats.yield_pct().zip(reference.stats.yield_pct()).map(|(yld, ref_yld)| yld - ref_yld),
+            limits_changed: file.lo_limit != reference.lo_limit || file.hi_limit != reference.hi_limit,
+        })
+    }
+
+    /// true when some of the files don't have the test
+    pub fn missing_in_some(&self) -> bool {
+        self.files.iter().any(Option::is_none)
+    }
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: Tests of several STDFs side by side, every file is compared
+///              with the first one
+//////////////////////////////////////////////////////////////////////
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct StdfComparison {
+    pub stdf_paths: Vec<String>,
+    /// Tests in test number order
+    pub tests: Vec<TestComparison>,
+}
+
+// statistics of the PTR/MPR tests of an STDF by the key they are matched with
+fn read_test_stats(stdf_path: &String) -> Result<BTreeMap<CompareKey, FileTestStats>, StdfUtilError> {
+    // later records of a test may leave out its name and limits, the parser fills them in
+    let parser = StdfParser::new(stdf_path, &None)?;
+    let mut statistics = TestStatistics::new();
+    for parsed_rec in parser {
+        statistics.add_record(&parsed_rec?.record);
+    }
+
+    Ok(statistics.tests().map(|(test_id, summary)| {
+        let key = CompareKey { test_id: *test_id, test_txt: summary.test_txt.to_owned() };
+        (key, FileTestStats { units: summary.units.to_owned(), lo_limit: summary.lo_limit, hi_limit: summary.hi_limit, stats: summary.total() })
+    }).collect())
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: Reads the PTR/MPR statistics of the STDFs and matches their tests
+///              by test number and name
+//////////////////////////////////////////////////////////////////////
+pub fn compare_stdf(stdf_paths: &[String]) -> Result<StdfComparison, StdfUtilError> {
+    let mut tests: BTreeMap<CompareKey, Vec<Option<FileTestStats>>> = BTreeMap::new();
+    for (file_idx, stdf_path) in stdf_paths.iter().enumerate() {
+        for (key, file_stats) in read_test_stats(stdf_path)? {
+            tests.entry(key).or_insert_with(|| vec![None; stdf_paths.len()])[file_idx] = Some(file_stats);
+        }
+    }
+
+    Ok(StdfComparison {
+        stdf_paths: stdf_paths.to_vec(),
+        tests: tests.into_iter().map(|(key, files)| TestComparison { key, files }).collect(),
+    })
+}
+
+// the results are f32's, more digits would only show rounding noise
+fn csv_value(value: Option<f64>) -> String {
+    value.map(|value| (value as f32).to_string()).unwrap_or_default()
+}
+
+// value of the text table, very small or large values in scientific notation
+fn text_value(value: Option<f64>) -> String {
+    match value {
+        Some(value) if value != 0.0 && (value.abs() < 1e-3 || value.abs() >= 1e6) => format!("{:.3e}", value),
+        Some(value) => format!("{:.4}", value),
+        None => "".to_string(),
+    }
+}
+
+// limits of a file that differ from the reference ones, e.g. "hi 1.5 -> 1.6"
+fn limit_changes(reference: &FileTestStats, file: &FileTestStats) -> String {
+    let limit = |limit: Option<f64>| limit.map(|limit| (limit as f32).to_string()).unwrap_or("none".to_string());
+    let mut changes = Vec::new();
+    if file.lo_limit != reference.lo_limit { changes.push(format!("lo {} -> {}", limit(reference.lo_limit), limit(file.lo_limit))); }
+    if file.hi_limit != reference.hi_limit { changes.push(format!("hi {} -> {}", limit(reference.hi_limit), limit(file.hi_limit))); }
+    changes.join(", ")
+}
+
+impl StdfComparison {
+    /// Label of a file in the reports, [1] is the reference
+    pub fn file_tag(file_idx: usize) -> String {
+        format!("[{}]", file_idx + 1)
+    }
+
+    //////////////////////////////////////////////////////////////////////
+    /// Description: Writes a row per test and file, the shift columns of the
+    ///              reference and of files without the test are empty
+    //////////////////////////////////////////////////////////////////////
+    pub fn write_csv(&self, csv_path: &str) -> Result<(), StdfUtilError> {
+        let mut csv_file = BufWriter::new(File::create(csv_path).map_err(StdfUtilError::io(csv_path))?);
+        writeln!(csv_file, "\"Test Number\",\"Test Name\",\"Units\",\"File\",\"Present\",\"Lo Limit\",\"Hi Limit\",\"Count\",\"Mean\",\"Stdev\",\"Yield\",\"Mean Shift\",\"Mean Shift Sigma\",\"Stdev Ratio\",\"KS\",\"Yield Delta\",\"Limits Changed\"")
+            .map_err(StdfUtilError::io(csv_path))?;
+
+        for test in &self.tests {
+            let test_txt = test.key.test_txt.replace('"', "\"\"");
+            let units = test.files.iter().flatten().map(|file| file.units.as_str()).find(|units| !units.is_empty()).unwrap_or_default().replace('"', "\"\"");
+
+            for (file_idx, file) in test.files.iter().enumerate() {
+                let stdf_path = self.stdf_paths[file_idx].replace('"', "\"\"");
+                let Some(file) = file else {
+                    writeln!(csv_file, "\"{}\",\"{}\",\"{}\",\"{}\",\"false\",\"\",\"\",\"\",\"\",\"\",\"\",\"\",\"\",\"\",\"\",\"\",\"\"", test.key.test_id, test_txt, units, stdf_path)
+                        .map_err(StdfUtilError::io(csv_path))?;
+                    continue;
+                };
+
+                let shift = if file_idx > 0 { test.shift(file_idx) } else { None };
+                let shift_value = |value: fn(&TestShift) -> Option<f64>| csv_value(shift.as_ref().and_then(value));
+                writeln!(csv_file, "\"{}\",\"{}\",\"{}\",\"{}\",\"true\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\"",
+                    test.key.test_id, test_txt, units, stdf_path, csv_value(file.lo_limit), csv_value(file.hi_limit),
+                    file.stats.count, csv_value(file.stats.mean()), csv_value(file.stats.stdev()), csv_value(file.stats.yield_pct()),
+                    shift_value(|shift| shift.mean_shift), shift_value(|shift| shift.mean_shift_sigma), shift_value(|shift| shift.stdev_ratio),
+                    shift_value(|shift| shift.ks), shift_value(|shift| shift.yield_delta),
+                    shift.as_ref().map(|shift| shift.limits_changed.to_string()).unwrap_or_default())
+                    .map_err(StdfUtilError::io(csv_path))?;
+            }
+        }
+
+        csv_file.flush().map_err(StdfUtilError::io(csv_path))
+    }
+
+    //////////////////////////////////////////////////////////////////////
+    /// Description: Table of the tests with a line per file, the files are listed
+    ///              by their tag first, tests missing in a file are called out
+    //////////////////////////////////////////////////////////////////////
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for (file_idx, stdf_path) in self.stdf_paths.iter().enumerate() {
+            let role = if file_idx == 0 { " (reference)" } else { "" };
+            text += &format!("{} {}{}\n", Self::file_tag(file_idx), stdf_path, role);
+        }
+        text += "\n";
+
+        let mut rows: Vec<Vec<String>> = vec![["Test", "Name", "File", "Count", "Mean", "Stdev", "Yield", "Shift", "Shift/Sigma", "KS", "Yield Delta", "Limits"].map(String::from).to_vec()];
+        for test in &self.tests {
+            for (file_idx, file) in test.files.iter().enumerate() {
+                // the test is named on its first line only
+                let mut row = if file_idx == 0 { vec![test.key.test_id.to_string(), test.key.test_txt.to_owned()] } else { vec!["".to_string(), "".to_string()] };
+                row.push(Self::file_tag(file_idx));
+
+                match (file, test.files[0].as_ref()) {
+                    (None, _) => row.push("missing".to_string()),
+                    (Some(file), reference) => {
+                        row.extend([file.stats.count.to_string(), text_value(file.stats.mean()), text_value(file.stats.stdev()),
+                            file.stats.yield_pct().map(|yld| format!("{:.2}%", yld)).unwrap_or_default()]);
+                        if let (Some(shift), Some(reference)) = (test.shift(file_idx).filter(|_| file_idx > 0), reference) {
+                            row.extend([text_value(shift.mean_shift), shift.mean_shift_sigma.map(|sigma| format!("{:.2}", sigma)).unwrap_or_default(),
+                                shift.ks.map(|ks| format!("{:.3}", ks)).unwrap_or_default(),
+                                shift.yield_delta.map(|delta| format!("{:+.2}%", delta)).unwrap_or_default(), limit_changes(reference, file)]);
+                        }
+                    },
+                }
+                rows.push(row);
+            }
+        }
+
+        text += &text_table(&rows);
+
+        let missing = self.tests.iter().filter(|test| test.missing_in_some()).count();
+        text += &format!("\n{} tests, {} missing in some of the files\n", self.tests.len(), missing);
+        text
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+use rust_stdf::*;
+
+use crate::{summary_counts::{result_test, OpenParts, SummaryCounts, TestDefaults}, StdfParser, StdfUtilError, StdfWriter};
+
+//////////////////////////////////////////////////////////////////////
+/// Description: Selects the parts and tests kept by filter_stdf, a criteria
+///              left empty selects everything
+//////////////////////////////////////////////////////////////////////
+#[derive(Debug, Default, Clone)]
+pub struct StdfFilter {
+    pub part_id: Option<Regex>,
+    pub hard_bins: Vec<u16>,
+    pub soft_bins: Vec<u16>,
+    pub sites: Vec<u8>,
+    /// Inclusive wafer region, (min, max)
+    pub x_range: Option<(i16, i16)>,
+    pub y_range: Option<(i16, i16)>,
+
+    /// Inclusive test number ranges, (first, last)
+    pub test_nums: Vec<(u32, u32)>,
+    pub test_txt: Option<Regex>,
+}
+
+impl StdfFilter {
+    /// true if any of the part criteria is used
+    pub fn filters_parts(&self) -> bool {
+        self.part_id.is_some() || !self.hard_bins.is_empty() || !self.soft_bins.is_empty() || !self.sites.is_empty() || self.x_range.is_some() || self.y_range.is_some()
+    }
+
+    /// true if any of the test criteria is used
+    pub fn filters_tests(&self) -> bool {
+        !self.test_nums.is_empty() || self.test_txt.is_some()
+    }
+
+    pub fn part_selected(&self, rec: &PRR) -> bool {
+        let in_range = |range: Option<(i16, i16)>, coord: i16| range.is_none_or(|(min, max)| min <= coord && coord <= max);
+
+        self.part_id.as_ref().is_none_or(|part_id| part_id.is_match(&rec.part_id))
+            && (self.hard_bins.is_empty() || self.hard_bins.contains(&rec.hard_bin))
+            && (self.soft_bins.is_empty() || self.soft_bins.contains(&rec.soft_bin))
+            && (self.sites.is_empty() || self.sites.contains(&rec.site_num))
+            && in_range(self.x_range, rec.x_coord)
+            && in_range(self.y_range, rec.y_coord)
+    }
+
+    pub fn test_selected(&self, test_num: u32, test_txt: &str) -> bool {
+        (self.test_nums.is_empty() || self.test_nums.iter().any(|(first, last)| *first <= test_num && test_num <= *last))
+            && self.test_txt.as_ref().is_none_or(|regex| regex.is_match(test_txt))
+    }
+}
+
+/// Number of parts seen and kept by filter_stdf
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct FilterCounts {
+    pub parts_read: u32,
+    pub parts_kept: u32,
+}
+
+// state of a running filter_stdf
+struct StdfFilterWriter<'a> {
+    filter: &'a StdfFilter,
+    writer: StdfWriter,
+    counts: FilterCounts,
+    stats: SummaryCounts,
+
+    open_parts: OpenParts,
+    // summary records are written once all parts are known
+    summary_recs: Vec<StdfRecord>,
+
+    // tests are selected by the text of their first record, later ones may leave it out
+    test_selection: HashMap<u32, bool>,
+    test_defaults: TestDefaults,
+}
+
+impl StdfFilterWriter<'_> {
+    fn test_selected(&mut self, test_num: u32, test_txt: &str) -> bool {
+        let filter = self.filter;
+        *self.test_selection.entry(test_num).or_insert_with(|| filter.test_selected(test_num, test_txt))
+    }
+
+    fn write_part(&mut self, records: Vec<StdfRecord>, prr: Option<PRR>) -> Result<(), StdfUtilError> {
+        let mut num_test = 0;
+
+        for mut rec in records {
+            // the first PTR/MPR of a test written has to carry its defaults, the original one may have been dropped
+            self.test_defaults.fill(&mut rec);
+            if result_test(&rec).is_some() {
+                num_test += 1;
+                self.stats.add_test(&rec);
+            }
+
+            self.writer.write_record(&rec)?;
+        }
+
+        if let Some(mut prr) = prr {
+            // the test count has to match what is left of the part
+            if self.filter.filters_tests() { prr.num_test = num_test; }
+
+            self.stats.add_part(&prr);
+            self.counts.parts_kept += 1;
+            self.writer.write_record(&StdfRecord::PRR(prr))?;
+        }
+
+        Ok(())
+    }
+
+    fn process(&mut self, rec: StdfRecord) -> Result<(), StdfUtilError> {
+        match rec {
+            StdfRecord::PIR(pir) => {
+                // a part that never got its PRR can only be judged when no part criteria is used
+                let key = (pir.head_num, pir.site_num);
+                let kept = !self.filter.filters_parts();
+                if let Some(records) = self.open_parts.close(key, kept) {
+                    if kept { self.write_part(records, None)?; }
+                }
+                self.open_parts.open(key, StdfRecord::PIR(pir));
+            },
+
+            StdfRecord::PTR(_) | StdfRecord::MPR(_) | StdfRecord::FTR(_) | StdfRecord::STR(_) => {
+                let Some((head_num, site_num, test_num, test_txt)) = result_test(&rec) else { return Ok(()) };
+                let key = (head_num, site_num);
+
+                if self.test_selected(test_num, test_txt) {
+                    self.test_defaults.read(&rec);
+                    self.open_parts.add_result(key, rec);
+                }
+            },
+
+            StdfRecord::DTR(_) | StdfRecord::GDR(_) | StdfRecord::BPS(_) | StdfRecord::EPS(_) => {
+                if let Some(rec) = self.open_parts.add_unowned(rec) {
+                    self.writer.write_record(&rec)?;
+                }
+            },
+
+            StdfRecord::PRR(prr) => {
+                let kept = self.filter.part_selected(&prr);
+                let records = self.open_parts.close((prr.head_num, prr.site_num), kept).unwrap_or_default();
+
+                self.counts.parts_read += 1;
+                if kept {
+                    self.write_part(records, Some(prr))?;
+                }
+            },
+
+            StdfRecord::TSR(ref tsr) => {
+                if self.test_selected(tsr.test_num, &tsr.test_nam) {
+                    self.summary_recs.push(rec);
+                }
+            },
+
+            StdfRecord::PCR(_) | StdfRecord::HBR(_) | StdfRecord::SBR(_) => {
+                self.summary_recs.push(rec);
+            },
+
+            StdfRecord::WIR(ref wir) => {
+                self.stats.start_wafer(wir.head_num);
+                self.writer.write_record(&rec)?;
+            },
+
+            StdfRecord::WRR(mut wrr) => {
+                self.stats.update_wrr(&mut wrr);
+                self.writer.write_record(&StdfRecord::WRR(wrr))?;
+            },
+
+            StdfRecord::MRR(_) => {
+                self.finish_parts()?;
+                self.writer.write_record(&rec)?;
+            },
+
+            // everything else isn't tied to a part and goes straight through
+            rec => self.writer.write_record(&rec)?,
+        }
+
+        Ok(())
+    }
+
+    // writes the parts still open and the summary records
+    fn finish_parts(&mut self) -> Result<(), StdfUtilError> {
+        let kept = !self.filter.filters_parts();
+        for records in self.open_parts.close_all(kept) {
+            if kept { self.write_part(records, None)?; }
+        }
+
+        // bins and tests of the kept parts the STDF had no summary for get one
+        let mut summary_recs = std::mem::take(&mut self.summary_recs);
+        summary_recs.extend(self.stats.missing_summaries(&summary_recs));
+        for mut rec in summary_recs {
+            self.stats.update_summary(&mut rec);
+            self.writer.write_record(&rec)?;
+        }
+
+        Ok(())
+    }
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: Writes the parts and tests selected by the filter to a new STDF,
+///              summary records are recomputed from what was kept
+//////////////////////////////////////////////////////////////////////
+pub fn filter_stdf(stdf_path: &String, out_path: &String, filter: &StdfFilter) -> Result<FilterCounts, StdfUtilError> {
+    // records are written the way they are in the STDF, test defaults are handled here
+    let mut parser = StdfParser::new(stdf_path, &None)?;
+    parser.set_use_test_defaults(false);
+
+    // keep the byte order of the original, the FAR comes first
+    let first_rec = parser.next().transpose()?.map(|parsed_rec| parsed_rec.record);
+    let byte_order = match &first_rec {
+        Some(StdfRecord::FAR(far)) if far.cpu_type == 1 => ByteOrder::BigEndian,
+        _ => ByteOrder::LittleEndian,
+    };
+
+    let mut filter_writer = StdfFilterWriter {
+        filter,
+        writer: StdfWriter::new(out_path, byte_order)?,
+        counts: FilterCounts::default(),
+        stats: SummaryCounts::default(),
+        open_parts: OpenParts::default(),
+        summary_recs: Vec::new(),
+        test_selection: HashMap::new(),
+        test_defaults: TestDefaults::default(),
+    };
+
+    let mut mrr_found = false;
+    for rec in first_rec.into_iter().map(Ok).chain(parser.map(|parsed_rec| parsed_rec.map(|parsed_rec| parsed_rec.record))) {
+        let rec = rec?;
+        mrr_found |= matches!(rec, StdfRecord::MRR(_));
+        filter_writer.process(rec)?;
+    }
+
+    // STDF's cut short still get their summary
+    if !mrr_found {
+        filter_writer.finish_parts()?;
+    }
+
+    let counts = filter_writer.counts;
+    filter_writer.writer.finish()?;
+
+    Ok(counts)
+}
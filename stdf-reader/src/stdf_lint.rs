@@ -0,0 +1,233 @@
+use std::{collections::{BTreeSet, HashMap, HashSet}, fmt};
+
+use rust_stdf::*;
+
+use crate::{PtrOptFlags, StdfParser, StdfUtilError};
+
+//////////////////////////////////////////////////////////////////////
+/// Description: A structural or field level violation of the STDF V4 spec
+//////////////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, PartialEq)]
+pub struct LintIssue {
+    /// Offset of the record header in the uncompressed STDF
+    pub offset: u64,
+    /// Type of the record with the issue, STDF when the file itself is broken
+    pub record: &'static str,
+    pub msg: String,
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "offset {}: {}: {}", self.offset, self.record, self.msg)
+    }
+}
+
+// checks the limits of a PTR/MPR against the opt_flag bits telling which of them are valid,
+// the limits and opt_flag may come from the first record of the test
+fn check_limits(opt_flag: Option<[u8; 1]>, lo_limit: Option<f32>, hi_limit: Option<f32>) -> Vec<String> {
+    // without an opt_flag nothing says the limits are valid
+    let Some([opt_flag]) = opt_flag else { return Vec::new() };
+    let lo_valid = PtrOptFlags(opt_flag).lo_limit_valid();
+    let hi_valid = PtrOptFlags(opt_flag).hi_limit_valid();
+
+    let mut issues = Vec::new();
+    if lo_valid && lo_limit.is_none() { issues.push(format!("opt_flag 0x{:02X} says there is a lo_limit but neither the record nor the first of its test has one", opt_flag)); }
+    if hi_valid && hi_limit.is_none() { issues.push(format!("opt_flag 0x{:02X} says there is a hi_limit but neither the record nor the first of its test has one", opt_flag)); }
+    if let (true, true, Some(lo_limit), Some(hi_limit)) = (lo_valid, hi_valid, lo_limit, hi_limit) {
+        if lo_limit > hi_limit { issues.push(format!("lo_limit {} is above hi_limit {}", lo_limit, hi_limit)); }
+    }
+    issues
+}
+
+// state of a running lint_stdf
+#[derive(Default)]
+struct StdfLinter {
+    issues: Vec<LintIssue>,
+    rec_count: u64,
+
+    mir_found: bool,
+    before_mir_reported: bool,
+    mrr_offset: Option<u64>,
+    after_mrr_reported: bool,
+
+    // offset of the PIR of the parts in test by (head, site) and of the WIR of the wafers in test by head
+    open_parts: HashMap<(u8, u8), u64>,
+    open_wafers: HashMap<u8, (u64, String)>,
+
+    pmr_indexes: HashSet<u16>,
+    first_ptrs: HashMap<u32, PTR>,
+    first_mprs: HashMap<u32, MPR>,
+}
+
+impl StdfLinter {
+    fn issue(&mut self, offset: u64, record: &'static str, msg: String) {
+        self.issues.push(LintIssue { offset, record, msg });
+    }
+
+    fn check_pmr_indexes(&mut self, offset: u64, record: &'static str, field: &str, indexes: &[u16]) {
+        let missing: BTreeSet<u16> = indexes.iter().filter(|indx| !self.pmr_indexes.contains(indx)).copied().collect();
+        if !missing.is_empty() {
+            self.issue(offset, record, format!("{} references PMR indexes {:?} that aren't defined before it", field, missing));
+        }
+    }
+
+    fn check_part_open(&mut self, offset: u64, record: &'static str, head_num: u8, site_num: u8) {
+        if !self.open_parts.contains_key(&(head_num, site_num)) {
+            self.issue(offset, record, format!("head {} site {} has no part in test, the record isn't between a PIR and PRR", head_num, site_num));
+        }
+    }
+
+    fn check_order(&mut self, offset: u64, record: &'static str, rec: &StdfRecord) {
+        if self.rec_count == 0 && !matches!(rec, StdfRecord::FAR(_)) {
+            self.issue(offset, record, "the STDF has to start with a FAR".to_string());
+        }
+
+        if let Some(mrr_offset) = self.mrr_offset.filter(|_| !self.after_mrr_reported) {
+            self.after_mrr_reported = true;
+            self.issue(offset, record, format!("record after the MRR at offset {}, the MRR has to be the last record", mrr_offset));
+        }
+
+        // only the FAR and the ATR/VUR's that come with it may precede the MIR
+        if !self.mir_found && !self.before_mir_reported && !matches!(rec, StdfRecord::FAR(_) | StdfRecord::ATR(_) | StdfRecord::VUR(_) | StdfRecord::MIR(_)) {
+            self.before_mir_reported = true;
+            self.issue(offset, record, "record before the MIR, only the FAR, ATR's and VUR's can precede it".to_string());
+        }
+    }
+
+    fn check(&mut self, offset: u64, rec: &StdfRecord) {
+        let record = stdf_record_type::get_rec_name_from_code(rec.get_type());
+        self.check_order(offset, record, rec);
+        self.rec_count += 1;
+
+        match rec {
+            StdfRecord::FAR(rec) => {
+                if self.rec_count > 1 { self.issue(offset, record, "only the first record can be a FAR".to_string()); }
+                if rec.stdf_ver != 4 { self.issue(offset, record, format!("stdf_ver is {}, not STDF V4", rec.stdf_ver)); }
+            },
+
+            StdfRecord::MIR(_) => {
+                if self.mir_found { self.issue(offset, record, "the STDF has more than one MIR".to_string()); }
+                self.mir_found = true;
+            },
+
+            StdfRecord::MRR(_) => {
+                if self.mrr_offset.is_some() { self.issue(offset, record, "the STDF has more than one MRR".to_string()); }
+                self.mrr_offset.get_or_insert(offset);
+            },
+
+            StdfRecord::PMR(rec) => { self.pmr_indexes.insert(rec.pmr_indx); },
+            StdfRecord::PGR(rec) => self.check_pmr_indexes(offset, record, "pmr_indx", &rec.pmr_indx),
+
+            StdfRecord::WIR(rec) => {
+                if let Some((wir_offset, _)) = self.open_wafers.insert(rec.head_num, (offset, rec.wafer_id.to_owned())) {
+                    self.issue(offset, record, format!("head {} still has the wafer of the WIR at offset {} in test, it got no WRR", rec.head_num, wir_offset));
+                }
+            },
+            StdfRecord::WRR(rec) => match self.open_wafers.remove(&rec.head_num) {
+                Some((_, wafer_id)) if !wafer_id.is_empty() && !rec.wafer_id.is_empty() && wafer_id != rec.wafer_id => {
+                    self.issue(offset, record, format!("wafer_id \"{}\" doesn't match \"{}\" of its WIR", rec.wafer_id, wafer_id));
+                },
+                Some(_) => {},
+                None => self.issue(offset, record, format!("head {} has no wafer in test, the WRR has no WIR", rec.head_num)),
+            },
+
+            StdfRecord::PIR(rec) => {
+                if let Some(pir_offset) = self.open_parts.insert((rec.head_num, rec.site_num), offset) {
+                    self.issue(offset, record, format!("head {} site {} still has the part of the PIR at offset {} in test, it got no PRR", rec.head_num, rec.site_num, pir_offset));
+                }
+            },
+            StdfRecord::PRR(rec) if self.open_parts.remove(&(rec.head_num, rec.site_num)).is_none() => {
+                self.issue(offset, record, format!("head {} site {} has no part in test, the PRR has no PIR", rec.head_num, rec.site_num));
+            },
+
+            StdfRecord::PTR(rec) => {
+                self.check_part_open(offset, record, rec.head_num, rec.site_num);
+
+                let first = self.first_ptrs.entry(rec.test_num).or_insert_with(|| rec.to_owned());
+                let issues = check_limits(rec.opt_flag.or(first.opt_flag), rec.lo_limit.or(first.lo_limit), rec.hi_limit.or(first.hi_limit));
+                for msg in issues {
+                    self.issue(offset, record, format!("test {}: {}", rec.test_num, msg));
+                }
+            },
+            StdfRecord::MPR(rec) => {
+                self.check_part_open(offset, record, rec.head_num, rec.site_num);
+
+                let first = self.first_mprs.entry(rec.test_num).or_insert_with(|| rec.to_owned());
+                let mut issues = check_limits(rec.opt_flag.or(first.opt_flag), rec.lo_limit.or(first.lo_limit), rec.hi_limit.or(first.hi_limit));
+
+                // an rtn_icnt of 0 takes the pin indexes of the first MPR of the test
+                let rtn_indx = if rec.rtn_icnt > 0 { rec.rtn_indx.to_owned() } else { first.rtn_indx.to_owned() };
+                let pin_cnt = rtn_indx.as_ref().map_or(0, |rtn_indx| rtn_indx.len());
+                if rec.rtn_icnt > 0 && pin_cnt != rec.rtn_icnt as usize {
+                    issues.push(format!("rtn_indx has {} entries but rtn_icnt is {}", pin_cnt, rec.rtn_icnt));
+                }
+                if pin_cnt > 0 && rec.rslt_cnt > 0 && pin_cnt != rec.rslt_cnt as usize {
+                    issues.push(format!("rtn_rslt has {} results for the {} pins of rtn_indx", rec.rslt_cnt, pin_cnt));
+                }
+                for msg in issues {
+                    self.issue(offset, record, format!("test {}: {}", rec.test_num, msg));
+                }
+                self.check_pmr_indexes(offset, record, "rtn_indx", &rtn_indx.unwrap_or_default());
+            },
+            StdfRecord::FTR(rec) => {
+                self.check_part_open(offset, record, rec.head_num, rec.site_num);
+                self.check_pmr_indexes(offset, record, "rtn_indx", &rec.rtn_indx);
+                self.check_pmr_indexes(offset, record, "pgm_indx", &rec.pgm_indx);
+            },
+            StdfRecord::STR(rec) => self.check_part_open(offset, record, rec.head_num, rec.site_num),
+
+            _ => {},
+        }
+    }
+
+    // reports what the STDF left open once it ended at end_offset
+    fn finish(mut self, end_offset: u64) -> Vec<LintIssue> {
+        if self.rec_count == 0 {
+            self.issue(end_offset, "STDF", "the STDF has no records".to_string());
+        } else if self.mrr_offset.is_none() {
+            self.issue(end_offset, "MRR", "the STDF ended without an MRR".to_string());
+        }
+
+        let mut open_parts: Vec<((u8, u8), u64)> = self.open_parts.drain().collect();
+        open_parts.sort_by_key(|(_, offset)| *offset);
+        for ((head_num, site_num), offset) in open_parts {
+            self.issue(offset, "PIR", format!("the part on head {} site {} never got a PRR", head_num, site_num));
+        }
+
+        let mut open_wafers: Vec<(u8, (u64, String))> = self.open_wafers.drain().collect();
+        open_wafers.sort_by_key(|(_, (offset, _))| *offset);
+        for (head_num, (offset, _)) in open_wafers {
+            self.issue(offset, "WIR", format!("the wafer on head {} never got a WRR", head_num));
+        }
+
+        // report in file order
+        self.issues.sort_by_key(|issue| issue.offset);
+        self.issues
+    }
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: Checks an STDF against the STDF V4 spec, the record order, the
+///              pairing of PIR/PRR and WIR/WRR, the PTR/MPR limits and the PMR
+///              references, every issue is reported with the offset of its record
+//////////////////////////////////////////////////////////////////////
+pub fn lint_stdf(stdf_path: &String) -> Result<Vec<LintIssue>, StdfUtilError> {
+    // the records have to be checked the way they are in the STDF, without the test defaults
+    let mut parser = StdfParser::new(stdf_path, &None)?;
+    parser.set_use_test_defaults(false);
+
+    let mut linter = StdfLinter::default();
+    while let Some(parsed_rec) = parser.next() {
+        match parsed_rec {
+            Ok(parsed_rec) => linter.check(parsed_rec.offset, &parsed_rec.record),
+            // nothing after a record that can't be decoded can be checked
+            Err(StdfUtilError::Decode { msg, .. }) => {
+                linter.issue(parser.offset(), "STDF", format!("unable to decode the record: {}", msg));
+                break;
+            },
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(linter.finish(parser.offset()))
+}
@@ -0,0 +1,313 @@
+use std::collections::{HashMap, HashSet};
+
+use rust_stdf::*;
+
+use crate::{summary_counts::{result_test, summary_key, OpenParts, SummaryCounts, TestDefaults}, StdfParser, StdfUtilError, StdfWriter};
+
+/// How a part tested more than once across the merged STDF's is recognized
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RetestKey {
+    /// Same part_id, parts without a part_id are never retests
+    PartId,
+    /// Same wafer_id and x/y coordinates, parts without coordinates are never retests
+    Coordinates,
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: Options of merge_stdf, the default keeps every part with its part_id
+///              and the MIR of the first STDF
+//////////////////////////////////////////////////////////////////////
+#[derive(Debug, Default, Clone)]
+pub struct MergeOptions {
+    /// Replaces the part_id of every part written by its position in the merged STDF, starting at 1
+    pub renumber_part_ids: bool,
+    /// Only keeps the last test of a retested part
+    pub keep_last_retest: Option<RetestKey>,
+    /// Takes the MIR of the last STDF instead of the first one
+    pub use_last_mir: bool,
+}
+
+/// Number of parts seen and kept by merge_stdf
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MergeCounts {
+    pub parts_read: u32,
+    pub parts_kept: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum RetestId {
+    PartId(String),
+    Die(String, i16, i16),
+}
+
+fn retest_id(key: RetestKey, rec: &PRR, wafer_ids: &HashMap<u8, String>) -> Option<RetestId> {
+    match key {
+        RetestKey::PartId if !rec.part_id.is_empty() => Some(RetestId::PartId(rec.part_id.to_owned())),
+        RetestKey::Coordinates if rec.x_coord != -32768 && rec.y_coord != -32768 => {
+            let wafer_id = wafer_ids.get(&rec.head_num).cloned().unwrap_or_default();
+            Some(RetestId::Die(wafer_id, rec.x_coord, rec.y_coord))
+        },
+        _ => None,
+    }
+}
+
+// what merge_stdf needs to know before writing: the byte order, the MIR to use
+// and the last test of every retested part
+#[derive(Default)]
+struct MergeScan {
+    byte_order: Option<ByteOrder>,
+    mirs: Vec<Option<MIR>>,
+    last_tests: HashMap<RetestId, (usize, u32)>,
+    // wafer in test by head, tracked across the STDF's the way StdfMergeWriter does
+    wafer_ids: HashMap<u8, String>,
+}
+
+impl MergeScan {
+    fn scan(&mut self, file_idx: usize, stdf_path: &String, retest_key: Option<RetestKey>) -> Result<(), StdfUtilError> {
+        let mut parser = StdfParser::new(stdf_path, &None)?;
+        parser.set_use_test_defaults(false);
+
+        let mut mir = None;
+        let mut prr_idx = 0;
+        for parsed_rec in parser {
+            match parsed_rec?.record {
+                // keep the byte order of the first STDF
+                StdfRecord::FAR(rec) => { self.byte_order.get_or_insert(if rec.cpu_type == 1 { ByteOrder::BigEndian } else { ByteOrder::LittleEndian }); },
+                StdfRecord::MIR(rec) => { mir.get_or_insert(rec); },
+                // only the MIR is needed when retests are kept, it comes before any part
+                StdfRecord::PIR(_) if retest_key.is_none() => break,
+                StdfRecord::WIR(rec) => { self.wafer_ids.insert(rec.head_num, rec.wafer_id); },
+                StdfRecord::WRR(rec) => { self.wafer_ids.remove(&rec.head_num); },
+                StdfRecord::PRR(rec) => {
+                    prr_idx += 1;
+                    if let Some(id) = retest_key.and_then(|key| retest_id(key, &rec, &self.wafer_ids)) {
+                        self.last_tests.insert(id, (file_idx, prr_idx));
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        self.mirs.push(mir);
+        Ok(())
+    }
+}
+
+// state of a running merge_stdf
+struct StdfMergeWriter<'a> {
+    options: &'a MergeOptions,
+    last_tests: HashMap<RetestId, (usize, u32)>,
+    writer: StdfWriter,
+    counts: MergeCounts,
+    stats: SummaryCounts,
+    test_defaults: TestDefaults,
+
+    // path and position of the STDF being merged, position of the PRR within it
+    stdf_path: &'a str,
+    file_idx: usize,
+    prr_idx: u32,
+
+    // pins by pmr_indx and site groups by (head, site_grp) already written, later STDF's may only repeat them
+    pins: HashMap<u16, PMR>,
+    site_groups: HashMap<(u8, u8), SDR>,
+
+    open_parts: OpenParts,
+    // wafer in test by head, a wafer continued in the next STDF keeps its WIR from the first one
+    open_wafers: HashMap<u8, String>,
+    // summary records are written once all parts are known
+    summary_recs: Vec<StdfRecord>,
+    summary_keys: HashSet<(u8, u8, u8, u32)>,
+    mrr: Option<MRR>,
+}
+
+impl StdfMergeWriter<'_> {
+    fn write_part(&mut self, records: Vec<StdfRecord>, prr: Option<PRR>) -> Result<(), StdfUtilError> {
+        for mut rec in records {
+            // the PTR/MPR defaults of each STDF have to survive the merge
+            self.test_defaults.fill(&mut rec);
+            if result_test(&rec).is_some() {
+                self.stats.add_test(&rec);
+            }
+
+            self.writer.write_record(&rec)?;
+        }
+
+        if let Some(mut prr) = prr {
+            self.counts.parts_kept += 1;
+            if self.options.renumber_part_ids { prr.part_id = self.counts.parts_kept.to_string(); }
+
+            self.stats.add_part(&prr);
+            self.writer.write_record(&StdfRecord::PRR(prr))?;
+        }
+
+        Ok(())
+    }
+
+    fn process(&mut self, rec: StdfRecord) -> Result<(), StdfUtilError> {
+        match rec {
+            // the merged STDF has a single FAR and MIR, written before any STDF is merged
+            StdfRecord::FAR(_) | StdfRecord::MIR(_) => {},
+
+            StdfRecord::PIR(pir) => {
+                let key = (pir.head_num, pir.site_num);
+                if let Some(records) = self.open_parts.close(key, true) {
+                    self.write_part(records, None)?;
+                }
+                self.open_parts.open(key, StdfRecord::PIR(pir));
+            },
+
+            StdfRecord::PTR(_) | StdfRecord::MPR(_) | StdfRecord::FTR(_) | StdfRecord::STR(_) => {
+                let Some((head_num, site_num, _, _)) = result_test(&rec) else { return Ok(()) };
+                self.test_defaults.read(&rec);
+                self.open_parts.add_result((head_num, site_num), rec);
+            },
+
+            StdfRecord::DTR(_) | StdfRecord::GDR(_) | StdfRecord::BPS(_) | StdfRecord::EPS(_) => {
+                if let Some(rec) = self.open_parts.add_unowned(rec) {
+                    self.writer.write_record(&rec)?;
+                }
+            },
+
+            StdfRecord::PMR(pmr) => match self.pins.get(&pmr.pmr_indx) {
+                None => {
+                    self.pins.insert(pmr.pmr_indx, pmr.to_owned());
+                    self.writer.write_record(&StdfRecord::PMR(pmr))?;
+                },
+                Some(pin) if *pin == pmr => {},
+                Some(_) => return Err(StdfUtilError::MergeConflict { path: self.stdf_path.to_owned(), record: "PMR", index: pmr.pmr_indx as u32 }),
+            },
+
+            StdfRecord::SDR(sdr) => match self.site_groups.get(&(sdr.head_num, sdr.site_grp)) {
+                None => {
+                    self.site_groups.insert((sdr.head_num, sdr.site_grp), sdr.to_owned());
+                    self.writer.write_record(&StdfRecord::SDR(sdr))?;
+                },
+                Some(site_group) if *site_group == sdr => {},
+                Some(_) => return Err(StdfUtilError::MergeConflict { path: self.stdf_path.to_owned(), record: "SDR", index: sdr.site_grp as u32 }),
+            },
+
+            StdfRecord::PRR(prr) => {
+                self.counts.parts_read += 1;
+                self.prr_idx += 1;
+
+                // a retested part is only kept for its last test
+                let retest = self.options.keep_last_retest
+                    .and_then(|key| retest_id(key, &prr, &self.open_wafers))
+                    .and_then(|id| self.last_tests.get(&id))
+                    .is_some_and(|last_test| *last_test != (self.file_idx, self.prr_idx));
+                let records = self.open_parts.close((prr.head_num, prr.site_num), !retest).unwrap_or_default();
+                if !retest {
+                    self.write_part(records, Some(prr))?;
+                }
+            },
+
+            StdfRecord::PCR(_) | StdfRecord::HBR(_) | StdfRecord::SBR(_) | StdfRecord::TSR(_) => {
+                if summary_key(&rec).is_some_and(|key| self.summary_keys.insert(key)) {
+                    self.summary_recs.push(rec);
+                }
+            },
+
+            StdfRecord::WIR(wir) => {
+                if self.open_wafers.get(&wir.head_num) != Some(&wir.wafer_id) {
+                    self.stats.start_wafer(wir.head_num);
+                    self.open_wafers.insert(wir.head_num, wir.wafer_id.to_owned());
+                    self.writer.write_record(&StdfRecord::WIR(wir))?;
+                }
+            },
+
+            StdfRecord::WRR(mut wrr) => {
+                self.open_wafers.remove(&wrr.head_num);
+                self.stats.update_wrr(&mut wrr);
+                self.writer.write_record(&StdfRecord::WRR(wrr))?;
+            },
+
+            StdfRecord::MRR(mrr) => {
+                // the last MRR wins, but the lot finished when the last STDF did
+                let finish_t = self.mrr.as_ref().map_or(0, |last| last.finish_t).max(mrr.finish_t);
+                self.mrr = Some(MRR { finish_t, ..mrr });
+            },
+
+            // everything else isn't tied to a part and goes straight through
+            rec => self.writer.write_record(&rec)?,
+        }
+
+        Ok(())
+    }
+
+    // writes the parts the STDF left open, the next STDF starts with nothing in test
+    fn finish_stdf(&mut self) -> Result<(), StdfUtilError> {
+        for records in self.open_parts.close_all(true) {
+            self.write_part(records, None)?;
+        }
+
+        self.file_idx += 1;
+        self.prr_idx = 0;
+        self.test_defaults.next_stdf();
+        Ok(())
+    }
+
+    // writes the summary records and the MRR, bins and tests none of the STDF's had a summary for get one
+    fn finish(mut self) -> Result<MergeCounts, StdfUtilError> {
+        let mut summary_recs = std::mem::take(&mut self.summary_recs);
+        summary_recs.extend(self.stats.missing_summaries(&summary_recs));
+        for mut rec in summary_recs {
+            self.stats.update_summary(&mut rec);
+            self.writer.write_record(&rec)?;
+        }
+        self.writer.write_record(&StdfRecord::MRR(self.mrr.take().unwrap_or_default()))?;
+        self.writer.finish()?;
+
+        Ok(self.counts)
+    }
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: Merges STDF's of the same lot into a single STDF, in the order given,
+///              summary records are recomputed from the merged parts
+//////////////////////////////////////////////////////////////////////
+pub fn merge_stdf(stdf_paths: &[String], out_path: &String, options: &MergeOptions) -> Result<MergeCounts, StdfUtilError> {
+    // find the MIR to use and which tests of the retested parts are kept
+    let mut scan = MergeScan::default();
+    for (file_idx, stdf_path) in stdf_paths.iter().enumerate() {
+        scan.scan(file_idx, stdf_path, options.keep_last_retest)?;
+    }
+    let mir = if options.use_last_mir { scan.mirs.pop() } else { scan.mirs.into_iter().next() }.flatten();
+
+    let mut writer = StdfWriter::new(out_path, scan.byte_order.unwrap_or(ByteOrder::LittleEndian))?;
+    if let Some(mir) = &mir {
+        writer.write_record(&StdfRecord::MIR(mir.to_owned()))?;
+    }
+
+    let mut merge_writer = StdfMergeWriter {
+        options,
+        last_tests: scan.last_tests,
+        writer,
+        counts: MergeCounts::default(),
+        stats: SummaryCounts::default(),
+        test_defaults: TestDefaults::default(),
+        stdf_path: "",
+        file_idx: 0,
+        prr_idx: 0,
+        pins: HashMap::new(),
+        site_groups: HashMap::new(),
+        open_parts: OpenParts::default(),
+        open_wafers: HashMap::new(),
+        summary_recs: Vec::new(),
+        summary_keys: HashSet::new(),
+        mrr: None,
+    };
+
+    for stdf_path in stdf_paths {
+        // records are written the way they are in the STDF, test defaults are handled by the merge
+        let mut parser = StdfParser::new(stdf_path, &None)?;
+        parser.set_use_test_defaults(false);
+
+        merge_writer.stdf_path = stdf_path;
+        for parsed_rec in parser {
+            merge_writer.process(parsed_rec?.record)?;
+        }
+        merge_writer.finish_stdf()?;
+    }
+
+    merge_writer.finish()
+}
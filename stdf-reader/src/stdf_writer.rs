@@ -0,0 +1,491 @@
+use std::{fs::File, io::{BufWriter, Write}};
+
+#[cfg(feature = "gzip")]
+use flate2::{write::GzEncoder, Compression};
+use rust_stdf::*;
+
+use crate::StdfUtilError;
+
+// numeric STDF types in the byte order of the file being written
+macro_rules! encode_num {
+    ($($name:ident: $typ:ty),*) => {$(
+        fn $name(&mut self, value: $typ) {
+            match self.order {
+                ByteOrder::LittleEndian => self.data.extend(value.to_le_bytes()),
+                ByteOrder::BigEndian => self.data.extend(value.to_be_bytes()),
+            }
+        }
+    )*};
+}
+
+// serializes the fields of a single record, the first problem found is kept and reported at the end
+struct RecordEncoder {
+    order: ByteOrder,
+    data: Vec<u8>,
+    error: Option<String>,
+}
+
+impl RecordEncoder {
+    fn new(order: ByteOrder) -> Self {
+        Self { order, data: Vec::new(), error: None }
+    }
+
+    fn fail(&mut self, msg: String) {
+        self.error.get_or_insert(msg);
+    }
+
+    encode_num!(u1: u8, u2: u16, u4: u32, u8: u64, i1: i8, i2: i16, i4: i32, r4: f32, r8: f64);
+
+    fn b1(&mut self, value: [u8; 1]) {
+        self.data.extend(value);
+    }
+
+    fn c1(&mut self, value: char) {
+        let bytes = self.string_bytes(&value.to_string());
+        self.data.push(bytes.first().copied().unwrap_or(b' '));
+    }
+
+    fn string_bytes(&mut self, value: &str) -> Vec<u8> {
+        // rust_stdf reads every byte as a char, so only chars that fit in a byte can go back out
+        value.chars().map(|c| u8::try_from(c).unwrap_or_else(|_| {
+            self.fail(format!("'{}' in \"{}\" can't be stored in a single byte", c, value));
+            b'?'
+        })).collect()
+    }
+
+    fn cn(&mut self, value: &str) {
+        let bytes = self.string_bytes(value);
+        match u8::try_from(bytes.len()) {
+            Ok(len) => { self.u1(len); self.data.extend(bytes); },
+            Err(_) => self.fail(format!("\"{}\" is longer than 255 bytes", value)),
+        }
+    }
+
+    fn sn(&mut self, value: &str) {
+        let bytes = self.string_bytes(value);
+        match u16::try_from(bytes.len()) {
+            Ok(len) => { self.u2(len); self.data.extend(bytes); },
+            Err(_) => self.fail(format!("string of {} bytes is longer than 65535 bytes", bytes.len())),
+        }
+    }
+
+    fn cf(&mut self, value: &str, size: u8) {
+        // fixed length strings are padded with spaces
+        let mut bytes = self.string_bytes(value);
+        if bytes.len() > size as usize {
+            self.fail(format!("\"{}\" is longer than the fixed length of {}", value, size));
+        }
+        bytes.resize(size as usize, b' ');
+        self.data.extend(bytes);
+    }
+
+    fn bn(&mut self, value: &[u8]) {
+        match u8::try_from(value.len()) {
+            Ok(len) => { self.u1(len); self.data.extend(value); },
+            Err(_) => self.fail(format!("Bn of {} bytes is longer than 255 bytes", value.len())),
+        }
+    }
+
+    fn dn(&mut self, value: &[u8]) {
+        // rust_stdf keeps whole bytes, so every bit of them is written back
+        match u16::try_from(value.len() * 8) {
+            Ok(bit_count) => { self.u2(bit_count); self.data.extend(value); },
+            Err(_) => self.fail(format!("Dn of {} bytes is longer than 65535 bits", value.len())),
+        }
+    }
+
+    fn count(&mut self, field: &str, count: usize, len: usize) {
+        if count != len {
+            self.fail(format!("{} has {} entries but its count is {}", field, len, count));
+        }
+    }
+
+    fn kx_u1(&mut self, values: &[u8]) {
+        self.data.extend(values);
+    }
+
+    fn kx_u2(&mut self, values: &[u16]) {
+        values.iter().for_each(|v| self.u2(*v));
+    }
+
+    fn kx_u4(&mut self, values: &[u32]) {
+        values.iter().for_each(|v| self.u4(*v));
+    }
+
+    fn kx_u8(&mut self, values: &[u64]) {
+        values.iter().for_each(|v| self.u8(*v));
+    }
+
+    fn kx_r4(&mut self, values: &[f32]) {
+        values.iter().for_each(|v| self.r4(*v));
+    }
+
+    fn kx_cn(&mut self, values: &[String]) {
+        values.iter().for_each(|v| self.cn(v));
+    }
+
+    fn kx_sn(&mut self, values: &[String]) {
+        values.iter().for_each(|v| self.sn(v));
+    }
+
+    fn kx_cf(&mut self, values: &[String], size: u8) {
+        values.iter().for_each(|v| self.cf(v, size));
+    }
+
+    fn kx_n1(&mut self, values: &[u8]) {
+        // two nibbles per byte, first one in the low nibble
+        for pair in values.chunks(2) {
+            self.data.push((pair[0] & 0x0F) | (pair.get(1).unwrap_or(&0) & 0x0F) << 4);
+        }
+    }
+
+    fn kx_uf(&mut self, field: &str, values: &KxUf, size: u8) {
+        match (values, size) {
+            (KxUf::F1(values), _) if values.is_empty() => {},
+            (KxUf::F1(values), 1) => self.kx_u1(values),
+            (KxUf::F2(values), 2) => self.kx_u2(values),
+            (KxUf::F4(values), 4) => self.kx_u4(values),
+            (KxUf::F8(values), 8) => self.kx_u8(values),
+            _ => self.fail(format!("{} entries don't have the size of {} bytes", field, size)),
+        }
+    }
+
+    fn v1(&mut self, value: &V1) {
+        match value {
+            V1::B0 => self.u1(0),
+            V1::U1(v) => { self.u1(1); self.u1(*v) },
+            V1::U2(v) => { self.u1(2); self.u2(*v) },
+            V1::U4(v) => { self.u1(3); self.u4(*v) },
+            V1::I1(v) => { self.u1(4); self.i1(*v) },
+            V1::I2(v) => { self.u1(5); self.i2(*v) },
+            V1::I4(v) => { self.u1(6); self.i4(*v) },
+            V1::R4(v) => { self.u1(7); self.r4(*v) },
+            V1::R8(v) => { self.u1(8); self.r8(*v) },
+            V1::Cn(v) => { self.u1(10); self.cn(v) },
+            V1::Bn(v) => { self.u1(11); self.bn(v) },
+            V1::Dn(v) => { self.u1(12); self.dn(v) },
+            V1::N1(v) => { self.u1(13); self.u1(*v & 0x0F) },
+            V1::Invalid => self.fail("GDR contains an invalid field".into()),
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: Serializes the data of a record in the given byte order,
+///              returns the record type and sub type along with the data
+//////////////////////////////////////////////////////////////////////
+fn encode_record(rec: &StdfRecord, order: ByteOrder) -> Result<(u8, u8, Vec<u8>), String> {
+    let (rec_typ, rec_sub) = stdf_record_type::get_typ_sub_from_code(rec.get_type()).map_err(|e| e.msg)?;
+    let mut e = RecordEncoder::new(order);
+
+    match rec {
+        StdfRecord::FAR(rec) => { e.u1(rec.cpu_type); e.u1(rec.stdf_ver); },
+        StdfRecord::ATR(rec) => { e.u4(rec.mod_tim); e.cn(&rec.cmd_line); },
+        StdfRecord::VUR(rec) => e.cn(&rec.upd_nam),
+        StdfRecord::MIR(rec) => {
+            e.u4(rec.setup_t); e.u4(rec.start_t); e.u1(rec.stat_num);
+            e.c1(rec.mode_cod); e.c1(rec.rtst_cod); e.c1(rec.prot_cod); e.u2(rec.burn_tim); e.c1(rec.cmod_cod);
+            for text in [&rec.lot_id, &rec.part_typ, &rec.node_nam, &rec.tstr_typ, &rec.job_nam, &rec.job_rev, &rec.sblot_id,
+                         &rec.oper_nam, &rec.exec_typ, &rec.exec_ver, &rec.test_cod, &rec.tst_temp, &rec.user_txt, &rec.aux_file,
+                         &rec.pkg_typ, &rec.famly_id, &rec.date_cod, &rec.facil_id, &rec.floor_id, &rec.proc_id, &rec.oper_frq,
+                         &rec.spec_nam, &rec.spec_ver, &rec.flow_id, &rec.setup_id, &rec.dsgn_rev, &rec.eng_id, &rec.rom_cod,
+                         &rec.serl_num, &rec.supr_nam] {
+                e.cn(text);
+            }
+        },
+        StdfRecord::MRR(rec) => { e.u4(rec.finish_t); e.c1(rec.disp_cod); e.cn(&rec.usr_desc); e.cn(&rec.exc_desc); },
+        StdfRecord::PCR(rec) => {
+            e.u1(rec.head_num); e.u1(rec.site_num);
+            e.u4(rec.part_cnt); e.u4(rec.rtst_cnt); e.u4(rec.abrt_cnt); e.u4(rec.good_cnt); e.u4(rec.func_cnt);
+        },
+        StdfRecord::HBR(rec) => {
+            e.u1(rec.head_num); e.u1(rec.site_num); e.u2(rec.hbin_num); e.u4(rec.hbin_cnt); e.c1(rec.hbin_pf); e.cn(&rec.hbin_nam);
+        },
+        StdfRecord::SBR(rec) => {
+            e.u1(rec.head_num); e.u1(rec.site_num); e.u2(rec.sbin_num); e.u4(rec.sbin_cnt); e.c1(rec.sbin_pf); e.cn(&rec.sbin_nam);
+        },
+        StdfRecord::PMR(rec) => {
+            e.u2(rec.pmr_indx); e.u2(rec.chan_typ); e.cn(&rec.chan_nam); e.cn(&rec.phy_nam); e.cn(&rec.log_nam);
+            e.u1(rec.head_num); e.u1(rec.site_num);
+        },
+        StdfRecord::PGR(rec) => {
+            e.count("PMR_INDX", rec.indx_cnt as usize, rec.pmr_indx.len());
+            e.u2(rec.grp_indx); e.cn(&rec.grp_nam); e.u2(rec.indx_cnt); e.kx_u2(&rec.pmr_indx);
+        },
+        StdfRecord::PLR(rec) => {
+            let grp_cnt = rec.grp_cnt as usize;
+            e.count("GRP_INDX", grp_cnt, rec.grp_indx.len());
+            e.count("GRP_MODE", grp_cnt, rec.grp_mode.len());
+            e.count("GRP_RADX", grp_cnt, rec.grp_radx.len());
+            e.count("PGM_CHAR", grp_cnt, rec.pgm_char.len());
+            e.count("RTN_CHAR", grp_cnt, rec.rtn_char.len());
+            e.count("PGM_CHAL", grp_cnt, rec.pgm_chal.len());
+            e.count("RTN_CHAL", grp_cnt, rec.rtn_chal.len());
+            e.u2(rec.grp_cnt); e.kx_u2(&rec.grp_indx); e.kx_u2(&rec.grp_mode); e.kx_u1(&rec.grp_radx);
+            e.kx_cn(&rec.pgm_char); e.kx_cn(&rec.rtn_char); e.kx_cn(&rec.pgm_chal); e.kx_cn(&rec.rtn_chal);
+        },
+        StdfRecord::RDR(rec) => {
+            e.count("RTST_BIN", rec.num_bins as usize, rec.rtst_bin.len());
+            e.u2(rec.num_bins); e.kx_u2(&rec.rtst_bin);
+        },
+        StdfRecord::SDR(rec) => {
+            e.count("SITE_NUM", rec.site_cnt as usize, rec.site_num.len());
+            e.u1(rec.head_num); e.u1(rec.site_grp); e.u1(rec.site_cnt); e.kx_u1(&rec.site_num);
+            for text in [&rec.hand_typ, &rec.hand_id, &rec.card_typ, &rec.card_id, &rec.load_typ, &rec.load_id, &rec.dib_typ,
+                         &rec.dib_id, &rec.cabl_typ, &rec.cabl_id, &rec.cont_typ, &rec.cont_id, &rec.lasr_typ, &rec.lasr_id,
+                         &rec.extr_typ, &rec.extr_id] {
+                e.cn(text);
+            }
+        },
+        StdfRecord::PSR(rec) => {
+            let locp_cnt = rec.locp_cnt as usize;
+            e.count("PAT_BGN", locp_cnt, rec.pat_bgn.len());
+            e.count("PAT_END", locp_cnt, rec.pat_end.len());
+            e.count("PAT_FILE", locp_cnt, rec.pat_file.len());
+            e.count("PAT_LBL", locp_cnt, rec.pat_lbl.len());
+            e.count("FILE_UID", locp_cnt, rec.file_uid.len());
+            e.count("ATPG_DSC", locp_cnt, rec.atpg_dsc.len());
+            e.count("SRC_ID", locp_cnt, rec.src_id.len());
+            e.b1(rec.cont_flg); e.u2(rec.psr_indx); e.cn(&rec.psr_nam); e.b1(rec.opt_flg); e.u2(rec.totp_cnt); e.u2(rec.locp_cnt);
+            e.kx_u8(&rec.pat_bgn); e.kx_u8(&rec.pat_end);
+            e.kx_cn(&rec.pat_file); e.kx_cn(&rec.pat_lbl); e.kx_cn(&rec.file_uid); e.kx_cn(&rec.atpg_dsc); e.kx_cn(&rec.src_id);
+        },
+        StdfRecord::NMR(rec) => {
+            e.count("PMR_INDX", rec.locm_cnt as usize, rec.pmr_indx.len());
+            e.count("ATPG_NAM", rec.locm_cnt as usize, rec.atpg_nam.len());
+            e.b1(rec.cont_flg); e.u2(rec.totm_cnt); e.u2(rec.locm_cnt); e.kx_u2(&rec.pmr_indx); e.kx_cn(&rec.atpg_nam);
+        },
+        StdfRecord::CNR(rec) => { e.u2(rec.chn_num); e.u4(rec.bit_pos); e.sn(&rec.cell_nam); },
+        StdfRecord::SSR(rec) => {
+            e.count("CHN_LIST", rec.chn_cnt as usize, rec.chn_list.len());
+            e.cn(&rec.ssr_nam); e.u2(rec.chn_cnt); e.kx_u2(&rec.chn_list);
+        },
+        StdfRecord::CDR(rec) => {
+            e.count("M_CLKS", rec.mstr_cnt as usize, rec.m_clks.len());
+            e.count("S_CLKS", rec.slav_cnt as usize, rec.s_clks.len());
+            e.count("CELL_LST", rec.lst_cnt as usize, rec.cell_lst.len());
+            e.b1(rec.cont_flg); e.u2(rec.cdr_indx); e.cn(&rec.chn_nam); e.u4(rec.chn_len); e.u2(rec.sin_pin); e.u2(rec.sout_pin);
+            e.u1(rec.mstr_cnt); e.kx_u2(&rec.m_clks); e.u1(rec.slav_cnt); e.kx_u2(&rec.s_clks);
+            e.u1(rec.inv_val); e.u2(rec.lst_cnt); e.kx_sn(&rec.cell_lst);
+        },
+        StdfRecord::WIR(rec) => { e.u1(rec.head_num); e.u1(rec.site_grp); e.u4(rec.start_t); e.cn(&rec.wafer_id); },
+        StdfRecord::WRR(rec) => {
+            e.u1(rec.head_num); e.u1(rec.site_grp); e.u4(rec.finish_t);
+            e.u4(rec.part_cnt); e.u4(rec.rtst_cnt); e.u4(rec.abrt_cnt); e.u4(rec.good_cnt); e.u4(rec.func_cnt);
+            for text in [&rec.wafer_id, &rec.fabwf_id, &rec.frame_id, &rec.mask_id, &rec.usr_desc, &rec.exc_desc] {
+                e.cn(text);
+            }
+        },
+        StdfRecord::WCR(rec) => {
+            e.r4(rec.wafr_siz); e.r4(rec.die_ht); e.r4(rec.die_wid); e.u1(rec.wf_units); e.c1(rec.wf_flat);
+            e.i2(rec.center_x); e.i2(rec.center_y); e.c1(rec.pos_x); e.c1(rec.pos_y);
+        },
+        StdfRecord::PIR(rec) => { e.u1(rec.head_num); e.u1(rec.site_num); },
+        StdfRecord::PRR(rec) => {
+            e.u1(rec.head_num); e.u1(rec.site_num); e.b1(rec.part_flg); e.u2(rec.num_test); e.u2(rec.hard_bin); e.u2(rec.soft_bin);
+            e.i2(rec.x_coord); e.i2(rec.y_coord); e.u4(rec.test_t); e.cn(&rec.part_id); e.cn(&rec.part_txt); e.bn(&rec.part_fix);
+        },
+        StdfRecord::TSR(rec) => {
+            e.u1(rec.head_num); e.u1(rec.site_num); e.c1(rec.test_typ); e.u4(rec.test_num);
+            e.u4(rec.exec_cnt); e.u4(rec.fail_cnt); e.u4(rec.alrm_cnt);
+            e.cn(&rec.test_nam); e.cn(&rec.seq_name); e.cn(&rec.test_lbl); e.b1(rec.opt_flag);
+            e.r4(rec.test_tim); e.r4(rec.test_min); e.r4(rec.test_max); e.r4(rec.tst_sums); e.r4(rec.tst_sqrs);
+        },
+        StdfRecord::PTR(rec) => {
+            e.u4(rec.test_num); e.u1(rec.head_num); e.u1(rec.site_num); e.b1(rec.test_flg); e.b1(rec.parm_flg);
+            e.r4(rec.result); e.cn(&rec.test_txt); e.cn(&rec.alarm_id);
+
+            // optional fields end the record at the first one missing, same as rust_stdf reads them
+            let _ = (|| {
+                e.b1(rec.opt_flag?); e.i1(rec.res_scal?); e.i1(rec.llm_scal?); e.i1(rec.hlm_scal?);
+                e.r4(rec.lo_limit?); e.r4(rec.hi_limit?);
+                e.cn(rec.units.as_ref()?); e.cn(rec.c_resfmt.as_ref()?); e.cn(rec.c_llmfmt.as_ref()?); e.cn(rec.c_hlmfmt.as_ref()?);
+                e.r4(rec.lo_spec?); e.r4(rec.hi_spec?);
+                Some(())
+            })();
+        },
+        StdfRecord::MPR(rec) => {
+            e.count("RTN_STAT", rec.rtn_icnt as usize, rec.rtn_stat.len());
+            e.count("RTN_RSLT", rec.rslt_cnt as usize, rec.rtn_rslt.len());
+            if let Some(rtn_indx) = &rec.rtn_indx {
+                e.count("RTN_INDX", rec.rtn_icnt as usize, rtn_indx.len());
+            }
+            e.u4(rec.test_num); e.u1(rec.head_num); e.u1(rec.site_num); e.b1(rec.test_flg); e.b1(rec.parm_flg);
+            e.u2(rec.rtn_icnt); e.u2(rec.rslt_cnt); e.kx_n1(&rec.rtn_stat); e.kx_r4(&rec.rtn_rslt);
+            e.cn(&rec.test_txt); e.cn(&rec.alarm_id);
+
+            // optional fields end the record at the first one missing, same as rust_stdf reads them
+            let _ = (|| {
+                e.b1(rec.opt_flag?); e.i1(rec.res_scal?); e.i1(rec.llm_scal?); e.i1(rec.hlm_scal?);
+                e.r4(rec.lo_limit?); e.r4(rec.hi_limit?); e.r4(rec.start_in?); e.r4(rec.incr_in?);
+                e.kx_u2(rec.rtn_indx.as_ref()?);
+                e.cn(rec.units.as_ref()?); e.cn(rec.units_in.as_ref()?);
+                e.cn(rec.c_resfmt.as_ref()?); e.cn(rec.c_llmfmt.as_ref()?); e.cn(rec.c_hlmfmt.as_ref()?);
+                e.r4(rec.lo_spec?); e.r4(rec.hi_spec?);
+                Some(())
+            })();
+        },
+        StdfRecord::FTR(rec) => {
+            e.count("RTN_INDX", rec.rtn_icnt as usize, rec.rtn_indx.len());
+            e.count("RTN_STAT", rec.rtn_icnt as usize, rec.rtn_stat.len());
+            e.count("PGM_INDX", rec.pgm_icnt as usize, rec.pgm_indx.len());
+            e.count("PGM_STAT", rec.pgm_icnt as usize, rec.pgm_stat.len());
+            e.u4(rec.test_num); e.u1(rec.head_num); e.u1(rec.site_num); e.b1(rec.test_flg); e.b1(rec.opt_flag);
+            e.u4(rec.cycl_cnt); e.u4(rec.rel_vadr); e.u4(rec.rept_cnt); e.u4(rec.num_fail);
+            e.i4(rec.xfail_ad); e.i4(rec.yfail_ad); e.i2(rec.vect_off);
+            e.u2(rec.rtn_icnt); e.u2(rec.pgm_icnt);
+            e.kx_u2(&rec.rtn_indx); e.kx_n1(&rec.rtn_stat); e.kx_u2(&rec.pgm_indx); e.kx_n1(&rec.pgm_stat);
+            e.dn(&rec.fail_pin);
+            for text in [&rec.vect_nam, &rec.time_set, &rec.op_code, &rec.test_txt, &rec.alarm_id, &rec.prog_txt, &rec.rslt_txt] {
+                e.cn(text);
+            }
+            e.u1(rec.patg_num); e.dn(&rec.spin_map);
+        },
+        StdfRecord::STR(rec) => {
+            e.count("LIM_INDX", rec.lim_cnt as usize, rec.lim_indx.len());
+            e.count("LIM_SPEC", rec.lim_cnt as usize, rec.lim_spec.len());
+            e.count("COND_LST", rec.cond_cnt as usize, rec.cond_lst.len());
+            e.count("EXP_DATA", rec.exp_cnt as usize, rec.exp_data.len());
+            e.count("CAP_DATA", rec.cap_cnt as usize, rec.cap_data.len());
+            e.count("NEW_DATA", rec.new_cnt as usize, rec.new_data.len());
+            e.count("USER_TXT", rec.txt_cnt as usize, rec.user_txt.len());
+            e.b1(rec.cont_flg); e.u4(rec.test_num); e.u1(rec.head_num); e.u1(rec.site_num); e.u2(rec.psr_ref); e.b1(rec.test_flg);
+            for text in [&rec.log_typ, &rec.test_txt, &rec.alarm_id, &rec.prog_txt, &rec.rslt_txt] {
+                e.cn(text);
+            }
+            e.u1(rec.z_val); e.b1(rec.fmu_flg); e.dn(&rec.mask_map); e.dn(&rec.fal_map);
+            e.u8(rec.cyc_cnt_t); e.u4(rec.totf_cnt); e.u4(rec.totl_cnt); e.u8(rec.cyc_base); e.u4(rec.bit_base);
+            e.u2(rec.cond_cnt); e.u2(rec.lim_cnt);
+            for size in [rec.cyc_size, rec.pmr_size, rec.chn_size, rec.pat_size, rec.bit_size, rec.u1_size, rec.u2_size, rec.u3_size, rec.utx_size] {
+                e.u1(size);
+            }
+            e.u2(rec.cap_bgn); e.kx_u2(&rec.lim_indx); e.kx_u4(&rec.lim_spec); e.kx_cn(&rec.cond_lst);
+            e.u2(rec.cyc_cnt); e.kx_uf("CYC_OFST", &rec.cyc_ofst, rec.cyc_size);
+            e.u2(rec.pmr_cnt); e.kx_uf("PMR_INDX", &rec.pmr_indx, rec.pmr_size);
+            e.u2(rec.chn_cnt); e.kx_uf("CHN_NUM", &rec.chn_num, rec.chn_size);
+            e.u2(rec.exp_cnt); e.kx_u1(&rec.exp_data);
+            e.u2(rec.cap_cnt); e.kx_u1(&rec.cap_data);
+            e.u2(rec.new_cnt); e.kx_u1(&rec.new_data);
+            e.u2(rec.pat_cnt); e.kx_uf("PAT_NUM", &rec.pat_num, rec.pat_size);
+            e.u2(rec.bpos_cnt); e.kx_uf("BIT_POS", &rec.bit_pos, rec.bit_size);
+            e.u2(rec.usr1_cnt); e.kx_uf("USR1", &rec.usr1, rec.u1_size);
+            e.u2(rec.usr2_cnt); e.kx_uf("USR2", &rec.usr2, rec.u2_size);
+            e.u2(rec.usr3_cnt); e.kx_uf("USR3", &rec.usr3, rec.u3_size);
+            e.u2(rec.txt_cnt); e.kx_cf(&rec.user_txt, rec.utx_size);
+        },
+        StdfRecord::BPS(rec) => e.cn(&rec.seq_name),
+        StdfRecord::EPS(_) => {},
+        StdfRecord::GDR(rec) => {
+            e.count("GEN_DATA", rec.fld_cnt as usize, rec.gen_data.len());
+            e.u2(rec.fld_cnt);
+            rec.gen_data.iter().for_each(|v| e.v1(v));
+        },
+        StdfRecord::DTR(rec) => e.cn(&rec.text_dat),
+        // rust_stdf doesn't keep the type of these, so there is nothing to write them as
+        StdfRecord::ReservedRec(_) | StdfRecord::InvalidRec(_) => return Err("reserved and invalid records can't be written".into()),
+    }
+
+    match e.error {
+        Some(msg) => Err(msg),
+        None if e.data.len() > u16::MAX as usize => Err(format!("record data of {} bytes is longer than 65535 bytes", e.data.len())),
+        None => Ok((rec_typ, rec_sub, e.data)),
+    }
+}
+
+// destination of the STDF, compressed based on the file extension
+enum StdfOutput {
+    Uncompressed(BufWriter<File>),
+    #[cfg(feature = "gzip")]
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: Writes StdfRecords to a new STDF file, the FAR is always the
+///              first record and matches the byte order the file is written in
+//////////////////////////////////////////////////////////////////////
+pub struct StdfWriter {
+    path: String,
+    output: StdfOutput,
+    byte_order: ByteOrder,
+    far_written: bool,
+}
+
+impl StdfWriter {
+    //////////////////////////////////////////////////////////////////////
+    /// Description: Creates the STDF, files ending in .gz are gzip compressed
+    //////////////////////////////////////////////////////////////////////
+    pub fn new(path: &String, byte_order: ByteOrder) -> Result<Self, StdfUtilError> {
+        let stdf_file = BufWriter::with_capacity(2 << 20, File::create(path).map_err(StdfUtilError::io(path))?);
+
+        let output = match path.rsplit('.').next() {
+            #[cfg(feature = "gzip")]
+            Some("gz") => StdfOutput::Gzip(GzEncoder::new(stdf_file, Compression::default())),
+            _ => StdfOutput::Uncompressed(stdf_file),
+        };
+
+        Ok(Self { path: path.to_owned(), output, byte_order, far_written: false })
+    }
+
+    /// Byte order of every record written
+    pub fn byte_order(&self) -> ByteOrder {
+        self.byte_order
+    }
+
+    fn write_raw(&mut self, rec_typ: u8, rec_sub: u8, data: &[u8]) -> Result<(), StdfUtilError> {
+        let rec_len = data.len() as u16;
+        let mut raw = match self.byte_order {
+            ByteOrder::LittleEndian => rec_len.to_le_bytes().to_vec(),
+            ByteOrder::BigEndian => rec_len.to_be_bytes().to_vec(),
+        };
+        raw.extend([rec_typ, rec_sub]);
+        raw.extend(data);
+
+        match &mut self.output {
+            StdfOutput::Uncompressed(out) => out.write_all(&raw),
+            #[cfg(feature = "gzip")]
+            StdfOutput::Gzip(out) => out.write_all(&raw),
+        }.map_err(StdfUtilError::io(&self.path))
+    }
+
+    //////////////////////////////////////////////////////////////////////
+    /// Description: Writes the record, a default FAR is written ahead of the first
+    ///              record when it isn't a FAR and any later FAR is an error
+    //////////////////////////////////////////////////////////////////////
+    pub fn write_record(&mut self, rec: &StdfRecord) -> Result<(), StdfUtilError> {
+        let rec_name = stdf_record_type::get_rec_name_from_code(rec.get_type());
+
+        if !self.far_written {
+            // cpu_type tells other readers the byte order, 1 is big endian (sun) and 2 little endian (x86)
+            let cpu_type = match self.byte_order { ByteOrder::BigEndian => 1, ByteOrder::LittleEndian => 2 };
+            let stdf_ver = if let StdfRecord::FAR(far) = rec { far.stdf_ver } else { 4 };
+
+            self.write_raw(0, 10, &[cpu_type, stdf_ver])?;
+            self.far_written = true;
+
+            if let StdfRecord::FAR(_) = rec {
+                return Ok(());
+            }
+        } else if let StdfRecord::FAR(_) = rec {
+            return Err(StdfUtilError::Encode { path: self.path.to_owned(), record: rec_name, msg: "FAR has to be the first record".into() });
+        }
+
+        let (rec_typ, rec_sub, data) = encode_record(rec, self.byte_order)
+            .map_err(|msg| StdfUtilError::Encode { path: self.path.to_owned(), record: rec_name, msg })?;
+
+        self.write_raw(rec_typ, rec_sub, &data)
+    }
+
+    //////////////////////////////////////////////////////////////////////
+    /// Description: Flushes the STDF and finishes the compression stream if any
+    //////////////////////////////////////////////////////////////////////
+    pub fn finish(self) -> Result<(), StdfUtilError> {
+        let path = self.path;
+        let stdf_file = match self.output {
+            StdfOutput::Uncompressed(out) => out,
+            #[cfg(feature = "gzip")]
+            StdfOutput::Gzip(out) => out.finish().map_err(StdfUtilError::io(&path))?,
+        };
+
+        stdf_file.into_inner().map(|_| ()).map_err(|e| StdfUtilError::Io { path: path.to_owned(), source: e.into_error() })
+    }
+}
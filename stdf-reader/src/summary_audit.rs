@@ -0,0 +1,151 @@
+use std::{collections::{BTreeMap, BTreeSet}, fmt};
+
+use rust_stdf::*;
+
+use crate::{summary_counts::{summary_matches, SummaryCounts}, StdfParser, StdfUtilError};
+
+// counts the STDF leaves empty, they can't be checked
+const MISSING_COUNT: u32 = u32::MAX;
+
+//////////////////////////////////////////////////////////////////////
+/// Description: A summary record count that disagrees with the PRR's and test
+///              records of the STDF, or a summary record that is missing
+//////////////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, PartialEq)]
+pub struct SummaryMismatch {
+    /// PCR, HBR, SBR or TSR
+    pub record: &'static str,
+    pub head_num: u8,
+    pub site_num: u8,
+    /// Bin number of an HBR/SBR, test number of a TSR, 0 for a PCR
+    pub number: u32,
+    /// Field that is off, e.g. hbin_cnt
+    pub field: &'static str,
+    /// Count found in the summary record, None when the record is missing
+    pub reported: Option<u32>,
+    /// Count recomputed from the PRR's and test records
+    pub computed: u32,
+}
+
+impl fmt::Display for SummaryMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let number = match self.record {
+            "HBR" | "SBR" => format!(" bin {}", self.number),
+            "TSR" => format!(" test {}", self.number),
+            _ => String::new(),
+        };
+
+        match self.reported {
+            Some(reported) => write!(f, "{} head {} site {}{}: {} is {} but the STDF data gives {}", self.record, self.head_num, self.site_num, number, self.field, reported, self.computed),
+            None => write!(f, "{} head {} site {}{} is missing, the STDF data gives {} {}", self.record, self.head_num, self.site_num, number, self.field, self.computed),
+        }
+    }
+}
+
+/// Result of audit_summary
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SummaryAudit {
+    pub parts_read: u32,
+    pub summary_recs: u32,
+    pub mismatches: Vec<SummaryMismatch>,
+}
+
+impl SummaryAudit {
+    /// true when every summary record agrees with the STDF data
+    pub fn passed(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+// compares the counts of a summary record with the ones recomputed from the STDF data
+fn audit_record(rec: &StdfRecord, counts: &SummaryCounts) -> Vec<SummaryMismatch> {
+    let mut computed = rec.to_owned();
+    counts.update_summary(&mut computed);
+
+    let (record, head_num, site_num, number, fields) = match (rec, &computed) {
+        (StdfRecord::PCR(rec), StdfRecord::PCR(computed)) => ("PCR", rec.head_num, rec.site_num, 0, vec![
+            ("part_cnt", rec.part_cnt, computed.part_cnt),
+            ("rtst_cnt", rec.rtst_cnt, computed.rtst_cnt),
+            ("abrt_cnt", rec.abrt_cnt, computed.abrt_cnt),
+            ("good_cnt", rec.good_cnt, computed.good_cnt),
+        ]),
+        (StdfRecord::HBR(rec), StdfRecord::HBR(computed)) => ("HBR", rec.head_num, rec.site_num, rec.hbin_num as u32, vec![("hbin_cnt", rec.hbin_cnt, computed.hbin_cnt)]),
+        (StdfRecord::SBR(rec), StdfRecord::SBR(computed)) => ("SBR", rec.head_num, rec.site_num, rec.sbin_num as u32, vec![("sbin_cnt", rec.sbin_cnt, computed.sbin_cnt)]),
+        (StdfRecord::TSR(rec), StdfRecord::TSR(computed)) => ("TSR", rec.head_num, rec.site_num, rec.test_num, vec![
+            ("exec_cnt", rec.exec_cnt, computed.exec_cnt),
+            ("fail_cnt", rec.fail_cnt, computed.fail_cnt),
+            ("alrm_cnt", rec.alrm_cnt, computed.alrm_cnt),
+        ]),
+        _ => return Vec::new(),
+    };
+
+    fields.into_iter()
+        .filter(|(_, reported, computed)| *reported != MISSING_COUNT && reported != computed)
+        .map(|(field, reported, computed)| SummaryMismatch { record, head_num, site_num, number, field, reported: Some(reported), computed })
+        .collect()
+}
+
+// bins/tests with data but no summary record, only checked for the head/sites the STDF has summary records for
+fn audit_missing(record: &'static str, field: &'static str, found: &BTreeSet<(u8, u8, u32)>, data: impl Iterator<Item = (u8, u8, u32, u32)>) -> Vec<SummaryMismatch> {
+    let scopes: BTreeSet<(u8, u8)> = found.iter().map(|(head, site, _)| (*head, *site)).collect();
+
+    let mut computed: BTreeMap<(u8, u8, u32), u32> = BTreeMap::new();
+    for (head, site, number, count) in data.filter(|(.., count)| *count > 0) {
+        for (scope_head, scope_site) in scopes.iter().filter(|(scope_head, scope_site)| summary_matches(*scope_head, *scope_site, head, site)) {
+            *computed.entry((*scope_head, *scope_site, number)).or_default() += count;
+        }
+    }
+
+    computed.into_iter()
+        .filter(|(key, _)| !found.contains(key))
+        .map(|((head_num, site_num, number), computed)| SummaryMismatch { record, head_num, site_num, number, field, reported: None, computed })
+        .collect()
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: Recomputes the part, bin and test counts of the PCR/HBR/SBR/TSR
+///              records from the PRR's and test records and reports every mismatch
+//////////////////////////////////////////////////////////////////////
+pub fn audit_summary(stdf_path: &String) -> Result<SummaryAudit, StdfUtilError> {
+    // counts don't need the test defaults
+    let mut parser = StdfParser::new(stdf_path, &None)?;
+    parser.set_use_test_defaults(false);
+
+    let mut audit = SummaryAudit::default();
+    let mut counts = SummaryCounts::default();
+    let mut summary_recs = Vec::new();
+
+    for parsed_rec in parser {
+        let rec = parsed_rec?.record;
+        match &rec {
+            StdfRecord::PRR(prr) => {
+                audit.parts_read += 1;
+                counts.add_part(prr);
+            },
+            StdfRecord::PTR(_) | StdfRecord::MPR(_) | StdfRecord::FTR(_) | StdfRecord::STR(_) => counts.add_test(&rec),
+            StdfRecord::PCR(_) | StdfRecord::HBR(_) | StdfRecord::SBR(_) | StdfRecord::TSR(_) => summary_recs.push(rec),
+            _ => {},
+        }
+    }
+
+    // summary records usually come last, but nothing says they have to
+    audit.summary_recs = summary_recs.len() as u32;
+    let mut hard_bins = BTreeSet::new();
+    let mut soft_bins = BTreeSet::new();
+    let mut tests = BTreeSet::new();
+    for rec in &summary_recs {
+        audit.mismatches.extend(audit_record(rec, &counts));
+        match rec {
+            StdfRecord::HBR(rec) => { hard_bins.insert((rec.head_num, rec.site_num, rec.hbin_num as u32)); },
+            StdfRecord::SBR(rec) => { soft_bins.insert((rec.head_num, rec.site_num, rec.sbin_num as u32)); },
+            StdfRecord::TSR(rec) => { tests.insert((rec.head_num, rec.site_num, rec.test_num)); },
+            _ => {},
+        }
+    }
+
+    audit.mismatches.extend(audit_missing("HBR", "hbin_cnt", &hard_bins, counts.hard_bins.iter().map(|((head, site, bin), count)| (*head, *site, *bin as u32, *count))));
+    audit.mismatches.extend(audit_missing("SBR", "sbin_cnt", &soft_bins, counts.soft_bins.iter().map(|((head, site, bin), count)| (*head, *site, *bin as u32, *count))));
+    audit.mismatches.extend(audit_missing("TSR", "exec_cnt", &tests, counts.tests.iter().map(|((head, site, test_num), test)| (*head, *site, *test_num, test.exec_cnt))));
+
+    Ok(audit)
+}
@@ -0,0 +1,449 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use rust_stdf::*;
+
+use crate::{PartFlags, TestFlags, TsrOptFlags};
+
+// part counts for PCR and WRR records
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct PartCounts {
+    pub part_cnt: u32,
+    pub rtst_cnt: u32,
+    pub abrt_cnt: u32,
+    pub good_cnt: u32,
+}
+
+impl PartCounts {
+    pub fn add(&mut self, rec: &PRR) {
+        self.part_cnt += 1;
+        let part_flags = PartFlags::from(rec.part_flg);
+        if part_flags.is_retest() { self.rtst_cnt += 1; }
+        if part_flags.abnormal_end() { self.abrt_cnt += 1; }
+        if part_flags.is_pass() { self.good_cnt += 1; }
+    }
+
+    pub fn merge(&mut self, other: &PartCounts) {
+        self.part_cnt += other.part_cnt;
+        self.rtst_cnt += other.rtst_cnt;
+        self.abrt_cnt += other.abrt_cnt;
+        self.good_cnt += other.good_cnt;
+    }
+}
+
+// execution counts and result statistics for TSR records
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct TestCounts {
+    pub exec_cnt: u32,
+    pub fail_cnt: u32,
+    pub alrm_cnt: u32,
+    pub result_cnt: u32,
+    pub test_min: f32,
+    pub test_max: f32,
+    pub tst_sums: f32,
+    pub tst_sqrs: f32,
+}
+
+impl TestCounts {
+    pub fn add(&mut self, test_flg: [u8; 1], result: Option<f32>) {
+        self.exec_cnt += 1;
+        let test_flags = TestFlags::from(test_flg);
+        if test_flags.is_fail() { self.fail_cnt += 1; }
+        if test_flags.alarm() { self.alrm_cnt += 1; }
+
+        // only valid results count towards the statistics
+        if let Some(result) = result.filter(|result| result.is_finite() && !test_flags.result_invalid()) {
+            self.test_min = if self.result_cnt == 0 { result } else { self.test_min.min(result) };
+            self.test_max = if self.result_cnt == 0 { result } else { self.test_max.max(result) };
+            self.tst_sums += result;
+            self.tst_sqrs += result * result;
+            self.result_cnt += 1;
+        }
+    }
+
+    pub fn merge(&mut self, other: &TestCounts) {
+        if other.result_cnt > 0 {
+            self.test_min = if self.result_cnt == 0 { other.test_min } else { self.test_min.min(other.test_min) };
+            self.test_max = if self.result_cnt == 0 { other.test_max } else { self.test_max.max(other.test_max) };
+        }
+        self.exec_cnt += other.exec_cnt;
+        self.fail_cnt += other.fail_cnt;
+        self.alrm_cnt += other.alrm_cnt;
+        self.result_cnt += other.result_cnt;
+        self.tst_sums += other.tst_sums;
+        self.tst_sqrs += other.tst_sqrs;
+    }
+}
+
+// head/site 255 in a summary record stands for all heads/sites
+pub(crate) fn summary_matches(rec_head: u8, rec_site: u8, head: u8, site: u8) -> bool {
+    rec_head == 255 || (rec_head == head && (rec_site == 255 || rec_site == site))
+}
+
+// summary records of the same kind and head/site/number, PCR's come first, then HBR's, SBR's and TSR's
+pub(crate) fn summary_key(rec: &StdfRecord) -> Option<(u8, u8, u8, u32)> {
+    match rec {
+        StdfRecord::PCR(rec) => Some((0, rec.head_num, rec.site_num, 0)),
+        StdfRecord::HBR(rec) => Some((1, rec.head_num, rec.site_num, rec.hbin_num as u32)),
+        StdfRecord::SBR(rec) => Some((2, rec.head_num, rec.site_num, rec.sbin_num as u32)),
+        StdfRecord::TSR(rec) => Some((3, rec.head_num, rec.site_num, rec.test_num)),
+        _ => None,
+    }
+}
+
+pub(crate) fn sum_matching<K, T: Default>(map: &HashMap<K, T>, matches: impl Fn(&K) -> bool, merge: impl Fn(&mut T, &T)) -> T {
+    let mut total = T::default();
+    map.iter().filter(|(key, _)| matches(key)).for_each(|(_, value)| merge(&mut total, value));
+    total
+}
+
+// counts of the parts and tests written, used to keep summary records consistent with them
+#[derive(Default)]
+pub(crate) struct SummaryCounts {
+    pub parts: HashMap<(u8, u8), PartCounts>,
+    pub wafer_parts: HashMap<u8, PartCounts>,
+    pub hard_bins: HashMap<(u8, u8, u16), u32>,
+    pub soft_bins: HashMap<(u8, u8, u16), u32>,
+    pub tests: HashMap<(u8, u8, u32), TestCounts>,
+    // type and name of the tests counted, for the TSR's the STDF doesn't have
+    pub test_names: HashMap<u32, (char, String)>,
+}
+
+impl SummaryCounts {
+    pub fn add_part(&mut self, rec: &PRR) {
+        self.parts.entry((rec.head_num, rec.site_num)).or_default().add(rec);
+        self.wafer_parts.entry(rec.head_num).or_default().add(rec);
+        *self.hard_bins.entry((rec.head_num, rec.site_num, rec.hard_bin)).or_default() += 1;
+        *self.soft_bins.entry((rec.head_num, rec.site_num, rec.soft_bin)).or_default() += 1;
+    }
+
+    pub fn add_test(&mut self, rec: &StdfRecord) {
+        let (test_typ, test_flg, result) = match rec {
+            StdfRecord::PTR(rec) => ('P', rec.test_flg, Some(rec.result)),
+            StdfRecord::MPR(rec) => ('M', rec.test_flg, None),
+            StdfRecord::FTR(rec) => ('F', rec.test_flg, None),
+            StdfRecord::STR(rec) => (' ', rec.test_flg, None),
+            _ => return,
+        };
+        let Some((head_num, site_num, test_num, test_txt)) = result_test(rec) else { return };
+        self.tests.entry((head_num, site_num, test_num)).or_default().add(test_flg, result);
+
+        // later records of a test may leave the name out
+        let (_, test_nam) = self.test_names.entry(test_num).or_insert_with(|| (test_typ, String::new()));
+        if test_nam.is_empty() { *test_nam = test_txt.to_owned(); }
+    }
+
+    // a WIR starts counting the parts of its head again
+    pub fn start_wafer(&mut self, head_num: u8) {
+        self.wafer_parts.remove(&head_num);
+    }
+
+    pub fn update_wrr(&self, rec: &mut WRR) {
+        let counts = self.wafer_parts.get(&rec.head_num).copied().unwrap_or_default();
+        rec.part_cnt = counts.part_cnt;
+        rec.rtst_cnt = counts.rtst_cnt;
+        rec.abrt_cnt = counts.abrt_cnt;
+        rec.good_cnt = counts.good_cnt;
+        rec.func_cnt = u32::MAX;
+    }
+
+    // recomputes the counts of a summary record from the kept parts and tests
+    pub fn update_summary(&self, rec: &mut StdfRecord) {
+        match rec {
+            StdfRecord::PCR(rec) => {
+                let counts = sum_matching(&self.parts, |(head, site)| summary_matches(rec.head_num, rec.site_num, *head, *site), PartCounts::merge);
+                rec.part_cnt = counts.part_cnt;
+                rec.rtst_cnt = counts.rtst_cnt;
+                rec.abrt_cnt = counts.abrt_cnt;
+                rec.good_cnt = counts.good_cnt;
+                rec.func_cnt = u32::MAX;
+            },
+            StdfRecord::HBR(rec) => {
+                rec.hbin_cnt = sum_matching(&self.hard_bins, |(head, site, bin)| *bin == rec.hbin_num && summary_matches(rec.head_num, rec.site_num, *head, *site), |a, b| *a += b);
+            },
+            StdfRecord::SBR(rec) => {
+                rec.sbin_cnt = sum_matching(&self.soft_bins, |(head, site, bin)| *bin == rec.sbin_num && summary_matches(rec.head_num, rec.site_num, *head, *site), |a, b| *a += b);
+            },
+            StdfRecord::TSR(rec) => {
+                let counts = sum_matching(&self.tests, |(head, site, test_num)| *test_num == rec.test_num && summary_matches(rec.head_num, rec.site_num, *head, *site), TestCounts::merge);
+                rec.exec_cnt = counts.exec_cnt;
+                rec.fail_cnt = counts.fail_cnt;
+                rec.alrm_cnt = counts.alrm_cnt;
+
+                // min, max, sums and squares are only valid when there were results to compute them from
+                let mut opt_flags = TsrOptFlags::from(rec.opt_flag);
+                opt_flags.set_result_stats_valid(counts.result_cnt > 0);
+                rec.opt_flag = [opt_flags.0];
+                if counts.result_cnt > 0 {
+                    rec.test_min = counts.test_min;
+                    rec.test_max = counts.test_max;
+                    rec.tst_sums = counts.tst_sums;
+                    rec.tst_sqrs = counts.tst_sqrs;
+                }
+            },
+            _ => {},
+        }
+    }
+
+    // summary records for the parts, bins and tests counted that the written ones don't cover, in the
+    // head/site scopes the written records of the same kind use or for all heads/sites without any,
+    // update_summary fills in their counts
+    pub fn missing_summaries(&self, written: &[StdfRecord]) -> Vec<StdfRecord> {
+        let written: HashSet<(u8, u8, u8, u32)> = written.iter().filter_map(summary_key).collect();
+        let mut scopes: [BTreeSet<(u8, u8)>; 4] = Default::default();
+        for (kind, head, site, _) in &written {
+            scopes[*kind as usize].insert((*head, *site));
+        }
+        for kind_scopes in scopes.iter_mut().filter(|kind_scopes| kind_scopes.is_empty()) {
+            kind_scopes.insert((255, 255));
+        }
+
+        let counted = self.parts.keys().map(|(head, site)| (0, *head, *site, 0))
+            .chain(self.hard_bins.keys().map(|(head, site, bin)| (1, *head, *site, *bin as u32)))
+            .chain(self.soft_bins.keys().map(|(head, site, bin)| (2, *head, *site, *bin as u32)))
+            .chain(self.tests.keys().map(|(head, site, test_num)| (3, *head, *site, *test_num)));
+        let mut missing = BTreeSet::new();
+        for (kind, head, site, number) in counted {
+            for (scope_head, scope_site) in scopes[kind as usize].iter().filter(|(scope_head, scope_site)| summary_matches(*scope_head, *scope_site, head, site)) {
+                let key = (kind, *scope_head, *scope_site, number);
+                if !written.contains(&key) { missing.insert(key); }
+            }
+        }
+
+        missing.into_iter().map(|(kind, head_num, site_num, number)| match kind {
+            0 => StdfRecord::PCR(PCR { head_num, site_num, ..Default::default() }),
+            1 => StdfRecord::HBR(HBR { head_num, site_num, hbin_num: number as u16, ..Default::default() }),
+            2 => StdfRecord::SBR(SBR { head_num, site_num, sbin_num: number as u16, ..Default::default() }),
+            _ => {
+                let (test_typ, test_nam) = self.test_names.get(&number).cloned().unwrap_or((' ', String::new()));
+                StdfRecord::TSR(TSR { head_num, site_num, test_num: number, test_typ, test_nam, opt_flag: [TsrOptFlags::TEST_TIM_INVALID], ..Default::default() })
+            },
+        }).collect()
+    }
+}
+
+pub(crate) fn result_test(rec: &StdfRecord) -> Option<(u8, u8, u32, &str)> {
+    match rec {
+        StdfRecord::PTR(rec) => Some((rec.head_num, rec.site_num, rec.test_num, rec.test_txt.as_str())),
+        StdfRecord::MPR(rec) => Some((rec.head_num, rec.site_num, rec.test_num, rec.test_txt.as_str())),
+        StdfRecord::FTR(rec) => Some((rec.head_num, rec.site_num, rec.test_num, rec.test_txt.as_str())),
+        StdfRecord::STR(rec) => Some((rec.head_num, rec.site_num, rec.test_num, rec.test_txt.as_str())),
+        _ => None,
+    }
+}
+
+// owner of a program section opened by a BPS
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SectionOwner {
+    // the BPS was written, its EPS has to be too
+    Written,
+    // the BPS was dropped with its part, so is its EPS
+    Dropped,
+    // the BPS is in the records of the part in test on (head, site)
+    Part((u8, u8)),
+}
+
+// records of the parts in test by (head, site), from PIR up to the PRR
+#[derive(Default)]
+pub(crate) struct OpenParts {
+    parts: HashMap<(u8, u8), Vec<StdfRecord>>,
+    // part opened last, the owner of the records that don't name a head/site
+    last_opened: Option<(u8, u8)>,
+    // program sections not ended yet, innermost last
+    sections: Vec<SectionOwner>,
+}
+
+impl OpenParts {
+    pub fn open(&mut self, key: (u8, u8), pir: StdfRecord) {
+        self.parts.insert(key, vec![pir]);
+        self.last_opened = Some(key);
+    }
+
+    // adds a result, a result without a PIR opens its part
+    pub fn add_result(&mut self, key: (u8, u8), rec: StdfRecord) {
+        if !self.parts.contains_key(&key) { self.last_opened = Some(key); }
+        self.parts.entry(key).or_default().push(rec);
+    }
+
+    // DTR/GDR/BPS/EPS records don't name a head/site, between a PIR and its PRR they go to the part
+    // opened last so they are written once and dropped with it, an EPS goes where its BPS went.
+    // The record is handed back when it has to be written now
+    pub fn add_unowned(&mut self, rec: StdfRecord) -> Option<StdfRecord> {
+        let owner = self.last_opened.filter(|key| self.parts.contains_key(key));
+
+        let owner = match rec {
+            StdfRecord::BPS(_) => {
+                self.sections.push(owner.map_or(SectionOwner::Written, SectionOwner::Part));
+                owner
+            },
+            StdfRecord::EPS(_) => match self.sections.pop() {
+                Some(SectionOwner::Part(key)) => Some(key),
+                Some(SectionOwner::Dropped) => return None,
+                Some(SectionOwner::Written) | None => None,
+            },
+            _ => owner,
+        };
+
+        match owner.and_then(|key| self.parts.get_mut(&key)) {
+            Some(records) => { records.push(rec); None },
+            None => Some(rec),
+        }
+    }
+
+    // takes the records of a part that is ending, kept tells whether they are written
+    pub fn close(&mut self, key: (u8, u8), kept: bool) -> Option<Vec<StdfRecord>> {
+        let records = self.parts.remove(&key)?;
+        let resolved = if kept { SectionOwner::Written } else { SectionOwner::Dropped };
+        self.sections.iter_mut().filter(|owner| **owner == SectionOwner::Part(key)).for_each(|owner| *owner = resolved);
+        Some(records)
+    }
+
+    // takes the records of every part still in test, in head/site order
+    pub fn close_all(&mut self, kept: bool) -> Vec<Vec<StdfRecord>> {
+        let mut keys: Vec<(u8, u8)> = self.parts.keys().copied().collect();
+        keys.sort();
+        keys.into_iter().filter_map(|key| self.close(key, kept)).collect()
+    }
+}
+
+// fills the fields later PTR's are allowed to leave out from the first PTR of the test
+fn fill_ptr_defaults(rec: &mut PTR, first: &PTR) {
+    if rec.test_txt.is_empty() { rec.test_txt = first.test_txt.to_owned(); }
+    rec.opt_flag = rec.opt_flag.or(first.opt_flag);
+    rec.res_scal = rec.res_scal.or(first.res_scal);
+    rec.llm_scal = rec.llm_scal.or(first.llm_scal);
+    rec.hlm_scal = rec.hlm_scal.or(first.hlm_scal);
+    rec.lo_limit = rec.lo_limit.or(first.lo_limit);
+    rec.hi_limit = rec.hi_limit.or(first.hi_limit);
+    rec.units = rec.units.take().or_else(|| first.units.to_owned());
+    rec.c_resfmt = rec.c_resfmt.take().or_else(|| first.c_resfmt.to_owned());
+    rec.c_llmfmt = rec.c_llmfmt.take().or_else(|| first.c_llmfmt.to_owned());
+    rec.c_hlmfmt = rec.c_hlmfmt.take().or_else(|| first.c_hlmfmt.to_owned());
+    rec.lo_spec = rec.lo_spec.or(first.lo_spec);
+    rec.hi_spec = rec.hi_spec.or(first.hi_spec);
+}
+
+// fills the fields later MPR's are allowed to leave out from the first MPR of the test
+fn fill_mpr_defaults(rec: &mut MPR, first: &MPR) {
+    if rec.test_txt.is_empty() { rec.test_txt = first.test_txt.to_owned(); }
+    rec.opt_flag = rec.opt_flag.or(first.opt_flag);
+    rec.res_scal = rec.res_scal.or(first.res_scal);
+    rec.llm_scal = rec.llm_scal.or(first.llm_scal);
+    rec.hlm_scal = rec.hlm_scal.or(first.hlm_scal);
+    rec.lo_limit = rec.lo_limit.or(first.lo_limit);
+    rec.hi_limit = rec.hi_limit.or(first.hi_limit);
+    rec.start_in = rec.start_in.or(first.start_in);
+    rec.incr_in = rec.incr_in.or(first.incr_in);
+    if rec.rtn_indx.is_none() && first.rtn_indx.as_ref().is_some_and(|rtn_indx| rtn_indx.len() == rec.rtn_icnt as usize) {
+        rec.rtn_indx = first.rtn_indx.to_owned();
+    }
+    rec.units = rec.units.take().or_else(|| first.units.to_owned());
+    rec.units_in = rec.units_in.take().or_else(|| first.units_in.to_owned());
+    rec.c_resfmt = rec.c_resfmt.take().or_else(|| first.c_resfmt.to_owned());
+    rec.c_llmfmt = rec.c_llmfmt.take().or_else(|| first.c_llmfmt.to_owned());
+    rec.c_hlmfmt = rec.c_hlmfmt.take().or_else(|| first.c_hlmfmt.to_owned());
+    rec.lo_spec = rec.lo_spec.or(first.lo_spec);
+    rec.hi_spec = rec.hi_spec.or(first.hi_spec);
+}
+
+// the fields a PTR may leave out once the first PTR of its test gave them
+fn ptr_defaults(rec: &PTR) -> PTR {
+    PTR {
+        test_num: rec.test_num,
+        test_txt: rec.test_txt.to_owned(),
+        opt_flag: rec.opt_flag,
+        res_scal: rec.res_scal,
+        llm_scal: rec.llm_scal,
+        hlm_scal: rec.hlm_scal,
+        lo_limit: rec.lo_limit,
+        hi_limit: rec.hi_limit,
+        units: rec.units.to_owned(),
+        c_resfmt: rec.c_resfmt.to_owned(),
+        c_llmfmt: rec.c_llmfmt.to_owned(),
+        c_hlmfmt: rec.c_hlmfmt.to_owned(),
+        lo_spec: rec.lo_spec,
+        hi_spec: rec.hi_spec,
+        ..Default::default()
+    }
+}
+
+// the fields an MPR may leave out once the first MPR of its test gave them
+fn mpr_defaults(rec: &MPR) -> MPR {
+    MPR {
+        test_num: rec.test_num,
+        test_txt: rec.test_txt.to_owned(),
+        opt_flag: rec.opt_flag,
+        res_scal: rec.res_scal,
+        llm_scal: rec.llm_scal,
+        hlm_scal: rec.hlm_scal,
+        lo_limit: rec.lo_limit,
+        hi_limit: rec.hi_limit,
+        start_in: rec.start_in,
+        incr_in: rec.incr_in,
+        rtn_indx: rec.rtn_indx.to_owned(),
+        units: rec.units.to_owned(),
+        units_in: rec.units_in.to_owned(),
+        c_resfmt: rec.c_resfmt.to_owned(),
+        c_llmfmt: rec.c_llmfmt.to_owned(),
+        c_hlmfmt: rec.c_hlmfmt.to_owned(),
+        lo_spec: rec.lo_spec,
+        hi_spec: rec.hi_spec,
+        ..Default::default()
+    }
+}
+
+// keeps the PTR/MPR defaults of a rewritten STDF right when the records that carried them are dropped
+// or when records of several STDF's with different defaults end up in the same file
+#[derive(Default)]
+pub(crate) struct TestDefaults {
+    // first record of each test in the STDF being read
+    first_ptrs: HashMap<u32, PTR>,
+    first_mprs: HashMap<u32, MPR>,
+    // defaults carried by the first record of each test written
+    written_ptrs: HashMap<u32, PTR>,
+    written_mprs: HashMap<u32, MPR>,
+}
+
+impl TestDefaults {
+    // the next records come from another STDF with its own first records
+    pub fn next_stdf(&mut self) {
+        self.first_ptrs.clear();
+        self.first_mprs.clear();
+    }
+
+    pub fn read(&mut self, rec: &StdfRecord) {
+        match rec {
+            StdfRecord::PTR(ptr) => { self.first_ptrs.entry(ptr.test_num).or_insert_with(|| ptr.to_owned()); },
+            StdfRecord::MPR(mpr) => { self.first_mprs.entry(mpr.test_num).or_insert_with(|| mpr.to_owned()); },
+            _ => {},
+        }
+    }
+
+    // fills in the defaults a record about to be written can't rely on
+    pub fn fill(&mut self, rec: &mut StdfRecord) {
+        match rec {
+            StdfRecord::PTR(ptr) => {
+                let Some(first) = self.first_ptrs.get(&ptr.test_num) else { return };
+                match self.written_ptrs.get(&ptr.test_num) {
+                    Some(written) if *written == ptr_defaults(first) => {},
+                    Some(_) => fill_ptr_defaults(ptr, first),
+                    None => {
+                        fill_ptr_defaults(ptr, first);
+                        self.written_ptrs.insert(ptr.test_num, ptr_defaults(ptr));
+                    },
+                }
+            },
+            StdfRecord::MPR(mpr) => {
+                let Some(first) = self.first_mprs.get(&mpr.test_num) else { return };
+                match self.written_mprs.get(&mpr.test_num) {
+                    Some(written) if *written == mpr_defaults(first) => {},
+                    Some(_) => fill_mpr_defaults(mpr, first),
+                    None => {
+                        fill_mpr_defaults(mpr, first);
+                        self.written_mprs.insert(mpr.test_num, mpr_defaults(mpr));
+                    },
+                }
+            },
+            _ => {},
+        }
+    }
+}
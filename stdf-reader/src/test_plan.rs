@@ -0,0 +1,284 @@
+use std::{collections::HashMap, fmt, fs::File, io::{BufWriter, Write}};
+
+use rust_stdf::*;
+
+use crate::{test_stats::valid_limit, PtrOptFlags, StdfParser, StdfUtilError};
+
+//////////////////////////////////////////////////////////////////////
+/// Description: A test of the test plan as its first PTR/MPR/FTR gives it,
+///              fields the record type doesn't have are None/empty
+//////////////////////////////////////////////////////////////////////
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TestPlanEntry {
+    /// PTR, MPR or FTR
+    pub rec_type: &'static str,
+    pub test_num: u32,
+    pub test_txt: String,
+    pub units: String,
+    pub lo_limit: Option<f32>,
+    pub hi_limit: Option<f32>,
+    pub lo_spec: Option<f32>,
+    pub hi_spec: Option<f32>,
+    pub res_scal: Option<i8>,
+    pub llm_scal: Option<i8>,
+    pub hlm_scal: Option<i8>,
+    pub c_resfmt: String,
+    pub c_llmfmt: String,
+    pub c_hlmfmt: String,
+    /// Number of pins of an MPR
+    pub pin_count: Option<u16>,
+    pub vect_nam: String,
+    pub time_set: String,
+    pub op_code: String,
+}
+
+impl TestPlanEntry {
+    /// Entry of the first PTR/MPR/FTR of a test, None for other records
+    pub fn from_record(rec: &StdfRecord) -> Option<Self> {
+        // limits, specs and scales flagged as invalid are left out
+        let opt_flags = match rec {
+            StdfRecord::PTR(rec) => PtrOptFlags::from(rec.opt_flag),
+            StdfRecord::MPR(rec) => PtrOptFlags::from(rec.opt_flag),
+            _ => PtrOptFlags::default(),
+        };
+
+        let entry = match rec {
+            StdfRecord::PTR(rec) => Self {
+                rec_type: "PTR",
+                test_num: rec.test_num,
+                test_txt: rec.test_txt.to_owned(),
+                units: rec.units.to_owned().unwrap_or_default(),
+                lo_limit: valid_limit(rec.lo_limit, opt_flags.lo_limit_valid()).map(|limit| limit as f32),
+                hi_limit: valid_limit(rec.hi_limit, opt_flags.hi_limit_valid()).map(|limit| limit as f32),
+                lo_spec: rec.lo_spec.filter(|_| opt_flags.lo_spec_valid()),
+                hi_spec: rec.hi_spec.filter(|_| opt_flags.hi_spec_valid()),
+                res_scal: rec.res_scal.filter(|_| opt_flags.res_scal_valid()),
+                llm_scal: rec.llm_scal.filter(|_| opt_flags.lo_limit_valid()),
+                hlm_scal: rec.hlm_scal.filter(|_| opt_flags.hi_limit_valid()),
+                c_resfmt: rec.c_resfmt.to_owned().unwrap_or_default(),
+                c_llmfmt: rec.c_llmfmt.to_owned().unwrap_or_default(),
+                c_hlmfmt: rec.c_hlmfmt.to_owned().unwrap_or_default(),
+                ..Default::default()
+            },
+            StdfRecord::MPR(rec) => Self {
+                rec_type: "MPR",
+                test_num: rec.test_num,
+                test_txt: rec.test_txt.to_owned(),
+                units: rec.units.to_owned().unwrap_or_default(),
+                lo_limit: valid_limit(rec.lo_limit, opt_flags.lo_limit_valid()).map(|limit| limit as f32),
+                hi_limit: valid_limit(rec.hi_limit, opt_flags.hi_limit_valid()).map(|limit| limit as f32),
+                lo_spec: rec.lo_spec.filter(|_| opt_flags.lo_spec_valid()),
+                hi_spec: rec.hi_spec.filter(|_| opt_flags.hi_spec_valid()),
+                res_scal: rec.res_scal.filter(|_| opt_flags.res_scal_valid()),
+                llm_scal: rec.llm_scal.filter(|_| opt_flags.lo_limit_valid()),
+                hlm_scal: rec.hlm_scal.filter(|_| opt_flags.hi_limit_valid()),
+                c_resfmt: rec.c_resfmt.to_owned().unwrap_or_default(),
+                c_llmfmt: rec.c_llmfmt.to_owned().unwrap_or_default(),
+                c_hlmfmt: rec.c_hlmfmt.to_owned().unwrap_or_default(),
+                pin_count: Some(rec.rtn_icnt.max(rec.rslt_cnt)),
+                ..Default::default()
+            },
+            StdfRecord::FTR(rec) => Self {
+                rec_type: "FTR",
+                test_num: rec.test_num,
+                test_txt: rec.test_txt.to_owned(),
+                vect_nam: rec.vect_nam.to_owned(),
+                time_set: rec.time_set.to_owned(),
+                op_code: rec.op_code.to_owned(),
+                ..Default::default()
+            },
+            _ => return None,
+        };
+        Some(entry)
+    }
+
+    //////////////////////////////////////////////////////////////////////
+    /// Description: Name and value of the fields compared between test plans,
+    ///              everything but the record type, test number and name
+    //////////////////////////////////////////////////////////////////////
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let opt = |value: Option<String>| value.unwrap_or_default();
+        vec![
+            ("units", self.units.to_owned()),
+            ("lo_limit", opt(self.lo_limit.map(|value| value.to_string()))),
+            ("hi_limit", opt(self.hi_limit.map(|value| value.to_string()))),
+            ("lo_spec", opt(self.lo_spec.map(|value| value.to_string()))),
+            ("hi_spec", opt(self.hi_spec.map(|value| value.to_string()))),
+            ("res_scal", opt(self.res_scal.map(|value| value.to_string()))),
+            ("llm_scal", opt(self.llm_scal.map(|value| value.to_string()))),
+            ("hlm_scal", opt(self.hlm_scal.map(|value| value.to_string()))),
+            ("c_resfmt", self.c_resfmt.to_owned()),
+            ("c_llmfmt", self.c_llmfmt.to_owned()),
+            ("c_hlmfmt", self.c_hlmfmt.to_owned()),
+            ("pin_count", opt(self.pin_count.map(|value| value.to_string()))),
+            ("vect_nam", self.vect_nam.to_owned()),
+            ("time_set", self.time_set.to_owned()),
+            ("op_code", self.op_code.to_owned()),
+        ]
+    }
+}
+
+/// A field of a test that differs between two test plans
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    pub name: &'static str,
+    pub old: String,
+    pub new: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlanChangeKind {
+    Added,
+    Removed,
+    /// Same record type and test number, different test_txt
+    Renamed,
+    /// Same record type and test_txt, different test number
+    Renumbered,
+    /// Same record type, test number and test_txt, other fields differ
+    Changed,
+}
+
+/// A difference between two test plans, old/new are None for added/removed tests
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanChange {
+    pub kind: PlanChangeKind,
+    pub old: Option<TestPlanEntry>,
+    pub new: Option<TestPlanEntry>,
+    /// Fields that differ, besides the number or name of a renumbered/renamed test
+    pub fields: Vec<FieldChange>,
+}
+
+impl PlanChange {
+    fn matched(kind: PlanChangeKind, old: &TestPlanEntry, new: &TestPlanEntry) -> Self {
+        let fields = old.fields().into_iter().zip(new.fields())
+            .filter(|((_, old), (_, new))| old != new)
+            .map(|((name, old), (_, new))| FieldChange { name, old, new })
+            .collect();
+        Self { kind, old: Some(old.to_owned()), new: Some(new.to_owned()), fields }
+    }
+
+    // the entry the change is sorted and shown by
+    fn entry(&self) -> &TestPlanEntry {
+        self.new.as_ref().or(self.old.as_ref()).expect("a plan change has an old or new entry")
+    }
+}
+
+impl fmt::Display for PlanChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.kind, &self.old, &self.new) {
+            (PlanChangeKind::Added, _, Some(new)) => write!(f, "added {} {} '{}'", new.rec_type, new.test_num, new.test_txt)?,
+            (PlanChangeKind::Removed, Some(old), _) => write!(f, "removed {} {} '{}'", old.rec_type, old.test_num, old.test_txt)?,
+            (PlanChangeKind::Renamed, Some(old), Some(new)) => write!(f, "renamed {} {} '{}' -> '{}'", new.rec_type, new.test_num, old.test_txt, new.test_txt)?,
+            (PlanChangeKind::Renumbered, Some(old), Some(new)) => write!(f, "renumbered {} '{}' {} -> {}", new.rec_type, new.test_txt, old.test_num, new.test_num)?,
+            (_, _, _) => {
+                let entry = self.entry();
+                write!(f, "changed {} {} '{}'", entry.rec_type, entry.test_num, entry.test_txt)?
+            },
+        }
+
+        let fields: Vec<String> = self.fields.iter().map(|field| format!("{} '{}' -> '{}'", field.name, field.old, field.new)).collect();
+        if !fields.is_empty() { write!(f, ": {}", fields.join(", "))?; }
+        Ok(())
+    }
+}
+
+// whether two entries of the same record type are the same test
+type SameTest = fn(&TestPlanEntry, &TestPlanEntry) -> bool;
+
+//////////////////////////////////////////////////////////////////////
+/// Description: The tests of an STDF in the order they first show up, each one
+///              described by its first PTR/MPR/FTR which holds the defaults
+///              of the later ones, feed it the records without test defaults
+//////////////////////////////////////////////////////////////////////
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TestPlan {
+    pub entries: Vec<TestPlanEntry>,
+    // (record type, test number) to the index of the entry
+    index: HashMap<(&'static str, u32), usize>,
+}
+
+impl TestPlan {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the test of a PTR/MPR/FTR unless the plan already has it
+    pub fn add_record(&mut self, rec: &StdfRecord) {
+        let Some(entry) = TestPlanEntry::from_record(rec) else { return };
+        if self.index.contains_key(&(entry.rec_type, entry.test_num)) { return; }
+
+        self.index.insert((entry.rec_type, entry.test_num), self.entries.len());
+        self.entries.push(entry);
+    }
+
+    pub fn get(&self, rec_type: &str, test_num: u32) -> Option<&TestPlanEntry> {
+        self.index.get(&(rec_type, test_num)).map(|idx| &self.entries[*idx])
+    }
+
+    //////////////////////////////////////////////////////////////////////
+    /// Description: Reads the test plan of an STDF
+    //////////////////////////////////////////////////////////////////////
+    pub fn read(stdf_path: &String) -> Result<Self, StdfUtilError> {
+        // the first record of a test is what defines it, the defaults would only hide later differences
+        let mut parser = StdfParser::new(stdf_path, &None)?;
+        parser.set_use_test_defaults(false);
+
+        let mut plan = Self::new();
+        for parsed_rec in parser {
+            plan.add_record(&parsed_rec?.record);
+        }
+        Ok(plan)
+    }
+
+    //////////////////////////////////////////////////////////////////////
+    /// Description: Writes a row per test in test plan order
+    //////////////////////////////////////////////////////////////////////
+    pub fn write_csv(&self, csv_path: &str) -> Result<(), StdfUtilError> {
+        let mut csv_file = BufWriter::new(File::create(csv_path).map_err(StdfUtilError::io(csv_path))?);
+        let quote = |value: &str| format!("\"{}\"", value.replace('"', "\"\""));
+
+        let mut header = vec![quote("Record"), quote("Test Number"), quote("Test Name")];
+        header.extend(TestPlanEntry::default().fields().into_iter().map(|(name, _)| quote(name)));
+        writeln!(csv_file, "{}", header.join(",")).map_err(StdfUtilError::io(csv_path))?;
+
+        for entry in &self.entries {
+            let mut row = vec![quote(entry.rec_type), quote(&entry.test_num.to_string()), quote(&entry.test_txt)];
+            row.extend(entry.fields().into_iter().map(|(_, value)| quote(&value)));
+            writeln!(csv_file, "{}", row.join(",")).map_err(StdfUtilError::io(csv_path))?;
+        }
+
+        csv_file.flush().map_err(StdfUtilError::io(csv_path))
+    }
+
+    //////////////////////////////////////////////////////////////////////
+    /// Description: Changes from this test plan to a newer one, sorted by record
+    ///              type and test number. Tests are matched by number and name
+    ///              first, then by name (renumbered) and then by number (renamed),
+    ///              the tests left over were removed or added
+    //////////////////////////////////////////////////////////////////////
+    pub fn diff(&self, new_plan: &TestPlan) -> Vec<PlanChange> {
+        let mut old_left: Vec<&TestPlanEntry> = self.entries.iter().collect();
+        let mut new_left: Vec<&TestPlanEntry> = new_plan.entries.iter().collect();
+        let mut changes = Vec::new();
+
+        let passes: [(PlanChangeKind, SameTest); 3] = [
+            (PlanChangeKind::Changed, |old, new| old.test_num == new.test_num && old.test_txt == new.test_txt),
+            (PlanChangeKind::Renumbered, |old, new| !old.test_txt.is_empty() && old.test_txt == new.test_txt),
+            (PlanChangeKind::Renamed, |old, new| old.test_num == new.test_num),
+        ];
+        for (kind, same_test) in passes {
+            old_left.retain(|old| {
+                let Some(pos) = new_left.iter().position(|new| old.rec_type == new.rec_type && same_test(old, new)) else { return true };
+                let change = PlanChange::matched(kind, old, new_left.remove(pos));
+                // a test that didn't change isn't reported
+                if kind != PlanChangeKind::Changed || !change.fields.is_empty() { changes.push(change); }
+                false
+            });
+        }
+
+        changes.extend(old_left.into_iter().map(|old| PlanChange { kind: PlanChangeKind::Removed, old: Some(old.to_owned()), new: None, fields: Vec::new() }));
+        changes.extend(new_left.into_iter().map(|new| PlanChange { kind: PlanChangeKind::Added, old: None, new: Some(new.to_owned()), fields: Vec::new() }));
+        changes.sort_by_key(|change| (change.entry().rec_type, change.entry().test_num));
+        changes
+    }
+}
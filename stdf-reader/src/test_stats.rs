@@ -0,0 +1,415 @@
+use std::{borrow::Cow, collections::BTreeMap, fs::File, io::{BufWriter, Write}};
+
+use rust_stdf::*;
+
+use crate::{scale_value, scaled_units, ParmFlags, Part, ParsedRecord, PtrOptFlags, StdfUtilError, TestFlags};
+
+/// Percentiles written to the test summary csv
+pub const SUMMARY_PERCENTILES: [f64; 7] = [1.0, 5.0, 25.0, 50.0, 75.0, 95.0, 99.0];
+
+// results kept exactly before the digest starts merging them, and the t-digest compression after that
+const DIGEST_MAX_CENTROIDS: usize = 2000;
+const DIGEST_COMPRESSION: f64 = 200.0;
+
+// t-digest of the results: sorted (mean, weight) centroids, every result is its own centroid
+// until there are too many, then neighbours get merged, less so towards both tails
+#[derive(Debug, Default, Clone)]
+struct ResultDigest {
+    centroids: Vec<(f64, u64)>,
+    sorted: bool,
+}
+
+impl ResultDigest {
+    fn add(&mut self, value: f64) {
+        self.centroids.push((value, 1));
+        self.sorted = false;
+        if self.centroids.len() > DIGEST_MAX_CENTROIDS { self.compress(); }
+    }
+
+    fn merge(&mut self, other: &ResultDigest) {
+        self.centroids.extend(&other.centroids);
+        self.sorted = false;
+        if self.centroids.len() > DIGEST_MAX_CENTROIDS { self.compress(); }
+    }
+
+    // number of results in the digest
+    fn count(&self) -> u64 {
+        self.centroids.iter().map(|(_, weight)| weight).sum()
+    }
+
+    fn sort(&mut self) {
+        if !self.sorted {
+            self.centroids.sort_by(|lhs, rhs| lhs.0.total_cmp(&rhs.0));
+            self.sorted = true;
+        }
+    }
+
+    fn compress(&mut self) {
+        self.sort();
+        let total = self.count() as f64;
+
+        let mut merged: Vec<(f64, u64)> = Vec::with_capacity(DIGEST_MAX_CENTROIDS);
+        let mut weight_before = 0;
+        for (mean, weight) in std::mem::take(&mut self.centroids) {
+            if let Some(last) = merged.last_mut() {
+                let combined = last.1 + weight;
+                let q = (weight_before as f64 + combined as f64 / 2.0) / total;
+                if combined as f64 <= 4.0 * total * q * (1.0 - q) / DIGEST_COMPRESSION {
+                    last.0 += (mean - last.0) * weight as f64 / combined as f64;
+                    last.1 = combined;
+                    continue;
+                }
+                weight_before += last.1;
+            }
+            merged.push((mean, weight));
+        }
+        self.centroids = merged;
+    }
+
+    // the centroids in value order, without touching a digest that is shared
+    fn sorted_centroids(&self) -> Cow<'_, [(f64, u64)]> {
+        if self.sorted { return self.centroids.as_slice().into(); }
+        let mut digest = self.clone();
+        digest.sort();
+        digest.centroids.into()
+    }
+}
+
+// the order results were added in doesn't matter
+impl PartialEq for ResultDigest {
+    fn eq(&self, other: &Self) -> bool {
+        self.sorted_centroids() == other.sorted_centroids()
+    }
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: Running statistics of the results of one test, mean and variance
+///              are updated with Welford's algorithm. Percentiles and the KS statistic
+///              come from a t-digest, exact up to 2000 results and bounded after that
+//////////////////////////////////////////////////////////////////////
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TestStats {
+    /// Results that went into the statistics
+    pub count: u64,
+    /// Executions that failed, including the ones without a valid result
+    pub fails: u64,
+    /// Executions, with or without a valid result
+    pub executions: u64,
+    pub min: f64,
+    pub max: f64,
+    mean: f64,
+    m2: f64,
+    digest: ResultDigest,
+}
+
+impl TestStats {
+    //////////////////////////////////////////////////////////////////////
+    /// Description: Adds one execution of the test, result is None when the
+    ///              tester flagged it as invalid
+    //////////////////////////////////////////////////////////////////////
+    pub fn add(&mut self, result: Option<f32>, failed: bool) {
+        self.executions += 1;
+        if failed { self.fails += 1; }
+
+        let Some(result) = result.filter(|result| result.is_finite()) else { return };
+        let value = result as f64;
+
+        self.min = if self.count == 0 { value } else { self.min.min(value) };
+        self.max = if self.count == 0 { value } else { self.max.max(value) };
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+        self.digest.add(value);
+    }
+
+    //////////////////////////////////////////////////////////////////////
+    /// Description: Combines the statistics of another site/head into these
+    //////////////////////////////////////////////////////////////////////
+    pub fn merge(&mut self, other: &TestStats) {
+        self.merge_moments(other);
+        if other.count > 0 { self.digest.merge(&other.digest); }
+    }
+
+    //////////////////////////////////////////////////////////////////////
+    /// Description: Combines everything but the results of another site/head into
+    ///              these, percentiles and the KS statistic only see the own results
+    //////////////////////////////////////////////////////////////////////
+    pub fn merge_moments(&mut self, other: &TestStats) {
+        if other.count > 0 {
+            self.min = if self.count == 0 { other.min } else { self.min.min(other.min) };
+            self.max = if self.count == 0 { other.max } else { self.max.max(other.max) };
+
+            // Chan's update of the Welford sums
+            let count = self.count + other.count;
+            let delta = other.mean - self.mean;
+            self.mean += delta * other.count as f64 / count as f64;
+            self.m2 += other.m2 + delta * delta * self.count as f64 * other.count as f64 / count as f64;
+            self.count = count;
+        }
+        self.fails += other.fails;
+        self.executions += other.executions;
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.mean)
+    }
+
+    /// Sample standard deviation, needs at least 2 results
+    pub fn stdev(&self) -> Option<f64> {
+        (self.count > 1).then(|| (self.m2 / (self.count - 1) as f64).sqrt())
+    }
+
+    /// Percentage of executions that passed
+    pub fn yield_pct(&self) -> Option<f64> {
+        (self.executions > 0).then(|| (self.executions - self.fails) as f64 * 100.0 / self.executions as f64)
+    }
+
+    //////////////////////////////////////////////////////////////////////
+    /// Description: Percentiles (0 to 100) of the results, interpolated between
+    ///              the closest ranks, the results merged into a centroid all have its mean
+    //////////////////////////////////////////////////////////////////////
+    pub fn percentiles(&self, percents: &[f64]) -> Vec<Option<f64>> {
+        let centroids = self.digest.sorted_centroids();
+        let count = self.digest.count();
+        // value of the result at a rank, starting at 0
+        let value_at = |rank: u64| {
+            let mut weight_before = 0;
+            centroids.iter().find(|(_, weight)| { weight_before += weight; rank < weight_before }).map_or(self.max, |(mean, _)| *mean)
+        };
+
+        percents.iter().map(|percent| {
+            if count == 0 { return None; }
+            let rank = percent.clamp(0.0, 100.0) / 100.0 * (count - 1) as f64;
+            let (lower, upper) = (value_at(rank.floor() as u64), value_at(rank.ceil() as u64));
+            Some(lower + (upper - lower) * rank.fract())
+        }).collect()
+    }
+
+    //////////////////////////////////////////////////////////////////////
+    /// Description: Two sample Kolmogorov-Smirnov statistic, the largest distance
+    ///              between the distributions of the results of both stats, binned by
+    ///              the centroids of their digests
+    //////////////////////////////////////////////////////////////////////
+    pub fn ks_statistic(&self, other: &TestStats) -> Option<f64> {
+        let (lhs_count, rhs_count) = (self.digest.count(), other.digest.count());
+        if lhs_count == 0 || rhs_count == 0 { return None; }
+
+        let lhs = self.digest.sorted_centroids();
+        let rhs = other.digest.sorted_centroids();
+
+        // walk both sorted centroids, equal means move both distributions at once
+        let (mut i, mut j, mut lhs_weight, mut rhs_weight, mut distance) = (0, 0, 0, 0, 0.0f64);
+        while i < lhs.len() && j < rhs.len() {
+            let value = lhs[i].0.min(rhs[j].0);
+            while i < lhs.len() && lhs[i].0 == value { lhs_weight += lhs[i].1; i += 1; }
+            while j < rhs.len() && rhs[j].0 == value { rhs_weight += rhs[j].1; j += 1; }
+            distance = distance.max((lhs_weight as f64 / lhs_count as f64 - rhs_weight as f64 / rhs_count as f64).abs());
+        }
+        Some(distance)
+    }
+
+    /// Process capability, needs both limits
+    pub fn cp(&self, lo_limit: Option<f64>, hi_limit: Option<f64>) -> Option<f64> {
+        let stdev = self.stdev().filter(|stdev| *stdev > 0.0)?;
+        Some((hi_limit? - lo_limit?) / (6.0 * stdev))
+    }
+
+    /// Process capability index, from the closest limit when only one is given
+    pub fn cpk(&self, lo_limit: Option<f64>, hi_limit: Option<f64>) -> Option<f64> {
+        let stdev = self.stdev().filter(|stdev| *stdev > 0.0)?;
+        let mean = self.mean()?;
+
+        let cpl = lo_limit.map(|lo_limit| (mean - lo_limit) / (3.0 * stdev));
+        let cpu = hi_limit.map(|hi_limit| (hi_limit - mean) / (3.0 * stdev));
+        match (cpl, cpu) {
+            (Some(cpl), Some(cpu)) => Some(cpl.min(cpu)),
+            (cpk, None) | (None, cpk) => cpk,
+        }
+    }
+}
+
+/// A PTR test, or one pin of an MPR test
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TestId {
+    pub test_num: u32,
+    /// Position of the result in the MPR, None for a PTR
+    pub pin: Option<u16>,
+}
+
+impl std::fmt::Display for TestId {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // same numbering as the tests csv
+        match self.pin {
+            Some(pin) => write!(f, "{}.{}", self.test_num, pin),
+            None => write!(f, "{}", self.test_num),
+        }
+    }
+}
+
+/// Statistics of one test, by head/site
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TestSummary {
+    pub test_txt: String,
+    pub units: String,
+    /// Limits of the test, unscaled
+    pub lo_limit: Option<f64>,
+    pub hi_limit: Option<f64>,
+    /// res_scal of the test, the statistics are kept unscaled too
+    pub res_scal: Option<i8>,
+    /// Statistics by (head, site)
+    pub sites: BTreeMap<(u8, u8), TestStats>,
+}
+
+impl TestSummary {
+    /// Statistics of every site of the head
+    pub fn head(&self, head_num: u8) -> TestStats {
+        let mut stats = TestStats::default();
+        self.sites.iter().filter(|((head, _), _)| *head == head_num).for_each(|(_, site_stats)| stats.merge(site_stats));
+        stats
+    }
+
+    /// Statistics of every head and site
+    pub fn total(&self) -> TestStats {
+        let mut stats = TestStats::default();
+        self.sites.values().for_each(|site_stats| stats.merge(site_stats));
+        stats
+    }
+
+    /// Statistics of every head and site without their results, cheap enough to redo on every update
+    pub fn total_moments(&self) -> TestStats {
+        let mut stats = TestStats::default();
+        self.sites.values().for_each(|site_stats| stats.merge_moments(site_stats));
+        stats
+    }
+
+    /// A result, limit or stdev in the units of scaled_units, the way the tests csv shows the results
+    pub fn scaled(&self, value: Option<f64>) -> Option<f64> {
+        value.map(|value| scale_value(value, self.res_scal))
+    }
+
+    pub fn scaled_units(&self) -> String {
+        scaled_units(&self.units, self.res_scal)
+    }
+}
+
+// a limit is only usable when opt_flag doesn't flag it as missing or invalid, see PtrOptFlags
+pub(crate) fn valid_limit(limit: Option<f32>, valid: bool) -> Option<f64> {
+    limit.filter(|limit| valid && limit.is_finite()).map(|limit| limit as f64)
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: Collects the statistics of every PTR test and MPR pin of an STDF,
+///              feed it the records with their test defaults filled in
+//////////////////////////////////////////////////////////////////////
+#[derive(Debug, Default, Clone)]
+pub struct TestStatistics {
+    tests: BTreeMap<TestId, TestSummary>,
+}
+
+impl TestStatistics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn summary_mut(&mut self, test_id: TestId, test_txt: &str, units: &Option<String>, res_scal: Option<i8>, lo_limit: Option<f64>, hi_limit: Option<f64>) -> &mut TestSummary {
+        let summary = self.tests.entry(test_id).or_default();
+
+        // later records may leave out what the first one of the test gave
+        if summary.test_txt.is_empty() { summary.test_txt = test_txt.to_owned(); }
+        if summary.units.is_empty() { summary.units = units.to_owned().unwrap_or_default(); }
+        summary.res_scal = summary.res_scal.or(res_scal);
+        summary.lo_limit = summary.lo_limit.or(lo_limit);
+        summary.hi_limit = summary.hi_limit.or(hi_limit);
+        summary
+    }
+
+    //////////////////////////////////////////////////////////////////////
+    /// Description: Adds the results of a PTR or MPR, other records are ignored
+    //////////////////////////////////////////////////////////////////////
+    pub fn add_record(&mut self, rec: &StdfRecord) {
+        match rec {
+            StdfRecord::PTR(rec) => {
+                let opt_flags = PtrOptFlags::from(rec.opt_flag);
+                let lo_limit = valid_limit(rec.lo_limit, opt_flags.lo_limit_valid());
+                let hi_limit = valid_limit(rec.hi_limit, opt_flags.hi_limit_valid());
+                let test_flags = TestFlags::from(rec.test_flg);
+                let result = test_flags.is_valid().then_some(rec.result);
+                let failed = test_flags.is_fail();
+
+                self.summary_mut(TestId { test_num: rec.test_num, pin: None }, &rec.test_txt, &rec.units, rec.res_scal, lo_limit, hi_limit)
+                    .sites.entry((rec.head_num, rec.site_num)).or_default().add(result, failed);
+            },
+            StdfRecord::MPR(rec) => {
+                let opt_flags = PtrOptFlags::from(rec.opt_flag);
+                let lo_limit = valid_limit(rec.lo_limit, opt_flags.lo_limit_valid());
+                let hi_limit = valid_limit(rec.hi_limit, opt_flags.hi_limit_valid());
+                let test_flags = TestFlags::from(rec.test_flg);
+                let results_valid = test_flags.is_valid();
+
+                for (pin, result) in rec.rtn_rslt.iter().enumerate() {
+                    // the tester only judges the whole test, of a failed test the pins outside
+                    // the limits failed, all of them when there are no limits to tell
+                    let failed = test_flags.is_fail() && (!results_valid || (lo_limit.is_none() && hi_limit.is_none())
+                        || !ParmFlags::from(rec.parm_flg).within_limits(*result, lo_limit, hi_limit));
+
+                    self.summary_mut(TestId { test_num: rec.test_num, pin: Some(pin as u16) }, &rec.test_txt, &rec.units, rec.res_scal, lo_limit, hi_limit)
+                        .sites.entry((rec.head_num, rec.site_num)).or_default().add(results_valid.then_some(*result), failed);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    /// Adds the results of every PTR/MPR of the part
+    pub fn add_part(&mut self, part: &Part) {
+        part.results.iter().for_each(|ParsedRecord { record, .. }| self.add_record(record));
+    }
+
+    /// Statistics of every test in test number order
+    pub fn tests(&self) -> impl Iterator<Item = (&TestId, &TestSummary)> {
+        self.tests.iter()
+    }
+
+    pub fn get(&self, test_id: &TestId) -> Option<&TestSummary> {
+        self.tests.get(test_id)
+    }
+
+    //////////////////////////////////////////////////////////////////////
+    /// Description: Writes a row per test and head/site, followed by a row for all
+    ///              sites of each head (site 255) and one for all heads (head 255).
+    ///              Values are scaled by the res_scal of the test like the tests csv
+    //////////////////////////////////////////////////////////////////////
+    pub fn write_csv(&self, csv_path: &str) -> Result<(), StdfUtilError> {
+        let mut csv_file = BufWriter::new(File::create(csv_path).map_err(StdfUtilError::io(csv_path))?);
+        // the results are f32's, more digits would only show rounding noise
+        let opt = |value: Option<f64>| value.map(|value| (value as f32).to_string()).unwrap_or_default();
+
+        let percentiles: Vec<String> = SUMMARY_PERCENTILES.iter().map(|percent| format!(",\"P{}\"", percent)).collect();
+        writeln!(csv_file, "\"Test Number\",\"Test Name\",\"Units\",\"Head\",\"Site\",\"Lo Limit\",\"Hi Limit\",\"Executions\",\"Count\",\"Fails\",\"Yield\",\"Min\",\"Max\",\"Mean\",\"Stdev\"{},\"Cp\",\"Cpk\"", percentiles.concat())
+            .map_err(StdfUtilError::io(csv_path))?;
+
+        for (test_id, summary) in &self.tests {
+            let mut rows: Vec<(u8, u8, TestStats)> = summary.sites.iter().map(|((head, site), stats)| (*head, *site, stats.to_owned())).collect();
+            let mut heads: Vec<u8> = summary.sites.keys().map(|(head, _)| *head).collect();
+            heads.dedup();
+            rows.extend(heads.into_iter().map(|head| (head, 255, summary.head(head))));
+            rows.push((255, 255, summary.total()));
+
+            let test_txt = summary.test_txt.replace('"', "\"\"");
+            let units = summary.scaled_units().replace('"', "\"\"");
+            let scaled = |value: Option<f64>| opt(summary.scaled(value));
+            for (head, site, stats) in rows {
+                let percentiles: Vec<String> = stats.percentiles(&SUMMARY_PERCENTILES).into_iter().map(|value| format!(",\"{}\"", scaled(value))).collect();
+                writeln!(csv_file, "\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\",\"{}\"{},\"{}\",\"{}\"",
+                    test_id, test_txt, units, head, site, scaled(summary.lo_limit), scaled(summary.hi_limit),
+                    stats.executions, stats.count, stats.fails, opt(stats.yield_pct()),
+                    scaled((stats.count > 0).then_some(stats.min)), scaled((stats.count > 0).then_some(stats.max)), scaled(stats.mean()), scaled(stats.stdev()),
+                    percentiles.concat(), opt(stats.cp(summary.lo_limit, summary.hi_limit)), opt(stats.cpk(summary.lo_limit, summary.hi_limit)))
+                    .map_err(StdfUtilError::io(csv_path))?;
+            }
+        }
+
+        csv_file.flush().map_err(StdfUtilError::io(csv_path))
+    }
+}
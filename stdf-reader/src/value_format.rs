@@ -0,0 +1,162 @@
+use std::sync::OnceLock;
+
+use regex::Regex;
+use rust_stdf::*;
+use sprintf::sprintf;
+
+//////////////////////////////////////////////////////////////////////
+/// Description: Unit prefix of a res_scal/llm_scal/hlm_scal, as listed by the
+///              STDF spec. A value times 10^scale is in the prefixed units
+//////////////////////////////////////////////////////////////////////
+pub fn scale_prefix(scale: i8) -> Option<&'static str> {
+    match scale {
+        15 => Some("f"),
+        12 => Some("p"),
+        9 => Some("n"),
+        6 => Some("u"),
+        3 => Some("m"),
+        2 => Some("%"),
+        0 => Some(""),
+        -3 => Some("K"),
+        -6 => Some("M"),
+        -9 => Some("G"),
+        -12 => Some("T"),
+        _ => None,
+    }
+}
+
+/// Value in the units scaled_units gives for the same scale
+pub fn scale_value(value: f64, scale: Option<i8>) -> f64 {
+    value * 10f64.powi(scale.unwrap_or(0) as i32)
+}
+
+/// Units with the prefix of the scale, e.g. "mV" for a scale of 3, a scale
+/// without a prefix gives its factor instead, e.g. "x10^-1 V" for a scale of 1
+pub fn scaled_units(units: &str, scale: Option<i8>) -> String {
+    let scale = scale.unwrap_or(0);
+    match scale_prefix(scale) {
+        Some(prefix) => format!("{}{}", prefix, units),
+        None => format!("x10^{} {}", -(scale as i32), units).trim_end().to_string(),
+    }
+}
+
+// a C format of a single floating point conversion, with a width and precision that can't blow up
+fn c_format_regex() -> &'static Regex {
+    static C_FORMAT: OnceLock<Regex> = OnceLock::new();
+    C_FORMAT.get_or_init(|| Regex::new(r"^(?:[^%]|%%)*%[-+ #0]*[0-9]{0,2}(?:\.[0-9]{1,2})?[lLh]?[eEfFgG](?:[^%]|%%)*$").unwrap())
+}
+
+fn length_modifier_regex() -> &'static Regex {
+    static LENGTH_MODIFIER: OnceLock<Regex> = OnceLock::new();
+    LENGTH_MODIFIER.get_or_init(|| Regex::new(r"%([-+ #0]*[0-9]{0,2}(?:\.[0-9]{1,2})?)[lLh]([eEfFgG])").unwrap())
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: Formats a PTR/MPR value with its scale and C format (c_resfmt,
+///              c_llmfmt or c_hlmfmt). A format that isn't a single floating point
+///              conversion is ignored, as is one sprintf can't apply. Missing and
+///              non finite values are empty
+//////////////////////////////////////////////////////////////////////
+pub fn format_value(value: Option<f32>, scale: Option<i8>, c_format: &Option<String>) -> String {
+    format_with_default(value, scale, c_format, None)
+}
+
+// format_value with the C format used when the value has none or an unusable one
+fn format_with_default(value: Option<f32>, scale: Option<i8>, c_format: &Option<String>, default_format: Option<&str>) -> String {
+    let Some(value) = value.filter(|value| value.is_finite()) else { return "".to_string() };
+    let value = scale_value(value as f64, scale) as f32;
+
+    // sprintf has no length modifiers, %lf is the same as %f
+    let c_format = c_format.as_deref().filter(|c_format| c_format_regex().is_match(c_format)).or(default_format)
+        .map(|c_format| length_modifier_regex().replace(c_format, "%$1$2").to_string());
+    c_format.and_then(|c_format| sprintf!(c_format.as_str(), value).ok()).unwrap_or(value.to_string())
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: How the result and limits of a PTR/MPR are shown, feed it
+///              records with their test defaults filled in
+//////////////////////////////////////////////////////////////////////
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TestFormat {
+    pub units: String,
+    /// Scale of the result, the limits are shown in the units of the result too
+    pub res_scal: Option<i8>,
+    pub c_resfmt: Option<String>,
+    pub c_llmfmt: Option<String>,
+    pub c_hlmfmt: Option<String>,
+    /// C format of the values without a usable one of their own, None shows them as they are
+    pub default_format: Option<String>,
+}
+
+impl From<&PTR> for TestFormat {
+    fn from(rec: &PTR) -> Self {
+        Self {
+            units: rec.units.to_owned().unwrap_or_default(),
+            res_scal: rec.res_scal,
+            c_resfmt: rec.c_resfmt.to_owned(),
+            c_llmfmt: rec.c_llmfmt.to_owned(),
+            c_hlmfmt: rec.c_hlmfmt.to_owned(),
+            default_format: None,
+        }
+    }
+}
+
+impl From<&MPR> for TestFormat {
+    fn from(rec: &MPR) -> Self {
+        Self {
+            units: rec.units.to_owned().unwrap_or_default(),
+            res_scal: rec.res_scal,
+            c_resfmt: rec.c_resfmt.to_owned(),
+            c_llmfmt: rec.c_llmfmt.to_owned(),
+            c_hlmfmt: rec.c_hlmfmt.to_owned(),
+            default_format: None,
+        }
+    }
+}
+
+impl TestFormat {
+    /// Formats the values without a usable C format of their own with this one, e.g. "%f"
+    pub fn with_default_format(self, default_format: &str) -> Self {
+        Self { default_format: Some(default_format.to_owned()), ..self }
+    }
+
+    /// Units of the result, with the prefix of res_scal
+    pub fn units(&self) -> String {
+        scaled_units(&self.units, self.res_scal)
+    }
+
+    pub fn result(&self, result: f32) -> String {
+        format_with_default(Some(result), self.res_scal, &self.c_resfmt, self.default_format.as_deref())
+    }
+
+    /// Lo limit in the units of the result
+    pub fn lo_limit(&self, lo_limit: Option<f32>) -> String {
+        format_with_default(lo_limit, self.res_scal, &self.c_llmfmt, self.default_format.as_deref())
+    }
+
+    /// Hi limit in the units of the result
+    pub fn hi_limit(&self, hi_limit: Option<f32>) -> String {
+        format_with_default(hi_limit, self.res_scal, &self.c_hlmfmt, self.default_format.as_deref())
+    }
+
+    /// Result or limit in the units of the result, for graphs and statistics
+    pub fn scale(&self, value: f64) -> f64 {
+        scale_value(value, self.res_scal)
+    }
+}
+
+// rows of a text report as a table, the first row is the header and columns are as wide as their widest value
+pub(crate) fn text_table(rows: &[Vec<String>]) -> String {
+    let mut widths = vec![0; rows.first().map_or(0, |header| header.len())];
+    for row in rows {
+        row.iter().enumerate().for_each(|(col, value)| widths[col] = widths[col].max(value.chars().count()));
+    }
+
+    let mut text = String::new();
+    for row in rows {
+        let line: Vec<String> = row.iter().enumerate().map(|(col, value)| format!("{:<width$}", value, width = widths[col])).collect();
+        text += line.join("  ").trim_end();
+        text += "\n";
+    }
+    text
+}
@@ -0,0 +1,285 @@
+use std::collections::BTreeMap;
+
+use rust_stdf::*;
+
+use crate::{Part, PartFlags, PartTracker, StdfParser, StdfUtilError};
+
+// x/y coordinate the PRR uses for a part without a die position
+const MISSING_COORD: i16 = -32768;
+
+/// A die of a wafer map, from the PRR of the part tested on it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Die {
+    pub x: i16,
+    pub y: i16,
+    /// Index of the part in the STDF, same numbering as Part::part_idx
+    pub part_idx: u32,
+    pub head_num: u8,
+    pub site_num: u8,
+    pub part_id: String,
+    pub hard_bin: u16,
+    pub soft_bin: u16,
+    /// None when the PRR flags its pass/fail as invalid
+    pub passed: Option<bool>,
+}
+
+/// The dies tested between a WIR and its WRR
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct WaferMap {
+    pub head_num: u8,
+    /// Wafer id of the WIR, or of the WRR when the WIR has none
+    pub wafer_id: String,
+    /// Dies in test order, a retested die shows up once per test
+    pub dies: Vec<Die>,
+    /// false until the WRR of the wafer
+    pub finished: bool,
+}
+
+/// Name and pass/fail of a bin from its HBR/SBR
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BinInfo {
+    pub name: String,
+    /// None when the HBR/SBR leaves hbin_pf/sbin_pf unknown
+    pub pass: Option<bool>,
+}
+
+/// Side of the map a wafer flat is on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaferSide {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: Wafer configuration of the WCR, without a WCR x increases to the
+///              right and y increases downward
+//////////////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, PartialEq)]
+pub struct WaferConfig {
+    pub flat: Option<WaferSide>,
+    /// pos_x of the WCR, true unless x increases to the left
+    pub x_right: bool,
+    /// pos_y of the WCR, true when y increases upward
+    pub y_up: bool,
+    /// Diameter of the wafer and size of a die in wf_units, 0 when unknown
+    pub wafer_size: f32,
+    pub die_ht: f32,
+    pub die_wid: f32,
+    pub wf_units: u8,
+    pub center_x: Option<i16>,
+    pub center_y: Option<i16>,
+}
+
+impl Default for WaferConfig {
+    fn default() -> Self {
+        Self { flat: None, x_right: true, y_up: false, wafer_size: 0.0, die_ht: 0.0, die_wid: 0.0, wf_units: 0, center_x: None, center_y: None }
+    }
+}
+
+impl From<&WCR> for WaferConfig {
+    fn from(rec: &WCR) -> Self {
+        let flat = match rec.wf_flat {
+            'U' => Some(WaferSide::Up),
+            'D' => Some(WaferSide::Down),
+            'L' => Some(WaferSide::Left),
+            'R' => Some(WaferSide::Right),
+            _ => None,
+        };
+
+        Self {
+            flat,
+            x_right: rec.pos_x != 'L',
+            y_up: rec.pos_y == 'U',
+            wafer_size: rec.wafr_siz,
+            die_ht: rec.die_ht,
+            die_wid: rec.die_wid,
+            wf_units: rec.wf_units,
+            center_x: Some(rec.center_x).filter(|center_x| *center_x != MISSING_COORD),
+            center_y: Some(rec.center_y).filter(|center_y| *center_y != MISSING_COORD),
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: Positions of the dies of a wafer laid out for display, rows top
+///              to bottom and columns left to right as the WCR orients them
+//////////////////////////////////////////////////////////////////////
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DieGrid {
+    pub cols: usize,
+    pub rows: usize,
+    x_min: i16,
+    x_max: i16,
+    y_min: i16,
+    y_max: i16,
+    x_right: bool,
+    y_up: bool,
+    cells: Vec<Option<usize>>,
+}
+
+impl DieGrid {
+    /// Index in WaferMap::dies of the last die tested at the position
+    pub fn die(&self, col: usize, row: usize) -> Option<usize> {
+        if col >= self.cols || row >= self.rows { return None; }
+        self.cells[row * self.cols + col]
+    }
+
+    /// x/y coordinates of a position
+    pub fn coords(&self, col: usize, row: usize) -> (i16, i16) {
+        let x = if self.x_right { self.x_min as i32 + col as i32 } else { self.x_max as i32 - col as i32 };
+        let y = if self.y_up { self.y_max as i32 - row as i32 } else { self.y_min as i32 + row as i32 };
+        (x as i16, y as i16)
+    }
+
+    /// Position of x/y coordinates, None when they are outside of the grid
+    pub fn position(&self, x: i16, y: i16) -> Option<(usize, usize)> {
+        if self.cols == 0 || x < self.x_min || x > self.x_max || y < self.y_min || y > self.y_max { return None; }
+        let col = if self.x_right { x as i32 - self.x_min as i32 } else { self.x_max as i32 - x as i32 };
+        let row = if self.y_up { self.y_max as i32 - y as i32 } else { y as i32 - self.y_min as i32 };
+        Some((col as usize, row as usize))
+    }
+}
+
+impl WaferMap {
+    //////////////////////////////////////////////////////////////////////
+    /// Description: Lays out the dies as the WCR orients them, the last test of
+    ///              a retested die wins
+    //////////////////////////////////////////////////////////////////////
+    pub fn grid(&self, config: &WaferConfig) -> DieGrid {
+        let Some(first) = self.dies.first() else { return DieGrid::default() };
+        let (mut x_min, mut x_max, mut y_min, mut y_max) = (first.x, first.x, first.y, first.y);
+        for die in &self.dies {
+            x_min = x_min.min(die.x);
+            x_max = x_max.max(die.x);
+            y_min = y_min.min(die.y);
+            y_max = y_max.max(die.y);
+        }
+
+        let cols = (x_max as i32 - x_min as i32 + 1) as usize;
+        let rows = (y_max as i32 - y_min as i32 + 1) as usize;
+        let mut grid = DieGrid { cols, rows, x_min, x_max, y_min, y_max, x_right: config.x_right, y_up: config.y_up, cells: vec![None; cols * rows] };
+        for (idx, die) in self.dies.iter().enumerate() {
+            if let Some((col, row)) = grid.position(die.x, die.y) {
+                grid.cells[row * cols + col] = Some(idx);
+            }
+        }
+        grid
+    }
+
+    /// Last test of every die position, in test order
+    pub fn last_dies(&self) -> Vec<&Die> {
+        let mut last: BTreeMap<(i16, i16), usize> = BTreeMap::new();
+        for (idx, die) in self.dies.iter().enumerate() {
+            last.insert((die.x, die.y), idx);
+        }
+        let mut idxs: Vec<usize> = last.into_values().collect();
+        idxs.sort();
+        idxs.into_iter().map(|idx| &self.dies[idx]).collect()
+    }
+}
+
+//////////////////////////////////////////////////////////////////////
+/// Description: The wafers of an STDF with their configuration and bin names,
+///              feed it the records and parts in STDF order
+//////////////////////////////////////////////////////////////////////
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct WaferMaps {
+    pub config: WaferConfig,
+    pub wafers: Vec<WaferMap>,
+    pub hard_bins: BTreeMap<u16, BinInfo>,
+    pub soft_bins: BTreeMap<u16, BinInfo>,
+}
+
+// bin info of an HBR/SBR, the first name given for a bin is kept
+fn add_bin(bins: &mut BTreeMap<u16, BinInfo>, bin_num: u16, pf: char, name: &str) {
+    let bin = bins.entry(bin_num).or_default();
+    if bin.name.is_empty() { bin.name = name.to_owned(); }
+    bin.pass = match pf {
+        'P' => Some(true),
+        'F' => Some(false),
+        _ => bin.pass,
+    };
+}
+
+impl WaferMaps {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // wafer in test on the head, parts without a WIR get a wafer without an id
+    fn open_wafer_mut(&mut self, head_num: u8) -> &mut WaferMap {
+        match self.wafers.iter().rposition(|wafer| wafer.head_num == head_num && !wafer.finished) {
+            Some(idx) => &mut self.wafers[idx],
+            None => {
+                self.wafers.push(WaferMap { head_num, ..Default::default() });
+                self.wafers.last_mut().unwrap()
+            },
+        }
+    }
+
+    //////////////////////////////////////////////////////////////////////
+    /// Description: Handles a WCR, WIR, WRR, HBR or SBR, other records are ignored
+    //////////////////////////////////////////////////////////////////////
+    pub fn add_record(&mut self, rec: &StdfRecord) {
+        match rec {
+            StdfRecord::WCR(rec) => self.config = WaferConfig::from(rec),
+            StdfRecord::WIR(rec) => {
+                // a WIR without the WRR of the previous wafer ends that wafer
+                if let Some(wafer) = self.wafers.iter_mut().rev().find(|wafer| wafer.head_num == rec.head_num && !wafer.finished) {
+                    wafer.finished = true;
+                }
+                self.wafers.push(WaferMap { head_num: rec.head_num, wafer_id: rec.wafer_id.to_owned(), ..Default::default() });
+            },
+            StdfRecord::WRR(rec) => {
+                let wafer = self.open_wafer_mut(rec.head_num);
+                if wafer.wafer_id.is_empty() { wafer.wafer_id = rec.wafer_id.to_owned(); }
+                wafer.finished = true;
+            },
+            StdfRecord::HBR(rec) => add_bin(&mut self.hard_bins, rec.hbin_num, rec.hbin_pf, &rec.hbin_nam),
+            StdfRecord::SBR(rec) => add_bin(&mut self.soft_bins, rec.sbin_num, rec.sbin_pf, &rec.sbin_nam),
+            _ => {},
+        }
+    }
+
+    /// Adds the die of a part, parts without x/y coordinates are ignored
+    pub fn add_part(&mut self, part: &Part) {
+        if part.x_coord == MISSING_COORD || part.y_coord == MISSING_COORD { return; }
+
+        let die = Die {
+            x: part.x_coord,
+            y: part.y_coord,
+            part_idx: part.part_idx,
+            head_num: part.head_num,
+            site_num: part.site_num,
+            part_id: part.part_id.to_owned(),
+            hard_bin: part.hard_bin,
+            soft_bin: part.soft_bin,
+            passed: PartFlags::from(part.part_flg).failed().map(|failed| !failed),
+        };
+        self.open_wafer_mut(part.head_num).dies.push(die);
+    }
+
+    //////////////////////////////////////////////////////////////////////
+    /// Description: Reads the wafer maps of an STDF, wafers without dies are dropped
+    //////////////////////////////////////////////////////////////////////
+    pub fn read(stdf_path: &String) -> Result<Self, StdfUtilError> {
+        // bins and coordinates don't need the test defaults
+        let mut parser = StdfParser::new(stdf_path, &None)?;
+        parser.set_use_test_defaults(false);
+
+        let mut tracker = PartTracker::tracking_only();
+        let mut wafer_maps = Self::new();
+        for parsed_rec in parser {
+            let parsed_rec = parsed_rec?;
+            wafer_maps.add_record(&parsed_rec.record);
+            if let Some(part) = tracker.process(&parsed_rec) {
+                wafer_maps.add_part(&part);
+            }
+        }
+
+        wafer_maps.wafers.retain(|wafer| !wafer.dies.is_empty());
+        Ok(wafer_maps)
+    }
+}
@@ -10,6 +10,7 @@ clear_on_prr=true               # if linking to record, data is sticky unless cl
 
 use std::io::Write;
 
+use polars::prelude::{ParquetReader, SerReader};
 use stdf_reader::*;
 
 #[test]
@@ -23,7 +24,7 @@ fn parse_dtr() {
     std::fs::File::create("dtr_config.ini").unwrap().write_all(DTR_CONFIG_FILE_EXAMPLE.as_bytes()).unwrap();
 
     // load configuration file
-    let dtr_cfg = stdf_reader::load_dtr_config(&Some("dtr_config.ini".into()));
+    let dtr_cfg = stdf_reader::load_dtr_config(&Some("dtr_config.ini".into())).unwrap();
     println!("dtr_cfg: {:#?}", dtr_cfg);
 
     // delete configuration file
@@ -56,4 +57,1412 @@ fn parse_dtr() {
 //     // run data
 //     stdf_reader::convert_stdf2text(&"test.stdf.gz".to_string(), &"test.stdf.gz.txt".to_string(), false, false)
 //         .expect("There was an error loading reference stdf.");
-// }
\ No newline at end of file
+// }
+//////////////////////////////////////////////////////////////////////
+// helpers to synthesize small little endian STDF files
+//////////////////////////////////////////////////////////////////////
+fn stdf_rec(rec_typ: u8, rec_sub: u8, data: Vec<u8>) -> Vec<u8> {
+    let mut rec = (data.len() as u16).to_le_bytes().to_vec();
+    rec.extend([rec_typ, rec_sub]);
+    rec.extend(data);
+    rec
+}
+
+fn cn(text: &str) -> Vec<u8> {
+    let mut data = vec![text.len() as u8];
+    data.extend(text.as_bytes());
+    data
+}
+
+fn far() -> Vec<u8> {
+    stdf_rec(0, 10, vec![2, 4])
+}
+
+fn pir(head_num: u8, site_num: u8) -> Vec<u8> {
+    stdf_rec(5, 10, vec![head_num, site_num])
+}
+
+fn prr(head_num: u8, site_num: u8, part_id: &str, bin: u16, x_coord: i16) -> Vec<u8> {
+    let mut data = vec![head_num, site_num, 0];
+    data.extend(3u16.to_le_bytes());
+    data.extend(bin.to_le_bytes());
+    data.extend(bin.to_le_bytes());
+    data.extend(x_coord.to_le_bytes());
+    data.extend(0i16.to_le_bytes());
+    data.extend(10u32.to_le_bytes());
+    data.extend(cn(part_id));
+    stdf_rec(5, 20, data)
+}
+
+fn ptr(test_num: u32, head_num: u8, site_num: u8, result: f32) -> Vec<u8> {
+    let mut data = test_num.to_le_bytes().to_vec();
+    data.extend([head_num, site_num, 0, 0]);
+    data.extend(result.to_le_bytes());
+    data.extend(cn(&format!("test{}", test_num)));
+    data.extend(cn(""));
+    stdf_rec(15, 10, data)
+}
+
+fn dtr(text: &str) -> Vec<u8> {
+    stdf_rec(50, 30, cn(text))
+}
+
+// path in the temp dir, the process id keeps test runs that happen at the same time apart
+fn temp_path(name: &str) -> String {
+    std::env::temp_dir().join(format!("{}_{}", std::process::id(), name)).to_string_lossy().to_string()
+}
+
+fn write_stdf(name: &str, recs: Vec<Vec<u8>>) -> String {
+    let path = temp_path(name);
+    std::fs::write(&path, recs.concat()).unwrap();
+    path
+}
+
+// sites 1 and 3 are tested together, site 3 finishes first and site 7 starts before site 1 finishes
+fn interleaved_multi_site_recs() -> Vec<Vec<u8>> {
+    vec![
+        far(),
+        pir(1, 1), pir(1, 3),
+        dtr("COND: temp=25"),
+        ptr(100, 1, 1, 1.0), ptr(100, 1, 3, 2.0),
+        ptr(101, 1, 3, 2.1), ptr(101, 1, 1, 1.1),
+        prr(1, 3, "P2", 5, 3),
+        pir(1, 7),
+        ptr(100, 1, 7, 7.0), ptr(101, 1, 7, 7.1),
+        prr(1, 1, "P1", 1, 1),
+        prr(1, 7, "P3", 1, 7),
+    ]
+}
+
+#[test]
+fn part_iterator_interleaved_sites() {
+    let stdf_path = write_stdf("part_iterator_interleaved_sites.stdf", interleaved_multi_site_recs());
+    let dtr_cfg_path = temp_path("part_iterator_dtr_config.ini");
+    std::fs::write(&dtr_cfg_path, DTR_CONFIG_FILE_EXAMPLE).unwrap();
+
+    let parts = PartIterator::new(&stdf_path, &Some(dtr_cfg_path.to_owned())).unwrap()
+        .collect::<Result<Vec<Part>, StdfUtilError>>().unwrap();
+
+    std::fs::remove_file(&stdf_path).unwrap();
+    std::fs::remove_file(&dtr_cfg_path).unwrap();
+
+    // parts come out in PRR order, part_idx is the PIR order
+    let summary: Vec<(u32, u8, String, u16, i16)> = parts.iter().map(|p| (p.part_idx, p.site_num, p.part_id.to_owned(), p.hard_bin, p.x_coord)).collect();
+    assert_eq!(summary, vec![(2, 3, "P2".into(), 5, 3), (1, 1, "P1".into(), 1, 1), (3, 7, "P3".into(), 1, 7)]);
+    assert!(parts.iter().all(|p| p.finished));
+
+    // every result landed on the part of its own site
+    for part in &parts {
+        let results: Vec<f32> = part.results.iter().map(|r| match &r.record {
+            StdfRecord::PTR(rec) => { assert_eq!(rec.site_num, part.site_num); rec.result },
+            rec => panic!("unexpected record {:?}", rec),
+        }).collect();
+        let expected = match part.site_num { 1 => [1.0, 1.1], 3 => [2.0, 2.1], _ => [7.0, 7.1] };
+        assert_eq!(results, expected);
+    }
+
+    // the DTR shows up before any result and is cleared by the first PRR
+    assert!(parts[0].results.iter().all(|r| r.dtr_info.len() == 1 && r.dtr_info[0].text == "25"));
+    assert!(parts[2].results.iter().all(|r| r.dtr_info.is_empty()));
+}
+
+#[test]
+fn part_iterator_unfinished_part() {
+    let stdf_path = write_stdf("part_iterator_unfinished_part.stdf", vec![far(), pir(1, 1), pir(1, 2), ptr(100, 1, 2, 1.0), prr(1, 2, "P2", 1, 0), ptr(100, 1, 1, 0.5)]);
+
+    let parts = PartIterator::new(&stdf_path, &None).unwrap()
+        .collect::<Result<Vec<Part>, StdfUtilError>>().unwrap();
+
+    std::fs::remove_file(&stdf_path).unwrap();
+
+    assert_eq!(parts.len(), 2);
+    assert!(parts[0].finished && parts[0].part_id == "P2");
+    assert!(!parts[1].finished && parts[1].site_num == 1 && parts[1].results.len() == 1);
+}
+
+#[test]
+fn convert_stdf2csv_late_dtr_column() {
+    let stdf_path = write_stdf("convert_stdf2csv_late_dtr_column.stdf", vec![
+        far(),
+        dtr("COND: temp=25"),
+        pir(1, 1), ptr(100, 1, 1, 1.0), prr(1, 1, "P1", 1, 0),
+        dtr("COND: vdd=1.2"),
+        pir(1, 1), ptr(100, 1, 1, 2.0), prr(1, 1, "P2", 1, 1),
+    ]);
+    let dtr_cfg_path = temp_path("convert_stdf2csv_late_dtr_config.ini");
+    std::fs::write(&dtr_cfg_path, DTR_CONFIG_FILE_EXAMPLE).unwrap();
+    let csv_path = temp_path("convert_stdf2csv_late_dtr_column.csv");
+
+    stdf_reader::convert_stdf2csv(&stdf_path, &csv_path, &Some(dtr_cfg_path.to_owned())).unwrap();
+
+    let tests_csv = std::fs::read_to_string(csv_path.replace(".csv", ".tests.csv")).unwrap();
+    for path in [stdf_path, dtr_cfg_path, csv_path.replace(".csv", ".tests.csv"), csv_path.replace(".csv", ".part.summary.csv"), csv_path.replace(".csv", ".stdf.summary.csv"), csv_path.replace(".csv", ".test.summary.csv")] {
+        std::fs::remove_file(path).unwrap();
+    }
+
+    // the vdd column only shows up on the second part, the first part gets an empty cell
+    let lines: Vec<&str> = tests_csv.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].ends_with(",\"Hi Limit\",\"temp\",\"vdd\""));
+    assert!(lines[1].starts_with("\"P1\",\"100\",\"1\"") && lines[1].ends_with(",\"25\",\"\""));
+    assert!(lines[2].starts_with("\"P2\",\"100\",\"1\"") && lines[2].ends_with(",\"\",\"1.2\""));
+}
+
+#[test]
+fn convert_stdf2csv_rows_with_line_breaks() {
+    let stdf_path = temp_path("convert_stdf2csv_line_breaks.stdf");
+    let dtr_cfg_path = temp_path("convert_stdf2csv_line_breaks_config.ini");
+    let csv_path = temp_path("convert_stdf2csv_line_breaks.csv");
+    let part = |test_txt: &str, part_id: &str| vec![
+        StdfRecord::PIR(PIR { head_num: 1, site_num: 1 }),
+        StdfRecord::PTR(PTR { test_num: 100, head_num: 1, site_num: 1, result: 1.0, test_txt: test_txt.into(), ..Default::default() }),
+        StdfRecord::PRR(PRR { head_num: 1, site_num: 1, hard_bin: 1, soft_bin: 1, part_id: part_id.into(), ..Default::default() }),
+    ];
+
+    // the test text of the first part spans two lines, the vdd column only shows up after it
+    let mut recs = vec![StdfRecord::DTR(DTR { text_dat: "COND: temp=25".into() })];
+    recs.extend(part("vdd\nleak", "P1"));
+    recs.push(StdfRecord::DTR(DTR { text_dat: "COND: vdd=1.2".into() }));
+    recs.extend(part("vdd", "P2"));
+    write_records(&stdf_path, ByteOrder::LittleEndian, &recs);
+    std::fs::write(&dtr_cfg_path, DTR_CONFIG_FILE_EXAMPLE).unwrap();
+
+    stdf_reader::convert_stdf2csv(&stdf_path, &csv_path, &Some(dtr_cfg_path.to_owned())).unwrap();
+
+    let tests_csv = std::fs::read_to_string(csv_path.replace(".csv", ".tests.csv")).unwrap();
+    for path in [stdf_path, dtr_cfg_path, csv_path.replace(".csv", ".tests.csv"), csv_path.replace(".csv", ".part.summary.csv"), csv_path.replace(".csv", ".stdf.summary.csv"), csv_path.replace(".csv", ".test.summary.csv")] {
+        std::fs::remove_file(path).unwrap();
+    }
+
+    // the padding of the first row comes after its whole test text
+    let lines: Vec<&str> = tests_csv.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[1].starts_with("\"P1\",\"100\",\"1\",\"=\"\"vdd") && !lines[1].contains("25"));
+    assert!(lines[2].starts_with("leak\"\"\"") && lines[2].ends_with(",\"25\",\"\""));
+    assert!(lines[3].starts_with("\"P2\",\"100\",\"1\"") && lines[3].ends_with(",\"\",\"1.2\""));
+}
+
+#[test]
+fn convert_stdf2csv_failure_leaves_no_body_file() {
+    // the last record is cut short
+    let stdf_path = write_stdf("convert_stdf2csv_cut_short.stdf", vec![far(), pir(1, 1), ptr(100, 1, 1, 1.0), ptr(101, 1, 1, 2.0)[..8].to_vec()]);
+    let csv_path = temp_path("convert_stdf2csv_cut_short.csv");
+
+    let result = stdf_reader::convert_stdf2csv(&stdf_path, &csv_path, &None);
+    let body_left = std::path::Path::new(&csv_path.replace(".csv", ".tests.csv.tmp")).exists();
+    for path in [stdf_path, csv_path.replace(".csv", ".part.summary.csv"), csv_path.replace(".csv", ".stdf.summary.csv")] {
+        std::fs::remove_file(path).unwrap();
+    }
+
+    assert!(matches!(result, Err(StdfUtilError::Decode { .. })));
+    assert!(!body_left);
+}
+
+#[test]
+fn convert_stdf2csv_interleaved_sites() {
+    // sparse sites finishing out of order, plus site 1 of a second head tested alongside site 1 of the first
+    let mut recs = interleaved_multi_site_recs();
+    recs.insert(3, pir(2, 1));
+    recs.insert(6, ptr(100, 2, 1, 21.0));
+    recs.push(ptr(101, 2, 1, 21.1));
+    recs.push(prr(2, 1, "P4", 1, 9));
+    let stdf_path = write_stdf("convert_stdf2csv_interleaved_sites.stdf", recs);
+    let csv_path = temp_path("convert_stdf2csv_interleaved_sites.csv");
+
+    stdf_reader::convert_stdf2csv(&stdf_path, &csv_path, &None).unwrap();
+
+    let tests_csv = std::fs::read_to_string(csv_path.replace(".csv", ".tests.csv")).unwrap();
+    let part_summary_csv = std::fs::read_to_string(csv_path.replace(".csv", ".part.summary.csv")).unwrap();
+    let test_summary_csv = std::fs::read_to_string(csv_path.replace(".csv", ".test.summary.csv")).unwrap();
+    for path in [stdf_path, csv_path.replace(".csv", ".tests.csv"), csv_path.replace(".csv", ".part.summary.csv"), csv_path.replace(".csv", ".stdf.summary.csv"), csv_path.replace(".csv", ".test.summary.csv")] {
+        std::fs::remove_file(path).unwrap();
+    }
+
+    // every result is labeled with the part of its own head/site
+    let rows: Vec<Vec<&str>> = tests_csv.lines().skip(1).map(|line| line.split(',').take(3).map(|s| s.trim_matches('"')).collect()).collect();
+    assert_eq!(rows, vec![
+        vec!["P2", "100", "3"], vec!["P2", "101", "3"],
+        vec!["P1", "100", "1"], vec!["P1", "101", "1"],
+        vec!["P3", "100", "7"], vec!["P3", "101", "7"],
+        vec!["P4", "100", "1"], vec!["P4", "101", "1"],
+    ]);
+
+    let parts: Vec<&str> = part_summary_csv.lines().skip(1).map(|line| line.split(',').next().unwrap()).collect();
+    assert_eq!(parts, vec!["\"P2\"", "\"P1\"", "\"P3\"", "\"P4\""]);
+
+    // test 100 per head/site, per head and over all heads
+    let rows: Vec<Vec<&str>> = test_summary_csv.lines().skip(1).map(|line| line.split(',').take(8).map(|s| s.trim_matches('"')).collect()).filter(|row: &Vec<&str>| row[0] == "100").collect();
+    assert_eq!(rows.iter().map(|row| (row[3], row[4], row[7])).collect::<Vec<_>>(), vec![
+        ("1", "1", "1"), ("1", "3", "1"), ("1", "7", "1"), ("2", "1", "1"), ("1", "255", "3"), ("2", "255", "1"), ("255", "255", "4"),
+    ]);
+}
+
+// one of most record types with the optional and array fields filled in
+fn writer_test_recs() -> Vec<StdfRecord> {
+    vec![
+        StdfRecord::MIR(MIR { setup_t: 1000, start_t: 1001, stat_num: 3, mode_cod: 'P', burn_tim: 10, lot_id: "LOT1".into(), job_nam: "prog".into(), supr_nam: "sup".into(), ..Default::default() }),
+        StdfRecord::SDR(SDR { head_num: 1, site_grp: 1, site_cnt: 3, site_num: vec![1, 3, 7], hand_typ: "handler".into(), ..Default::default() }),
+        StdfRecord::PMR(PMR { pmr_indx: 1, chan_typ: 2, chan_nam: "ch1".into(), phy_nam: "P1".into(), log_nam: "A0".into(), head_num: 1, site_num: 3 }),
+        StdfRecord::PGR(PGR { grp_indx: 32768, grp_nam: "grp".into(), indx_cnt: 2, pmr_indx: vec![1, 2] }),
+        StdfRecord::WCR(WCR { wafr_siz: 300.0, die_ht: 1.5, die_wid: 2.5, wf_units: 3, wf_flat: 'D', center_x: 10, center_y: -10, pos_x: 'R', pos_y: 'U' }),
+        StdfRecord::GDR(GDR { fld_cnt: 13, gen_data: vec![V1::Cn("SHMOO".into()), V1::B0, V1::U1(1), V1::U2(2), V1::U4(4), V1::I1(-1), V1::I2(-2), V1::I4(-4), V1::R4(1.5), V1::R8(2.5), V1::Bn(vec![1, 2]), V1::Dn(vec![0xAA]), V1::N1(7)] }),
+        StdfRecord::DTR(DTR { text_dat: "COND: temp=25".into() }),
+        StdfRecord::PIR(PIR { head_num: 1, site_num: 3 }),
+        StdfRecord::PTR(PTR { test_num: 100, head_num: 1, site_num: 3, test_flg: [0x80], parm_flg: [0xC0], result: 1.25, test_txt: "ptr full".into(), alarm_id: "".into(),
+            opt_flag: Some([0x02]), res_scal: Some(-3), llm_scal: Some(0), hlm_scal: Some(3), lo_limit: Some(0.5), hi_limit: Some(1.5), units: Some("V".into()),
+            c_resfmt: Some("%7.3f".into()), c_llmfmt: Some("".into()), c_hlmfmt: Some("%f".into()), lo_spec: Some(0.1), hi_spec: Some(2.0) }),
+        StdfRecord::PTR(PTR { test_num: 101, head_num: 1, site_num: 3, result: -2.0, test_txt: "ptr short".into(), ..Default::default() }),
+        StdfRecord::MPR(MPR { test_num: 200, head_num: 1, site_num: 3, rtn_icnt: 3, rslt_cnt: 3, rtn_stat: vec![1, 2, 3], rtn_rslt: vec![0.5, 1.5, 2.5], test_txt: "mpr".into(),
+            opt_flag: Some([0]), res_scal: Some(0), llm_scal: Some(0), hlm_scal: Some(0), lo_limit: Some(0.0), hi_limit: Some(2.0), start_in: Some(0.0), incr_in: Some(0.1),
+            rtn_indx: Some(vec![1, 2, 3]), units: Some("A".into()), units_in: Some("V".into()), c_resfmt: Some("".into()), c_llmfmt: Some("".into()), c_hlmfmt: Some("".into()),
+            lo_spec: Some(-1.0), hi_spec: Some(3.0), ..Default::default() }),
+        StdfRecord::FTR(FTR { test_num: 300, head_num: 1, site_num: 3, test_flg: [0x80], cycl_cnt: 10, num_fail: 1, xfail_ad: -5, vect_off: -1, rtn_icnt: 1, pgm_icnt: 2,
+            rtn_indx: vec![1], rtn_stat: vec![4], pgm_indx: vec![1, 2], pgm_stat: vec![5, 6], fail_pin: vec![0x01, 0x80], vect_nam: "pat1".into(), test_txt: "ftr".into(),
+            patg_num: 2, spin_map: vec![0xFF], ..Default::default() }),
+        StdfRecord::STR(STR { test_num: 400, head_num: 1, site_num: 3, log_typ: "scan".into(), mask_map: vec![0x0F], cyc_cnt_t: 1 << 40, totf_cnt: 2, cyc_base: 7,
+            cond_cnt: 1, cond_lst: vec!["VDD=1.2".into()], lim_cnt: 1, lim_indx: vec![1], lim_spec: vec![9], cyc_size: 4, pmr_size: 2, utx_size: 4,
+            cyc_cnt: 2, cyc_ofst: KxUf::F4(vec![1, 2]), pmr_cnt: 2, pmr_indx: KxUf::F2(vec![1, 2]), exp_cnt: 1, exp_data: vec![1], txt_cnt: 2, user_txt: vec!["ab  ".into(), "cdef".into()],
+            ..Default::default() }),
+        StdfRecord::PRR(PRR { head_num: 1, site_num: 3, part_flg: [0x08], num_test: 5, hard_bin: 5, soft_bin: 50, x_coord: 1, y_coord: -1, test_t: 10, part_id: "P1".into(), part_fix: vec![1, 2, 3], ..Default::default() }),
+        StdfRecord::TSR(TSR { head_num: 255, site_num: 1, test_typ: 'P', test_num: 100, exec_cnt: 1, test_nam: "ptr full".into(), opt_flag: [0xC0], test_min: 1.25, test_max: 1.25, ..Default::default() }),
+        StdfRecord::HBR(HBR { head_num: 255, hbin_num: 5, hbin_cnt: 1, hbin_pf: 'F', hbin_nam: "fail".into(), ..Default::default() }),
+        StdfRecord::SBR(SBR { head_num: 255, sbin_num: 50, sbin_cnt: 1, sbin_pf: 'F', sbin_nam: "fail".into(), ..Default::default() }),
+        StdfRecord::PCR(PCR { head_num: 255, part_cnt: 1, good_cnt: 0, ..Default::default() }),
+        StdfRecord::MRR(MRR { finish_t: 2000, disp_cod: 'Q', usr_desc: "done".into(), ..Default::default() }),
+    ]
+}
+
+fn read_records(stdf_path: &String) -> Vec<StdfRecord> {
+    let mut parser = StdfParser::new(stdf_path, &None).unwrap();
+    parser.set_use_test_defaults(false);
+    parser.map(|parsed_rec| parsed_rec.unwrap().record).collect()
+}
+
+fn write_records(stdf_path: &String, byte_order: ByteOrder, recs: &[StdfRecord]) {
+    let mut writer = StdfWriter::new(stdf_path, byte_order).unwrap();
+    for rec in recs {
+        writer.write_record(rec).unwrap();
+    }
+    writer.finish().unwrap();
+}
+
+#[test]
+fn stdf_writer_round_trip() {
+    let recs = writer_test_recs();
+
+    for (name, byte_order, cpu_type) in [("stdf_writer_le.stdf", ByteOrder::LittleEndian, 2), ("stdf_writer_be.stdf", ByteOrder::BigEndian, 1), ("stdf_writer_le.stdf.gz", ByteOrder::LittleEndian, 2)] {
+        let stdf_path = temp_path(name);
+        write_records(&stdf_path, byte_order, &recs);
+        let read_recs = read_records(&stdf_path);
+        std::fs::remove_file(&stdf_path).unwrap();
+
+        // the FAR is added in front and matches the byte order
+        assert_eq!(read_recs[0], StdfRecord::FAR(FAR { cpu_type, stdf_ver: 4 }), "{}", name);
+        assert_eq!(read_recs.len(), recs.len() + 1, "{}", name);
+        for (rec, read_rec) in recs.iter().zip(&read_recs[1..]) {
+            assert_eq!(rec_to_string(rec, false), rec_to_string(read_rec, false), "{}", name);
+            assert_eq!(rec, read_rec, "{}", name);
+        }
+    }
+}
+
+#[test]
+fn stdf_writer_rewrites_stdf() {
+    // read a file written by something else, write it back out and read it again
+    let stdf_path = write_stdf("stdf_writer_rewrites_stdf.stdf", interleaved_multi_site_recs());
+    let out_path = temp_path("stdf_writer_rewrites_stdf.out.stdf");
+
+    let recs = read_records(&stdf_path);
+    write_records(&out_path, ByteOrder::LittleEndian, &recs);
+
+    let text: Vec<String> = recs.iter().map(|rec| rec_to_string(rec, false)).collect();
+    let read_text: Vec<String> = read_records(&out_path).iter().map(|rec| rec_to_string(rec, false)).collect();
+    assert_eq!(text, read_text);
+
+    std::fs::remove_file(&stdf_path).unwrap();
+    std::fs::remove_file(&out_path).unwrap();
+}
+
+#[test]
+fn stdf_writer_rejects_bad_records() {
+    let stdf_path = temp_path("stdf_writer_rejects_bad_records.stdf");
+    let mut writer = StdfWriter::new(&stdf_path, ByteOrder::LittleEndian).unwrap();
+
+    writer.write_record(&StdfRecord::PIR(PIR { head_num: 1, site_num: 1 })).unwrap();
+    assert!(matches!(writer.write_record(&StdfRecord::FAR(FAR { cpu_type: 2, stdf_ver: 4 })), Err(StdfUtilError::Encode { record: "FAR", .. })));
+    assert!(matches!(writer.write_record(&StdfRecord::DTR(DTR { text_dat: "x".repeat(256) })), Err(StdfUtilError::Encode { record: "DTR", .. })));
+    assert!(matches!(writer.write_record(&StdfRecord::PGR(PGR { indx_cnt: 2, pmr_indx: vec![1], ..Default::default() })), Err(StdfUtilError::Encode { record: "PGR", .. })));
+    writer.finish().unwrap();
+
+    // nothing of the rejected records made it into the file
+    let recs = read_records(&stdf_path);
+    std::fs::remove_file(&stdf_path).unwrap();
+    assert_eq!(recs, vec![StdfRecord::FAR(FAR { cpu_type: 2, stdf_ver: 4 }), StdfRecord::PIR(PIR { head_num: 1, site_num: 1 })]);
+}
+
+#[test]
+fn filter_stdf_keeps_defaults_and_summaries() {
+    let stdf_path = temp_path("filter_stdf_in.stdf");
+    let out_path = temp_path("filter_stdf_out.stdf");
+
+    // the first PTR of test 100 carries the limits and belongs to the part that gets dropped, so does its DTR
+    let full_ptr = PTR { test_num: 100, head_num: 1, site_num: 1, result: 1.0, test_txt: "vdd".into(), opt_flag: Some([0x0E]), res_scal: Some(0), llm_scal: Some(0), hlm_scal: Some(0),
+        lo_limit: Some(0.5), hi_limit: Some(1.5), units: Some("V".into()), c_resfmt: Some("".into()), c_llmfmt: Some("".into()), c_hlmfmt: Some("".into()), ..Default::default() };
+    write_records(&stdf_path, ByteOrder::LittleEndian, &[
+        StdfRecord::PIR(PIR { head_num: 1, site_num: 1 }),
+        StdfRecord::DTR(DTR { text_dat: "COND: part=1".into() }),
+        StdfRecord::PTR(full_ptr.to_owned()),
+        StdfRecord::FTR(FTR { test_num: 200, head_num: 1, site_num: 1, test_txt: "func".into(), ..Default::default() }),
+        StdfRecord::PRR(PRR { head_num: 1, site_num: 1, part_flg: [0x08], num_test: 2, hard_bin: 5, soft_bin: 5, part_id: "P1".into(), ..Default::default() }),
+        StdfRecord::PIR(PIR { head_num: 1, site_num: 1 }),
+        StdfRecord::PTR(PTR { test_num: 100, head_num: 1, site_num: 1, result: 1.25, ..Default::default() }),
+        StdfRecord::DTR(DTR { text_dat: "COND: part=2".into() }),
+        StdfRecord::FTR(FTR { test_num: 200, head_num: 1, site_num: 1, ..Default::default() }),
+        StdfRecord::PRR(PRR { head_num: 1, site_num: 1, num_test: 2, hard_bin: 1, soft_bin: 1, part_id: "P2".into(), ..Default::default() }),
+        StdfRecord::TSR(TSR { head_num: 255, site_num: 255, test_typ: 'P', test_num: 100, exec_cnt: 2, fail_cnt: 0, test_nam: "vdd".into(), ..Default::default() }),
+        StdfRecord::TSR(TSR { head_num: 255, site_num: 255, test_typ: 'F', test_num: 200, exec_cnt: 2, fail_cnt: 0, test_nam: "func".into(), ..Default::default() }),
+        StdfRecord::HBR(HBR { head_num: 255, site_num: 255, hbin_num: 1, hbin_cnt: 1, hbin_pf: 'P', ..Default::default() }),
+        StdfRecord::HBR(HBR { head_num: 255, site_num: 255, hbin_num: 5, hbin_cnt: 1, hbin_pf: 'F', ..Default::default() }),
+        StdfRecord::PCR(PCR { head_num: 255, site_num: 255, part_cnt: 2, good_cnt: 1, ..Default::default() }),
+        StdfRecord::MRR(MRR::default()),
+    ]);
+
+    let filter = StdfFilter { hard_bins: vec![1], test_txt: Some(regex::Regex::new("^vdd$").unwrap()), ..Default::default() };
+    let counts = filter_stdf(&stdf_path, &out_path, &filter).unwrap();
+    let recs = read_records(&out_path);
+    std::fs::remove_file(&stdf_path).unwrap();
+    std::fs::remove_file(&out_path).unwrap();
+
+    assert_eq!(counts, FilterCounts { parts_read: 2, parts_kept: 1 });
+    assert_eq!(recs, vec![
+        StdfRecord::FAR(FAR { cpu_type: 2, stdf_ver: 4 }),
+        StdfRecord::PIR(PIR { head_num: 1, site_num: 1 }),
+        StdfRecord::PTR(PTR { result: 1.25, ..full_ptr }),
+        StdfRecord::DTR(DTR { text_dat: "COND: part=2".into() }),
+        StdfRecord::PRR(PRR { head_num: 1, site_num: 1, num_test: 1, hard_bin: 1, soft_bin: 1, part_id: "P2".into(), ..Default::default() }),
+        StdfRecord::TSR(TSR { head_num: 255, site_num: 255, test_typ: 'P', test_num: 100, exec_cnt: 1, fail_cnt: 0, alrm_cnt: 0, test_nam: "vdd".into(),
+            test_min: 1.25, test_max: 1.25, tst_sums: 1.25, tst_sqrs: 1.5625, ..Default::default() }),
+        StdfRecord::HBR(HBR { head_num: 255, site_num: 255, hbin_num: 1, hbin_cnt: 1, hbin_pf: 'P', ..Default::default() }),
+        StdfRecord::HBR(HBR { head_num: 255, site_num: 255, hbin_num: 5, hbin_cnt: 0, hbin_pf: 'F', ..Default::default() }),
+        StdfRecord::PCR(PCR { head_num: 255, site_num: 255, part_cnt: 1, rtst_cnt: 0, abrt_cnt: 0, good_cnt: 1, ..Default::default() }),
+        // the STDF had no SBR's, the kept part still gets one
+        StdfRecord::SBR(SBR { head_num: 255, site_num: 255, sbin_num: 1, sbin_cnt: 1, ..Default::default() }),
+        StdfRecord::MRR(MRR::default()),
+    ]);
+}
+
+// the interleaved sites inside a program section, with a second section opened while sites 1 and 3 are in test
+fn sectioned_multi_site_recs() -> Vec<Vec<u8>> {
+    let bps = |seq_name: &str| stdf_rec(20, 10, cn(seq_name));
+    let eps = || stdf_rec(20, 20, vec![]);
+
+    let mut recs = interleaved_multi_site_recs();
+    recs.push(eps());
+    recs.insert(9, eps());
+    recs.insert(4, bps("part"));
+    recs.insert(1, bps("lot"));
+    recs
+}
+
+// number of DTR, BPS and EPS records, panics if an EPS has no BPS
+fn unowned_counts(recs: &[StdfRecord]) -> (usize, usize, usize) {
+    let count = |is_type: fn(&StdfRecord) -> bool| recs.iter().filter(|rec| is_type(rec)).count();
+    let mut depth = 0;
+    for rec in recs {
+        match rec {
+            StdfRecord::BPS(_) => depth += 1,
+            StdfRecord::EPS(_) => { assert!(depth > 0, "EPS without BPS"); depth -= 1; },
+            _ => {},
+        }
+    }
+    assert_eq!(depth, 0, "BPS without EPS");
+
+    (count(|rec| matches!(rec, StdfRecord::DTR(_))), count(|rec| matches!(rec, StdfRecord::BPS(_))), count(|rec| matches!(rec, StdfRecord::EPS(_))))
+}
+
+#[test]
+fn filter_stdf_writes_in_part_records_once() {
+    let stdf_path = write_stdf("filter_stdf_multi_site_in.stdf", sectioned_multi_site_recs());
+    let out_path = temp_path("filter_stdf_multi_site_out.stdf");
+    let filtered = |filter: StdfFilter| {
+        filter_stdf(&stdf_path, &out_path, &filter).unwrap();
+        unowned_counts(&read_records(&out_path))
+    };
+
+    // the DTR and the second section belong to the part opened last, P2 on site 3
+    let everything = filtered(StdfFilter::default());
+    let without_p2 = filtered(StdfFilter { hard_bins: vec![1], ..Default::default() });
+    let only_p2 = filtered(StdfFilter { sites: vec![3], ..Default::default() });
+    std::fs::remove_file(&stdf_path).unwrap();
+    std::fs::remove_file(&out_path).unwrap();
+
+    assert_eq!(everything, (1, 2, 2));
+    assert_eq!(without_p2, (0, 1, 1));
+    assert_eq!(only_p2, (1, 2, 2));
+}
+
+#[test]
+fn merge_stdf_keeps_last_retest() {
+    let first_path = temp_path("merge_stdf_first.stdf");
+    let second_path = temp_path("merge_stdf_second.stdf");
+    let out_path = temp_path("merge_stdf_out.stdf");
+
+    // both STDF's have different limits for test 100, only given by their first PTR
+    let limits_ptr = |lo_limit: f32, hi_limit: f32| PTR { test_num: 100, head_num: 1, site_num: 1, test_txt: "vdd".into(), opt_flag: Some([0x0E]), res_scal: Some(0), llm_scal: Some(0), hlm_scal: Some(0),
+        lo_limit: Some(lo_limit), hi_limit: Some(hi_limit), units: Some("V".into()), c_resfmt: Some("".into()), c_llmfmt: Some("".into()), c_hlmfmt: Some("".into()), ..Default::default() };
+    let short_ptr = |result: f32| PTR { test_num: 100, head_num: 1, site_num: 1, result, ..Default::default() };
+    let part = |ptr: PTR, part_id: &str, bin: u16| vec![
+        StdfRecord::PIR(PIR { head_num: 1, site_num: 1 }),
+        StdfRecord::PTR(ptr),
+        StdfRecord::PRR(PRR { head_num: 1, site_num: 1, num_test: 1, hard_bin: bin, soft_bin: bin, part_id: part_id.into(), ..Default::default() }),
+    ];
+    let hbr = |hbin_num: u16, hbin_cnt: u32| StdfRecord::HBR(HBR { head_num: 255, site_num: 255, hbin_num, hbin_cnt, hbin_pf: if hbin_num == 1 { 'P' } else { 'F' }, ..Default::default() });
+
+    let mut recs = vec![StdfRecord::MIR(MIR { setup_t: 100, lot_id: "LOT1".into(), ..Default::default() })];
+    recs.extend(part(PTR { result: 2.0, ..limits_ptr(0.5, 1.5) }, "P1", 5));
+    recs.extend(part(short_ptr(1.0), "P2", 1));
+    recs.extend([hbr(1, 1), hbr(5, 1), StdfRecord::MRR(MRR { finish_t: 300, ..Default::default() })]);
+    write_records(&first_path, ByteOrder::LittleEndian, &recs);
+
+    let mut recs = vec![StdfRecord::MIR(MIR { setup_t: 400, lot_id: "LOT1".into(), ..Default::default() })];
+    recs.extend(part(PTR { result: 1.1, ..limits_ptr(0.4, 1.6) }, "P1", 1));
+    recs.extend(part(short_ptr(1.2), "P3", 1));
+    recs.extend(part(short_ptr(1.3), "P4", 7));
+    recs.extend([hbr(1, 2), StdfRecord::MRR(MRR { finish_t: 500, ..Default::default() })]);
+    write_records(&second_path, ByteOrder::BigEndian, &recs);
+
+    let options = MergeOptions { renumber_part_ids: true, keep_last_retest: Some(RetestKey::PartId), use_last_mir: false };
+    let counts = merge_stdf(&[first_path.to_owned(), second_path.to_owned()], &out_path, &options).unwrap();
+    let recs = read_records(&out_path);
+    for path in [&first_path, &second_path, &out_path] {
+        std::fs::remove_file(path).unwrap();
+    }
+
+    assert_eq!(counts, MergeCounts { parts_read: 5, parts_kept: 4 });
+    let mut expected = vec![
+        StdfRecord::FAR(FAR { cpu_type: 2, stdf_ver: 4 }),
+        StdfRecord::MIR(MIR { setup_t: 100, lot_id: "LOT1".into(), ..Default::default() }),
+    ];
+    expected.extend(part(PTR { result: 1.0, ..limits_ptr(0.5, 1.5) }, "1", 1));
+    expected.extend(part(PTR { result: 1.1, ..limits_ptr(0.4, 1.6) }, "2", 1));
+    expected.extend(part(PTR { result: 1.2, ..limits_ptr(0.4, 1.6) }, "3", 1));
+    expected.extend(part(PTR { result: 1.3, ..limits_ptr(0.4, 1.6) }, "4", 7));
+    // bin 7 and the summaries missing from both STDF's are added after the ones they had
+    expected.extend([hbr(1, 3), hbr(5, 0),
+        StdfRecord::PCR(PCR { head_num: 255, site_num: 255, part_cnt: 4, rtst_cnt: 0, abrt_cnt: 0, good_cnt: 4, ..Default::default() }),
+        StdfRecord::HBR(HBR { head_num: 255, site_num: 255, hbin_num: 7, hbin_cnt: 1, ..Default::default() }),
+        StdfRecord::SBR(SBR { head_num: 255, site_num: 255, sbin_num: 1, sbin_cnt: 3, ..Default::default() }),
+        StdfRecord::SBR(SBR { head_num: 255, site_num: 255, sbin_num: 7, sbin_cnt: 1, ..Default::default() }),
+        StdfRecord::TSR(TSR { head_num: 255, site_num: 255, test_typ: 'P', test_num: 100, exec_cnt: 4, fail_cnt: 0, alrm_cnt: 0, test_nam: "vdd".into(), opt_flag: [TsrOptFlags::TEST_TIM_INVALID],
+            test_min: 1.0, test_max: 1.3, tst_sums: 4.6, tst_sqrs: 5.34, ..Default::default() }),
+        StdfRecord::MRR(MRR { finish_t: 500, ..Default::default() })]);
+    assert_eq!(recs, expected);
+}
+
+#[test]
+fn audit_summary_reports_mismatches() {
+    let stdf_path = temp_path("audit_summary.stdf");
+
+    let part = |site_num: u8, bin: u16, test_flg: u8| vec![
+        StdfRecord::PIR(PIR { head_num: 1, site_num }),
+        StdfRecord::PTR(PTR { test_num: 100, head_num: 1, site_num, test_flg: [test_flg], result: 1.0, ..Default::default() }),
+        StdfRecord::PRR(PRR { head_num: 1, site_num, part_flg: [if bin == 1 { 0 } else { 0x08 }], num_test: 1, hard_bin: bin, soft_bin: bin, ..Default::default() }),
+    ];
+    let mut recs = [part(1, 1, 0), part(2, 1, 0), part(1, 7, 0x80)].concat();
+    recs.extend([
+        // per site HBR's are right, the overall one is off and bin 7 has no overall HBR
+        StdfRecord::HBR(HBR { head_num: 1, site_num: 1, hbin_num: 1, hbin_cnt: 1, ..Default::default() }),
+        StdfRecord::HBR(HBR { head_num: 1, site_num: 1, hbin_num: 7, hbin_cnt: 1, ..Default::default() }),
+        StdfRecord::HBR(HBR { head_num: 1, site_num: 2, hbin_num: 1, hbin_cnt: 1, ..Default::default() }),
+        StdfRecord::HBR(HBR { head_num: 255, site_num: 255, hbin_num: 1, hbin_cnt: 3, ..Default::default() }),
+        StdfRecord::SBR(SBR { head_num: 255, site_num: 255, sbin_num: 1, sbin_cnt: 2, ..Default::default() }),
+        StdfRecord::SBR(SBR { head_num: 255, site_num: 255, sbin_num: 7, sbin_cnt: 1, ..Default::default() }),
+        // alrm_cnt is left empty and can't be checked
+        StdfRecord::TSR(TSR { head_num: 255, site_num: 255, test_num: 100, exec_cnt: 3, fail_cnt: 0, alrm_cnt: u32::MAX, ..Default::default() }),
+        StdfRecord::PCR(PCR { head_num: 255, site_num: 255, part_cnt: 3, rtst_cnt: 0, abrt_cnt: 0, good_cnt: 2, ..Default::default() }),
+        StdfRecord::MRR(MRR::default()),
+    ]);
+    write_records(&stdf_path, ByteOrder::LittleEndian, &recs);
+
+    let audit = audit_summary(&stdf_path).unwrap();
+    std::fs::remove_file(&stdf_path).unwrap();
+
+    let mismatch = |record: &'static str, head_num: u8, site_num: u8, number: u32, field: &'static str, reported: Option<u32>, computed: u32| {
+        SummaryMismatch { record, head_num, site_num, number, field, reported, computed }
+    };
+    assert_eq!(audit.parts_read, 3);
+    assert_eq!(audit.summary_recs, 8);
+    assert!(!audit.passed());
+    assert_eq!(audit.mismatches, vec![
+        mismatch("HBR", 255, 255, 1, "hbin_cnt", Some(3), 2),
+        mismatch("TSR", 255, 255, 100, "fail_cnt", Some(0), 1),
+        mismatch("HBR", 255, 255, 7, "hbin_cnt", None, 1),
+    ]);
+    assert_eq!(audit.mismatches[0].to_string(), "HBR head 255 site 255 bin 1: hbin_cnt is 3 but the STDF data gives 2");
+    assert_eq!(audit.mismatches[2].to_string(), "HBR head 255 site 255 bin 7 is missing, the STDF data gives hbin_cnt 1");
+}
+
+#[test]
+fn lint_stdf_reports_issues_with_offsets() {
+    let stdf_path = temp_path("lint_stdf.stdf");
+
+    write_records(&stdf_path, ByteOrder::LittleEndian, &[
+        StdfRecord::MIR(MIR::default()),
+        StdfRecord::PMR(PMR { pmr_indx: 1, head_num: 1, site_num: 1, ..Default::default() }),
+        StdfRecord::PIR(PIR { head_num: 1, site_num: 1 }),
+        StdfRecord::PTR(PTR { test_num: 100, head_num: 1, site_num: 1, opt_flag: Some([0x02]), res_scal: Some(0), llm_scal: Some(0), hlm_scal: Some(0),
+            lo_limit: Some(2.0), hi_limit: Some(1.0), units: Some("V".into()), c_resfmt: Some("".into()), c_llmfmt: Some("".into()), c_hlmfmt: Some("".into()), ..Default::default() }),
+        StdfRecord::MPR(MPR { test_num: 200, head_num: 1, site_num: 1, rtn_icnt: 2, rslt_cnt: 2, rtn_stat: vec![0, 0], rtn_rslt: vec![1.0, 2.0], opt_flag: Some([0xC0]),
+            res_scal: Some(0), llm_scal: Some(0), hlm_scal: Some(0), lo_limit: Some(0.0), hi_limit: Some(0.0), start_in: Some(0.0), incr_in: Some(0.0), rtn_indx: Some(vec![1, 3]), ..Default::default() }),
+        StdfRecord::PRR(PRR { head_num: 1, site_num: 1, ..Default::default() }),
+        StdfRecord::PRR(PRR { head_num: 1, site_num: 2, ..Default::default() }),
+        StdfRecord::PIR(PIR { head_num: 1, site_num: 3 }),
+        StdfRecord::WRR(WRR { head_num: 1, ..Default::default() }),
+        StdfRecord::MRR(MRR::default()),
+        StdfRecord::DTR(DTR { text_dat: "late".into() }),
+    ]);
+
+    let issues = lint_stdf(&stdf_path).unwrap();
+    let offsets: Vec<u64> = StdfParser::new(&stdf_path, &None).unwrap().map(|parsed_rec| parsed_rec.unwrap().offset).collect();
+    std::fs::remove_file(&stdf_path).unwrap();
+
+    // the FAR is 6 bytes long, header included
+    assert_eq!(offsets[..2], [0, 6]);
+    let issue = |rec_idx: usize, record: &'static str, msg: &str| LintIssue { offset: offsets[rec_idx], record, msg: msg.into() };
+    assert_eq!(issues, vec![
+        issue(4, "PTR", "test 100: lo_limit 2 is above hi_limit 1"),
+        issue(5, "MPR", "rtn_indx references PMR indexes {3} that aren't defined before it"),
+        issue(7, "PRR", "head 1 site 2 has no part in test, the PRR has no PIR"),
+        issue(8, "PIR", "the part on head 1 site 3 never got a PRR"),
+        issue(9, "WRR", "head 1 has no wafer in test, the WRR has no WIR"),
+        issue(11, "DTR", &format!("record after the MRR at offset {}, the MRR has to be the last record", offsets[10])),
+    ]);
+}
+
+#[test]
+fn stdf_parser_applies_flagged_limits() {
+    let stdf_path = temp_path("flagged_limits.stdf");
+
+    // test 100 has both limits in its first PTR, the first PTR of test 200 says there is no lo_limit
+    let first_ptr = |test_num: u32, opt_flag: u8| StdfRecord::PTR(PTR { test_num, head_num: 1, site_num: 1, opt_flag: Some([opt_flag]), res_scal: Some(0), llm_scal: Some(0), hlm_scal: Some(0),
+        lo_limit: Some(0.5), hi_limit: Some(1.5), units: Some("V".into()), c_resfmt: Some("".into()), c_llmfmt: Some("".into()), c_hlmfmt: Some("".into()), ..Default::default() });
+    let later_ptr = |test_num: u32, opt_flag: Option<[u8; 1]>| StdfRecord::PTR(PTR { test_num, head_num: 1, site_num: 1, opt_flag, res_scal: Some(0), llm_scal: Some(0), hlm_scal: Some(0),
+        lo_limit: Some(9.0), hi_limit: Some(9.5), ..Default::default() });
+    write_records(&stdf_path, ByteOrder::LittleEndian, &[
+        first_ptr(100, 0x0E),
+        StdfRecord::PTR(PTR { test_num: 100, head_num: 1, site_num: 1, ..Default::default() }),
+        later_ptr(100, Some([0x1E])),
+        later_ptr(100, Some([0x2E])),
+        later_ptr(100, Some([0x4E])),
+        first_ptr(200, 0x4E),
+        StdfRecord::PTR(PTR { test_num: 200, head_num: 1, site_num: 1, ..Default::default() }),
+    ]);
+
+    type Limits = (Option<f32>, Option<f32>, Option<[u8; 1]>);
+    let limits: Vec<Limits> = StdfParser::new(&stdf_path, &None).unwrap()
+        .filter_map(|parsed_rec| match parsed_rec.unwrap().record {
+            StdfRecord::PTR(rec) => Some((rec.lo_limit, rec.hi_limit, rec.opt_flag)),
+            _ => None,
+        })
+        .collect();
+    std::fs::remove_file(&stdf_path).unwrap();
+
+    // invalid limits are the ones of the first PTR, missing limits are dropped even when the first PTR had them
+    assert_eq!(limits, vec![
+        (Some(0.5), Some(1.5), Some([0x0E])),
+        (Some(0.5), Some(1.5), Some([0x0E])),
+        (Some(0.5), Some(9.5), Some([0x0E])),
+        (Some(9.0), Some(1.5), Some([0x0E])),
+        (None, Some(9.5), Some([0x4E])),
+        (None, Some(1.5), Some([0x4E])),
+        (None, Some(1.5), Some([0x4E])),
+    ]);
+}
+
+#[test]
+fn test_statistics_by_site() {
+    let ptr = |site_num: u8, result: f32, test_flg: u8| StdfRecord::PTR(PTR { test_num: 100, head_num: 1, site_num, result, test_flg: [test_flg], test_txt: "vdd".into(),
+        opt_flag: Some([0x0E]), lo_limit: Some(0.0), hi_limit: Some(10.0), units: Some("V".into()), ..Default::default() });
+
+    let mut statistics = TestStatistics::new();
+    for rec in [ptr(1, 2.0, 0), ptr(1, 4.0, 0), ptr(2, 4.0, 0), ptr(2, 11.0, 0x80), ptr(2, 5.0, 0x02)] {
+        statistics.add_record(&rec);
+    }
+    statistics.add_record(&StdfRecord::MPR(MPR { test_num: 200, head_num: 1, site_num: 1, rslt_cnt: 2, rtn_rslt: vec![0.5, 1.5], parm_flg: [0x80],
+        opt_flag: Some([0x00]), lo_limit: Some(0.0), hi_limit: Some(1.5), ..Default::default() }));
+    for (rtn_rslt, test_flg) in [(vec![0.5, 2.0], 0x80), (vec![0.5, 2.0], 0x00)] {
+        statistics.add_record(&StdfRecord::MPR(MPR { test_num: 300, head_num: 1, site_num: 1, rslt_cnt: 2, rtn_rslt, test_flg: [test_flg],
+            opt_flag: Some([0x00]), lo_limit: Some(0.0), hi_limit: Some(1.5), ..Default::default() }));
+    }
+
+    let summary = statistics.get(&TestId { test_num: 100, pin: None }).unwrap();
+    assert_eq!((summary.test_txt.as_str(), summary.units.as_str(), summary.lo_limit, summary.hi_limit), ("vdd", "V", Some(0.0), Some(10.0)));
+
+    // the invalid result of site 2 counts as an execution only
+    let site2 = &summary.sites[&(1, 2)];
+    assert_eq!((site2.executions, site2.count, site2.fails, site2.min, site2.max), (3, 2, 1, 4.0, 11.0));
+
+    let total = summary.total();
+    assert_eq!(total, summary.head(1));
+    assert_eq!((total.executions, total.count, total.fails), (5, 4, 1));
+    assert_eq!(total.mean(), Some(5.25));
+    assert!((total.stdev().unwrap() - (46.75f64 / 3.0).sqrt()).abs() < 1e-9);
+    assert_eq!(total.yield_pct(), Some(80.0));
+    assert_eq!(total.percentiles(&[0.0, 50.0, 100.0]), vec![Some(2.0), Some(4.0), Some(11.0)]);
+    // without the results only the percentiles are missing
+    let moments = summary.total_moments();
+    assert_eq!((moments.executions, moments.count, moments.fails, moments.min, moments.max, moments.mean(), moments.stdev()),
+        (total.executions, total.count, total.fails, total.min, total.max, total.mean(), total.stdev()));
+    assert_eq!(moments.percentiles(&[50.0]), vec![None]);
+    assert!((total.cp(summary.lo_limit, summary.hi_limit).unwrap() - 10.0 / (6.0 * total.stdev().unwrap())).abs() < 1e-9);
+    assert!((total.cpk(summary.lo_limit, summary.hi_limit).unwrap() - 4.75 / (3.0 * total.stdev().unwrap())).abs() < 1e-9);
+    assert_eq!(total.cpk(None, None), None);
+
+    // only the pins of a failed MPR outside the limits fail, parm_flg lets a result equal to the hi limit pass
+    let pins: Vec<(String, u64)> = statistics.tests().filter(|(test_id, _)| test_id.pin.is_some()).map(|(test_id, summary)| (test_id.to_string(), summary.total().fails)).collect();
+    assert_eq!(pins, vec![("200.0".to_string(), 0), ("200.1".to_string(), 0), ("300.0".to_string(), 0), ("300.1".to_string(), 1)]);
+}
+
+#[test]
+fn test_stats_percentiles_stay_bounded() {
+    let mut stats = TestStats::default();
+    let mut other = TestStats::default();
+    for result in 0..100_000 {
+        stats.add(Some(result as f32), false);
+        other.add(Some((result + 10_000) as f32), false);
+    }
+
+    // the extremes stay exact, the rest is close to the rank
+    let percentiles = stats.percentiles(&[0.0, 1.0, 50.0, 99.0, 100.0]);
+    assert_eq!((percentiles[0], percentiles[4]), (Some(0.0), Some(99_999.0)));
+    for (percentile, expected) in [(percentiles[1], 1_000.0), (percentiles[2], 50_000.0), (percentiles[3], 99_000.0)] {
+        assert!((percentile.unwrap() - expected).abs() < 500.0, "{:?} != {}", percentile, expected);
+    }
+    assert!((stats.ks_statistic(&other).unwrap() - 0.1).abs() < 0.01);
+
+    stats.merge(&other);
+    assert_eq!(stats.count, 200_000);
+    assert!((stats.percentiles(&[50.0])[0].unwrap() - 55_000.0).abs() < 1_000.0);
+}
+
+#[test]
+fn wafer_maps_follow_wcr_orientation() {
+    let stdf_path = temp_path("wafer_maps.stdf");
+
+    // x increases to the left and y upward, the second wafer has no WIR/WRR
+    let die = |site_num: u8, part_id: &str, bin: u16, x_coord: i16, y_coord: i16| vec![
+        StdfRecord::PIR(PIR { head_num: 1, site_num }),
+        StdfRecord::PRR(PRR { head_num: 1, site_num, part_flg: [if bin == 1 { 0 } else { 0x08 }], hard_bin: bin, soft_bin: bin + 10, x_coord, y_coord, part_id: part_id.into(), ..Default::default() }),
+    ];
+    let mut recs = vec![
+        StdfRecord::MIR(MIR { lot_id: "LOT1".into(), ..Default::default() }),
+        StdfRecord::WCR(WCR { wf_flat: 'D', pos_x: 'L', pos_y: 'U', center_x: -32768, center_y: -32768, ..Default::default() }),
+        StdfRecord::WIR(WIR { head_num: 1, site_grp: 255, wafer_id: "W01".into(), ..Default::default() }),
+    ];
+    recs.extend(die(1, "P1", 1, 0, 0));
+    recs.extend(die(2, "P2", 5, 1, 0));
+    recs.extend(die(1, "P3", 1, 1, 1));
+    recs.extend(die(2, "P4", 1, 1, 0));
+    recs.push(StdfRecord::WRR(WRR { head_num: 1, site_grp: 255, wafer_id: "W01".into(), ..Default::default() }));
+    recs.extend(die(1, "P5", 1, 3, 3));
+    recs.extend(die(1, "P6", 1, -32768, -32768));
+    recs.extend([
+        StdfRecord::HBR(HBR { head_num: 255, site_num: 255, hbin_num: 1, hbin_pf: 'P', hbin_nam: "GOOD".into(), ..Default::default() }),
+        StdfRecord::HBR(HBR { head_num: 255, site_num: 255, hbin_num: 5, hbin_pf: 'F', hbin_nam: "OPEN".into(), ..Default::default() }),
+        StdfRecord::MRR(MRR::default()),
+    ]);
+    write_records(&stdf_path, ByteOrder::LittleEndian, &recs);
+    let wafer_maps = WaferMaps::read(&stdf_path).unwrap();
+    std::fs::remove_file(&stdf_path).unwrap();
+
+    assert_eq!(wafer_maps.config.flat, Some(WaferSide::Down));
+    assert_eq!(wafer_maps.hard_bins.get(&5), Some(&BinInfo { name: "OPEN".into(), pass: Some(false) }));
+    assert_eq!(wafer_maps.wafers.iter().map(|wafer| (wafer.wafer_id.as_str(), wafer.dies.len(), wafer.finished)).collect::<Vec<_>>(), vec![("W01", 4, true), ("", 1, false)]);
+
+    let wafer = &wafer_maps.wafers[0];
+    assert_eq!(wafer.dies[1], Die { x: 1, y: 0, part_idx: 2, head_num: 1, site_num: 2, part_id: "P2".into(), hard_bin: 5, soft_bin: 15, passed: Some(false) });
+    assert_eq!(wafer.last_dies().iter().map(|die| die.part_id.as_str()).collect::<Vec<_>>(), vec!["P1", "P3", "P4"]);
+
+    // x 1 is the left column and y 1 the top row, the retest of 1/0 wins
+    let grid = wafer.grid(&wafer_maps.config);
+    assert_eq!((grid.cols, grid.rows), (2, 2));
+    assert_eq!(grid.coords(0, 0), (1, 1));
+    assert_eq!(grid.position(0, 0), Some((1, 1)));
+    assert_eq!(grid.die(0, 1).map(|idx| wafer.dies[idx].part_id.as_str()), Some("P4"));
+    assert_eq!(grid.die(1, 0), None);
+}
+
+#[test]
+fn convert_stdf2wafermap_writes_maps_per_wafer() {
+    let stdf_path = temp_path("stdf2wafermap.stdf");
+
+    // x increases to the right and y upward, 0/1 has no die, test 100 is in mV
+    let die = |part_id: &str, bin: u16, x_coord: i16, y_coord: i16, result: f32| vec![
+        StdfRecord::PIR(PIR { head_num: 1, site_num: 1 }),
+        StdfRecord::PTR(if part_id == "P1" {
+            PTR { test_num: 100, head_num: 1, site_num: 1, result, test_txt: "vdd".into(), opt_flag: Some([0xCE]), res_scal: Some(3), llm_scal: Some(3), hlm_scal: Some(3), lo_limit: Some(0.0), hi_limit: Some(0.0),
+                units: Some("V".into()), c_resfmt: Some("".into()), c_llmfmt: Some("".into()), c_hlmfmt: Some("".into()), ..Default::default() }
+        } else {
+            PTR { test_num: 100, head_num: 1, site_num: 1, result, ..Default::default() }
+        }),
+        StdfRecord::PRR(PRR { head_num: 1, site_num: 1, part_flg: [if bin == 1 { 0 } else { 0x08 }], hard_bin: bin, soft_bin: bin, x_coord, y_coord, part_id: part_id.into(), ..Default::default() }),
+    ];
+    let mut recs = vec![
+        StdfRecord::MIR(MIR { lot_id: "LOT1".into(), ..Default::default() }),
+        StdfRecord::WCR(WCR { wf_flat: 'D', pos_x: 'R', pos_y: 'U', center_x: -32768, center_y: -32768, ..Default::default() }),
+        StdfRecord::WIR(WIR { head_num: 1, site_grp: 255, wafer_id: "W/01".into(), ..Default::default() }),
+    ];
+    recs.extend(die("P1", 1, 0, 0, 0.001));
+    recs.extend(die("P2", 12, 1, 0, 0.002));
+    recs.extend(die("P3", 1, 1, 1, 0.003));
+    recs.extend([
+        StdfRecord::WRR(WRR { head_num: 1, site_grp: 255, wafer_id: "W/01".into(), ..Default::default() }),
+        StdfRecord::HBR(HBR { head_num: 255, site_num: 255, hbin_num: 12, hbin_pf: 'F', hbin_nam: "OPEN".into(), ..Default::default() }),
+        StdfRecord::MRR(MRR::default()),
+    ]);
+    write_records(&stdf_path, ByteOrder::LittleEndian, &recs);
+
+    let options = WaferMapOptions { formats: vec![WaferMapFormat::Ascii, WaferMapFormat::E142, WaferMapFormat::Png, WaferMapFormat::Svg], heat_map_tests: vec![100], ..Default::default() };
+    let written = convert_stdf2wafermap(&stdf_path, &stdf_path, &options).unwrap();
+    let ascii_map = std::fs::read_to_string(format!("{}.W_01.hbin.txt", stdf_path)).unwrap();
+    let e142_map = std::fs::read_to_string(format!("{}.W_01.hbin.xml", stdf_path)).unwrap();
+    let png_header = std::fs::read(format!("{}.W_01.test100.png", stdf_path)).unwrap()[..8].to_vec();
+    let heat_map_svg = std::fs::read_to_string(format!("{}.W_01.test100.svg", stdf_path)).unwrap();
+    for path in written.iter().chain([&stdf_path]) {
+        std::fs::remove_file(path).unwrap();
+    }
+
+    // heat maps are only written as images
+    let suffixes: Vec<&str> = written.iter().map(|path| path.trim_start_matches(&stdf_path)).collect();
+    assert_eq!(suffixes, vec![".W_01.hbin.txt", ".W_01.hbin.xml", ".W_01.hbin.png", ".W_01.hbin.svg", ".W_01.test100.png", ".W_01.test100.svg"]);
+    assert_eq!(png_header, b"\x89PNG\r\n\x1a\n");
+
+    // the heat map legend is in the scaled units of the first PTR
+    for text in [">max 3 mV<", ">mid 2 mV<", ">min 1 mV<"] {
+        assert!(heat_map_svg.contains(text), "{}", text);
+    }
+
+    // the top row is y 1
+    let ascii_lines: Vec<&str> = ascii_map.lines().collect();
+    assert!(ascii_lines.contains(&"BIN 12 F      1 OPEN"));
+    assert_eq!(&ascii_lines[ascii_lines.len() - 2..], ["..  1", " 1 12"]);
+    assert!(e142_map.contains(r#"OriginLocation="LowerLeft" AxisDirection="UpRight""#));
+    assert!(e142_map.contains("<BinCode>FFFF0001</BinCode>"));
+    assert!(e142_map.contains("<BinCode>0001000C</BinCode>"));
+}
+
+#[test]
+fn record_query_matches_record_fields() {
+    let ptr = RecordFields {
+        rec_type: "PTR",
+        head_num: Some(1),
+        site_num: Some(3),
+        part_idx: Some(42),
+        test_num: Some(1500),
+        test_txt: "vdd_leakage".into(),
+        result: Some(1.95),
+        lo_limit: Some(0.0),
+        hi_limit: Some(2.0),
+        failed: Some(true),
+        dtr: vec![("temp".into(), "85".into())],
+        ..Default::default()
+    };
+    let query = |text: &str| RecordQuery::parse(text, &[]).unwrap().matches(&ptr);
+
+    assert!(query("type=PTR and test_num in 1000..2000 and site=3 and failed"));
+    assert!(query("type=ptr and result > hi_limit*0.9"));
+    assert!(query("part=42"));
+    assert!(query("test_txt ~ '^vdd_' and dtr.temp = 85"));
+    assert!(query("(site=1 or site=3) and not passed"));
+    assert!(query("-(result - hi_limit) > 0.04"));
+    assert!(!query("test_num in 1..999 or part != 42"));
+    // a missing field never matches, not even with !=
+    assert!(!query("dtr.volt != 1"));
+    assert!(!query("passed"));
+
+    // record types without tests don't have their fields
+    let dtr = RecordFields { rec_type: "DTR", text: "COND: temp=85".into(), ..Default::default() };
+    let query = RecordQuery::parse("text ~ 'temp=85' and not failed", &[]).unwrap();
+    assert!(query.matches(&dtr));
+    assert!(!RecordQuery::parse("result < 10", &[]).unwrap().matches(&dtr));
+}
+
+#[test]
+fn record_query_uses_scaled_results() {
+    // a PTR in mV, the query sees the values and units stdf2ui shows
+    let ptr = PTR { test_num: 100, head_num: 1, site_num: 1, result: 0.0015, test_txt: "vdd".into(), opt_flag: Some([0x02]), res_scal: Some(3), llm_scal: Some(3), hlm_scal: Some(3),
+        lo_limit: Some(0.001), hi_limit: Some(0.002), units: Some("V".into()), ..Default::default() };
+    let parsed_rec = ParsedRecord { record: StdfRecord::PTR(ptr), offset: 0, dtr_info: Vec::new() };
+    let fields = RecordFields::new(&parsed_rec, &PartTracker::new());
+
+    assert_eq!(fields.units, "mV");
+    let query = |text: &str| RecordQuery::parse(text, &[]).unwrap().matches(&fields);
+    assert!(query("result > 1.4 and result < 1.6 and units = mV"));
+    assert!(query("lo_limit > 0.9 and hi_limit < 2.1"));
+    assert!(!query("result < 0.01"));
+}
+
+#[test]
+fn record_query_reports_errors() {
+    let error = |text: &str| match RecordQuery::parse(text, &["file"]) {
+        Err(StdfUtilError::Query { msg, .. }) => msg,
+        other => panic!("{} should not parse: {:?}", text, other.map(|_| ())),
+    };
+
+    assert_eq!(error("type=PTR and tset_num = 5"), "'tset_num' at 13 is not a field");
+    assert_eq!(error("1 = 1"), "the condition at 0 doesn't use any field");
+    assert_eq!(error("vdd"), "'vdd' at 0 is not a field");
+    assert_eq!(error("(type=PTR"), "expected ')' at the end");
+    assert_eq!(error("test_num in 1000 2000"), "expected '..' at 17, found 2000");
+    assert_eq!(error("site=3 site=4"), "expected 'and' or 'or' at 7, found 'site'");
+    assert_eq!(error("test_txt ~ '('").split(':').next(), Some("invalid regex '('"));
+    assert_eq!(error("site = 'x"), "unterminated string at 7");
+    assert_eq!(error(""), "the query is empty");
+
+    // fields of the caller are fields too
+    assert!(RecordQuery::parse("file=2", &["file"]).is_ok());
+}
+
+#[test]
+fn compare_stdf_reports_shifts_and_missing_tests() {
+    let first_path = temp_path("compare_stdf_first.stdf");
+    let second_path = temp_path("compare_stdf_second.stdf");
+
+    // test 100 moves up by 3 sigmas and gets a new hi limit, the other tests are only in one of the files
+    let limits_ptr = |hi_limit: f32| PTR { test_num: 100, head_num: 1, site_num: 1, test_txt: "vdd".into(), opt_flag: Some([0x0E]), res_scal: Some(0), llm_scal: Some(0), hlm_scal: Some(0),
+        lo_limit: Some(0.5), hi_limit: Some(hi_limit), units: Some("V".into()), c_resfmt: Some("".into()), c_llmfmt: Some("".into()), c_hlmfmt: Some("".into()), ..Default::default() };
+    let ptr = |test_num: u32, test_txt: &str, result: f32, test_flg: u8| StdfRecord::PTR(PTR { test_num, head_num: 1, site_num: 1, result, test_flg: [test_flg], test_txt: test_txt.into(), ..Default::default() });
+    write_records(&first_path, ByteOrder::LittleEndian, &[
+        StdfRecord::PTR(PTR { result: 1.0, ..limits_ptr(1.5) }),
+        ptr(100, "vdd", 1.1, 0),
+        ptr(100, "vdd", 1.2, 0),
+        ptr(101, "idd", 0.1, 0),
+        StdfRecord::MRR(MRR::default()),
+    ]);
+    write_records(&second_path, ByteOrder::LittleEndian, &[
+        StdfRecord::PTR(PTR { result: 1.3, ..limits_ptr(2.0) }),
+        ptr(100, "vdd", 1.4, 0),
+        ptr(100, "vdd", 1.5, 0x80),
+        ptr(102, "iddq", 0.2, 0),
+        StdfRecord::MRR(MRR::default()),
+    ]);
+
+    let comparison = compare_stdf(&[first_path.to_owned(), second_path.to_owned()]).unwrap();
+    std::fs::remove_file(&first_path).unwrap();
+    std::fs::remove_file(&second_path).unwrap();
+
+    let keys: Vec<(String, &str)> = comparison.tests.iter().map(|test| (test.key.test_id.to_string(), test.key.test_txt.as_str())).collect();
+    assert_eq!(keys, vec![("100".to_string(), "vdd"), ("101".to_string(), "idd"), ("102".to_string(), "iddq")]);
+
+    let vdd = &comparison.tests[0];
+    assert!(!vdd.missing_in_some());
+    let shift = vdd.shift(1).unwrap();
+    assert!((shift.mean_shift.unwrap() - 0.3).abs() < 1e-6);
+    assert!((shift.mean_shift_sigma.unwrap() - 3.0).abs() < 1e-4);
+    assert!((shift.stdev_ratio.unwrap() - 1.0).abs() < 1e-4);
+    assert_eq!(shift.ks, Some(1.0));
+    assert!((shift.yield_delta.unwrap() + 100.0 / 3.0).abs() < 1e-9);
+    assert!(shift.limits_changed);
+
+    // a test of one file only has no shift
+    assert!(comparison.tests[1].missing_in_some() && comparison.tests[1].shift(1).is_none());
+    assert!(comparison.tests[2].files[0].is_none() && comparison.tests[2].shift(1).is_none());
+
+    let text = comparison.to_text();
+    assert!(text.contains("hi 1.5 -> 2"), "{}", text);
+    assert!(text.lines().any(|line| line.trim() == "[2]   missing"), "{}", text);
+    assert!(text.ends_with("3 tests, 2 missing in some of the files\n"), "{}", text);
+}
+
+#[test]
+fn test_plan_diff_reports_plan_changes() {
+    let old_path = temp_path("test_plan_old.stdf");
+    let new_path = temp_path("test_plan_new.stdf");
+
+    // the first record of a test defines it, the later ones leave out the name and limits
+    let ptr = |test_num: u32, test_txt: &str, hi_limit: f32| StdfRecord::PTR(PTR { test_num, head_num: 1, site_num: 1, test_txt: test_txt.into(), opt_flag: Some([0x0E]),
+        res_scal: Some(0), llm_scal: Some(0), hlm_scal: Some(0), lo_limit: Some(0.5), hi_limit: Some(hi_limit), units: Some("V".into()),
+        c_resfmt: Some("".into()), c_llmfmt: Some("".into()), c_hlmfmt: Some("".into()), ..Default::default() });
+    let bare_ptr = |test_num: u32| StdfRecord::PTR(PTR { test_num, head_num: 1, site_num: 1, opt_flag: Some([0x3E]), ..Default::default() });
+    let ftr = |test_num: u32, test_txt: &str| StdfRecord::FTR(FTR { test_num, head_num: 1, site_num: 1, test_txt: test_txt.into(), vect_nam: "pat1".into(), ..Default::default() });
+    write_records(&old_path, ByteOrder::LittleEndian, &[
+        ptr(100, "vdd", 1.5), ptr(101, "idd", 1.0), ptr(102, "iddq", 1.0), ptr(103, "leak", 1.0), ftr(300, "func"),
+        bare_ptr(100), bare_ptr(101),
+        StdfRecord::MRR(MRR::default()),
+    ]);
+    write_records(&new_path, ByteOrder::LittleEndian, &[
+        ptr(100, "vdd", 2.0), ptr(101, "idd_core", 1.0), ptr(112, "iddq", 1.0), ptr(104, "ioff", 1.0), ftr(300, "func"),
+        bare_ptr(100),
+        StdfRecord::MRR(MRR::default()),
+    ]);
+
+    let old_plan = TestPlan::read(&old_path).unwrap();
+    let new_plan = TestPlan::read(&new_path).unwrap();
+    std::fs::remove_file(&old_path).unwrap();
+    std::fs::remove_file(&new_path).unwrap();
+
+    let tests: Vec<(&str, u32)> = old_plan.entries.iter().map(|entry| (entry.rec_type, entry.test_num)).collect();
+    assert_eq!(tests, vec![("PTR", 100), ("PTR", 101), ("PTR", 102), ("PTR", 103), ("FTR", 300)]);
+    assert_eq!(old_plan.get("PTR", 100).unwrap().hi_limit, Some(1.5));
+    assert_eq!(old_plan.get("FTR", 300).unwrap().vect_nam, "pat1");
+    assert!(old_plan.diff(&old_plan).is_empty());
+
+    let changes: Vec<String> = old_plan.diff(&new_plan).iter().map(|change| change.to_string()).collect();
+    assert_eq!(changes, vec![
+        "changed PTR 100 'vdd': hi_limit '1.5' -> '2'",
+        "renamed PTR 101 'idd' -> 'idd_core'",
+        "removed PTR 103 'leak'",
+        "added PTR 104 'ioff'",
+        "renumbered PTR 'iddq' 102 -> 112",
+    ]);
+}
+
+#[test]
+fn relimit_stdf_reports_yield_before_and_after() {
+    let stdf_path = temp_path("relimit_stdf.stdf");
+    let limits_path = temp_path("relimit_stdf_limits.csv");
+
+    // a result equal to the hi limit of vdd passes, parm_flg says so
+    let ptr = |site_num: u8, result: f32, test_flg: u8| StdfRecord::PTR(PTR { test_num: 100, head_num: 1, site_num, test_txt: "vdd".into(), result, test_flg: [test_flg], parm_flg: [0x80],
+        opt_flag: Some([0x0E]), res_scal: Some(0), llm_scal: Some(0), hlm_scal: Some(0), lo_limit: Some(0.5), hi_limit: Some(1.5), units: Some("V".into()),
+        c_resfmt: Some("".into()), c_llmfmt: Some("".into()), c_hlmfmt: Some("".into()), ..Default::default() });
+    let idd = |site_num: u8, result: f32| StdfRecord::PTR(PTR { test_num: 101, head_num: 1, site_num, test_txt: "idd".into(), result, test_flg: [if result > 1.0 { 0x80 } else { 0 }], opt_flag: Some([0x0E]), lo_limit: Some(0.0), hi_limit: Some(1.0), ..Default::default() });
+    let part = |site_num: u8, part_id: &str, vdd: f32, test_flg: u8, idd_result: f32, part_flg: u8, bin: u16| vec![
+        StdfRecord::PIR(PIR { head_num: 1, site_num }),
+        ptr(site_num, vdd, test_flg),
+        idd(site_num, idd_result),
+        StdfRecord::PRR(PRR { head_num: 1, site_num, part_id: part_id.into(), part_flg: [part_flg], hard_bin: bin, soft_bin: bin, ..Default::default() }),
+    ];
+    let mut records = Vec::new();
+    records.extend(part(1, "1", 1.0, 0, 0.5, 0, 1));
+    records.extend(part(2, "2", 1.2, 0, 0.5, 0, 1));
+    records.extend(part(1, "3", 1.6, 0x80, 0.5, 0x08, 5));
+    records.extend(part(2, "4", 1.6, 0x80, 2.0, 0x08, 5));
+    records.push(StdfRecord::MRR(MRR::default()));
+    write_records(&stdf_path, ByteOrder::LittleEndian, &records);
+
+    // vdd moves to [0.5, 1.2] and idd is found by its name
+    std::fs::write(&limits_path, "\"Test Number\",\"Test Name\",\"hi_limit\",\"hard_bin\",\"soft_bin\"\n\"100\",\"\",\"1.2\",\"\",\"\"\n\"\",\"IDD\",\"3\",\"\",\"\"\n").unwrap();
+    let overrides = LimitOverrides::read(&limits_path).unwrap();
+    std::fs::write(&limits_path, "\"Test Number\",\"hi_limit\"\n\"100\",\"high\"\n").unwrap();
+    let error = LimitOverrides::read(&limits_path).unwrap_err();
+    std::fs::remove_file(&limits_path).unwrap();
+    assert_eq!(error.to_string(), format!("Unable to load limits file '{}': line 2: 'high' is not a limit", limits_path));
+
+    let options = RelimitOptions { pass_hard_bin: Some(1), pass_soft_bin: Some(1), ..Default::default() };
+    let report = relimit_stdf(&stdf_path, &overrides, &options).unwrap();
+    std::fs::remove_file(&stdf_path).unwrap();
+
+    // part 2 sits on the new limit, part 3 still fails vdd, part 4 no longer fails idd but still fails vdd
+    let parts: Vec<(&str, bool, bool, u16)> = report.parts.iter().map(|part| (part.part_id.as_str(), part.failed_before, part.failed_after, part.hard_bin_after)).collect();
+    assert_eq!(parts, vec![("1", false, false, 1), ("2", false, false, 1), ("3", true, true, 5), ("4", true, true, 5)]);
+
+    assert_eq!(report.total(), SiteYield { parts: 4, passed_before: 2, passed_after: 2 });
+
+    let relaxed = {
+        let mut overrides = LimitOverrides::new();
+        overrides.insert_num(100, LimitOverride { hi_limit: Some(None), ..Default::default() });
+        overrides
+    };
+    let stdf_path = temp_path("relimit_stdf_relaxed.stdf");
+    write_records(&stdf_path, ByteOrder::LittleEndian, &records);
+    let report = relimit_stdf(&stdf_path, &relaxed, &options).unwrap();
+    std::fs::remove_file(&stdf_path).unwrap();
+
+    // part 3 now passes and gets the pass bin, part 4 still fails idd
+    assert_eq!(report.sites[&(1, 1)], SiteYield { parts: 2, passed_before: 1, passed_after: 2 });
+    assert_eq!(report.sites[&(1, 2)], SiteYield { parts: 2, passed_before: 1, passed_after: 1 });
+    assert_eq!((report.parts[2].hard_bin_after, report.parts[2].first_fail_after), (1, None));
+    assert_eq!(report.parts[3].first_fail_after, Some(101));
+    assert!(report.to_text().ends_with("1 parts now pass, 0 now fail, 1 rebinned\n"), "{}", report.to_text());
+
+    // part 2 fails the tighter vdd limit, it can't keep its pass bin
+    let tightened = {
+        let mut overrides = LimitOverrides::new();
+        overrides.insert_num(100, LimitOverride { hi_limit: Some(Some(1.1)), ..Default::default() });
+        overrides
+    };
+    write_records(&stdf_path, ByteOrder::LittleEndian, &records);
+    let error = relimit_stdf(&stdf_path, &tightened, &options).unwrap_err();
+    assert!(matches!(error, StdfUtilError::MissingFailBin { part_idx: 2, test_num: 100 }), "{}", error);
+    let options = RelimitOptions { fail_hard_bin: Some(7), fail_soft_bin: Some(70), ..options };
+    let report = relimit_stdf(&stdf_path, &tightened, &options).unwrap();
+    std::fs::remove_file(&stdf_path).unwrap();
+    assert_eq!((report.parts[1].failed_after, report.parts[1].hard_bin_after, report.parts[1].soft_bin_after), (true, 7, 70));
+}
+
+#[test]
+fn format_value_applies_scale_and_c_format() {
+    // 1.5 mA shown with the m prefix and its format, %lf is taken as %f
+    assert_eq!(format_value(Some(0.0015), Some(3), &Some("%7.3f".into())), "  1.500");
+    assert_eq!(format_value(Some(0.0015), Some(3), &Some("%lf".into())), "1.500000");
+    assert_eq!(format_value(Some(0.0015), Some(3), &None), "1.5");
+    assert_eq!(scaled_units("A", Some(3)), "mA");
+
+    // formats that aren't a single float conversion are ignored
+    assert_eq!(format_value(Some(1.5), None, &Some("%s".into())), "1.5");
+    assert_eq!(format_value(Some(1.5), None, &Some("%f %f".into())), "1.5");
+    assert_eq!(format_value(Some(1.5), None, &Some("%99999f".into())), "1.5");
+    assert_eq!(format_value(Some(f32::NAN), None, &None), "");
+
+    // a scale without a prefix still applies, its units carry the factor
+    assert_eq!((format_value(Some(1.5), Some(1), &None), scaled_units("V", Some(1))), ("15".to_string(), "x10^-1 V".to_string()));
+    assert_eq!((format_value(Some(1.5), Some(-2), &None), scaled_units("", Some(-2))), ("0.015".to_string(), "x10^2".to_string()));
+
+    // limits are shown in the units of the result, whatever their own scale
+    let test_format = TestFormat::from(&PTR { units: Some("V".into()), res_scal: Some(3), llm_scal: Some(3), hlm_scal: Some(0), ..Default::default() });
+    assert_eq!((test_format.lo_limit(Some(0.5)), test_format.result(1.2), test_format.hi_limit(Some(1.5)), test_format.units()),
+        ("500".to_string(), "1200".to_string(), "1500".to_string(), "mV".to_string()));
+
+    // statistics are kept unscaled and shown scaled
+    let mut statistics = TestStatistics::new();
+    statistics.add_record(&StdfRecord::PTR(PTR { test_num: 100, head_num: 1, site_num: 1, result: 0.0015, units: Some("A".into()), res_scal: Some(3), opt_flag: Some([0x0E]), hi_limit: Some(0.002), ..Default::default() }));
+    let summary = statistics.get(&TestId { test_num: 100, pin: None }).unwrap();
+    assert_eq!((summary.hi_limit.map(|limit| limit as f32), summary.scaled(summary.hi_limit).map(|limit| limit as f32), summary.scaled_units()), (Some(0.002), Some(2.0), "mA".to_string()));
+}
+
+#[test]
+fn csv_tests_format_limits_like_the_result() {
+    let stdf_path = temp_path("csv_test_format.stdf");
+    let csv_path = temp_path("csv_test_format.csv");
+
+    // the result is in mV, the hi limit has a scale of its own, none of them has a C format
+    write_records(&stdf_path, ByteOrder::LittleEndian, &[
+        StdfRecord::PIR(PIR { head_num: 1, site_num: 1 }),
+        StdfRecord::PTR(PTR { test_num: 100, head_num: 1, site_num: 1, result: 1.2, test_txt: "vdd".into(), opt_flag: Some([0x0E]), res_scal: Some(3), llm_scal: Some(3), hlm_scal: Some(0),
+            lo_limit: Some(0.5), hi_limit: Some(1.5), units: Some("V".into()), c_resfmt: Some("".into()), c_llmfmt: Some("".into()), c_hlmfmt: Some("".into()), ..Default::default() }),
+        StdfRecord::PRR(PRR { head_num: 1, site_num: 1, num_test: 1, hard_bin: 1, soft_bin: 1, part_id: "1".into(), ..Default::default() }),
+    ]);
+    convert_stdf2csv(&stdf_path, &csv_path, &None).unwrap();
+    let tests_csv = std::fs::read_to_string(csv_path.replace(".csv", ".tests.csv")).unwrap();
+    for path in [stdf_path, csv_path.replace(".csv", ".tests.csv"), csv_path.replace(".csv", ".part.summary.csv"), csv_path.replace(".csv", ".stdf.summary.csv"), csv_path.replace(".csv", ".test.summary.csv")] {
+        std::fs::remove_file(path).unwrap();
+    }
+
+    // values without a C format are written with %f as they always were, the limits stay numbers in the units of the result
+    assert_eq!(tests_csv.lines().nth(1), Some("\"1\",\"100\",\"1\",\"=\"\"vdd\"\"\",\"=\"\"units: mV\"\"\",\"500.000000\",\"1200.000000\",\"1500.000000\""));
+}
+
+#[test]
+fn flags_decode_pass_fail_and_limits() {
+    // a fail only counts when bit 6 says there is a pass/fail indication
+    assert_eq!((TestFlags(0x80).failed(), TestFlags(0xC0).failed(), TestFlags(0x00).failed()), (Some(true), None, Some(false)));
+    assert!(TestFlags(0x80).is_fail() && !TestFlags(0xC0).is_fail() && !TestFlags(0x40).is_pass());
+    assert!(TestFlags(0x00).is_valid() && !TestFlags(0x02).is_valid() && !TestFlags(0x10).is_valid());
+
+    // a result equal to a limit only passes an inclusive one
+    assert!(ParmFlags(0xC0).within_limits(1.0, Some(1.0), Some(2.0)) && ParmFlags(0xC0).within_limits(2.0, Some(1.0), Some(2.0)));
+    assert!(!ParmFlags(0x80).within_limits(1.0, Some(1.0), Some(2.0)) && !ParmFlags(0x40).within_limits(2.0, Some(1.0), Some(2.0)));
+    assert!(ParmFlags(0x00).within_limits(5.0, None, None));
+
+    // no opt_flag has every field valid, 0x50 drops the lo limit only
+    assert!(PtrOptFlags::from(None).lo_limit_valid() && PtrOptFlags::from(None).hi_limit_valid());
+    assert!(!PtrOptFlags::from(Some([0x10])).lo_limit_valid() && PtrOptFlags::from(Some([0x10])).has_lo_limit());
+    assert!(!PtrOptFlags(0x50).lo_limit_valid() && PtrOptFlags(0x50).hi_limit_valid());
+    assert!(!FtrOptFlags::from([0x08]).num_fail_valid() && FtrOptFlags::from([0x08]).cycl_cnt_valid());
+
+    assert_eq!((PartFlags(0x08).failed(), PartFlags(0x18).failed(), PartFlags(0x00).failed()), (Some(true), None, Some(false)));
+    assert!(PartFlags(0x02).is_retest() && PartFlags(0x04).abnormal_end() && !PartFlags(0x01).is_fail());
+
+    // the result statistics of a TSR are switched together, test_tim is left alone
+    let mut tsr_flags = TsrOptFlags::from([0x04]);
+    tsr_flags.set_result_stats_valid(false);
+    assert!(!tsr_flags.test_min_valid() && !tsr_flags.tst_sqrs_valid() && !tsr_flags.test_tim_valid());
+    tsr_flags.set_result_stats_valid(true);
+    assert_eq!(tsr_flags, TsrOptFlags(0x04));
+}
+
+// a PTR with units and limits, later PTR's of the test can leave them to the defaults
+fn ptr_with_limits(test_num: u32, head_num: u8, site_num: u8, result: f32, lo_limit: f32, hi_limit: f32, units: &str) -> Vec<u8> {
+    let mut data = test_num.to_le_bytes().to_vec();
+    data.extend([head_num, site_num, 0, 0xC0]);
+    data.extend(result.to_le_bytes());
+    data.extend(cn(&format!("test{}", test_num)));
+    data.extend(cn(""));
+    data.extend([0x0E, 0, 0, 0]);
+    data.extend(lo_limit.to_le_bytes());
+    data.extend(hi_limit.to_le_bytes());
+    data.extend(cn(units));
+    stdf_rec(15, 10, data)
+}
+
+#[test]
+fn convert_stdf2sqlite_writes_parts_tests_and_results() {
+    let stdf_path = write_stdf("convert_stdf2sqlite.stdf", vec![
+        far(),
+        dtr("COND: temp=25"),
+        pir(1, 1), ptr_with_limits(100, 1, 1, 1.0, 0.5, 1.5, "V"), ptr(101, 1, 1, 2.0), prr(1, 1, "P1", 1, 0),
+        pir(1, 1), ptr(100, 1, 1, 1.2), stdf_rec(15, 20, [100u32.to_le_bytes().to_vec(), vec![1, 1, 0]].concat()), prr(1, 1, "P2", 1, 1),
+        pir(1, 3), ptr(100, 1, 3, 1.3), pir(1, 3), prr(1, 3, "P4", 1, 3),
+        prr(1, 2, "P3", 5, 2),
+        pir(1, 4),
+    ]);
+    let dtr_cfg_path = temp_path("convert_stdf2sqlite_dtr_config.ini");
+    std::fs::write(&dtr_cfg_path, DTR_CONFIG_FILE_EXAMPLE).unwrap();
+    let sqlite_path = temp_path("convert_stdf2sqlite.sqlite");
+
+    stdf_reader::convert_stdf2sqlite(&stdf_path, &sqlite_path, &Some(dtr_cfg_path.to_owned())).unwrap();
+
+    type Limits = (Option<f64>, Option<f64>);
+    type PartRow = (i64, u8, Option<String>, Option<u16>, bool);
+    let conn = rusqlite::Connection::open(&sqlite_path).unwrap();
+    let parts: Vec<PartRow> = conn.prepare("SELECT part_idx, site_num, part_id, hard_bin, finished FROM parts ORDER BY part_idx").unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?))).unwrap()
+        .collect::<Result<_, _>>().unwrap();
+    let tests: Vec<(u32, String, Option<String>, Limits)> = conn.prepare("SELECT test_num, rec_type, units, lo_limit, hi_limit FROM tests ORDER BY test_num, rec_type").unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, (row.get(3)?, row.get(4)?)))).unwrap()
+        .collect::<Result<_, _>>().unwrap();
+    let ptr_results: Vec<(i64, u32, f64, Limits)> = conn.prepare("SELECT part_idx, test_num, result, lo_limit, hi_limit FROM ptr_results ORDER BY rec_idx").unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, (row.get(3)?, row.get(4)?)))).unwrap()
+        .collect::<Result<_, _>>().unwrap();
+    let ftr_units: Vec<(u32, Option<String>)> = conn.prepare("SELECT test_num, units FROM ftr_results JOIN tests USING (rec_type, test_num)").unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap()
+        .collect::<Result<_, _>>().unwrap();
+    let dtrs: Vec<(String, String, String)> = conn.prepare("SELECT dtr_id, text, text_dat FROM dtrs").unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))).unwrap()
+        .collect::<Result<_, _>>().unwrap();
+    drop(conn);
+    for path in [stdf_path, dtr_cfg_path, sqlite_path] {
+        std::fs::remove_file(path).unwrap();
+    }
+
+    // the PRR without a PIR still gets a part of its own, the part cut short by a PIR and the one left open aren't finished
+    assert_eq!(parts, vec![
+        (1, 1, Some("P1".into()), Some(1), true),
+        (2, 1, Some("P2".into()), Some(1), true),
+        (3, 3, None, None, false),
+        (4, 3, Some("P4".into()), Some(1), true),
+        (5, 2, Some("P3".into()), Some(5), true),
+        (6, 4, None, None, false),
+    ]);
+    // the FTR reusing the number of a PTR test keeps its own definition
+    assert_eq!(tests, vec![
+        (100, "FTR".into(), None, (None, None)),
+        (100, "PTR".into(), Some("V".into()), (Some(0.5), Some(1.5))),
+        (101, "PTR".into(), None, (None, None)),
+    ]);
+    assert_eq!(ftr_units, vec![(100, None)]);
+
+    // the second test 100 takes its limits from the first one
+    assert_eq!(ptr_results, vec![
+        (1, 100, 1.0, (Some(0.5), Some(1.5))),
+        (1, 101, 2.0, (None, None)),
+        (2, 100, 1.2f32 as f64, (Some(0.5), Some(1.5))),
+        (3, 100, 1.3f32 as f64, (Some(0.5), Some(1.5))),
+    ]);
+    assert_eq!(dtrs, vec![("temp".into(), "25".into(), "COND: temp=25".into())]);
+}
+
+// Part ID, TNum, SiteNum, Low Limit, Result and Hi Limit of a row of the convert_stdf2csv tests file
+type CsvTestRow = (String, String, u32, Option<f64>, Option<f64>, Option<f64>);
+
+fn csv_test_rows(name: &str, recs: Vec<Vec<u8>>) -> Vec<CsvTestRow> {
+    let stdf_path = write_stdf(&format!("{}.stdf", name), recs);
+    let csv_path = temp_path(&format!("{}.csv", name));
+
+    stdf_reader::convert_stdf2csv(&stdf_path, &csv_path, &None).unwrap();
+
+    let tests_csv = std::fs::read_to_string(csv_path.replace(".csv", ".tests.csv")).unwrap();
+    for path in [stdf_path, csv_path.replace(".csv", ".tests.csv"), csv_path.replace(".csv", ".part.summary.csv"), csv_path.replace(".csv", ".stdf.summary.csv"), csv_path.replace(".csv", ".test.summary.csv")] {
+        std::fs::remove_file(path).unwrap();
+    }
+
+    let value = |field: &str| (!field.is_empty()).then(|| field.parse::<f32>().unwrap() as f64);
+    tests_csv.lines().skip(1).map(|line| {
+        let fields: Vec<&str> = line.split(',').map(|field| field.trim_matches('"')).collect();
+        (fields[0].to_string(), fields[1].to_string(), fields[2].parse().unwrap(), value(fields[5]), value(fields[6]), value(fields[7]))
+    }).collect()
+}
+
+// the interleaved sites with limits on test 100, the later test 100's take them from the defaults
+fn dataframe_test_recs() -> Vec<Vec<u8>> {
+    let mut recs = interleaved_multi_site_recs();
+    recs[4] = ptr_with_limits(100, 1, 1, 1.0, 0.5, 1.5, "V");
+
+    // the first part on site 5 is cut short by the next PIR, the part on site 9 never gets a PRR
+    recs.extend([
+        pir(1, 5), ptr(100, 1, 5, 5.0),
+        pir(1, 5), ptr(100, 1, 5, 5.5), ptr(101, 1, 5, 5.6),
+        prr(1, 5, "P4", 1, 5),
+        pir(1, 9), ptr(101, 1, 9, 9.1),
+    ]);
+    recs
+}
+
+#[test]
+fn stdf_to_dataframe_long_matches_csv() {
+    let stdf_path = write_stdf("stdf_to_dataframe_long.stdf", dataframe_test_recs());
+    let parquet_path = temp_path("stdf_to_dataframe_long.parquet");
+    let df = stdf_to_dataframe(&stdf_path, &None, DataFrameLayout::Long).unwrap();
+    convert_stdf2parquet(&stdf_path, &parquet_path, &None, DataFrameLayout::Long).unwrap();
+    let parquet_df = ParquetReader::new(std::fs::File::open(&parquet_path).unwrap()).finish().unwrap();
+    std::fs::remove_file(&stdf_path).unwrap();
+    std::fs::remove_file(&parquet_path).unwrap();
+
+    // the parquet file holds the same frame
+    assert!(parquet_df.equals_missing(&df));
+
+    let part_ids = df.column("Part ID").unwrap().str().unwrap().into_no_null_iter();
+    let tnums = df.column("TNum").unwrap().str().unwrap().into_no_null_iter();
+    let site_nums = df.column("SiteNum").unwrap().u32().unwrap().into_no_null_iter();
+    let lo_limits = df.column("Low Limit").unwrap().f64().unwrap().into_iter();
+    let results = df.column("Result").unwrap().f64().unwrap().into_iter();
+    let hi_limits = df.column("Hi Limit").unwrap().f64().unwrap().into_iter();
+    let rows: Vec<CsvTestRow> = part_ids.zip(tnums).zip(site_nums).zip(lo_limits).zip(results).zip(hi_limits)
+        .map(|(((((part_id, tnum), site_num), lo_limit), result), hi_limit)| (part_id.to_string(), tnum.to_string(), site_num, lo_limit, result, hi_limit))
+        .collect();
+
+    // one row per result, in the same order and with the same limits as the csv
+    let csv_rows = csv_test_rows("stdf_to_dataframe_long", dataframe_test_recs());
+    assert_eq!(rows.len(), 10);
+    assert_eq!(rows, csv_rows);
+    assert!(rows.iter().all(|row| (row.3, row.5) == if row.1 == "100" { (Some(0.5), Some(1.5)) } else { (None, None) }));
+
+    // the part cut short and the part without a PRR keep their results without a part id
+    let unfinished: Vec<(u32, Option<f64>)> = rows.iter().filter(|row| row.0.is_empty()).map(|row| (row.2, row.4)).collect();
+    assert_eq!(unfinished, vec![(5, Some(5.0)), (9, Some(9.1f32 as f64))]);
+}
+
+#[test]
+fn stdf_to_dataframe_wide_matches_csv() {
+    let stdf_path = write_stdf("stdf_to_dataframe_wide.stdf", dataframe_test_recs());
+    let df = stdf_to_dataframe(&stdf_path, &None, DataFrameLayout::Wide).unwrap();
+    std::fs::remove_file(&stdf_path).unwrap();
+
+    // one row per part in PRR order, one column per test
+    let csv_rows = csv_test_rows("stdf_to_dataframe_wide", dataframe_test_recs());
+    assert_eq!(df.get_column_names(), ["Part ID", "SiteNum", "Hard Bin", "Soft Bin", "100:test100", "101:test101"]);
+    let part_ids: Vec<&str> = df.column("Part ID").unwrap().str().unwrap().into_no_null_iter().collect();
+    let site_nums: Vec<u32> = df.column("SiteNum").unwrap().u32().unwrap().into_no_null_iter().collect();
+    let mut csv_parts: Vec<(&str, u32)> = csv_rows.iter().map(|row| (row.0.as_str(), row.2)).collect();
+    csv_parts.dedup();
+    assert_eq!(part_ids.iter().copied().zip(site_nums.iter().copied()).collect::<Vec<_>>(), csv_parts);
+
+    // parts without a PRR have no bins
+    let hard_bins: Vec<Option<u32>> = df.column("Hard Bin").unwrap().u32().unwrap().into_iter().collect();
+    assert_eq!(hard_bins, vec![Some(5), Some(1), Some(1), None, Some(1), None]);
+
+    // every result lands in the row of its part and the column of its test
+    for (part_id, tnum, site_num, _, result, _) in &csv_rows {
+        let row = part_ids.iter().zip(&site_nums).position(|(id, site)| (*id, *site) == (part_id.as_str(), *site_num)).unwrap();
+        let column = df.column(&format!("{}:test{}", tnum, tnum)).unwrap().f64().unwrap();
+        assert_eq!(column.get(row), *result, "part {} test {}", part_id, tnum);
+    }
+}
+
+#[test]
+fn part_iterator_part_cut_short_by_pir() {
+    let recs = vec![far(), pir(1, 1), ptr(100, 1, 1, 0.5), pir(1, 1), ptr(100, 1, 1, 1.0), prr(1, 1, "P2", 1, 0)];
+    let stdf_path = write_stdf("part_iterator_part_cut_short_by_pir.stdf", recs);
+
+    let parts = PartIterator::new(&stdf_path, &None).unwrap()
+        .collect::<Result<Vec<Part>, StdfUtilError>>().unwrap();
+
+    // the part the second PIR cuts short comes out unfinished with its result
+    let summary: Vec<(u32, bool, String, usize)> = parts.iter().map(|p| (p.part_idx, p.finished, p.part_id.to_owned(), p.results.len())).collect();
+    assert_eq!(summary, vec![(1, false, "".into(), 1), (2, true, "P2".into(), 1)]);
+
+    // a tracking only tracker hands back the same parts without their results
+    let mut tracker = PartTracker::tracking_only();
+    let parts: Vec<Part> = StdfParser::new(&stdf_path, &None).unwrap().filter_map(|parsed_rec| tracker.process(&parsed_rec.unwrap())).collect();
+    std::fs::remove_file(&stdf_path).unwrap();
+
+    assert_eq!(parts.iter().map(|p| (p.part_idx, p.finished, p.results.len())).collect::<Vec<_>>(), vec![(1, false, 0), (2, true, 0)]);
+}
+
+#[test]
+fn merge_stdf_retest_coordinates_end_with_wafer() {
+    let first_path = temp_path("merge_stdf_wafer_first.stdf");
+    let second_path = temp_path("merge_stdf_wafer_second.stdf");
+    let out_path = temp_path("merge_stdf_wafer_out.stdf");
+
+    let part = |part_id: &str, bin: u16| vec![
+        StdfRecord::PIR(PIR { head_num: 1, site_num: 1 }),
+        StdfRecord::PRR(PRR { head_num: 1, site_num: 1, hard_bin: bin, soft_bin: bin, x_coord: 1, y_coord: 1, part_id: part_id.into(), ..Default::default() }),
+    ];
+
+    // the die after the WRR is no longer on wafer W1, only the second STDF retests it
+    let mut recs = vec![StdfRecord::MIR(MIR::default()), StdfRecord::WIR(WIR { head_num: 1, wafer_id: "W1".into(), ..Default::default() })];
+    recs.extend(part("P1", 5));
+    recs.push(StdfRecord::WRR(WRR { head_num: 1, wafer_id: "W1".into(), ..Default::default() }));
+    recs.extend(part("P2", 5));
+    recs.push(StdfRecord::MRR(MRR::default()));
+    write_records(&first_path, ByteOrder::LittleEndian, &recs);
+
+    let mut recs = vec![StdfRecord::MIR(MIR::default())];
+    recs.extend(part("P3", 1));
+    recs.push(StdfRecord::MRR(MRR::default()));
+    write_records(&second_path, ByteOrder::LittleEndian, &recs);
+
+    let options = MergeOptions { keep_last_retest: Some(RetestKey::Coordinates), ..Default::default() };
+    let counts = merge_stdf(&[first_path.to_owned(), second_path.to_owned()], &out_path, &options).unwrap();
+    let recs = read_records(&out_path);
+    for path in [&first_path, &second_path, &out_path] {
+        std::fs::remove_file(path).unwrap();
+    }
+
+    assert_eq!(counts, MergeCounts { parts_read: 3, parts_kept: 2 });
+    let part_ids: Vec<_> = recs.iter().filter_map(|rec| match rec { StdfRecord::PRR(prr) => Some(prr.part_id.as_str()), _ => None }).collect();
+    assert_eq!(part_ids, vec!["P1", "P3"]);
+}
+
+#[test]
+fn merge_stdf_keeps_dtrs_with_parts_and_pins_once() {
+    let first_path = temp_path("merge_stdf_dtr_first.stdf");
+    let second_path = temp_path("merge_stdf_dtr_second.stdf");
+    let conflict_path = temp_path("merge_stdf_dtr_conflict.stdf");
+    let out_path = temp_path("merge_stdf_dtr_out.stdf");
+
+    let pmr = |log_nam: &str| StdfRecord::PMR(PMR { pmr_indx: 1, head_num: 1, site_num: 1, chan_nam: "ch1".into(), log_nam: log_nam.into(), ..Default::default() });
+    let part = |cond: &str, bin: u16| vec![
+        StdfRecord::PIR(PIR { head_num: 1, site_num: 1 }),
+        StdfRecord::DTR(DTR { text_dat: cond.into() }),
+        StdfRecord::PRR(PRR { head_num: 1, site_num: 1, hard_bin: bin, soft_bin: bin, part_id: "P1".into(), ..Default::default() }),
+    ];
+
+    // both STDF's define the same pin, the first test of P1 and its DTR are dropped for the retest
+    for (path, log_nam, cond, bin) in [(&first_path, "A0", "COND: try=1", 5), (&second_path, "A0", "COND: try=2", 1), (&conflict_path, "A1", "COND: try=3", 1)] {
+        let mut recs = vec![pmr(log_nam)];
+        recs.extend(part(cond, bin));
+        recs.push(StdfRecord::MRR(MRR::default()));
+        write_records(path, ByteOrder::LittleEndian, &recs);
+    }
+
+    let options = MergeOptions { keep_last_retest: Some(RetestKey::PartId), ..Default::default() };
+    let counts = merge_stdf(&[first_path.to_owned(), second_path.to_owned()], &out_path, &options).unwrap();
+    let recs = read_records(&out_path);
+
+    // a pin defined differently by a later STDF can't be merged
+    let conflict = merge_stdf(&[first_path.to_owned(), conflict_path.to_owned()], &out_path, &options);
+    for path in [&first_path, &second_path, &conflict_path, &out_path] {
+        std::fs::remove_file(path).unwrap();
+    }
+
+    assert_eq!(counts, MergeCounts { parts_read: 2, parts_kept: 1 });
+    let mut expected = vec![StdfRecord::FAR(FAR { cpu_type: 2, stdf_ver: 4 }), pmr("A0")];
+    expected.extend(part("COND: try=2", 1));
+    assert_eq!(recs[..expected.len()], expected);
+    assert!(recs[expected.len()..].iter().all(|rec| !matches!(rec, StdfRecord::PMR(_) | StdfRecord::PIR(_) | StdfRecord::DTR(_))));
+    assert!(matches!(conflict, Err(StdfUtilError::MergeConflict { ref path, record: "PMR", index: 1 }) if *path == conflict_path));
+}
+
+#[test]
+fn merge_stdf_writes_in_part_records_once() {
+    let first_path = write_stdf("merge_stdf_multi_site_first.stdf", sectioned_multi_site_recs());
+    let second_path = write_stdf("merge_stdf_multi_site_second.stdf", sectioned_multi_site_recs());
+    let out_path = temp_path("merge_stdf_multi_site_out.stdf");
+    let merged = |options: MergeOptions| {
+        merge_stdf(&[first_path.to_owned(), second_path.to_owned()], &out_path, &options).unwrap();
+        unowned_counts(&read_records(&out_path))
+    };
+
+    // every STDF writes its DTR and sections once, the retests of the first STDF drop what belongs to P2
+    let everything = merged(MergeOptions::default());
+    let last_retests = merged(MergeOptions { keep_last_retest: Some(RetestKey::PartId), ..Default::default() });
+    for path in [&first_path, &second_path, &out_path] {
+        std::fs::remove_file(path).unwrap();
+    }
+
+    assert_eq!(everything, (2, 4, 4));
+    assert_eq!(last_retests, (1, 3, 3));
+}
@@ -0,0 +1,86 @@
+use argparse::{ArgumentParser, Store, StoreOption};
+use stdf_reader::{relimit_stdf, LimitOverrides, RelimitOptions};
+
+// Define a struct to hold the arguments
+struct Arguments {
+    stdf_filename: String,
+    limits_filename: String,
+    csv_filename: Option<String>,
+    fail_hard_bin: Option<u16>,
+    fail_soft_bin: Option<u16>,
+    pass_hard_bin: Option<u16>,
+    pass_soft_bin: Option<u16>,
+}
+
+fn main() {
+    // Call the function to parse the arguments
+    let args = parse_arguments();
+
+    let overrides = match LimitOverrides::read(&args.limits_filename) {
+        Ok(overrides) => overrides,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let options = RelimitOptions {
+        fail_hard_bin: args.fail_hard_bin,
+        fail_soft_bin: args.fail_soft_bin,
+        pass_hard_bin: args.pass_hard_bin,
+        pass_soft_bin: args.pass_soft_bin,
+    };
+    let report = match relimit_stdf(&args.stdf_filename, &overrides, &options) {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    print!("{}", report.to_text());
+
+    if let Some(csv_filename) = &args.csv_filename {
+        if let Err(err) = report.write_csv(csv_filename) {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Function to parse the arguments
+fn parse_arguments() -> Arguments {
+    let mut args = Arguments {
+        stdf_filename: String::new(),
+        limits_filename: String::new(),
+        csv_filename: None,
+        fail_hard_bin: None,
+        fail_soft_bin: None,
+        pass_hard_bin: None,
+        pass_soft_bin: None,
+    };
+
+    // force lifetime for Argument parser to be short
+    {
+        // Create ArgumentParser variable
+        let mut ap = ArgumentParser::new();
+
+        // Application description
+        ap.set_description("Judges the PTR/MPR results of an STDF again with new limits and reports the yield per site before and after, the limits file is an ini with a section per test number or name, or a csv with test number/name, lo_limit, hi_limit, hard_bin and soft_bin columns. An empty limit keeps the one of the STDF, none removes it");
+
+        // Add all arguments and associated variables
+        ap.refer(&mut args.limits_filename).add_option(&["-l", "--limits"], Store, "Limits file, .ini or .csv").required();
+        ap.refer(&mut args.csv_filename).add_option(&["-o", "--output"], StoreOption, "Write the pass/fail and bins of every part before and after to this csv file");
+        ap.refer(&mut args.fail_hard_bin).add_option(&["--fail-hbin"], StoreOption, "Hard bin of parts now failing a test that has no bins in the limits file, needed when a passing part does");
+        ap.refer(&mut args.fail_soft_bin).add_option(&["--fail-sbin"], StoreOption, "Soft bin of parts now failing a test that has no bins in the limits file, needed when a passing part does");
+        ap.refer(&mut args.pass_hard_bin).add_option(&["--pass-hbin"], StoreOption, "Hard bin of failing parts that now pass");
+        ap.refer(&mut args.pass_soft_bin).add_option(&["--pass-sbin"], StoreOption, "Soft bin of failing parts that now pass");
+        ap.refer(&mut args.stdf_filename).add_argument("Stdf Input", Store, "Stdf input file to judge again").required();
+
+        // parse arguments and store
+        ap.parse_args_or_exit();
+    }
+
+    // Return the arguments in a struct
+    args
+}
@@ -0,0 +1,75 @@
+use argparse::{ArgumentParser, List, StoreOption};
+use stdf_reader::TestPlan;
+
+// Define a struct to hold the arguments
+struct Arguments {
+    stdf_filenames: Vec<String>,
+    output_filename: Option<String>,
+}
+
+fn main() {
+    // Call the function to parse the arguments
+    let args = parse_arguments();
+
+    let mut plans = Vec::new();
+    for stdf_filename in &args.stdf_filenames {
+        match TestPlan::read(stdf_filename) {
+            Ok(plan) => plans.push(plan),
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    match plans.as_slice() {
+        // one file: write out its test plan
+        [plan] => {
+            let output_filename = args.output_filename.unwrap_or(format!("{}.testplan.csv", args.stdf_filenames[0]));
+            if let Err(err) = plan.write_csv(&output_filename) {
+                eprintln!("Error: {}", err);
+                std::process::exit(1);
+            }
+            println!("{} tests written to {}", plan.entries.len(), output_filename);
+        },
+        // two files: the changes from the first test plan to the second one
+        [old_plan, new_plan] => {
+            let changes = old_plan.diff(new_plan);
+            for change in &changes {
+                println!("{}", change);
+            }
+            println!("{} changes from {} to {}", changes.len(), args.stdf_filenames[0], args.stdf_filenames[1]);
+
+            // like diff, a changed test plan is reported by the exit code
+            if !changes.is_empty() { std::process::exit(1); }
+        },
+        _ => {
+            eprintln!("Error: expected one STDF file to extract its test plan or two to compare them");
+            std::process::exit(2);
+        },
+    }
+}
+
+// Function to parse the arguments
+fn parse_arguments() -> Arguments {
+    let mut args = Arguments { stdf_filenames: Vec::new(), output_filename: None };
+
+    // force lifetime for Argument parser to be short
+    {
+        // Create ArgumentParser variable
+        let mut ap = ArgumentParser::new();
+
+        // Application description
+        ap.set_description("Extracts the test plan of an STDF (test numbers, names, units, limits, scaling, formats and FTR patterns) to csv, or lists the tests added, removed, renamed, renumbered or changed between two STDF files");
+
+        // Add all arguments and associated variables
+        ap.refer(&mut args.output_filename).add_option(&["-o", "--output"], StoreOption, "Test plan csv file, defaults to <stdf>.testplan.csv");
+        ap.refer(&mut args.stdf_filenames).add_argument("Stdf Inputs", List, "One stdf file to extract its test plan, or the old and new stdf files to compare").required();
+
+        // parse arguments and store
+        ap.parse_args_or_exit();
+    }
+
+    // Return the arguments in a struct
+    args
+}
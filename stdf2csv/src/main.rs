@@ -1,11 +1,8 @@
-use std::io::stdout;
-
 use stdf_reader::convert_stdf2csv;
 use argparse::{ArgumentParser, Collect, Store};
 
 fn main() {
     let mut stdf_filenames = Vec::<String>::new();
-    let mut csv_filename = String::new();
     let mut dtr_cfg_filename = String::new();
 
     // force lifetime for Argument parser to be short
@@ -31,16 +28,19 @@ fn main() {
         ap.parse_args_or_exit();
     }
 
-    if stdf_filenames.len() <= 0 {
+    if stdf_filenames.is_empty() {
         println!("No stdf files provided.");
         return;
     }
 
     let dtr_cfg_filename = if dtr_cfg_filename.is_empty() { None } else { Some(dtr_cfg_filename) };
     for stdf_filename in stdf_filenames {
-        csv_filename = stdf_filename.clone() + ".csv";
+        let csv_filename = stdf_filename.clone() + ".csv";
 
         // do actual conversion
-        convert_stdf2csv(&stdf_filename, &csv_filename, &dtr_cfg_filename).unwrap();
+        if let Err(err) = convert_stdf2csv(&stdf_filename, &csv_filename, &dtr_cfg_filename) {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
     }
 }
@@ -0,0 +1,49 @@
+use stdf_reader::{convert_stdf2parquet, DataFrameLayout};
+use argparse::{ArgumentParser, Collect, Store, StoreTrue};
+
+fn main() {
+    let mut stdf_filenames = Vec::<String>::new();
+    let mut dtr_cfg_filename = String::new();
+    let mut wide = false;
+
+    // force lifetime for Argument parser to be short
+    {
+        // Create ArgumentParser variable
+        let mut ap = ArgumentParser::new();
+
+        // Application description
+        ap.set_description("Takes an STDF and converts the test results to a parquet file");
+
+        // Add all arguments and associated variables
+        ap.refer(&mut stdf_filenames).add_argument("Stdf Input", Collect, "Stdf input file to be converted").required();
+        ap.refer(&mut dtr_cfg_filename)
+            .add_option(&["-d", "--dtr-file"],
+                        Store,
+                        "Can be used to specify how to handle DTR's in the STDF, by default DTR's are ignored");
+        ap.refer(&mut wide)
+            .add_option(&["-w", "--wide"],
+                        StoreTrue,
+                        "Write one row per part with a column per test, by default one row per test result is written");
+
+        // parse arguments and store
+        ap.parse_args_or_exit();
+    }
+
+    if stdf_filenames.is_empty() {
+        println!("No stdf files provided.");
+        return;
+    }
+
+    let layout = if wide { DataFrameLayout::Wide } else { DataFrameLayout::Long };
+    let dtr_cfg_filename = if dtr_cfg_filename.is_empty() { None } else { Some(dtr_cfg_filename) };
+    for stdf_filename in stdf_filenames {
+        let parquet_filename = stdf_filename.clone() + ".parquet";
+
+        // do actual conversion
+        println!("Convert stdf file '{}' to parquet file '{}'", stdf_filename, parquet_filename);
+        if let Err(err) = convert_stdf2parquet(&stdf_filename, &parquet_filename, &dtr_cfg_filename, layout) {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use stdf_reader::convert_stdf2sqlite;
+use argparse::{ArgumentParser, Collect, Store};
+
+fn main() {
+    let mut stdf_filenames = Vec::<String>::new();
+    let mut dtr_cfg_filename = String::new();
+
+    // force lifetime for Argument parser to be short
+    {
+        // Create ArgumentParser variable
+        let mut ap = ArgumentParser::new();
+
+        // Application description
+        ap.set_description("Takes an STDF and converts it to a normalized SQLite database");
+
+        // Add all arguments and associated variables
+        ap.refer(&mut stdf_filenames).add_argument("Stdf Input", Collect, "Stdf input file to be converted").required();
+        ap.refer(&mut dtr_cfg_filename)
+            .add_option(&["-d", "--dtr-file"],
+                        Store,
+                        "Can be used to specify how to handle DTR's in the STDF, by default DTR's are ignored");
+
+        // parse arguments and store
+        ap.parse_args_or_exit();
+    }
+
+    if stdf_filenames.is_empty() {
+        println!("No stdf files provided.");
+        return;
+    }
+
+    let dtr_cfg_filename = if dtr_cfg_filename.is_empty() { None } else { Some(dtr_cfg_filename) };
+    for stdf_filename in stdf_filenames {
+        let sqlite_filename = stdf_filename.clone() + ".sqlite";
+
+        // do actual conversion
+        println!("Convert stdf file '{}' to sqlite database '{}'", stdf_filename, sqlite_filename);
+        if let Err(err) = convert_stdf2sqlite(&stdf_filename, &sqlite_filename, &dtr_cfg_filename) {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
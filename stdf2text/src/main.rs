@@ -33,7 +33,7 @@ fn main() {
         ap.parse_args_or_exit();
     }
 
-    if stdf_filenames.len() <= 0 {
+    if stdf_filenames.is_empty() {
         println!("No stdf files provided.");
         return;
     }
@@ -42,6 +42,9 @@ fn main() {
         let text_filename = stdf_filename.clone() + ".txt";
 
         println!("Convert stdf file '{}' to text file '{}'", stdf_filename, text_filename);
-        convert_stdf2text(&stdf_filename, &text_filename, pretty_print, !raw).unwrap();
+        if let Err(err) = convert_stdf2text(&stdf_filename, &text_filename, pretty_print, !raw) {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
     }
 }
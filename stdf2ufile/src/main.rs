@@ -1,7 +1,7 @@
-use std::{collections::HashMap, fs::File, io::Write};
+use std::{fs::File, io::Write};
 
 use argparse::{ArgumentParser, Store};
-use stdf_reader::{StdfParser, StdfRecord, V1};
+use stdf_reader::{ParmFlags, ParsedRecord, PartTracker, StdfParser, StdfRecord, StdfUtilError, TestFlags, TestFormat, V1};
 
 // Define a struct to hold the arguments
 struct Arguments {
@@ -9,92 +9,59 @@ struct Arguments {
     output_filename: String,
 }
 
-fn get_cstr_format(fmt: &Option<String>, val: &Option<f32>) -> String {
-    match val {
-        Some(llm) => {
-            let mut ret_val = format!("{}", llm);
-
-            if fmt.is_some() && fmt.clone().unwrap().len() > 0 {
-                if let Ok(s) = sprintf::sprintf!(fmt.clone().unwrap().as_str(), llm.to_owned()) { ret_val = s; }
-            }
-            // if let Some(fmt) = fmt {
-            //     if fmt.len() > 0 {
-            //         if let Ok(s) = sprintf::sprintf!(fmt.as_str(), llm) { ret_val = s; }
-            //     }
-            // }
-
-            ret_val
-        }
-        None => "".to_string()
-    }
-}
-
-fn rec_to_ufile_line(site_to_part_idx: &HashMap<u8, u32>, rec: &StdfRecord) -> Option<String> {
+fn rec_to_ufile_line(part_tracker: &PartTracker, rec: &StdfRecord) -> Option<String> {
     let fail_type_regex = regex::Regex::new(r"S[0-9]+_").unwrap();
 
     // do the thing
     match rec {
         // For all other record types, do nothing
         StdfRecord::DTR(rec) => {
-            Some(format!("{}", rec.text_dat.to_owned()))
+            Some(rec.text_dat.to_owned())
         },
         // For all other record types, do nothing
         StdfRecord::PTR(rec) => {
             // add to log
-            let part_idx = site_to_part_idx.get(&rec.site_num).unwrap_or(&0);
-            let llm = get_cstr_format(&rec.c_llmfmt, &rec.lo_limit);
-            let hlm = get_cstr_format(&rec.c_hlmfmt, &rec.hi_limit);
-            let result = get_cstr_format(&rec.c_resfmt, &Some(rec.result));
-            let units = if let Some(units) = rec.units.to_owned() { units } else { String::new() };
-            let units = if units.len() > 0 { format!("({})", units) } else { "".to_string() };
+            let part_idx = part_tracker.part_idx(rec.head_num, rec.site_num).unwrap_or(0);
+            let test_format = TestFormat::from(rec);
+            let llm = test_format.lo_limit(rec.lo_limit);
+            let hlm = test_format.hi_limit(rec.hi_limit);
+            let result = test_format.result(rec.result);
+            let units = test_format.units();
+            let units = if !units.is_empty() { format!("({})", units) } else { "".to_string() };
             let failed_string = if fail_type_regex.is_match(&rec.test_txt) { "Failed  " } else { "failed  " };
 
-            let mut text = if rec.test_flg[0] & 0b01011100 == 0 && rec.test_flg[0] & 0b10000000 != 0 {
+            let mut text = if TestFlags::from(rec.test_flg).is_fail() {
                 failed_string.to_string()
             } else {
                 "".to_string()
             };
-            let llm_cmp = if !llm.is_empty() { if rec.parm_flg[0] & 0x40 != 0 { " <= " } else { " < " }} else {""};
-            let hlm_cmp = if !hlm.is_empty() { if rec.parm_flg[0] & 0x80 != 0 { " <= " } else { " < " }} else {""};
+            let parm_flags = ParmFlags::from(rec.parm_flg);
+            let llm_cmp = if !llm.is_empty() { if parm_flags.lo_limit_inclusive() { " <= " } else { " < " }} else {""};
+            let hlm_cmp = if !hlm.is_empty() { if parm_flags.hi_limit_inclusive() { " <= " } else { " < " }} else {""};
             text = format!("{:04}  {}{}  {}{}{}{}{} {}\n", part_idx, text, rec.test_txt, llm, llm_cmp, result, hlm_cmp, hlm, units);
 
             Some(text)
         },
         StdfRecord::FTR(rec) => {
             // add to log
-            let part_idx = site_to_part_idx.get(&rec.site_num).unwrap_or(&0);
-            let opt_flag = rec.opt_flag[0];
-            let test_flag = rec.test_flg[0];
-            let mut text = "".to_string();
+            let part_idx = part_tracker.part_idx(rec.head_num, rec.site_num).unwrap_or(0);
             let failed_string = if fail_type_regex.is_match(&rec.test_txt) { "Failed  " } else { "failed  " };
+            let text = if TestFlags::from(rec.test_flg).is_fail() { failed_string } else { "" };
 
-            if opt_flag & 0x08 == 0 {
-                // return num_fail
-                if rec.num_fail > 0 {
-                    text = failed_string.to_string();
-                }
-            } else {
-                // no num_fail information
-                if test_flag & 0x54 == 0 && test_flag != 0 {
-                    // test failed, but no num_fail information
-                    text = failed_string.to_string();
-                }
-            };
-
-            text = format!("{:04}  {}{}  {}  {}\n", part_idx, text, rec.vect_nam, rec.num_fail, rec.test_txt);
+            let text = format!("{:04}  {}{}  {}  {}\n", part_idx, text, rec.vect_nam, rec.num_fail, rec.test_txt);
             Some(text)
         },
         StdfRecord::STR(rec) => {
             // add to log
-            let part_idx = site_to_part_idx.get(&rec.site_num).unwrap_or(&0);
+            let part_idx = part_tracker.part_idx(rec.head_num, rec.site_num).unwrap_or(0);
             let text = format!("{:04}  {:?}\n", part_idx, rec);
             Some(text)
         },
         StdfRecord::GDR(rec) => {
-            let text: String = if let Some(V1::Cn(usr_type)) = rec.gen_data.get(0)  {
+            let text: String = if let Some(V1::Cn(usr_type)) = rec.gen_data.first()  {
                 match usr_type.as_str() {
                     "SHMOO" => {
-                        let mut text = format!("SHMOO BEGIN\n");
+                        let mut text = "SHMOO BEGIN\n".to_string();
                         for (i, val) in rec.gen_data.iter().enumerate() {
                             if i == 0 { continue; }
                             if let V1::Cn(data) = val {
@@ -106,7 +73,7 @@ fn rec_to_ufile_line(site_to_part_idx: &HashMap<u8, u32>, rec: &StdfRecord) -> O
                     },
                     _ => {
                         // unhandled, just print it out
-                        format!("{}\n", format!("{:?}\n", rec))
+                        format!("{:?}\n\n", rec)
                     }
                 }
             } else {
@@ -123,40 +90,46 @@ fn main() {
     // Call the function to parse the arguments
     let args = parse_arguments();
     
-    let mut parser = StdfParser::new(&args.stdf_filename, &None).unwrap();
-    let mut site_to_part_idx = HashMap::<u8, u32>::new();
-    let mut part_idx = 1;
-    let mut out_f = File::create(args.output_filename).expect("Unable to create file");
-
-    loop {
-        if let Some(rec) = parser.next() {
-            // Loop until a record isn't found
-            match rec {
-                // Do nothing if the record is a MRR
-                Ok((StdfRecord::MRR(_), _)) => {
-                    break;
-                },
-                // If the record is a PIR, update the site_to_part_idx map
-                Ok((StdfRecord::PIR(rec), _)) => {
-                    // update site_to_part_idx
-                    let site_num = rec.site_num;
-                    site_to_part_idx.insert(site_num, part_idx);
-                    part_idx = part_idx + 1;
-                },
-                // All other records need to get formatted for the ufile
-                Ok((rec, _)) => {
-                    // add to log
-                    if let Some(msg) = rec_to_ufile_line(&site_to_part_idx, &rec) {
-                        out_f.write(msg.as_bytes()).expect("Unable to write data");
+    let parser = match StdfParser::new(&args.stdf_filename, &None) {
+        Ok(parser) => parser,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let mut part_tracker = PartTracker::tracking_only();
+    let mut out_f = match File::create(&args.output_filename).map_err(StdfUtilError::io(&args.output_filename)) {
+        Ok(out_f) => out_f,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    // Loop until a record isn't found
+    for parsed_rec in parser {
+        match parsed_rec {
+            // Do nothing if the record is a MRR
+            Ok(ParsedRecord { record: StdfRecord::MRR(_), .. }) => {
+                break;
+            },
+            // All other records need to get formatted for the ufile
+            Ok(parsed_rec) => {
+                // keep track of the part in test on each head/site
+                part_tracker.process(&parsed_rec);
+
+                // add to log
+                if let Some(msg) = rec_to_ufile_line(&part_tracker, &parsed_rec.record) {
+                    if let Err(err) = out_f.write_all(msg.as_bytes()).map_err(StdfUtilError::io(&args.output_filename)) {
+                        eprintln!("Error: {}", err);
+                        std::process::exit(1);
                     }
-                },
-                Err(err) => {
-                    eprintln!("Error: {}", err);
-                },
-            }
-        } else {
-            // we have gotten all the records
-            break;
+                }
+            },
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                std::process::exit(1);
+            },
         }
     }
 }
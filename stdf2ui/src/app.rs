@@ -1,9 +1,12 @@
-use std::{fs::OpenOptions, io::Write};
+use std::{collections::HashMap, fs::OpenOptions, io::Write};
+
+use stdf_reader::{Die, QueryFields, QueryValue, RecordFields, TestStats, TestSummary, WaferMaps};
 
 #[derive(PartialEq)]
 pub enum CurrentLayout {
     List,
     Plot,
+    WaferMap,
 }
 
 #[derive(PartialEq)]
@@ -12,6 +15,7 @@ pub enum ActiveWidget {
     Search,
     Filter,
     Help,
+    WaferMap,
 }
 
 #[derive(PartialEq)]
@@ -26,6 +30,124 @@ pub enum GraphYScale {
     Log10,
 }
 
+#[derive(PartialEq)]
+pub enum BinType {
+    Hard,
+    Soft,
+}
+
+/// Record type (PTR/FTR) and test number a log line belongs to
+pub type TestKey = (&'static str, u32);
+
+/// Index of the STDF in the session and test, tests of different files are kept apart
+pub type FileTestKey = (usize, TestKey);
+
+/// Test result carried by a log line
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestResult {
+    pub key: TestKey,
+    pub test_txt: String,
+    pub units: String,
+    pub lo_limit: Option<f32>,
+    pub hi_limit: Option<f32>,
+    pub part_idx: u32,
+    pub result: f32,
+}
+
+/// All results of a test across the parts of the log, graphed when one of its lines is selected
+#[derive(Debug, Default, PartialEq)]
+pub struct TestResults {
+    pub test_txt: String,
+    pub units: String,
+    pub lo_limit: Option<f32>,
+    pub hi_limit: Option<f32>,
+    /// (part index, result) in log order
+    pub results: Vec<(u32, f32)>,
+}
+
+/// Statistics of a test on one head/site, head and site are 255 for all of them
+#[derive(Debug, Clone, PartialEq)]
+pub struct SummaryRow {
+    pub head_num: u8,
+    pub site_num: u8,
+    pub count: u64,
+    pub fails: u64,
+    pub min: Option<f64>,
+    pub mean: Option<f64>,
+    pub max: Option<f64>,
+    pub stdev: Option<f64>,
+    pub cpk: Option<f64>,
+}
+
+impl SummaryRow {
+    fn new(head_num: u8, site_num: u8, stats: &TestStats, summary: &TestSummary) -> Self {
+        Self {
+            head_num,
+            site_num,
+            count: stats.count,
+            fails: stats.fails,
+            min: summary.scaled((stats.count > 0).then_some(stats.min)),
+            mean: summary.scaled(stats.mean()),
+            max: summary.scaled((stats.count > 0).then_some(stats.max)),
+            stdev: summary.scaled(stats.stdev()),
+            cpk: stats.cpk(summary.lo_limit, summary.hi_limit),
+        }
+    }
+}
+
+/// Statistics of a test by site as computed by the worker, shown in the summary pane
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TestSummaryRows {
+    pub test_txt: String,
+    pub units: String,
+    pub lo_limit: Option<f64>,
+    pub hi_limit: Option<f64>,
+    pub rows: Vec<SummaryRow>,
+}
+
+impl From<&TestSummary> for TestSummaryRows {
+    fn from(summary: &TestSummary) -> Self {
+        let mut rows: Vec<SummaryRow> = summary.sites.iter()
+            .map(|((head_num, site_num), stats)| SummaryRow::new(*head_num, *site_num, stats, summary))
+            .collect();
+
+        // all sites together only tell something new with more than one site, the rows don't need their results
+        if rows.len() > 1 {
+            rows.push(SummaryRow::new(255, 255, &summary.total_moments(), summary));
+        }
+
+        // shown in the units of the log lines
+        Self {
+            test_txt: summary.test_txt.to_owned(),
+            units: summary.scaled_units(),
+            lo_limit: summary.scaled(summary.lo_limit),
+            hi_limit: summary.scaled(summary.hi_limit),
+            rows,
+        }
+    }
+}
+
+/// Fields of a log line for filter queries, the record fields and the file of the line
+pub struct LineFields<'a> {
+    pub file_idx: usize,
+    pub fields: &'a RecordFields,
+}
+
+impl LineFields<'_> {
+    /// Fields the log lines have on top of the record ones
+    pub const EXTRA_FIELDS: [&'static str; 1] = ["file"];
+}
+
+impl QueryFields for LineFields<'_> {
+    fn field(&self, name: &str) -> Option<QueryValue> {
+        // files are numbered as their tag in the source column
+        match name {
+            "file" => Some(QueryValue::Num((self.file_idx + 1) as f64)),
+            _ => self.fields.field(name),
+        }
+    }
+}
+
 #[derive(PartialEq)]
 
 pub struct App {
@@ -35,15 +157,31 @@ pub struct App {
     pub graph_yscale: GraphYScale,
     pub search_string: String,
     pub filter_string: String,
+    /// Why the filter query couldn't be parsed, shown in the filter bar
+    pub filter_error: Option<String>,
     pub log_data: Vec<String>,
     pub filtered_log_data: Vec<String>,
+    pub log_tests: Vec<Option<TestKey>>,
+    pub log_files: Vec<usize>,
+    pub log_fields: Vec<Option<RecordFields>>,
+    pub filtered_log_index: Vec<usize>,
+    pub test_results: HashMap<FileTestKey, TestResults>,
+    pub test_summaries: HashMap<FileTestKey, TestSummaryRows>,
+    pub part_log_lines: HashMap<(usize, u32), usize>,
+    /// Wafer maps of each file of the session
+    pub wafer_maps: Vec<WaferMaps>,
+    pub wafer_idx: usize,
+    pub wafer_bin_type: BinType,
+    pub wafer_cursor: Option<(i16, i16)>,
     pub selected_line: usize,
     pub display_selected_line: usize,
     pub auto_scroll: bool,
     pub needs_refresh: bool,
     pub data_is_dirty: bool,
     pub lines_to_display: (usize, usize),
-    pub stdf_filename: String,
+    pub stdf_filenames: Vec<String>,
+    /// File whose lines are shown, all of them when None
+    pub file_filter: Option<usize>,
 }
 
 impl App {
@@ -55,29 +193,158 @@ impl App {
             graph_yscale: GraphYScale::Linear,
             search_string: String::new(),
             filter_string: String::new(),
+            filter_error: None,
             log_data: Vec::new(),
             filtered_log_data: Vec::with_capacity(1000),
+            log_tests: Vec::new(),
+            log_files: Vec::new(),
+            log_fields: Vec::new(),
+            filtered_log_index: Vec::with_capacity(1000),
+            test_results: HashMap::new(),
+            test_summaries: HashMap::new(),
+            part_log_lines: HashMap::new(),
+            wafer_maps: vec![WaferMaps::new()],
+            wafer_idx: 0,
+            wafer_bin_type: BinType::Hard,
+            wafer_cursor: None,
             selected_line: 0,
             display_selected_line: 0,
             auto_scroll: false,
             needs_refresh: false,
             data_is_dirty: false,
             lines_to_display: (0, 0),
-            stdf_filename: String::new(),
+            stdf_filenames: Vec::new(),
+            file_filter: None,
         }
     }
 
+    pub fn set_stdf_filenames(&mut self, stdf_filenames: &[String]) {
+        self.stdf_filenames = stdf_filenames.to_vec();
+        self.wafer_maps = vec![WaferMaps::new(); stdf_filenames.len().max(1)];
+    }
+
+    /// Tag of the file in the source column of the log, only used with more than one file
+    pub fn file_tag(&self, file_idx: usize) -> String {
+        format!("[{}]", file_idx + 1)
+    }
+
+    pub fn add_log_entry(&mut self, file_idx: usize, log_entry: String, test: Option<TestResult>, fields: Option<RecordFields>) {
+        // the source column tells the files of a session apart
+        if self.stdf_filenames.len() > 1 {
+            self.log_data.push(format!("{} {}", self.file_tag(file_idx), log_entry));
+        } else {
+            self.log_data.push(log_entry);
+        }
+        self.log_tests.push(test.as_ref().map(|test| test.key));
+        self.log_files.push(file_idx);
+        self.log_fields.push(fields);
+
+        // gather the results of each test for the graph
+        if let Some(test) = test {
+            self.part_log_lines.entry((file_idx, test.part_idx)).or_insert(self.log_data.len() - 1);
+
+            let results = self.test_results.entry((file_idx, test.key)).or_default();
+            if results.results.is_empty() {
+                results.test_txt = test.test_txt;
+                results.units = test.units;
+            }
+            results.lo_limit = results.lo_limit.or(test.lo_limit);
+            results.hi_limit = results.hi_limit.or(test.hi_limit);
+            results.results.push((test.part_idx, test.result));
+        }
+    }
+
+    /// Test of the selected line of the filtered log, if it is a PTR/FTR line
+    pub fn selected_test(&self) -> Option<TestKey> {
+        let log_idx = self.filtered_log_index.get(self.selected_line)?;
+        self.log_tests.get(*log_idx).copied().flatten()
+    }
+
+    /// Files whose lines are shown, in session order
+    pub fn shown_files(&self) -> Vec<usize> {
+        match self.file_filter {
+            Some(file_idx) => vec![file_idx],
+            None => (0..self.stdf_filenames.len()).collect(),
+        }
+    }
+
+    /// Shows the lines of the next file of the session, after the last file all files are shown again
+    pub fn select_file(&mut self) {
+        if self.stdf_filenames.len() < 2 { return; }
+        self.file_filter = match self.file_filter {
+            None => Some(0),
+            Some(file_idx) if file_idx + 1 < self.stdf_filenames.len() => Some(file_idx + 1),
+            Some(_) => None,
+        };
+        self.data_is_dirty = true;
+        self.wafer_idx = 0;
+        self.wafer_cursor = None;
+    }
+
+    /// Wafer maps of the shown file, the first file when all of them are shown
+    pub fn shown_wafer_maps(&self) -> &WaferMaps {
+        &self.wafer_maps[self.file_filter.unwrap_or(0)]
+    }
+
+    /// Die under the wafer map cursor, the last test of it when it was retested
+    pub fn selected_die(&self) -> Option<&Die> {
+        let wafer = self.shown_wafer_maps().wafers.get(self.wafer_idx)?;
+        let (x, y) = self.wafer_cursor?;
+        wafer.dies.iter().rev().find(|die| die.x == x && die.y == y)
+    }
+
+    pub fn toggle_wafer_bin_type(&mut self) {
+        self.wafer_bin_type = if self.wafer_bin_type == BinType::Hard { BinType::Soft } else { BinType::Hard };
+    }
+
+    pub fn select_wafer(&mut self, next: bool) {
+        let wafer_cnt = self.shown_wafer_maps().wafers.len();
+        if wafer_cnt == 0 { return; }
+        self.wafer_idx = if next { (self.wafer_idx + 1) % wafer_cnt } else { (self.wafer_idx + wafer_cnt - 1) % wafer_cnt };
+        self.wafer_cursor = None;
+    }
+
+    //////////////////////////////////////////////////////////////////////
+    /// Description: Moves the wafer map cursor by columns/rows of the map as it is
+    ///              displayed, the cursor starts on the first die of the wafer
+    //////////////////////////////////////////////////////////////////////
+    pub fn move_wafer_cursor(&mut self, d_col: i32, d_row: i32) {
+        let wafer_maps = self.shown_wafer_maps();
+        let Some(wafer) = wafer_maps.wafers.get(self.wafer_idx) else { return };
+        let grid = wafer.grid(&wafer_maps.config);
+        let position = self.wafer_cursor.and_then(|(x, y)| grid.position(x, y))
+            .or_else(|| wafer.dies.first().and_then(|die| grid.position(die.x, die.y)));
+
+        if let Some((col, row)) = position {
+            let col = (col as i32 + d_col).clamp(0, grid.cols as i32 - 1) as usize;
+            let row = (row as i32 + d_row).clamp(0, grid.rows as i32 - 1) as usize;
+            self.wafer_cursor = Some(grid.coords(col, row));
+        }
+    }
+
+    /// Selects the first log line of the die under the wafer map cursor, false when it has none
+    pub fn jump_to_selected_die(&mut self) -> bool {
+        let file_idx = self.file_filter.unwrap_or(0);
+        let Some(log_idx) = self.selected_die().and_then(|die| self.part_log_lines.get(&(file_idx, die.part_idx)).copied()) else { return false };
+        let Some(line) = self.filtered_log_index.iter().position(|idx| *idx >= log_idx) else { return false };
+
+        self.auto_scroll = false;
+        self.selected_line = line;
+        true
+    }
+
     pub fn save_to_log(&mut self, file_path: String) -> Result<(), std::io::Error> {
         // open file to save the log into
         let mut file = OpenOptions::new()
             .write(true)
             .create(true)
+            .truncate(true)
             .open(file_path)?;
 
         // write the log data to the file
         for line in &self.log_data {
-            file.write(line.as_bytes())?;
-            file.write(b"\n")?;
+            file.write_all(line.as_bytes())?;
+            file.write_all(b"\n")?;
         }
 
         // close the file
@@ -87,4 +354,8 @@ impl App {
     pub fn toggle_auto_scroll(&mut self) {
         self.auto_scroll = !self.auto_scroll;
     }
+
+    pub fn toggle_graph_type(&mut self) {
+        self.graph_type = if self.graph_type == GraphType::Line { GraphType::Histogram } else { GraphType::Line };
+    }
 }
@@ -1,12 +1,12 @@
 mod app;
 mod ui;
 
-use std::{collections::HashMap, error::Error, io::{stderr, Write}, sync::mpsc::{Receiver, Sender}, thread, time::Duration};
+use std::{collections::{BTreeMap, BTreeSet}, error::Error, io::{stderr, Write}, sync::mpsc::{Receiver, Sender}, thread, time::{Duration, Instant}};
 
-use app::{ActiveWidget, App};
+use app::{ActiveWidget, App, TestKey, TestResult, TestSummaryRows};
 use ui::ui;
 // Import the necessary modules
-use argparse::{ArgumentParser, Store, StoreOption};
+use argparse::{ArgumentParser, List, StoreOption};
 use stdf_reader::*;
 
 // use color_eyre::config::HookBuilder;
@@ -17,39 +17,47 @@ use ratatui::prelude::*;
 
 // Define a struct to hold the arguments
 struct Arguments {
-    stdf_filename: String,
+    stdf_filenames: Vec<String>,
     dtr_config_fname: Option<String>,
 }
 
-// structure used for sending messages between threads
+// structure used for sending messages between threads, file_idx is the index of the STDF in the session
 struct WorkerMessage {
-    _stdf_filename: String,
+    file_idx: usize,
     log_entry: String,
-    _result: f32,
+    test: Option<TestResult>,
+    fields: Option<RecordFields>,
 }
 
+// statistics of the tests that changed since the last message, sent by the worker of the file
+type SummaryMessage = (usize, Vec<(TestKey, TestSummaryRows)>);
+
+// wafer maps of a file, sent whole by its worker
+type WaferMessage = (usize, WaferMaps);
+
+// how often the worker sends the statistics and wafer maps while it reads the STDF
+const UPDATE_INTERVAL: Duration = Duration::from_millis(250);
+
 // Function to parse the arguments
 fn parse_arguments() -> Arguments {
     // Create a mutable vector to store the STDF filenames
-    let mut args = Arguments { stdf_filename: String::new(), dtr_config_fname: None };
+    let mut args = Arguments { stdf_filenames: Vec::new(), dtr_config_fname: None };
 
     // Create ArgumentParser variable
     let mut ap = ArgumentParser::new();
 
     // Set the application description
-    ap.set_description("Takes an STDF and converts it to a human readable text version");
+    ap.set_description("Takes one or more STDFs and shows them as a human readable log, several STDFs are shown side by side to compare them");
 
     // Add all arguments and associated variables
-    // Here we are adding an argument for the STDF input file
-    ap.refer(&mut args.stdf_filename)
-    .add_argument("Stdf Input", Store, "Stdf input file to be converted").required();
+    // Here we are adding an argument for the STDF input files
+    ap.refer(&mut args.stdf_filenames)
+    .add_argument("Stdf Inputs", List, "Stdf input files to be shown, a last file ending in .ini is the DTR configuration, same as --dtr-file").required();
 
     ap.refer(&mut args.dtr_config_fname)
-    .add_argument("Dtr Config", StoreOption, "Optional DTR Configuration file to be used to determine how DTR's are handled/attached to other records. If not provided, DTR's will be ignored.");
-    // ap.refer(&mut args.dtr_config_fname)
-    //         .add_option(&["-c", "--config_fname"], 
-    //             StoreOption, 
-    //             "Dtr configuration file used to determine how DTR's are handled/attached to other records. If not provided, DTR's will be ignored.");
+            .add_option(&["-d", "--dtr-file"],
+                StoreOption,
+                "Optional DTR Configuration file to be used to determine how DTR's are handled/attached to other records. If not provided, DTR's will be ignored.");
 
     // Parse the arguments and store them
     ap.parse_args_or_exit();
@@ -57,31 +65,25 @@ fn parse_arguments() -> Arguments {
     // Drop the ArgumentParser so we can return the arguments
     std::mem::drop(ap);
 
+    // stdf2ui used to take the DTR configuration as its second argument, keep "stdf2ui file.stdf dtr.ini" working,
+    // errors in the configuration are reported by main like they are for --dtr-file
+    let last_is_ini = args.stdf_filenames.last().is_some_and(|last| last.to_ascii_lowercase().ends_with(".ini"));
+    if args.dtr_config_fname.is_none() && args.stdf_filenames.len() > 1 && last_is_ini {
+        args.dtr_config_fname = args.stdf_filenames.pop();
+    }
+
     // Return the arguments in a struct
     args
 }
 
-fn get_cstr_format(fmt: &Option<String>, val: &Option<f32>) -> String {
-    match val {
-        Some(llm) => {
-            let mut ret_val = format!("{}", llm);
-
-            if fmt.is_some() && fmt.clone().unwrap().len() > 0 {
-                if let Ok(s) = sprintf::sprintf!(fmt.clone().unwrap().as_str(), llm.to_owned()) { ret_val = s; }
-            }
-            // if let Some(fmt) = fmt {
-            //     if fmt.len() > 0 {
-            //         if let Ok(s) = sprintf::sprintf!(fmt.as_str(), llm) { ret_val = s; }
-            //     }
-            // }
-
-            ret_val
-        }
-        None => "".to_string()
-    }
+// dtr info the DTR configuration attaches to a line, shown as a column at its end so filter and search match on it
+fn dtr_column(dtr_info: &[DtrInfo]) -> String {
+    if dtr_info.is_empty() { return "".to_string(); }
+    let fields: Vec<String> = dtr_info.iter().map(|info| format!("{}={}", info.id, info.text)).collect();
+    format!("  | {}", fields.join("  "))
 }
 
-fn rec_to_worker_message(stdf_filename: &String, site_to_part_idx: &HashMap<u8, u32>, rec: &StdfRecord) -> Option<WorkerMessage> {
+fn rec_to_worker_message(file_idx: usize, part_tracker: &PartTracker, rec: &StdfRecord) -> Option<WorkerMessage> {
     let fail_type_regex = regex::Regex::new(r"S[0-9]+_").unwrap();
 
     // do the thing
@@ -89,109 +91,234 @@ fn rec_to_worker_message(stdf_filename: &String, site_to_part_idx: &HashMap<u8,
         // For all other record types, do nothing
         StdfRecord::DTR(rec) => {
             Some(WorkerMessage {
-                _stdf_filename: stdf_filename.to_owned(),
+                file_idx,
                 log_entry: format!("DTR: {}", rec.text_dat.to_owned()),
-                _result: 0.0,
+                test: None,
+                fields: None,
             })
         },
         // For all other record types, do nothing
         StdfRecord::PTR(rec) => {
             // add to log
-            let part_idx = site_to_part_idx.get(&rec.site_num).unwrap_or(&0);
-            let llm = get_cstr_format(&rec.c_llmfmt, &rec.lo_limit);
-            let hlm = get_cstr_format(&rec.c_hlmfmt, &rec.hi_limit);
-            let result = get_cstr_format(&rec.c_resfmt, &Some(rec.result));
-            let units = if let Some(units) = rec.units.to_owned() { units } else { String::new() };
-            let units = if units.len() > 0 { format!("({})", units) } else { "".to_string() };
+            let part_idx = part_tracker.part_idx(rec.head_num, rec.site_num).unwrap_or(0);
+            let test_format = TestFormat::from(rec);
+            let llm = test_format.lo_limit(rec.lo_limit);
+            let hlm = test_format.hi_limit(rec.hi_limit);
+            let result = test_format.result(rec.result);
+            let test_units = test_format.units();
+            let units = if !test_units.is_empty() { format!("({})", test_units) } else { "".to_string() };
             let failed_string = if fail_type_regex.is_match(&rec.test_txt) { "Failed  " } else { "failed  " };
 
-            let mut text = if rec.test_flg[0] & 0b01011100 == 0 && rec.test_flg[0] & 0b10000000 != 0 {
+            let mut text = if TestFlags::from(rec.test_flg).is_fail() {
                 failed_string.to_string()
             } else {
                 "".to_string()
             };
-            let llm_cmp = if !llm.is_empty() { if rec.parm_flg[0] & 0x40 != 0 { " <= " } else { " < " }} else {""};
-            let hlm_cmp = if !hlm.is_empty() { if rec.parm_flg[0] & 0x80 != 0 { " <= " } else { " < " }} else {""};
+            let parm_flags = ParmFlags::from(rec.parm_flg);
+            let llm_cmp = if !llm.is_empty() { if parm_flags.lo_limit_inclusive() { " <= " } else { " < " }} else {""};
+            let hlm_cmp = if !hlm.is_empty() { if parm_flags.hi_limit_inclusive() { " <= " } else { " < " }} else {""};
             text = format!("{:04}  {}{}  {}{}{}{}{} {}", part_idx, text, rec.test_txt, llm, llm_cmp, result, hlm_cmp, hlm, units);
 
-            let result = rec.result;
             Some(WorkerMessage {
-                _stdf_filename: stdf_filename.to_owned(),
+                file_idx,
+                log_entry: text,
+                // graphed in the units of the log line, limits included, results the statistics leave out aren't graphed either
+                test: TestFlags::from(rec.test_flg).is_valid().then(|| TestResult {
+                    key: ("PTR", rec.test_num),
+                    test_txt: rec.test_txt.to_owned(),
+                    units: test_units,
+                    lo_limit: rec.lo_limit.map(|lo_limit| test_format.scale(lo_limit as f64) as f32),
+                    hi_limit: rec.hi_limit.map(|hi_limit| test_format.scale(hi_limit as f64) as f32),
+                    part_idx,
+                    result: test_format.scale(rec.result as f64) as f32,
+                }),
+                fields: None,
+            })
+        },
+        StdfRecord::MPR(rec) => {
+            // add to log, the pin results are shown but not graphed
+            let part_idx = part_tracker.part_idx(rec.head_num, rec.site_num).unwrap_or(0);
+            let test_format = TestFormat::from(rec);
+            let llm = test_format.lo_limit(rec.lo_limit);
+            let hlm = test_format.hi_limit(rec.hi_limit);
+            let results: Vec<String> = rec.rtn_rslt.iter().map(|result| test_format.result(*result)).collect();
+            let test_units = test_format.units();
+            let units = if !test_units.is_empty() { format!("({})", test_units) } else { "".to_string() };
+            let failed_string = if fail_type_regex.is_match(&rec.test_txt) { "Failed  " } else { "failed  " };
+
+            let mut text = if TestFlags::from(rec.test_flg).is_fail() {
+                failed_string.to_string()
+            } else {
+                "".to_string()
+            };
+            let parm_flags = ParmFlags::from(rec.parm_flg);
+            let llm_cmp = if !llm.is_empty() { if parm_flags.lo_limit_inclusive() { " <= " } else { " < " }} else {""};
+            let hlm_cmp = if !hlm.is_empty() { if parm_flags.hi_limit_inclusive() { " <= " } else { " < " }} else {""};
+            text = format!("{:04}  {}{}  {}{}[{}]{}{} {}", part_idx, text, rec.test_txt, llm, llm_cmp, results.join(", "), hlm_cmp, hlm, units);
+
+            Some(WorkerMessage {
+                file_idx,
                 log_entry: text,
-                _result: result,
+                test: None,
+                fields: None,
             })
         },
         StdfRecord::FTR(rec) => {
             // add to log
-            let part_idx = site_to_part_idx.get(&rec.site_num).unwrap_or(&0);
-            let opt_flag = rec.opt_flag[0];
-            let test_flag = rec.test_flg[0];
-            let mut text = "".to_string();
+            let part_idx = part_tracker.part_idx(rec.head_num, rec.site_num).unwrap_or(0);
+            let failed = TestFlags::from(rec.test_flg).is_fail();
             let failed_string = if fail_type_regex.is_match(&rec.test_txt) { "Failed  " } else { "failed  " };
+            let text = if failed { failed_string } else { "" };
 
-            let result = if opt_flag & 0x08 == 0 {
-                // return num_fail
-                if rec.num_fail > 0 {
-                    text = failed_string.to_string();
-                }
+            // graph num_fail, without it a failing test is -1
+            let result = if FtrOptFlags::from(rec.opt_flag).num_fail_valid() {
                 rec.num_fail as f32
+            } else if failed {
+                -1.0f32
             } else {
-                // no num_fail information
-                if test_flag & 0x54 != 0 || test_flag == 0 {
-                    // test passed
-                    0.0f32
-                } else {
-                    // test failed, but no num_fail information
-                    text = failed_string.to_string();
-                    -1.0f32
-                }
+                0.0f32
             };
 
-            text = format!("{:04}  {}{}  {}  {}", part_idx, text, rec.vect_nam, rec.num_fail, rec.test_txt);
+            let text = format!("{:04}  {}{}  {}  {}", part_idx, text, rec.vect_nam, rec.num_fail, rec.test_txt);
             Some(WorkerMessage {
-                _stdf_filename: stdf_filename.to_owned(),
+                file_idx,
                 log_entry: text,
-                _result: result,
+                test: TestFlags::from(rec.test_flg).is_valid().then(|| TestResult {
+                    key: ("FTR", rec.test_num),
+                    test_txt: rec.test_txt.to_owned(),
+                    units: String::new(),
+                    lo_limit: None,
+                    hi_limit: None,
+                    part_idx,
+                    result,
+                }),
+                fields: None,
             })
         },
         _ => { None }
     }
 }
 
-fn stdf_worker(stdf_filename: &String, tx: Sender<WorkerMessage>, rx: Receiver<bool>) {
-    let mut parser = StdfParser::new(stdf_filename, &None).unwrap();
-    let mut site_to_part_idx = HashMap::<u8, u32>::new();
-    let mut part_idx = 1;
+// statistics of the PTR/FTR tests, kept by the worker so the app only has to show them
+#[derive(Default)]
+struct WorkerStatistics {
+    ptr_statistics: TestStatistics,
+    ftr_summaries: BTreeMap<u32, TestSummary>,
+    changed: BTreeSet<TestKey>,
+}
+
+impl WorkerStatistics {
+    fn add_record(&mut self, rec: &StdfRecord) {
+        match rec {
+            StdfRecord::PTR(ptr) => {
+                self.ptr_statistics.add_record(rec);
+                self.changed.insert(("PTR", ptr.test_num));
+            },
+            StdfRecord::FTR(rec) => {
+                // the statistics of an FTR are the ones of its num_fail
+                let summary = self.ftr_summaries.entry(rec.test_num).or_default();
+                if summary.test_txt.is_empty() { summary.test_txt = rec.test_txt.to_owned(); }
+                let num_fail = FtrOptFlags::from(rec.opt_flag).num_fail_valid().then_some(rec.num_fail as f32);
+                let failed = TestFlags::from(rec.test_flg).is_fail();
+                summary.sites.entry((rec.head_num, rec.site_num)).or_default().add(num_fail, failed);
+                self.changed.insert(("FTR", rec.test_num));
+            },
+            _ => {},
+        }
+    }
+
+    // statistics of the tests that changed since the last call
+    fn take_changed(&mut self) -> Option<Vec<(TestKey, TestSummaryRows)>> {
+        if self.changed.is_empty() { return None; }
+
+        let changed = std::mem::take(&mut self.changed);
+        Some(changed.into_iter().filter_map(|key| {
+            let summary = match key {
+                ("PTR", test_num) => self.ptr_statistics.get(&TestId { test_num, pin: None }),
+                (_, test_num) => self.ftr_summaries.get(&test_num),
+            };
+            summary.map(|summary| (key, TestSummaryRows::from(summary)))
+        }).collect())
+    }
+}
+
+fn stdf_worker(file_idx: usize, stdf_filename: &String, dtr_config_fname: &Option<String>, tx: Sender<WorkerMessage>, summary_tx: Sender<SummaryMessage>, wafer_tx: Sender<WaferMessage>, rx: Receiver<bool>) {
+    let mut parser = match StdfParser::new(stdf_filename, dtr_config_fname) {
+        Ok(parser) => parser,
+        Err(err) => {
+            // nothing to read, report the error in the log
+            let _ = tx.send(WorkerMessage {
+                file_idx,
+                log_entry: format!("Error: {}", err),
+                test: None,
+                fields: None,
+            });
+            return;
+        }
+    };
+    let mut part_tracker = PartTracker::tracking_only();
+    let mut statistics = WorkerStatistics::default();
+    let mut wafer_maps = WaferMaps::new();
+    let mut wafers_changed = false;
+    let mut last_update = Instant::now();
 
     loop {
-        // Loop indefinitely
         // Match the next record from the parser
-        if let Some(rec) = parser.next() {
-            match rec {
-                // For all other record types, do nothing
-                Ok((StdfRecord::MRR(_), _)) => {
-                    break;
-                },
-                Ok((StdfRecord::PIR(rec), _)) => {
-                    // update site_to_part_idx
-                    let site_num = rec.site_num;
-                    site_to_part_idx.insert(site_num, part_idx);
-                    part_idx = part_idx + 1;
-                },
-                Ok((rec, _)) => {
-                    // add to log
-                    if let Some(msg) = rec_to_worker_message(&stdf_filename, &site_to_part_idx, &rec) {
-                        tx.send(msg).unwrap();
-                    }
-                },
-                Err(_) => {break},
-            }
+        match parser.next() {
+            // For all other record types, do nothing
+            Some(Ok(ParsedRecord { record: StdfRecord::MRR(_), .. })) => {
+                break;
+            },
+            Some(Ok(parsed_rec)) => {
+                // keep track of the part in test on each head/site, finished parts are dies of the wafer map
+                if let Some(part) = part_tracker.process(&parsed_rec) {
+                    wafer_maps.add_part(&part);
+                    wafers_changed = true;
+                }
+                if matches!(parsed_rec.record, StdfRecord::WCR(_) | StdfRecord::WIR(_) | StdfRecord::WRR(_) | StdfRecord::HBR(_) | StdfRecord::SBR(_)) {
+                    wafer_maps.add_record(&parsed_rec.record);
+                    wafers_changed = true;
+                }
+
+                // add to log, with the dtr info the parser attached to the record and the fields the filter queries
+                if let Some(mut msg) = rec_to_worker_message(file_idx, &part_tracker, &parsed_rec.record) {
+                    msg.log_entry += &dtr_column(&parsed_rec.dtr_info);
+                    msg.fields = Some(RecordFields::new(&parsed_rec, &part_tracker));
+                    // the app is gone once the user quits
+                    if tx.send(msg).is_err() { break; }
+                }
+
+                // statistics and wafer maps go to the app every UPDATE_INTERVAL
+                statistics.add_record(&parsed_rec.record);
+                if last_update.elapsed() >= UPDATE_INTERVAL {
+                    last_update = Instant::now();
+                    if let Some(msg) = statistics.take_changed() { let _ = summary_tx.send((file_idx, msg)); }
+                    if wafers_changed { let _ = wafer_tx.send((file_idx, wafer_maps.clone())); }
+                    wafers_changed = false;
+                }
+            },
+            Some(Err(err)) => {
+                // show the error as the last log entry and stop reading
+                let _ = tx.send(WorkerMessage {
+                    file_idx,
+                    log_entry: format!("Error: {}", err),
+                    test: None,
+                    fields: None,
+                });
+                break;
+            },
+            // we have gotten all the records
+            None => break,
         }
 
         if let Ok(should_break) = rx.try_recv() {
             if should_break { break; }
         }
     }
+
+    // whatever changed since the last update
+    if let Some(msg) = statistics.take_changed() { let _ = summary_tx.send((file_idx, msg)); }
+    if wafers_changed { let _ = wafer_tx.send((file_idx, wafer_maps)); }
 }
 
 fn init_terminal() -> Result<Terminal<CrosstermBackend<impl Write>>, Box<dyn Error>> {
@@ -222,18 +349,28 @@ fn handle_list_key(key: event::KeyEvent, app: &mut App) -> bool {
             app.active_widget = app::ActiveWidget::Search;
         },
         event::KeyCode::Char('e') => {
-            app.save_to_log(app.stdf_filename.to_owned() + ".txt").unwrap();
+            app.save_to_log(app.stdf_filenames[0].to_owned() + ".txt").unwrap();
         },
         event::KeyCode::Char('a') => {
             app.toggle_auto_scroll();
         },
         event::KeyCode::Char('g') => {
-            if app.current_layout == app::CurrentLayout::List {
+            if app.current_layout != app::CurrentLayout::Plot {
                 app.current_layout = app::CurrentLayout::Plot;
             } else {
                 app.current_layout = app::CurrentLayout::List;
             }
         },
+        event::KeyCode::Char('w') => {
+            app.current_layout = app::CurrentLayout::WaferMap;
+            app.active_widget = app::ActiveWidget::WaferMap;
+        },
+        event::KeyCode::Char('t') => {
+            app.toggle_graph_type();
+        },
+        event::KeyCode::Tab => {
+            app.select_file();
+        },
         event::KeyCode::Char('L') => {
             if app.graph_yscale == app::GraphYScale::Linear {
                 app.graph_yscale = app::GraphYScale::Log10;
@@ -241,14 +378,14 @@ fn handle_list_key(key: event::KeyEvent, app: &mut App) -> bool {
                 app.graph_yscale = app::GraphYScale::Linear;
             }
         },
-        event::KeyCode::Char('n') => {
+        event::KeyCode::Char('n') if !app.search_string.is_empty() => {
             // search for next match
-            if app.search_string.len() > 0 {
+            {
                 let idx = app.selected_line + 1;
                 let mut iter = app.filtered_log_data.iter().skip(idx);
-                if app.search_string.starts_with("/") {
-                    if let Ok(re) = regex::Regex::new(&app.search_string.trim_start_matches('/')) {
-                        let pos = iter.position(|x| re.is_match(&x));
+                if app.search_string.starts_with('/') {
+                    if let Ok(re) = regex::Regex::new(app.search_string.trim_start_matches('/')) {
+                        let pos = iter.position(|x| re.is_match(x));
                         if let Some(pos) = pos {
                             app.selected_line = idx + pos;
                         }
@@ -261,14 +398,14 @@ fn handle_list_key(key: event::KeyEvent, app: &mut App) -> bool {
                 }
             }
         },
-        event::KeyCode::Char('N') => {
-            // search for next match
-            if app.search_string.len() > 0 {
+        event::KeyCode::Char('N') if !app.search_string.is_empty() => {
+            // search for previous match
+            {
                 let idx = app.selected_line;
                 let mut iter = app.filtered_log_data.iter().take(idx).rev();
-                if app.search_string.starts_with("/") {
-                    if let Ok(re) = regex::Regex::new(&app.search_string.trim_start_matches('/')) {
-                        let pos = iter.position(|x| re.is_match(&x));
+                if app.search_string.starts_with('/') {
+                    if let Ok(re) = regex::Regex::new(app.search_string.trim_start_matches('/')) {
+                        let pos = iter.position(|x| re.is_match(x));
                         if let Some(pos) = pos {
                             app.selected_line = idx - pos - 1;
                         }
@@ -284,35 +421,16 @@ fn handle_list_key(key: event::KeyEvent, app: &mut App) -> bool {
         event::KeyCode::Char('q') => {
             should_break = true;
         },
-        event::KeyCode::Enter => {
-            if app.active_widget == app::ActiveWidget::Help {
-                app.active_widget = app::ActiveWidget::List;
-            } else if app.active_widget == app::ActiveWidget::Search {
-                app.active_widget = app::ActiveWidget::List;
-            } else if app.active_widget == app::ActiveWidget::Filter {
-                app.active_widget = app::ActiveWidget::List;
-            }
+        // help, search and filter all return to the list
+        event::KeyCode::Enter | event::KeyCode::Esc
+            if app.active_widget == app::ActiveWidget::Help || app.active_widget == app::ActiveWidget::Search || app.active_widget == app::ActiveWidget::Filter => {
+            app.active_widget = app::ActiveWidget::List;
         },
-        event::KeyCode::Esc => {
-            if app.active_widget == app::ActiveWidget::Help {
-                app.active_widget = app::ActiveWidget::List;
-            } else if app.active_widget == app::ActiveWidget::Search {
-                // restore search to old state
-                app.active_widget = app::ActiveWidget::List;
-            } else if app.active_widget == app::ActiveWidget::Filter {
-                // restore filter to old state
-                app.active_widget = app::ActiveWidget::List;
-            }
-        },
-        event::KeyCode::Up | event::KeyCode::Char('j') => {
-            if app.selected_line > 0 {
-                app.selected_line -= 1;
-            }
+        event::KeyCode::Up | event::KeyCode::Char('j') if app.selected_line > 0 => {
+            app.selected_line -= 1;
         },
-        event::KeyCode::Down | event::KeyCode::Char('k') => {
-            if app.selected_line < app.log_data.len() - 1 {
-                app.selected_line += 1;
-            }
+        event::KeyCode::Down | event::KeyCode::Char('k') if app.selected_line + 1 < app.log_data.len() => {
+            app.selected_line += 1;
         },
         event::KeyCode::End => {
             app.selected_line = app.log_data.len() - 1;
@@ -339,7 +457,7 @@ fn handle_list_key(key: event::KeyEvent, app: &mut App) -> bool {
             if start < display_size {
                 start = 0;
             } else {
-                start = start - display_size;
+                start -= display_size;
             }
             end = start + display_size;
             app.lines_to_display = (start, end);
@@ -351,6 +469,40 @@ fn handle_list_key(key: event::KeyEvent, app: &mut App) -> bool {
     should_break
 }
 
+fn handle_wafer_key(key: event::KeyEvent, app: &mut App) -> bool {
+    let mut should_break = false;
+    // wafer map active widget key handling, the arrows move the die cursor
+    match key.code {
+        event::KeyCode::Left | event::KeyCode::Char('h') => app.move_wafer_cursor(-1, 0),
+        event::KeyCode::Right | event::KeyCode::Char('l') => app.move_wafer_cursor(1, 0),
+        event::KeyCode::Up | event::KeyCode::Char('j') => app.move_wafer_cursor(0, -1),
+        event::KeyCode::Down | event::KeyCode::Char('k') => app.move_wafer_cursor(0, 1),
+        event::KeyCode::Char('b') => {
+            app.toggle_wafer_bin_type();
+        },
+        event::KeyCode::Char('[') => {
+            app.select_wafer(false);
+        },
+        event::KeyCode::Char(']') => {
+            app.select_wafer(true);
+        },
+        // the map stays up next to the log lines of the die
+        event::KeyCode::Enter if app.jump_to_selected_die() => {
+            app.active_widget = app::ActiveWidget::List;
+        },
+        event::KeyCode::Esc | event::KeyCode::Char('w') => {
+            app.current_layout = app::CurrentLayout::List;
+            app.active_widget = app::ActiveWidget::List;
+        },
+        event::KeyCode::Char('q') => {
+            should_break = true;
+        },
+        _ => {}
+    }
+
+    should_break
+}
+
 fn handle_filter_key(key: event::KeyEvent, prv_filter: &String, app: &mut App) -> bool {
     let restore_widget = app::ActiveWidget::List;
     app.data_is_dirty = true;
@@ -377,7 +529,7 @@ fn handle_filter_key(key: event::KeyEvent, prv_filter: &String, app: &mut App) -
         }
     }
 
-    return false;
+    false
 }
 
 fn handle_search_key(key: event::KeyEvent, prv_search: &String, prv_selected: usize, app: &mut App) -> bool {
@@ -385,9 +537,9 @@ fn handle_search_key(key: event::KeyEvent, prv_search: &String, prv_selected: us
     match key.code {
         event::KeyCode::Char(c) => {
             app.search_string.push(c);
-            if !app.search_string.is_empty() && app.search_string.starts_with("/") {
-                if let Ok(re) = regex::Regex::new(&app.search_string.trim_start_matches('/')) {
-                    app.selected_line = app.filtered_log_data.iter().position(|x| re.is_match(&x)).unwrap_or(prv_selected);
+            if app.search_string.starts_with('/') {
+                if let Ok(re) = regex::Regex::new(app.search_string.trim_start_matches('/')) {
+                    app.selected_line = app.filtered_log_data.iter().position(|x| re.is_match(x)).unwrap_or(prv_selected);
                 }
             } else {
                 app.selected_line = app.filtered_log_data.iter().position(|x| x.contains(&app.search_string)).unwrap_or(prv_selected);
@@ -397,9 +549,9 @@ fn handle_search_key(key: event::KeyEvent, prv_search: &String, prv_selected: us
             app.search_string.pop();
             if app.search_string.is_empty() {
                 app.selected_line = prv_selected;
-            } else if app.search_string.starts_with("/") {
-                if let Ok(re) = regex::Regex::new(&app.search_string.trim_start_matches('/')) {
-                    app.selected_line = app.filtered_log_data.iter().position(|x| re.is_match(&x)).unwrap_or(prv_selected);
+            } else if app.search_string.starts_with('/') {
+                if let Ok(re) = regex::Regex::new(app.search_string.trim_start_matches('/')) {
+                    app.selected_line = app.filtered_log_data.iter().position(|x| re.is_match(x)).unwrap_or(prv_selected);
                 }
             } else {
                 app.selected_line = app.filtered_log_data.iter().position(|x| x.contains(&app.search_string)).unwrap_or(prv_selected);
@@ -416,15 +568,15 @@ fn handle_search_key(key: event::KeyEvent, prv_search: &String, prv_selected: us
         _ => {}
     }
 
-    return false;
+    false
 }
 
-fn run_app(rx: Receiver<WorkerMessage>, tx: Sender<bool>, stdf_filename: &String) -> Result<(), String> {
+fn run_app(rx: Receiver<WorkerMessage>, summary_rx: Receiver<SummaryMessage>, wafer_rx: Receiver<WaferMessage>, terminate_txs: Vec<Sender<bool>>, stdf_filenames: &[String]) -> Result<(), String> {
     // open file for logging
     let mut terminal = init_terminal().map_err(|e| e.to_string())?;
 
     let mut app = App::new();
-    app.stdf_filename = stdf_filename.to_owned();
+    app.set_stdf_filenames(stdf_filenames);
     let mut prv_filter = app.filter_string.clone();
     let mut prv_search = app.search_string.clone();
     let mut prv_selected = 0;
@@ -441,12 +593,24 @@ fn run_app(rx: Receiver<WorkerMessage>, tx: Sender<bool>, stdf_filename: &String
 
         // grab all pending messages and add them to the log
         while let Ok(msg) = rx.try_recv() {
-            app.log_data.push(msg.log_entry);
+            app.add_log_entry(msg.file_idx, msg.log_entry, msg.test, msg.fields);
             
             app.needs_refresh = true;
             app.data_is_dirty = true;
         }
 
+        // the workers send the whole wafer maps of their file
+        while let Ok((file_idx, wafer_maps)) = wafer_rx.try_recv() {
+            app.wafer_maps[file_idx] = wafer_maps;
+            app.needs_refresh = true;
+        }
+
+        // replace the statistics of the tests the workers updated
+        while let Ok((file_idx, summaries)) = summary_rx.try_recv() {
+            app.test_summaries.extend(summaries.into_iter().map(|(key, summary)| ((file_idx, key), summary)));
+            app.needs_refresh = true;
+        }
+
         // wait for event for remainder of tick rate
         if crossterm::event::poll(Duration::from_millis(0)).map_err(|e| e.to_string())? {
             if let Event::Key(key) = event::read().map_err(|e| e.to_string())? {
@@ -468,9 +632,9 @@ fn run_app(rx: Receiver<WorkerMessage>, tx: Sender<bool>, stdf_filename: &String
                             prv_selected = app.selected_line;
                             app.search_string = "".to_string();
                         }
-                        if true == should_break {
-                            // terminate worker if running
-                            let _ = tx.send(true);
+                        if should_break {
+                            // terminate workers if running
+                            for tx in &terminate_txs { let _ = tx.send(true); }
                             break;
                         }
                     },
@@ -480,6 +644,14 @@ fn run_app(rx: Receiver<WorkerMessage>, tx: Sender<bool>, stdf_filename: &String
                     ActiveWidget::Search => {
                         handle_search_key(key, &prv_search, prv_selected, &mut app);
                     },
+                    ActiveWidget::WaferMap => {
+                        let should_break = handle_wafer_key(key, &mut app);
+                        if should_break {
+                            // terminate workers if running
+                            for tx in &terminate_txs { let _ = tx.send(true); }
+                            break;
+                        }
+                    },
                     _ => {}
                 }
             }
@@ -496,22 +668,41 @@ fn run_app(rx: Receiver<WorkerMessage>, tx: Sender<bool>, stdf_filename: &String
 fn main() -> Result<(), Box<dyn Error>> {
     // Call the function to parse the arguments
     let args = parse_arguments();
+
+    // a broken DTR configuration is reported before the terminal is taken over
+    if let Err(err) = load_dtr_config(&args.dtr_config_fname) {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    }
     
     let (tx, rx) = std::sync::mpsc::channel::<WorkerMessage>();
-    let (terminate_tx, terminate_rx) = std::sync::mpsc::channel::<bool>();
-    let stdf_filename = args.stdf_filename.clone();
+    let (summary_tx, summary_rx) = std::sync::mpsc::channel::<SummaryMessage>();
+    let (wafer_tx, wafer_rx) = std::sync::mpsc::channel::<WaferMessage>();
+
+    // one worker per file, all of them feed the same app
+    let mut terminate_txs = Vec::with_capacity(args.stdf_filenames.len());
+    let mut worker_handles = Vec::with_capacity(args.stdf_filenames.len());
+    for (file_idx, stdf_filename) in args.stdf_filenames.iter().enumerate() {
+        let (terminate_tx, terminate_rx) = std::sync::mpsc::channel::<bool>();
+        terminate_txs.push(terminate_tx);
+
+        let stdf_filename = stdf_filename.clone();
+        let dtr_config_fname = args.dtr_config_fname.clone();
+        let (tx, summary_tx, wafer_tx) = (tx.clone(), summary_tx.clone(), wafer_tx.clone());
+        worker_handles.push(thread::spawn(move || {
+            stdf_worker(file_idx, &stdf_filename, &dtr_config_fname, tx, summary_tx, wafer_tx, terminate_rx)
+        }));
+    }
 
+    let stdf_filenames = args.stdf_filenames.clone();
     let app_handle = thread::spawn(move || {
-        run_app(rx, terminate_tx, &stdf_filename)
-    });
-
-    let stdf_filename = args.stdf_filename.clone();
-    let worker_handle = thread::spawn(move || {
-        stdf_worker(&stdf_filename, tx, terminate_rx)
+        run_app(rx, summary_rx, wafer_rx, terminate_txs, &stdf_filenames)
     });
 
     app_handle.join().unwrap().map_err(|e| e.to_string())?;
-    worker_handle.join().unwrap();
+    for worker_handle in worker_handles {
+        worker_handle.join().unwrap();
+    }
 
     Ok(())
 }
\ No newline at end of file
@@ -1,11 +1,23 @@
 use std::rc::Rc;
 
-use ratatui::{layout::{Constraint, Direction, Layout, Rect}, style::{Color, Style, Stylize}, text::Text, widgets::{Block, Borders, List, ListState, Paragraph}, Frame};
+use ratatui::{layout::{Constraint, Direction, Layout, Rect}, style::{Color, Style, Stylize}, symbols::Marker, text::{Line, Span, Text}, widgets::{self, Axis, Block, Borders, Cell, Chart, Dataset, List, ListState, Paragraph, Row, Table}, Frame};
 
-use crate::app::{App, CurrentLayout, ActiveWidget};
+use stdf_reader::{BinInfo, Die, RecordQuery, StdfUtilError, WaferSide};
+
+use crate::app::{App, LineFields, CurrentLayout, ActiveWidget, BinType, GraphType, GraphYScale, SummaryRow, TestResults, TestSummaryRows};
 
 type Rects = Rc<[Rect]>;
 
+// number of bins of the histogram
+const HISTOGRAM_BINS: usize = 40;
+
+// colors of the files of a session in the graph, picked by file index
+const FILE_COLORS: [Color; 6] = [Color::Cyan, Color::Yellow, Color::Magenta, Color::Green, Color::LightBlue, Color::LightRed];
+
+// colors of the failing bins of the wafer map, picked by bin number
+const FAIL_BIN_COLORS: [Color; 10] = [Color::Red, Color::Yellow, Color::Magenta, Color::Cyan, Color::Blue,
+    Color::LightRed, Color::LightYellow, Color::LightMagenta, Color::LightCyan, Color::LightBlue];
+
 // we need the outer and body layouts, and we use them just not after assigned
 #[allow(unused)]
 struct UiLayout {
@@ -29,7 +41,7 @@ fn get_layout(f: &mut Frame, app: &App) -> UiLayout {
             Constraint::Min(3),
             Constraint::Length(1)
         ])
-        .split(f.size());
+        .split(f.area());
 
     // Create Inner Layouts
     //  1 is the header layout with 100% of the width
@@ -64,6 +76,15 @@ fn get_layout(f: &mut Frame, app: &App) -> UiLayout {
         .split(body_layout[0]);
     let inner_right =  if app.current_layout == CurrentLayout::List {
         None
+    } else if app.current_layout == CurrentLayout::WaferMap {
+        // the map needs more room than its legend
+        Some(Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Percentage(70),
+                Constraint::Percentage(30),
+            ])
+            .split(body_layout[1]))
     } else {
         Some(Layout::default()
             .direction(Direction::Vertical)
@@ -90,28 +111,48 @@ fn filter_and_trim_log_data(app: &mut App, display_size: u16) -> Vec<String> {
 
     if app.data_is_dirty {
         app.data_is_dirty = false;
-        if app.filter_string.starts_with("/") {
-            if let Ok(re) = regex::Regex::new(&app.filter_string.trim_start_matches('/')) {
-                app.filtered_log_data = app.log_data.iter()
-                    .filter(|t| if app.filter_string.is_empty() { true } else { re.is_match(t.as_str()) }).map(|v| v.to_owned()).collect();
+        // keep the index of each filtered line in the log, it links the line to its test
+        let file_filter = app.file_filter;
+        let log_files = &app.log_files;
+        let lines = app.log_data.iter().enumerate().filter(|(i, _)| file_filter.is_none_or(|file_idx| log_files[*i] == file_idx));
+        app.filter_error = None;
+        if app.filter_string.starts_with('?') {
+            // a query is evaluated against the fields of the records, lines without a record never match
+            match RecordQuery::parse(app.filter_string.trim_start_matches('?'), &LineFields::EXTRA_FIELDS) {
+                Ok(query) => {
+                    let log_fields = &app.log_fields;
+                    app.filtered_log_index = lines
+                        .filter(|(i, _)| log_fields[*i].as_ref().is_some_and(|fields| query.matches(&LineFields { file_idx: log_files[*i], fields })))
+                        .map(|(i, _)| i).collect();
+                },
+                Err(err) => {
+                    app.filter_error = Some(match err { StdfUtilError::Query { msg, .. } => msg, err => err.to_string() });
+                    app.filtered_log_index.clear();
+                },
+            }
+        } else if app.filter_string.starts_with('/') {
+            if let Ok(re) = regex::Regex::new(app.filter_string.trim_start_matches('/')) {
+                app.filtered_log_index = lines
+                    .filter(|(_, t)| if app.filter_string.is_empty() { true } else { re.is_match(t.as_str()) }).map(|(i, _)| i).collect();
             } else {
-                app.filtered_log_data.clear();
+                app.filtered_log_index.clear();
             }
         } else {
-            app.filtered_log_data = app.log_data.iter()
-                .filter(|t| if app.filter_string.is_empty() { true } else { t.contains(&app.filter_string) }).map(|v| v.to_owned()).collect();
+            app.filtered_log_index = lines
+                .filter(|(_, t)| if app.filter_string.is_empty() { true } else { t.contains(&app.filter_string) }).map(|(i, _)| i).collect();
         }
+        app.filtered_log_data = app.filtered_log_index.iter().map(|i| app.log_data[*i].to_owned()).collect();
     }
 
     // if we're supposed to auto-scroll, then we should always go to the last available entry.
     if app.auto_scroll {
-        selected_line = app.filtered_log_data.len() - 1;
+        selected_line = app.filtered_log_data.len().saturating_sub(1);
         app.selected_line = selected_line;
     }
 
     // move selected line to the end if it's passed the end
     if selected_line >= app.filtered_log_data.len() {
-        selected_line = app.filtered_log_data.len() - 1;
+        selected_line = app.filtered_log_data.len().saturating_sub(1);
         app.selected_line = selected_line;
     }
 
@@ -126,10 +167,10 @@ fn filter_and_trim_log_data(app: &mut App, display_size: u16) -> Vec<String> {
         start = selected_line;
         end = start + display_size as usize;
         app.lines_to_display = (start, end);
-    } else if end != app.filtered_log_data.len()-1 && selected_line >= end {
+    } else if end != app.filtered_log_data.len().saturating_sub(1) && selected_line >= end {
         let delta = selected_line - end;
-        start = start + delta;
-        end = end + delta;
+        start += delta;
+        end += delta;
         app.lines_to_display = (start, end);
     }
 
@@ -142,31 +183,386 @@ fn filter_and_trim_log_data(app: &mut App, display_size: u16) -> Vec<String> {
     filtered_data
 }
 
+// value drawn on the y axis, None when it can't be drawn on a log scale
+fn scale_y(yscale: &GraphYScale, y: f64) -> Option<f64> {
+    match yscale {
+        GraphYScale::Linear => Some(y),
+        GraphYScale::Log10 if y > 0.0 => Some(y.log10()),
+        GraphYScale::Log10 => None,
+    }
+}
+
+// bounds of the values with a margin, a single value gets a unit wide range
+fn get_bounds(values: impl Iterator<Item = f64>) -> [f64; 2] {
+    let (min, max) = values.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| (min.min(v), max.max(v)));
+    if min > max {
+        [0.0, 1.0]
+    } else if min == max {
+        [min - 0.5, max + 0.5]
+    } else {
+        let margin = (max - min) * 0.05;
+        [min - margin, max + margin]
+    }
+}
+
+// min, middle and max labels of an axis
+fn get_labels(bounds: [f64; 2], fmt: impl Fn(f64) -> String) -> Vec<String> {
+    [bounds[0], (bounds[0] + bounds[1]) / 2.0, bounds[1]].into_iter().map(fmt).collect()
+}
+
+// histogram bars of each file, (bin position, count) of the non empty bins, all files share the bins
+//  and the bars of a bin are side by side so the overlaid files stay readable
+fn get_histograms(results: &[(usize, &TestResults)]) -> Vec<Vec<(f64, f64)>> {
+    let [min, max] = get_bounds(results.iter().flat_map(|(_, results)| results.results.iter().map(|(_, result)| *result as f64)));
+    let bin_width = (max - min) / HISTOGRAM_BINS as f64;
+    let bar_offset = bin_width / results.len() as f64;
+
+    results.iter().enumerate().map(|(i, (_, results))| {
+        let mut counts = [0u32; HISTOGRAM_BINS];
+        for (_, result) in &results.results {
+            let bin = ((*result as f64 - min) / bin_width) as usize;
+            counts[bin.min(HISTOGRAM_BINS - 1)] += 1;
+        }
+
+        counts.iter().enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(bin, count)| (min + bin as f64 * bin_width + (i as f64 + 0.5) * bar_offset, *count as f64))
+            .collect()
+    }).collect()
+}
+
+fn render_graph(f: &mut Frame, app: &App, area: Rect) {
+    let graph_block = Block::default()
+        .borders(Borders::ALL)
+        .style(Style::default().bg(Color::Black)).fg(Color::Gray);
+
+    // the results of the test in every shown file are overlaid
+    let key = app.selected_test();
+    let all_results: Vec<(usize, &TestResults)> = key.iter()
+        .flat_map(|key| app.shown_files().into_iter().filter_map(move |file_idx| app.test_results.get(&(file_idx, *key)).map(|results| (file_idx, results))))
+        .collect();
+    let (Some(key), Some((_, results))) = (key, all_results.first()) else {
+        let graph = Paragraph::new("GRAPH: select a PTR or FTR line to graph its results")
+            .style(Style::new().fg(Color::Gray))
+            .block(graph_block);
+        f.render_widget(graph, area);
+        return;
+    };
+
+    let units = if !results.units.is_empty() { format!(" ({})", results.units) } else { "".to_string() };
+    let graph_name = if app.graph_type == GraphType::Histogram { "histogram" } else { "trend" };
+    let scale_name = if app.graph_yscale == GraphYScale::Log10 { "log10" } else { "linear" };
+    let graph_block = graph_block.title(format!(" {} {}: {}{}  [{}, {}] ", key.0, key.1, results.test_txt, units, graph_name, scale_name));
+
+    // limits of the first file, the limits of the files are compared in the summary
+    let limits: Vec<(&str, f64)> = [("lo limit", results.lo_limit), ("hi limit", results.hi_limit)].into_iter()
+        .filter_map(|(name, limit)| limit.map(|limit| (name, limit as f64)))
+        .collect();
+
+    // the limits are vertical markers on the histogram and horizontal ones on the trend
+    let (data, x_bounds, y_bounds, limit_lines, x_title, y_title) = if app.graph_type == GraphType::Histogram {
+        let data: Vec<Vec<(f64, f64)>> = get_histograms(&all_results).into_iter()
+            .map(|bars| bars.into_iter().filter_map(|(x, count)| scale_y(&app.graph_yscale, count).map(|y| (x, y))).collect())
+            .collect();
+        let x_bounds = get_bounds(all_results.iter().flat_map(|(_, results)| results.results.iter().map(|(_, result)| *result as f64)).chain(limits.iter().map(|(_, limit)| *limit)));
+        let y_max = data.iter().flatten().map(|(_, y)| *y).fold(0.0, f64::max);
+        let y_bounds = [0.0, if y_max > 0.0 { y_max * 1.05 } else { 1.0 }];
+        let limit_lines: Vec<(&str, Vec<(f64, f64)>)> = limits.iter().map(|(name, limit)| (*name, vec![(*limit, y_bounds[0]), (*limit, y_bounds[1])])).collect();
+        (data, x_bounds, y_bounds, limit_lines, format!("result{}", units), "count".to_string())
+    } else {
+        let data: Vec<Vec<(f64, f64)>> = all_results.iter()
+            .map(|(_, results)| results.results.iter()
+                .filter_map(|(part_idx, result)| scale_y(&app.graph_yscale, *result as f64).map(|y| (*part_idx as f64, y)))
+                .collect())
+            .collect();
+        let x_bounds = get_bounds(data.iter().flatten().map(|(x, _)| *x));
+        let limits: Vec<(&str, f64)> = limits.iter().filter_map(|(name, limit)| scale_y(&app.graph_yscale, *limit).map(|limit| (*name, limit))).collect();
+        let y_bounds = get_bounds(data.iter().flatten().map(|(_, y)| *y).chain(limits.iter().map(|(_, limit)| *limit)));
+        let limit_lines: Vec<(&str, Vec<(f64, f64)>)> = limits.iter().map(|(name, limit)| (*name, vec![(x_bounds[0], *limit), (x_bounds[1], *limit)])).collect();
+        (data, x_bounds, y_bounds, limit_lines, "part".to_string(), format!("result{}", units))
+    };
+
+    // a file of a session is named by its tag in the legend
+    let mut datasets: Vec<Dataset> = all_results.iter().zip(&data).map(|((file_idx, results), data)| {
+        let name = if app.stdf_filenames.len() > 1 { format!("{} {} results", app.file_tag(*file_idx), results.results.len()) } else { format!("{} results", results.results.len()) };
+        Dataset::default()
+            .name(name)
+            .marker(Marker::Braille)
+            .graph_type(if app.graph_type == GraphType::Histogram { widgets::GraphType::Bar } else { widgets::GraphType::Line })
+            .style(Style::new().fg(FILE_COLORS[file_idx % FILE_COLORS.len()]))
+            .data(data)
+    }).collect();
+    for (name, line) in &limit_lines {
+        datasets.push(Dataset::default()
+            .name(*name)
+            .marker(Marker::Braille)
+            .graph_type(widgets::GraphType::Line)
+            .style(Style::new().fg(Color::Red))
+            .data(line));
+    }
+
+    let x_labels = if app.graph_type == GraphType::Histogram { get_labels(x_bounds, |v| format!("{:.3}", v)) } else { get_labels(x_bounds, |v| format!("{:.0}", v)) };
+    let y_labels = if app.graph_yscale == GraphYScale::Log10 {
+        get_labels(y_bounds, |v| format!("{:.2e}", 10f64.powf(v)))
+    } else if app.graph_type == GraphType::Histogram {
+        get_labels(y_bounds, |v| format!("{:.0}", v))
+    } else {
+        get_labels(y_bounds, |v| format!("{:.3}", v))
+    };
+
+    let chart = Chart::new(datasets)
+        .block(graph_block)
+        .x_axis(Axis::default().title(x_title).bounds(x_bounds).labels(x_labels).style(Style::new().fg(Color::Gray)))
+        .y_axis(Axis::default().title(y_title).bounds(y_bounds).labels(y_labels).style(Style::new().fg(Color::Gray)));
+    f.render_widget(chart, area);
+}
+
+// statistic of the summary pane, very small or large values in scientific notation
+fn format_stat(value: Option<f64>) -> String {
+    match value {
+        Some(value) if value != 0.0 && (value.abs() < 1e-3 || value.abs() >= 1e6) => format!("{:.3e}", value),
+        Some(value) => format!("{:.4}", value),
+        None => "".to_string(),
+    }
+}
+
+fn format_limit(limit: Option<f64>) -> String {
+    if limit.is_some() { format_stat(limit) } else { "none".to_string() }
+}
+
+fn summary_row(file: &str, row: &SummaryRow) -> Row<'static> {
+    let site = if row.head_num == 255 { "all".to_string() } else { format!("{}/{}", row.head_num, row.site_num) };
+    let style = if row.fails > 0 { Style::new().fg(Color::Red) } else { Style::new().fg(Color::Gray) };
+    Row::new([
+        Cell::from(file.to_owned()),
+        Cell::from(site),
+        Cell::from(row.count.to_string()),
+        Cell::from(row.fails.to_string()),
+        Cell::from(format_stat(row.min)),
+        Cell::from(format_stat(row.mean)),
+        Cell::from(format_stat(row.max)),
+        Cell::from(format_stat(row.stdev)),
+        Cell::from(format_stat(row.cpk)),
+    ]).style(style)
+}
+
+fn render_summary(f: &mut Frame, app: &App, area: Rect) {
+    let summary_block = Block::default()
+        .borders(Borders::ALL)
+        .style(Style::default().bg(Color::Black)).fg(Color::Gray);
+
+    // the workers may not have sent the statistics of the test yet
+    let key = app.selected_test();
+    let summaries: Vec<(usize, &TestSummaryRows)> = key.iter()
+        .flat_map(|key| app.shown_files().into_iter().filter_map(move |file_idx| app.test_summaries.get(&(file_idx, *key)).map(|summary| (file_idx, summary))))
+        .collect();
+    let (Some(key), Some((_, summary))) = (key, summaries.first()) else {
+        let summary = Paragraph::new("SUMMARY: select a PTR or FTR line to see its statistics")
+            .style(Style::new().fg(Color::Gray))
+            .block(summary_block);
+        f.render_widget(summary, area);
+        return;
+    };
+
+    let units = if !summary.units.is_empty() { format!(" ({})", summary.units) } else { "".to_string() };
+    let summary_block = summary_block.title(format!(" {} {}: {}  lo {}  hi {}{} ", key.0, key.1, summary.test_txt,
+        format_limit(summary.lo_limit), format_limit(summary.hi_limit), units));
+
+    // the file column is only needed to compare the files of a session
+    let file_width = if app.stdf_filenames.len() > 1 { 5 } else { 0 };
+    let rows = summaries.iter().flat_map(|(file_idx, summary)| {
+        let file = app.file_tag(*file_idx);
+        summary.rows.iter().map(move |row| summary_row(&file, row))
+    });
+    let header = Row::new(["file", "head/site", "count", "fails", "min", "mean", "max", "stdev", "cpk"])
+        .style(Style::new().fg(Color::White).bold());
+    let table = Table::new(rows, [Constraint::Length(file_width), Constraint::Length(9), Constraint::Length(8), Constraint::Length(8), Constraint::Fill(1), Constraint::Fill(1), Constraint::Fill(1), Constraint::Fill(1), Constraint::Fill(1)])
+        .header(header)
+        .block(summary_block);
+    f.render_widget(table, area);
+}
+
+// bin of the die shown on the wafer map with its HBR/SBR info
+fn die_bin<'a>(app: &'a App, die: &Die) -> (u16, Option<&'a BinInfo>) {
+    match app.wafer_bin_type {
+        BinType::Hard => (die.hard_bin, app.shown_wafer_maps().hard_bins.get(&die.hard_bin)),
+        BinType::Soft => (die.soft_bin, app.shown_wafer_maps().soft_bins.get(&die.soft_bin)),
+    }
+}
+
+// passing bins are green, the HBR/SBR tells if a bin passes, otherwise the PRR of the die
+fn bin_color(bin: u16, pass: Option<bool>) -> Color {
+    match pass {
+        Some(true) => if bin.is_multiple_of(2) { Color::LightGreen } else { Color::Green },
+        _ => FAIL_BIN_COLORS[bin as usize % FAIL_BIN_COLORS.len()],
+    }
+}
+
+fn die_color(app: &App, die: &Die) -> Color {
+    let (bin, info) = die_bin(app, die);
+    bin_color(bin, info.and_then(|info| info.pass).or(die.passed))
+}
+
+// first of the cells shown so the cursor stays in view
+fn get_view_start(cursor: usize, cells: usize, view: usize) -> usize {
+    if cells <= view { 0 } else { cursor.saturating_sub(view / 2).min(cells - view) }
+}
+
+fn render_wafer_map(f: &mut Frame, app: &App, area: Rect) {
+    let wafer_block = Block::default()
+        .borders(Borders::ALL)
+        .style(Style::default().bg(Color::Black)).fg(Color::Gray);
+
+    let wafer_maps = app.shown_wafer_maps();
+    let Some(wafer) = wafer_maps.wafers.get(app.wafer_idx).filter(|wafer| !wafer.dies.is_empty()) else {
+        let wafer_map = Paragraph::new("WAFER MAP: no PRR with x/y coordinates yet")
+            .style(Style::new().fg(Color::Gray))
+            .block(wafer_block);
+        f.render_widget(wafer_map, area);
+        return;
+    };
+
+    let config = &wafer_maps.config;
+    let grid = wafer.grid(config);
+    let cursor = app.wafer_cursor.and_then(|(x, y)| grid.position(x, y))
+        .or_else(|| grid.position(wafer.dies[0].x, wafer.dies[0].y))
+        .unwrap_or_default();
+
+    let bin_type = if app.wafer_bin_type == BinType::Hard { "hard" } else { "soft" };
+    let die_info = match app.selected_die() {
+        Some(die) => format!("  x {} y {}: part {} bin {} site {}", die.x, die.y, die.part_id, die_bin(app, die).0, die.site_num),
+        None => "".to_string(),
+    };
+    let file = if app.stdf_filenames.len() > 1 { format!("{} ", app.file_tag(app.file_filter.unwrap_or(0))) } else { "".to_string() };
+    let wafer_block = wafer_block.title(format!(" {}wafer {} ({}/{}) head {}  [{} bins]{} ",
+        file, wafer.wafer_id, app.wafer_idx + 1, wafer_maps.wafers.len(), wafer.head_num, bin_type, die_info));
+
+    // dies are two characters wide when the map fits, the flat takes a line or column on its side
+    let flat_rows = if matches!(config.flat, Some(WaferSide::Up | WaferSide::Down)) { 1 } else { 0 };
+    let flat_cols = if matches!(config.flat, Some(WaferSide::Left | WaferSide::Right)) { 1 } else { 0 };
+    let width = area.width.saturating_sub(2 + flat_cols) as usize;
+    let height = area.height.saturating_sub(2 + flat_rows) as usize;
+    let die_width = if grid.cols * 2 <= width { 2 } else { 1 };
+    let view_cols = grid.cols.min(width / die_width);
+    let view_rows = grid.rows.min(height);
+    let col_start = get_view_start(cursor.0, grid.cols, view_cols);
+    let row_start = get_view_start(cursor.1, grid.rows, view_rows);
+
+    let flat_style = Style::new().fg(Color::White).bold();
+    let mut lines: Vec<Line> = Vec::with_capacity(view_rows + flat_rows as usize);
+    for row in row_start..row_start + view_rows {
+        let mut spans: Vec<Span> = Vec::with_capacity(view_cols + 2);
+        let flat_span = if (view_rows / 3..=view_rows * 2 / 3).contains(&(row - row_start)) { Span::styled("┃", flat_style) } else { Span::raw(" ") };
+        if config.flat == Some(WaferSide::Left) { spans.push(flat_span.to_owned()); }
+
+        for col in col_start..col_start + view_cols {
+            let die = grid.die(col, row).map(|idx| &wafer.dies[idx]);
+            let span = match (die, (col, row) == cursor) {
+                (Some(die), true) => Span::styled("▒".repeat(die_width), Style::new().fg(Color::White).bg(die_color(app, die))),
+                (Some(die), false) => Span::styled("█".repeat(die_width), Style::new().fg(die_color(app, die))),
+                (None, true) => Span::styled("+".repeat(die_width), Style::new().fg(Color::White)),
+                (None, false) => Span::raw(" ".repeat(die_width)),
+            };
+            spans.push(span);
+        }
+
+        if config.flat == Some(WaferSide::Right) { spans.push(flat_span); }
+        lines.push(Line::from(spans));
+    }
+
+    // the flat is a third of the map wide
+    let map_width = view_cols * die_width + flat_cols as usize;
+    let flat_line = Line::from(vec![Span::raw(" ".repeat(map_width / 3)), Span::styled("━".repeat(map_width / 3), flat_style)]);
+    match config.flat {
+        Some(WaferSide::Up) => lines.insert(0, flat_line),
+        Some(WaferSide::Down) => lines.push(flat_line),
+        _ => {},
+    }
+
+    let wafer_map = Paragraph::new(lines).block(wafer_block);
+    f.render_widget(wafer_map, area);
+}
+
+fn render_wafer_legend(f: &mut Frame, app: &App, area: Rect) {
+    let bin_type = if app.wafer_bin_type == BinType::Hard { "hard" } else { "soft" };
+    let legend_block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!(" {} bins ", bin_type))
+        .style(Style::default().bg(Color::Black)).fg(Color::Gray);
+
+    // count the last test of each die of the wafer
+    let mut bins: std::collections::BTreeMap<u16, (Color, Option<&BinInfo>, u32)> = std::collections::BTreeMap::new();
+    let dies = app.shown_wafer_maps().wafers.get(app.wafer_idx).map(|wafer| wafer.last_dies()).unwrap_or_default();
+    for die in &dies {
+        let (bin, info) = die_bin(app, die);
+        bins.entry(bin).or_insert((die_color(app, die), info, 0)).2 += 1;
+    }
+
+    let rows = bins.iter().map(|(bin, (color, info, count))| {
+        let pass = match info.and_then(|info| info.pass) {
+            Some(true) => "P",
+            Some(false) => "F",
+            None => "",
+        };
+        let pct = *count as f64 * 100.0 / dies.len() as f64;
+        Row::new([
+            Cell::from("██").style(Style::new().fg(*color)),
+            Cell::from(bin.to_string()),
+            Cell::from(info.map(|info| info.name.to_owned()).unwrap_or_default()),
+            Cell::from(pass),
+            Cell::from(count.to_string()),
+            Cell::from(format!("{:.2}%", pct)),
+        ])
+    });
+
+    let header = Row::new(["", "bin", "name", "p/f", "count", "percent"])
+        .style(Style::new().fg(Color::White).bold());
+    let table = Table::new(rows, [Constraint::Length(2), Constraint::Length(6), Constraint::Fill(1), Constraint::Length(3), Constraint::Length(8), Constraint::Length(8)])
+        .header(header)
+        .block(legend_block);
+    f.render_widget(table, area);
+}
+
 pub fn ui(f: &mut Frame, app: &mut App) {
     let layout = get_layout(f, app);
 
+    // filter first, the filter bar shows the error of the current filter
+    let log_data = filter_and_trim_log_data(app, layout.inner_left[0].height - 2);
+
     // Header / Footer widgets
     let header_block = Block::default()
         .borders(Borders::NONE)
         .style(Style::default().bg(Color::Gray));
+    // a query that doesn't parse says why next to it
+    let filter_line = Line::from(vec![
+        Span::raw(format!("FILTER: {}", app.filter_string)),
+        Span::styled(app.filter_error.as_ref().map(|err| format!("  ({})", err)).unwrap_or_default(), Style::new().fg(Color::Red)),
+    ]);
     let header_filter = if app.active_widget != ActiveWidget::Filter { 
         Paragraph::new(
-            format!("FILTER: {}", app.filter_string)
+            filter_line
         )
             .style(Style::new().fg(Color::Black).bold())
             .left_aligned()
             .block(header_block.to_owned())
     } else {
         Paragraph::new(
-            format!("FILTER: {}", app.filter_string)
+            filter_line
         )
             .style(Style::new().fg(Color::White).bold().bg(Color::Black))
             .left_aligned()
             .block(header_block.to_owned())
     };
-    let header_title = Paragraph::new(
-        format!("FILE: {}", app.stdf_filename)
-    )
+    // the files of a session are listed with their tag, the shown one is marked
+    let header_text = if app.stdf_filenames.len() > 1 {
+        let files: Vec<String> = app.stdf_filenames.iter().enumerate()
+            .map(|(file_idx, name)| if app.file_filter == Some(file_idx) { format!("*{} {}", app.file_tag(file_idx), name) } else { format!("{} {}", app.file_tag(file_idx), name) })
+            .collect();
+        format!("FILES: {}", files.join("  "))
+    } else {
+        format!("FILE: {}", app.stdf_filenames.first().map(String::as_str).unwrap_or_default())
+    };
+    let header_title = Paragraph::new(header_text)
         .style(Style::new().fg(Color::Black).bold())
         .right_aligned()
         .block(header_block.to_owned());
@@ -191,11 +587,14 @@ pub fn ui(f: &mut Frame, app: &mut App) {
             .left_aligned()
             .block(footer_block.to_owned())
     };
-    let log_data = filter_and_trim_log_data(app, layout.inner_left[0].height - 2);
 
-    let footer_help = Paragraph::new(
-        format!("q: quit  f: filter  s: search  n/N: next/prev match  e: export  g: graph toggle  L: linear/log scale  arrows: Navigate  hjkl: Navigate  Enter: Confirm"),
-    )
+    let footer_help = Paragraph::new(if app.active_widget == ActiveWidget::WaferMap {
+        "q: quit  arrows/hjkl: move die  Enter: jump to die log  b: hard/soft bins  [/]: prev/next wafer  Esc/w: close map"
+    } else if app.active_widget == ActiveWidget::Filter {
+        "Enter: apply  Esc: cancel  Up: previous filter  /regex  ?query e.g. ?type=PTR and test_num in 1000..2000 and site=3 and failed"
+    } else {
+        "q: quit  f: filter  s: search  n/N: next/prev match  e: export  g: graph toggle  t: histogram/trend  L: linear/log scale  w: wafer map  Tab: next file  arrows: Navigate  hjkl: Navigate  Enter: Confirm"
+    })
         .style(Style::new().fg(Color::Black).bold())
         .centered()
         .block(footer_block.to_owned());
@@ -220,26 +619,14 @@ pub fn ui(f: &mut Frame, app: &mut App) {
         // Graph and summary widgets
         // top is graph, bottom is summary
 
-        let graph_block = Block::default()
-            .borders(Borders::ALL)
-            .style(Style::default().bg(Color::Black)).fg(Color::Gray);
-        let graph = Paragraph::new(
-            format!("GRAPH: {}", "Graph Data Here")
-        )
-            .style(Style::new().fg(Color::Gray))
-            .block(graph_block.to_owned());
-        f.render_widget(graph, inner_right[0]);
-
-        // create and render summary widget
-        let summary_block = Block::default()
-            .borders(Borders::ALL)
-            .style(Style::default().bg(Color::Black)).fg(Color::Gray);
-        let summary = Paragraph::new(
-            format!("SUMMARY: {}", "Summary Data Here")
-        )
-            .style(Style::new().fg(Color::Gray))
-            .block(summary_block.to_owned());
-        f.render_widget(summary, inner_right[1]);
+        if app.current_layout == CurrentLayout::WaferMap {
+            render_wafer_map(f, app, inner_right[0]);
+            render_wafer_legend(f, app, inner_right[1]);
+        } else {
+            render_graph(f, app, inner_right[0]);
+            render_summary(f, app, inner_right[1]);
+        }
+
     }
 
     // Render Widgets
@@ -0,0 +1,95 @@
+use argparse::{ArgumentParser, Collect, List, Store, StoreTrue};
+use stdf_reader::{convert_stdf2wafermap, WaferMapFormat, WaferMapOptions};
+
+// Define a struct to hold the arguments
+struct Arguments {
+    stdf_filenames: Vec<String>,
+    output_dir: String,
+    formats: String,
+    soft_bins: bool,
+    heat_map_tests: Vec<u32>,
+    die_size: u32,
+}
+
+fn parse_formats(formats: &str) -> Result<Vec<WaferMapFormat>, String> {
+    formats.split(',').map(|format| match format.trim() {
+        "png" => Ok(WaferMapFormat::Png),
+        "svg" => Ok(WaferMapFormat::Svg),
+        "txt" => Ok(WaferMapFormat::Ascii),
+        "e142" => Ok(WaferMapFormat::E142),
+        _ => Err(format!("'{}' is not a valid --format, use png, svg, txt or e142", format)),
+    }).collect()
+}
+
+fn main() {
+    // Call the function to parse the arguments
+    let args = parse_arguments();
+
+    let options = match parse_formats(&args.formats) {
+        Ok(formats) if args.die_size > 0 => WaferMapOptions { formats, soft_bins: args.soft_bins, heat_map_tests: args.heat_map_tests, die_size: args.die_size },
+        Ok(_) => {
+            eprintln!("Error: --die-size has to be at least 1 pixel");
+            std::process::exit(1);
+        },
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    for stdf_filename in &args.stdf_filenames {
+        // the maps go next to the stdf unless an output directory is given
+        let out_prefix = if args.output_dir.is_empty() {
+            stdf_filename.to_owned()
+        } else {
+            let file_name = std::path::Path::new(stdf_filename).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+            std::path::Path::new(&args.output_dir).join(file_name).to_string_lossy().to_string()
+        };
+
+        // do actual conversion
+        println!("Convert stdf file '{}' to wafer maps '{}.*'", stdf_filename, out_prefix);
+        match convert_stdf2wafermap(stdf_filename, &out_prefix, &options) {
+            Ok(written) if written.is_empty() => println!("  no PRR with x/y coordinates, no wafer map written"),
+            Ok(written) => written.iter().for_each(|path| println!("  {}", path)),
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+// Function to parse the arguments
+fn parse_arguments() -> Arguments {
+    let mut args = Arguments {
+        stdf_filenames: Vec::new(),
+        output_dir: String::new(),
+        formats: "png,svg,txt,e142".to_string(),
+        soft_bins: false,
+        heat_map_tests: Vec::new(),
+        die_size: 8,
+    };
+
+    // force lifetime for Argument parser to be short
+    {
+        // Create ArgumentParser variable
+        let mut ap = ArgumentParser::new();
+
+        // Application description
+        ap.set_description("Writes the bin map of every wafer of STDF files as PNG/SVG images, ascii maps and SEMI E142 style XML");
+
+        // Add all arguments and associated variables
+        ap.refer(&mut args.output_dir).add_option(&["-o", "--output-dir"], Store, "Directory for the wafer maps, by default they go next to each stdf");
+        ap.refer(&mut args.formats).add_option(&["-f", "--format"], Store, "Comma separated formats to write: png, svg, txt and/or e142 (default all)");
+        ap.refer(&mut args.soft_bins).add_option(&["--soft"], StoreTrue, "Map the soft bins instead of the hard bins");
+        ap.refer(&mut args.heat_map_tests).add_option(&["-t", "--test"], Collect, "PTR test number to also write a png/svg heat map of its results for, can be repeated");
+        ap.refer(&mut args.die_size).add_option(&["--die-size"], Store, "Size of a die in pixels in the png/svg maps (default 8)");
+        ap.refer(&mut args.stdf_filenames).add_argument("Stdf Inputs", List, "Stdf input files to be mapped").required();
+
+        // parse arguments and store
+        ap.parse_args_or_exit();
+    }
+
+    // Return the arguments in a struct
+    args
+}